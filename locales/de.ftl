@@ -9,6 +9,7 @@ app-subtitle = Pacman-Update-Automatisierung mit künstlicher Intelligenz
 menu-updates = Updates
 menu-packages = Pakete
 menu-history = Verlauf
+menu-config-files = Konfigurationsdateien
 menu-settings = Einstellungen
 menu-about = Über
 
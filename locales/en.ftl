@@ -9,6 +9,7 @@ app-subtitle = Pacman update automation with artificial intelligence
 menu-updates = Updates
 menu-packages = Packages
 menu-history = History
+menu-config-files = Config files
 menu-settings = Settings
 menu-about = About
 
@@ -9,6 +9,7 @@ app-subtitle = Automatización de actualizaciones Pacman con inteligencia artifi
 menu-updates = Actualizaciones
 menu-packages = Paquetes
 menu-history = Historial
+menu-config-files = Archivos de configuración
 menu-settings = Configuración
 menu-about = Acerca de
 
@@ -9,6 +9,7 @@ app-subtitle = Automatisation des mises à jour Pacman avec intelligence artific
 menu-updates = Mises à jour
 menu-packages = Paquets
 menu-history = Historique
+menu-config-files = Fichiers de configuration
 menu-settings = Paramètres
 menu-about = À propos
 
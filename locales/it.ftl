@@ -9,6 +9,7 @@ app-subtitle = Automazione aggiornamenti Pacman con intelligenza artificiale
 menu-updates = Aggiornamenti
 menu-packages = Pacchetti
 menu-history = Cronologia
+menu-config-files = File di configurazione
 menu-settings = Impostazioni
 menu-about = Informazioni
 
@@ -9,6 +9,7 @@ app-subtitle = 人工知能による Pacman アップデート自動化
 menu-updates = アップデート
 menu-packages = パッケージ
 menu-history = 履歴
+menu-config-files = 設定ファイル
 menu-settings = 設定
 menu-about = について
 
@@ -9,6 +9,7 @@ app-subtitle = Automação de atualizações Pacman com inteligência artificial
 menu-updates = Atualizações
 menu-packages = Pacotes
 menu-history = Histórico
+menu-config-files = Arquivos de configuração
 menu-settings = Configurações
 menu-about = Sobre
 
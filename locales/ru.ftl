@@ -9,6 +9,7 @@ app-subtitle = Автоматизация обновлений Pacman с иск
 menu-updates = Обновления
 menu-packages = Пакеты
 menu-history = История
+menu-config-files = Файлы конфигурации
 menu-settings = Настройки
 menu-about = О программе
 
@@ -9,6 +9,7 @@ app-subtitle = 基于人工智能的 Pacman 更新自动化
 menu-updates = 更新
 menu-packages = 软件包
 menu-history = 历史记录
+menu-config-files = 配置文件
 menu-settings = 设置
 menu-about = 关于
 
@@ -0,0 +1,391 @@
+//! Avis de sécurité d'Arch Linux (équivalent de `arch-audit`)
+//!
+//! Le tracker de sécurité publie ses groupes de vulnérabilités (AVG) en JSON.
+//! Un paquet est vulnérable tant que sa version installée est inférieure à la
+//! version corrigée (ou sans correctif connu), et une mise à jour est un
+//! correctif de sécurité si sa nouvelle version atteint la version corrigée.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::pacman::PackageUpdate;
+use crate::version::vercmp;
+
+/// Export JSON de tous les groupes du tracker de sécurité Arch
+pub const DEFAULT_ADVISORY_URL: &str = "https://security.archlinux.org/issues/all.json";
+
+/// Gravité d'un groupe de vulnérabilités, dans l'ordre croissant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub enum Severity {
+    #[default]
+    Unknown,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Unknown => write!(f, "inconnue"),
+            Severity::Low => write!(f, "faible"),
+            Severity::Medium => write!(f, "moyenne"),
+            Severity::High => write!(f, "élevée"),
+            Severity::Critical => write!(f, "critique"),
+        }
+    }
+}
+
+/// État d'un groupe dans le tracker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AdvisoryStatus {
+    #[default]
+    Unknown,
+    Vulnerable,
+    /// Correctif disponible dans [testing]
+    Testing,
+    Fixed,
+    #[serde(rename = "Not affected")]
+    NotAffected,
+}
+
+/// Groupe de vulnérabilités (AVG) du tracker de sécurité
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Advisory {
+    /// Identifiant du groupe (`AVG-1234`)
+    pub name: String,
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub status: AdvisoryStatus,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Type de vulnérabilité (exécution de code, déni de service...)
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    /// Version connue comme vulnérable
+    #[serde(default)]
+    pub affected: String,
+    /// Première version corrigée, None tant qu'aucun correctif n'est publié
+    #[serde(default)]
+    pub fixed: Option<String>,
+    /// CVE du groupe
+    #[serde(default)]
+    pub issues: Vec<String>,
+}
+
+impl Advisory {
+    /// Vrai si la version installée est concernée (même logique que `arch-audit`)
+    pub fn affects(&self, version: &str) -> bool {
+        match self.status {
+            AdvisoryStatus::NotAffected => false,
+            _ => self.fixed.as_deref().is_none_or(|fixed| vercmp(version, fixed).is_lt()),
+        }
+    }
+
+    /// Vrai si la version atteint la version corrigée
+    pub fn fixed_by(&self, version: &str) -> bool {
+        self.fixed.as_deref().is_some_and(|fixed| vercmp(version, fixed).is_ge())
+    }
+}
+
+impl std::fmt::Display for Advisory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}, gravité {})", self.name, self.kind, self.severity)?;
+        if !self.issues.is_empty() {
+            write!(f, " {}", self.issues.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Correctifs de sécurité apportés par une mise à jour
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityFix {
+    /// Gravité la plus élevée parmi les groupes corrigés
+    pub severity: Severity,
+    pub advisories: Vec<String>,
+}
+
+impl std::fmt::Display for SecurityFix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "🛡️ {} (gravité {})", self.advisories.join(", "), self.severity)
+    }
+}
+
+/// Paquet installé concerné par un groupe de vulnérabilités
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vulnerability {
+    pub package: String,
+    pub installed_version: String,
+    pub advisory: Advisory,
+    /// Une mise à jour en attente corrige la vulnérabilité
+    pub fixed_by_update: bool,
+}
+
+impl Vulnerability {
+    /// Aucun correctif n'est installable pour le moment
+    pub fn is_unfixed(&self) -> bool {
+        !self.fixed_by_update
+    }
+}
+
+impl std::fmt::Display for Vulnerability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.package, self.installed_version, self.advisory)?;
+        match (&self.advisory.fixed, self.fixed_by_update) {
+            (_, true) => write!(f, " [corrigé par la mise à jour en attente]"),
+            (Some(fixed), false) => write!(f, " [corrigé en {}]", fixed),
+            (None, false) => write!(f, " [aucun correctif]"),
+        }
+    }
+}
+
+/// Politique des mises à jour automatiques
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoUpdatePolicy {
+    /// Toute mise à jour disponible déclenche l'installation
+    #[default]
+    All,
+    /// Une mise à jour corrigeant une vulnérabilité déclenche une mise à jour
+    /// complète (selon `upgrade_mode`), jamais limitée aux seuls correctifs:
+    /// le système ne reste pas partiellement à jour
+    #[serde(alias = "security_only")]
+    OnSecurityFix,
+}
+
+impl AutoUpdatePolicy {
+    /// Vrai si les mises à jour disponibles justifient une installation automatique
+    pub fn should_update(self, updates: &[PackageUpdate]) -> bool {
+        match self {
+            AutoUpdatePolicy::All => !updates.is_empty(),
+            AutoUpdatePolicy::OnSecurityFix => updates.iter().any(|update| update.security.is_some()),
+        }
+    }
+}
+
+/// Groupes du tracker indexés par paquet
+#[derive(Debug, Clone, Default)]
+pub struct AdvisoryDatabase {
+    by_package: HashMap<String, Vec<Advisory>>,
+}
+
+impl AdvisoryDatabase {
+    pub fn new(advisories: Vec<Advisory>) -> Self {
+        let mut by_package: HashMap<String, Vec<Advisory>> = HashMap::new();
+        for advisory in advisories {
+            for package in &advisory.packages {
+                by_package.entry(package.clone()).or_default().push(advisory.clone());
+            }
+        }
+        Self { by_package }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_package.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_package.is_empty()
+    }
+
+    /// Groupes concernant la version d'un paquet
+    pub fn affecting<'a>(&'a self, package: &str, version: &'a str) -> impl Iterator<Item = &'a Advisory> {
+        self.by_package
+            .get(package)
+            .into_iter()
+            .flatten()
+            .filter(move |advisory| advisory.affects(version))
+    }
+
+    /// Marque les mises à jour qui corrigent une vulnérabilité de la version installée
+    pub fn tag_updates(&self, updates: &mut [PackageUpdate]) {
+        for update in updates.iter_mut() {
+            let fixed: Vec<&Advisory> = self
+                .affecting(&update.name, &update.current_version)
+                .filter(|advisory| advisory.fixed_by(&update.new_version))
+                .collect();
+            update.security = fixed.iter().map(|advisory| advisory.severity).max().map(|severity| SecurityFix {
+                severity,
+                advisories: fixed.iter().map(|advisory| advisory.name.clone()).collect(),
+            });
+        }
+
+        let count = updates.iter().filter(|update| update.security.is_some()).count();
+        if count > 0 {
+            info!("🛡️ {} mises à jour corrigent des vulnérabilités", count);
+        }
+    }
+
+    /// Vulnérabilités des paquets installés, triées par gravité décroissante
+    pub fn vulnerabilities(
+        &self,
+        installed: &HashMap<String, String>,
+        updates: &[PackageUpdate],
+    ) -> Vec<Vulnerability> {
+        let mut vulnerabilities: Vec<Vulnerability> = installed
+            .iter()
+            .flat_map(|(package, version)| {
+                let pending = updates.iter().find(|update| &update.name == package);
+                self.affecting(package, version).map(move |advisory| Vulnerability {
+                    package: package.clone(),
+                    installed_version: version.clone(),
+                    advisory: advisory.clone(),
+                    fixed_by_update: pending.is_some_and(|update| advisory.fixed_by(&update.new_version)),
+                })
+            })
+            .collect();
+
+        vulnerabilities.sort_by(|a, b| {
+            b.advisory.severity
+                .cmp(&a.advisory.severity)
+                .then_with(|| a.package.cmp(&b.package))
+                .then_with(|| a.advisory.name.cmp(&b.advisory.name))
+        });
+        vulnerabilities
+    }
+}
+
+/// Parse l'export JSON du tracker de sécurité
+pub fn parse_advisories(json: &str) -> Result<Vec<Advisory>> {
+    serde_json::from_str(json).context("Export du tracker de sécurité invalide")
+}
+
+/// Chargement des avis de sécurité depuis le tracker ou un fichier local
+#[derive(Debug, Clone)]
+pub struct AdvisoryManager {
+    source: String,
+}
+
+impl AdvisoryManager {
+    pub fn new(source: String) -> Self {
+        Self { source }
+    }
+
+    /// Télécharge (ou lit localement) les avis de sécurité
+    pub async fn fetch(&self) -> Result<AdvisoryDatabase> {
+        debug!("🛡️ Lecture des avis de sécurité: {}", self.source);
+
+        let content = if self.source.starts_with("http://") || self.source.starts_with("https://") {
+            reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .user_agent(concat!("CachyPac/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .context("Impossible de créer le client HTTP")?
+                .get(&self.source)
+                .send()
+                .await
+                .context("Impossible de télécharger les avis de sécurité")?
+                .error_for_status()
+                .context("Réponse HTTP invalide pour les avis de sécurité")?
+                .text()
+                .await
+                .context("Impossible de lire les avis de sécurité")?
+        } else {
+            let path = self.source.strip_prefix("file://").unwrap_or(&self.source);
+            fs::read_to_string(path)
+                .await
+                .context(format!("Impossible de lire les avis de sécurité: {}", path))?
+        };
+
+        let database = AdvisoryDatabase::new(parse_advisories(&content)?);
+        if database.is_empty() {
+            warn!("⚠️ Aucun avis de sécurité dans {}", self.source);
+        } else {
+            debug!("🛡️ {} avis de sécurité chargés", database.len());
+        }
+        Ok(database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const TRACKER: &str = r#"[
+  {"name": "AVG-2843", "packages": ["openssl"], "status": "Fixed", "severity": "High", "type": "arbitrary code execution",
+   "affected": "3.0.7-1", "fixed": "3.0.8-1", "ticket": null, "issues": ["CVE-2023-0286", "CVE-2023-0215"], "advisories": ["ASA-202302-1"]},
+  {"name": "AVG-2900", "packages": ["lib32-curl", "curl"], "status": "Vulnerable", "severity": "Critical", "type": "denial of service",
+   "affected": "8.0.0-1", "fixed": null, "ticket": null, "issues": ["CVE-2023-1111"], "advisories": []},
+  {"name": "AVG-2901", "packages": ["firefox"], "status": "Not affected", "severity": "Medium", "type": "information disclosure",
+   "affected": "110.0-1", "fixed": null, "ticket": null, "issues": ["CVE-2023-2222"], "advisories": []}
+]"#;
+
+    fn installed() -> HashMap<String, String> {
+        [("openssl", "3.0.7-1"), ("curl", "8.0.1-1"), ("firefox", "110.0-1"), ("linux", "6.1.1.arch1-1")]
+            .into_iter()
+            .map(|(name, version)| (name.to_string(), version.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_advisory_matching() {
+        let advisories = parse_advisories(TRACKER).unwrap();
+        assert_eq!(advisories[0].issues.len(), 2);
+        assert_eq!(advisories[2].status, AdvisoryStatus::NotAffected);
+
+        let openssl = &advisories[0];
+        assert!(openssl.affects("3.0.7-1"));
+        assert!(!openssl.affects("1:3.0.7-1"));
+        assert!(!openssl.affects("3.0.8-1"));
+        assert!(openssl.fixed_by("3.0.10-1"));
+        // Sans correctif publié, toute version est vulnérable
+        assert!(advisories[1].affects("8.0.1-1"));
+        assert!(!advisories[1].fixed_by("9.0.0-1"));
+        assert!(!advisories[2].affects("110.0-1"));
+    }
+
+    #[test]
+    fn test_tag_updates_and_vulnerabilities() {
+        let database = AdvisoryDatabase::new(parse_advisories(TRACKER).unwrap());
+        let mut updates = vec![
+            PackageUpdate::new("openssl".to_string(), "3.0.7-1".to_string(), "3.0.8-1".to_string()),
+            PackageUpdate::new("curl".to_string(), "8.0.1-1".to_string(), "8.0.2-1".to_string()),
+            PackageUpdate::new("linux".to_string(), "6.1.1.arch1-1".to_string(), "6.1.2.arch1-1".to_string()),
+        ];
+        database.tag_updates(&mut updates);
+        assert_eq!(
+            updates[0].security,
+            Some(SecurityFix { severity: Severity::High, advisories: vec!["AVG-2843".to_string()] })
+        );
+        assert_eq!(updates[1].security, None);
+        assert_eq!(updates[2].security, None);
+
+        assert!(AutoUpdatePolicy::OnSecurityFix.should_update(&updates));
+        assert!(!AutoUpdatePolicy::OnSecurityFix.should_update(&updates[1..]));
+        assert!(AutoUpdatePolicy::All.should_update(&updates[1..]));
+        // Ancien nom de la politique, toujours accepté dans la configuration
+        let policy: AutoUpdatePolicy = serde_json::from_str("\"security_only\"").unwrap();
+        assert_eq!(policy, AutoUpdatePolicy::OnSecurityFix);
+
+        let vulnerabilities = database.vulnerabilities(&installed(), &updates);
+        let found: Vec<(&str, &str, bool)> = vulnerabilities
+            .iter()
+            .map(|v| (v.package.as_str(), v.advisory.name.as_str(), v.is_unfixed()))
+            .collect();
+        // La plus grave d'abord; firefox n'est pas concerné
+        assert_eq!(found, vec![("curl", "AVG-2900", true), ("openssl", "AVG-2843", false)]);
+        assert!(vulnerabilities[0].to_string().ends_with("[aucun correctif]"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_local_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("all.json");
+        std::fs::write(&path, TRACKER).unwrap();
+
+        let database = AdvisoryManager::new(format!("file://{}", path.display())).fetch().await.unwrap();
+        // AVG-2900 concerne deux paquets
+        assert_eq!(database.len(), 4);
+        assert_eq!(database.affecting("lib32-curl", "8.0.1-1").count(), 1);
+
+        std::fs::write(&path, "<html></html>").unwrap();
+        assert!(AdvisoryManager::new(path.to_string_lossy().to_string()).fetch().await.is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Point d'accès par défaut de l'interface RPC de l'AUR
+pub const DEFAULT_AUR_RPC_URL: &str = "https://aur.archlinux.org/rpc/";
+
+/// Nombre maximal de paquets par requête `info` (limite de longueur d'URL)
+const AUR_BATCH_SIZE: usize = 100;
+
+/// Paquet tel que décrit par la requête `info` de l'AUR
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AurPackage {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub package_base: Option<String>,
+    #[serde(default)]
+    pub out_of_date: Option<i64>,
+    #[serde(default)]
+    pub maintainer: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AurResponse {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    results: Vec<AurPackage>,
+}
+
+/// Client de l'interface RPC de l'AUR (version 5)
+#[derive(Debug, Clone)]
+pub struct AurClient {
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+impl AurClient {
+    pub fn new(endpoint: &str, timeout_secs: u64) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .user_agent(concat!("CachyPac/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("Impossible de créer le client HTTP de l'AUR")?;
+
+        Ok(Self {
+            endpoint: endpoint.to_string(),
+            client,
+        })
+    }
+
+    /// Récupère les informations des paquets demandés, par lots
+    pub async fn info(&self, names: &[String]) -> Result<Vec<AurPackage>> {
+        let mut packages = Vec::with_capacity(names.len());
+
+        for batch in names.chunks(AUR_BATCH_SIZE) {
+            debug!("🌐 Requête AUR info pour {} paquets", batch.len());
+
+            let mut query = vec![("v", "5"), ("type", "info")];
+            query.extend(batch.iter().map(|name| ("arg[]", name.as_str())));
+
+            let body = self.client
+                .get(&self.endpoint)
+                .query(&query)
+                .send()
+                .await
+                .context("Impossible de contacter l'AUR")?
+                .error_for_status()
+                .context("Réponse HTTP invalide de l'AUR")?
+                .text()
+                .await
+                .context("Impossible de lire la réponse de l'AUR")?;
+
+            packages.extend(parse_info_response(&body)?);
+        }
+
+        Ok(packages)
+    }
+}
+
+/// Parse la réponse JSON d'une requête `info`
+pub fn parse_info_response(body: &str) -> Result<Vec<AurPackage>> {
+    let response: AurResponse = serde_json::from_str(body)
+        .context("Réponse JSON de l'AUR invalide")?;
+
+    if response.kind == "error" {
+        return Err(anyhow::anyhow!(
+            "Erreur de l'AUR: {}",
+            response.error.unwrap_or_default()
+        ));
+    }
+
+    Ok(response.results)
+}
+
+/// Parse la sortie de `pacman -Qm` (paquets étrangers: nom version)
+pub fn parse_foreign_packages(output: &str) -> Vec<(String, String)> {
+    let packages: Vec<(String, String)> = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(name), Some(version)) => Some((name.to_string(), version.to_string())),
+                _ => None,
+            }
+        })
+        .collect();
+
+    info!("📦 {} paquets étrangers installés", packages.len());
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info_response() {
+        let body = r#"{"version":5,"type":"multiinfo","resultcount":2,"results":[
+            {"ID":1,"Name":"yay","PackageBase":"yay","Version":"12.3.5-1","OutOfDate":null,"Maintainer":"jguer"},
+            {"ID":2,"Name":"paru","PackageBase":"paru","Version":"2.0.3-1","OutOfDate":1700000000,"Maintainer":null}
+        ]}"#;
+
+        let packages = parse_info_response(body).unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "yay");
+        assert_eq!(packages[0].version, "12.3.5-1");
+        assert_eq!(packages[0].maintainer.as_deref(), Some("jguer"));
+        assert_eq!(packages[1].out_of_date, Some(1_700_000_000));
+
+        let error = r#"{"version":5,"type":"error","resultcount":0,"results":[],"error":"Too many package results."}"#;
+        assert!(parse_info_response(error).unwrap_err().to_string().contains("Too many"));
+    }
+
+    #[test]
+    fn test_parse_foreign_packages() {
+        let output = "yay 12.3.4-1\nparu-bin 2.0.3-1\n\nligne\n";
+        let packages = parse_foreign_packages(output);
+
+        assert_eq!(packages, vec![
+            ("yay".to_string(), "12.3.4-1".to_string()),
+            ("paru-bin".to_string(), "2.0.3-1".to_string()),
+        ]);
+    }
+}
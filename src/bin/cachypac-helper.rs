@@ -0,0 +1,72 @@
+//! cachypac-helper: exécute en root les transactions pacman demandées par
+//! l'interface et le daemon CachyPac (voir `cachypac::helper`)
+
+use anyhow::Result;
+use clap::{Arg, Command};
+use std::path::PathBuf;
+use tracing::info;
+
+use cachypac::cache::DEFAULT_PACKAGE_CACHE_DIR;
+use cachypac::helper::{AccessPolicy, HelperServer, DEFAULT_HELPER_GROUP, DEFAULT_HELPER_SOCKET};
+use cachypac::lock::DEFAULT_DB_PATH;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter("cachypac=info,cachypac_helper=info")
+        .init();
+
+    let matches = Command::new("cachypac-helper")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Helper privilégié de CachyPac (transactions pacman via une socket Unix)")
+        .arg(Arg::new("socket")
+            .short('s')
+            .long("socket")
+            .value_name("PATH")
+            .default_value(DEFAULT_HELPER_SOCKET)
+            .help("Socket Unix d'écoute"))
+        .arg(Arg::new("group")
+            .short('g')
+            .long("group")
+            .value_name("GROUP")
+            .default_value(DEFAULT_HELPER_GROUP)
+            .help("Groupe autorisé sans authentification polkit"))
+        .arg(Arg::new("no-group")
+            .long("no-group")
+            .action(clap::ArgAction::SetTrue)
+            .help("N'autoriser aucun groupe (polkit uniquement)"))
+        .arg(Arg::new("no-polkit")
+            .long("no-polkit")
+            .action(clap::ArgAction::SetTrue)
+            .help("Ne pas consulter polkit (groupe uniquement)"))
+        .arg(Arg::new("cache-dir")
+            .long("cache-dir")
+            .value_name("PATH")
+            .default_value(DEFAULT_PACKAGE_CACHE_DIR)
+            .help("Cache des paquets (CacheDir de pacman, cache_dir de CachyPac)"))
+        .arg(Arg::new("db-path")
+            .long("db-path")
+            .value_name("PATH")
+            .default_value(DEFAULT_DB_PATH)
+            .help("Base de données pacman (DBPath de pacman, db_path de CachyPac)"))
+        .get_matches();
+
+    let socket = PathBuf::from(matches.get_one::<String>("socket").expect("valeur par défaut"));
+    let policy = AccessPolicy {
+        group: if matches.get_flag("no-group") {
+            None
+        } else {
+            matches.get_one::<String>("group").cloned()
+        },
+        polkit: !matches.get_flag("no-polkit"),
+    };
+
+    info!("🚀 Démarrage de cachypac-helper v{}", env!("CARGO_PKG_VERSION"));
+    let cache_dir = PathBuf::from(matches.get_one::<String>("cache-dir").expect("valeur par défaut"));
+    let db_path = PathBuf::from(matches.get_one::<String>("db-path").expect("valeur par défaut"));
+    let server = HelperServer::new(socket, policy)
+        .with_cache_dir(cache_dir)
+        .with_db_path(db_path);
+    let listener = server.bind().await?;
+    server.serve(listener).await
+}
@@ -0,0 +1,352 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::pacman::format_size;
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+use crate::version::vercmp;
+
+/// Cache des paquets de pacman
+pub const DEFAULT_PACKAGE_CACHE_DIR: &str = "/var/cache/pacman/pkg";
+
+/// Archive de paquet présente dans le cache
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CachedPackage {
+    pub name: String,
+    /// Version complète (`epoch:pkgver-pkgrel`)
+    pub version: String,
+    pub arch: String,
+    pub path: PathBuf,
+    /// Taille de l'archive et de sa signature (renseignée par `scan_package_cache`)
+    pub size: u64,
+}
+
+impl CachedPackage {
+    /// Signature détachée de l'archive (`.sig`)
+    pub fn signature_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".sig");
+        PathBuf::from(path)
+    }
+}
+
+impl CachedPackage {
+    /// Décompose un nom d'archive `nom-pkgver-pkgrel-arch.pkg.tar.*`
+    ///
+    /// Les signatures (`.sig`) et fichiers partiellement téléchargés sont ignorés.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        if file_name.ends_with(".sig") || file_name.ends_with(".part") {
+            return None;
+        }
+
+        let stem = &file_name[..file_name.find(".pkg.tar")?];
+        let mut parts = stem.rsplitn(4, '-');
+        let arch = parts.next()?;
+        let pkgrel = parts.next()?;
+        let pkgver = parts.next()?;
+        let name = parts.next()?;
+
+        if name.is_empty() || pkgver.is_empty() || pkgrel.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            version: format!("{}-{}", pkgver, pkgrel),
+            arch: arch.to_string(),
+            path: path.to_path_buf(),
+            size: 0,
+        })
+    }
+}
+
+/// Liste les archives de paquets présentes dans le cache
+pub fn scan_package_cache(cache_dir: &Path) -> Vec<CachedPackage> {
+    let entries = match std::fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("⚠️ Impossible de lire le cache des paquets {}: {}", cache_dir.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut packages: Vec<CachedPackage> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let mut package = CachedPackage::from_path(&entry.path())?;
+            package.size = [package.path.clone(), package.signature_path()]
+                .iter()
+                .filter_map(|path| std::fs::metadata(path).ok())
+                .map(|metadata| metadata.len())
+                .sum();
+            Some(package)
+        })
+        .collect();
+    packages.sort();
+
+    debug!("📦 {} paquets dans le cache {}", packages.len(), cache_dir.display());
+    packages
+}
+
+/// Politique de conservation du cache, à la manière de `paccache -rk` / `-ruk`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheRetention {
+    /// Versions conservées par paquet installé
+    pub keep_installed: usize,
+    /// Versions conservées par paquet désinstallé
+    pub keep_uninstalled: usize,
+}
+
+impl Default for CacheRetention {
+    fn default() -> Self {
+        // Trois versions laissent de quoi revenir en arrière après deux mises à jour
+        Self {
+            keep_installed: 3,
+            keep_uninstalled: 1,
+        }
+    }
+}
+
+impl std::fmt::Display for CacheRetention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} versions par paquet installé, {} par paquet désinstallé",
+            self.keep_installed, self.keep_uninstalled
+        )
+    }
+}
+
+/// Archives à supprimer selon la politique de conservation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheCleanup {
+    pub remove: Vec<CachedPackage>,
+    /// Nombre d'archives conservées
+    pub kept: usize,
+}
+
+impl CacheCleanup {
+    pub fn is_empty(&self) -> bool {
+        self.remove.is_empty()
+    }
+
+    /// Espace récupérable (archives et signatures)
+    pub fn reclaimable(&self) -> u64 {
+        self.remove.iter().map(|package| package.size).sum()
+    }
+}
+
+impl std::fmt::Display for CacheCleanup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} archives à supprimer ({} récupérables), {} conservées",
+            self.remove.len(),
+            format_size(self.reclaimable()),
+            self.kept
+        )
+    }
+}
+
+/// Choisit les archives à supprimer: pour chaque paquet (et architecture), les
+/// versions les plus récentes sont conservées
+pub fn plan_cleanup(packages: Vec<CachedPackage>, installed: &HashSet<String>, retention: CacheRetention) -> CacheCleanup {
+    let mut groups: BTreeMap<(String, String), Vec<CachedPackage>> = BTreeMap::new();
+    for package in packages {
+        groups.entry((package.name.clone(), package.arch.clone())).or_default().push(package);
+    }
+
+    let mut cleanup = CacheCleanup::default();
+    for ((name, _), mut versions) in groups {
+        let keep = if installed.contains(&name) {
+            retention.keep_installed
+        } else {
+            retention.keep_uninstalled
+        };
+        versions.sort_by(|a, b| vercmp(&b.version, &a.version));
+        cleanup.kept += versions.len().min(keep);
+        cleanup.remove.extend(versions.into_iter().skip(keep));
+    }
+
+    cleanup
+}
+
+/// Nettoyage natif du cache des paquets (remplace `pacman -Sc`)
+#[derive(Debug, Clone)]
+pub struct CacheManager<R: CommandRunner = SystemRunner> {
+    runner: R,
+    cache_dir: PathBuf,
+    retention: CacheRetention,
+}
+
+impl CacheManager {
+    #[allow(dead_code)]
+    pub fn new(cache_dir: PathBuf, retention: CacheRetention) -> Self {
+        Self::with_runner(SystemRunner, cache_dir, retention)
+    }
+}
+
+impl<R: CommandRunner> CacheManager<R> {
+    pub fn with_runner(runner: R, cache_dir: PathBuf, retention: CacheRetention) -> Self {
+        Self { runner, cache_dir, retention }
+    }
+
+    /// Paquets installés (`pacman -Qq`)
+    async fn installed_packages(&self) -> Result<HashSet<String>> {
+        let output = self.runner
+            .run(&CommandSpec::new("pacman").arg("-Qq"))
+            .await
+            .context("Impossible de lister les paquets installés")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de pacman -Qq: {}", output.stderr.trim()));
+        }
+        Ok(output.stdout.split_whitespace().map(ToString::to_string).collect())
+    }
+
+    /// Calcule les archives à supprimer, sans rien supprimer
+    pub async fn plan(&self) -> Result<CacheCleanup> {
+        let installed = self.installed_packages().await?;
+        let cache_dir = self.cache_dir.clone();
+        let packages = tokio::task::spawn_blocking(move || scan_package_cache(&cache_dir))
+            .await
+            .context("Échec du parcours du cache des paquets")?;
+
+        let cleanup = plan_cleanup(packages, &installed, self.retention);
+        info!("🧹 Cache {} ({}): {}", self.cache_dir.display(), self.retention, cleanup);
+        Ok(cleanup)
+    }
+
+    /// Fichiers à supprimer: archives et signatures présentes
+    fn files(cleanup: &CacheCleanup) -> Vec<PathBuf> {
+        cleanup.remove
+            .iter()
+            .flat_map(|package| [package.path.clone(), package.signature_path()])
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// Supprime directement les archives (processus disposant des droits sur le cache)
+    pub async fn delete(&self, cleanup: &CacheCleanup) -> Result<u64> {
+        for path in Self::files(cleanup) {
+            tokio::fs::remove_file(&path)
+                .await
+                .context(format!("Impossible de supprimer {}", path.display()))?;
+            debug!("🗑️ {}", path.display());
+        }
+        Ok(cleanup.reclaimable())
+    }
+
+    /// Supprime les archives avec sudo
+    pub async fn delete_with_sudo(&self, cleanup: &CacheCleanup) -> Result<u64> {
+        let files = Self::files(cleanup);
+        if files.is_empty() {
+            return Ok(0);
+        }
+
+        let cmd = CommandSpec::new("sudo")
+            .args(["rm", "-f", "--"])
+            .args(files.iter().map(|path| path.to_string_lossy()));
+        let output = self.runner.run(&cmd).await.context("Impossible d'exécuter rm")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec du nettoyage du cache: {}", output.stderr.trim()));
+        }
+        Ok(cleanup.reclaimable())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_cached_package() {
+        let package = CachedPackage::from_path(Path::new("/var/cache/pacman/pkg/python-yaml-6.0.1-3-x86_64.pkg.tar.zst")).unwrap();
+        assert_eq!(package.name, "python-yaml");
+        assert_eq!(package.version, "6.0.1-3");
+        assert_eq!(package.arch, "x86_64");
+
+        let package = CachedPackage::from_path(Path::new("libjpeg-turbo-1:3.0.4-1-x86_64_v3.pkg.tar.xz")).unwrap();
+        assert_eq!(package.name, "libjpeg-turbo");
+        assert_eq!(package.version, "1:3.0.4-1");
+        assert_eq!(package.arch, "x86_64_v3");
+
+        assert!(CachedPackage::from_path(Path::new("firefox-92.0-1-x86_64.pkg.tar.zst.sig")).is_none());
+        assert!(CachedPackage::from_path(Path::new("firefox-92.0-1-x86_64.pkg.tar.zst.part")).is_none());
+        assert!(CachedPackage::from_path(Path::new("download-a1b2c3")).is_none());
+    }
+
+    #[test]
+    fn test_scan_package_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in [
+            "firefox-92.0-1-x86_64.pkg.tar.zst",
+            "firefox-92.0-1-x86_64.pkg.tar.zst.sig",
+            "firefox-91.0-1-x86_64.pkg.tar.zst",
+            "README",
+        ] {
+            std::fs::write(temp_dir.path().join(name), "").unwrap();
+        }
+
+        let packages = scan_package_cache(temp_dir.path());
+        let versions: Vec<&str> = packages.iter().map(|p| p.version.as_str()).collect();
+        assert_eq!(versions, vec!["91.0-1", "92.0-1"]);
+        assert!(scan_package_cache(&temp_dir.path().join("absent")).is_empty());
+    }
+
+    fn write_cache(dir: &Path, files: &[&str]) {
+        for name in files {
+            std::fs::write(dir.join(name), "0123456789").unwrap();
+            std::fs::write(dir.join(format!("{name}.sig")), "sig").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_plan_cleanup() {
+        let temp_dir = TempDir::new().unwrap();
+        write_cache(temp_dir.path(), &[
+            "firefox-90.0-1-x86_64.pkg.tar.zst",
+            "firefox-92.0-1-x86_64.pkg.tar.zst",
+            "firefox-91.0-1-x86_64.pkg.tar.zst",
+            "firefox-91.0.1-1-x86_64.pkg.tar.zst",
+            "gtk2-2.24.33-2-x86_64.pkg.tar.zst",
+            "gtk2-2.24.33-3-x86_64.pkg.tar.zst",
+        ]);
+        let installed: HashSet<String> = ["firefox".to_string()].into();
+
+        let cleanup = plan_cleanup(scan_package_cache(temp_dir.path()), &installed, CacheRetention::default());
+        let removed: Vec<String> = cleanup.remove.iter().map(|p| format!("{} {}", p.name, p.version)).collect();
+        // Comparaison de versions pacman, pas de chaînes: 91.0.1 est plus récent que 91.0
+        assert_eq!(removed, vec!["firefox 90.0-1", "gtk2 2.24.33-2"]);
+        assert_eq!(cleanup.kept, 4);
+        assert_eq!(cleanup.reclaimable(), 2 * 13);
+
+        let none_uninstalled = CacheRetention { keep_installed: 1, keep_uninstalled: 0 };
+        let cleanup = plan_cleanup(scan_package_cache(temp_dir.path()), &installed, none_uninstalled);
+        assert_eq!(cleanup.remove.len(), 5);
+        assert_eq!(cleanup.kept, 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_manager_delete() {
+        use crate::runner::{ScriptedResponse, ScriptedRunner};
+
+        let temp_dir = TempDir::new().unwrap();
+        write_cache(temp_dir.path(), &["firefox-91.0-1-x86_64.pkg.tar.zst", "firefox-92.0-1-x86_64.pkg.tar.zst"]);
+        let runner = ScriptedRunner::new().on(&["pacman", "-Qq"], ScriptedResponse::ok("firefox\nlinux\n"));
+        let retention = CacheRetention { keep_installed: 1, keep_uninstalled: 0 };
+        let manager = CacheManager::with_runner(runner, temp_dir.path().to_path_buf(), retention);
+
+        let cleanup = manager.plan().await.unwrap();
+        assert_eq!(cleanup.to_string(), "1 archives à supprimer (13 B récupérables), 1 conservées");
+        assert_eq!(manager.delete(&cleanup).await.unwrap(), 13);
+        assert!(!temp_dir.path().join("firefox-91.0-1-x86_64.pkg.tar.zst").exists());
+        assert!(!temp_dir.path().join("firefox-91.0-1-x86_64.pkg.tar.zst.sig").exists());
+        assert!(temp_dir.path().join("firefox-92.0-1-x86_64.pkg.tar.zst").exists());
+        assert!(manager.plan().await.unwrap().is_empty());
+    }
+}
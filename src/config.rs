@@ -6,6 +6,13 @@ use std::time::{Duration, Instant};
 use tokio::fs;
 use tracing::{debug, info, warn};
 
+use crate::advisory::{AutoUpdatePolicy, Severity};
+use crate::cache::CacheRetention;
+use crate::hold::{HoldEngine, HoldRule};
+use crate::plan::UpgradeMode;
+use crate::snapshot::SnapshotBackend;
+use crate::syncdb::UpdateBackend;
+
 /// Cache pour la configuration avec TTL
 #[derive(Debug)]
 struct ConfigCache {
@@ -39,6 +46,18 @@ pub struct Config {
     pub scheduler: SchedulerConfig,
     pub telegram: TelegramConfig,
     pub gui: GuiConfig,
+    #[serde(default)]
+    pub snapshots: SnapshotConfig,
+    #[serde(default)]
+    pub orphans: OrphanConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub restart: RestartConfig,
+    #[serde(default)]
+    pub prefetch: PrefetchConfig,
+    #[serde(default)]
+    pub mirrors: MirrorsConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,8 +65,16 @@ pub struct GeneralConfig {
     pub data_dir: String,
     pub log_level: String,
     pub check_interval: u64,
+    /// Mode daemon: installer les mises à jour sans intervention (sinon notification seule)
     pub auto_update: bool,
     pub backup_before_update: bool,
+    /// Flux RSS des actualités Arch (URL, chemin ou file://)
+    #[serde(default = "default_news_feed_url")]
+    pub news_feed_url: String,
+}
+
+fn default_news_feed_url() -> String {
+    crate::news::DEFAULT_NEWS_FEED_URL.to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +86,89 @@ pub struct PacmanConfig {
     pub include_aur: bool,
     pub clean_cache_after: bool,
     pub check_keyring: bool,
+    /// Point d'accès RPC de l'AUR (configurable pour les tests)
+    #[serde(default = "default_aur_rpc_url")]
+    pub aur_rpc_url: String,
+    /// Outil de fusion des fichiers .pacnew (lancé via sudo)
+    #[serde(default = "default_merge_tool")]
+    pub merge_tool: String,
+    /// Stratégie de mise à jour: full, full_with_ignores ou selective
+    #[serde(default)]
+    pub upgrade_mode: UpgradeMode,
+    /// Autorise le mode sélectif à laisser des dépendances partiellement à jour
+    #[serde(default)]
+    pub allow_partial_upgrades: bool,
+    /// Mode daemon: revient aux versions du cache si une mise à jour automatique échoue
+    #[serde(default)]
+    pub rollback_on_failure: bool,
+    /// Journal de pacman importé dans l'historique
+    #[serde(default = "default_pacman_log")]
+    pub log_file: PathBuf,
+    /// Règles retenant des mises à jour (motifs, dépôts, groupes, versions)
+    #[serde(default)]
+    pub hold_rules: Vec<HoldRule>,
+    /// Base de données de pacman (contient le verrou db.lck)
+    #[serde(default = "default_db_path")]
+    pub db_path: PathBuf,
+    /// Attente maximale de la libération du verrou, en secondes
+    #[serde(default = "default_lock_timeout")]
+    pub lock_timeout: u64,
+    /// Supprimer automatiquement un verrou sans processus pacman actif
+    #[serde(default)]
+    pub remove_stale_lock: bool,
+    /// Socket de cachypac-helper pour les transactions (None: sudo)
+    #[serde(default)]
+    pub helper_socket: Option<PathBuf>,
+    /// Cache des paquets nettoyé par `clean_cache`
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: PathBuf,
+    /// Versions conservées dans le cache
+    #[serde(default)]
+    pub cache_retention: CacheRetention,
+    /// Vérification des mises à jour: auto, checkupdates ou native
+    #[serde(default)]
+    pub update_backend: UpdateBackend,
+    /// Configuration de pacman: paquets ignorés, HoldPkg, dépôts et miroirs
+    #[serde(default = "default_pacman_conf")]
+    pub pacman_conf: PathBuf,
+    /// Bases de synchronisation privées de la vérification native
+    #[serde(default = "default_sync_db_dir")]
+    pub sync_db_dir: PathBuf,
+}
+
+fn default_pacman_conf() -> PathBuf {
+    PathBuf::from(crate::pacman_conf::DEFAULT_PACMAN_CONF)
+}
+
+fn default_sync_db_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("cachypac")
+        .join("sync")
+}
+
+fn default_cache_dir() -> PathBuf {
+    PathBuf::from(crate::cache::DEFAULT_PACKAGE_CACHE_DIR)
+}
+
+fn default_db_path() -> PathBuf {
+    PathBuf::from(crate::lock::DEFAULT_DB_PATH)
+}
+
+fn default_lock_timeout() -> u64 {
+    600
+}
+
+fn default_pacman_log() -> PathBuf {
+    PathBuf::from(crate::pacman_log::DEFAULT_PACMAN_LOG)
+}
+
+fn default_merge_tool() -> String {
+    "meld".to_string()
+}
+
+fn default_aur_rpc_url() -> String {
+    crate::aur::DEFAULT_AUR_RPC_URL.to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,6 +191,175 @@ pub struct TelegramConfig {
     pub message_format: String,
 }
 
+/// Instantanés du système de fichiers (utilisés si `backup_before_update`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// auto, snapper, btrfs ou timeshift
+    pub backend: SnapshotBackend,
+    /// Nombre de mises à jour dont les instantanés pre/post sont conservés (0 = illimité)
+    pub retention: usize,
+    /// Configuration snapper utilisée
+    pub snapper_config: String,
+    /// Sous-volume btrfs à capturer
+    pub btrfs_subvolume: String,
+    /// Répertoire des instantanés btrfs
+    pub btrfs_snapshot_dir: String,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            backend: SnapshotBackend::Auto,
+            retention: 10,
+            snapper_config: "root".to_string(),
+            btrfs_subvolume: "/".to_string(),
+            btrfs_snapshot_dir: "/.snapshots/cachypac".to_string(),
+        }
+    }
+}
+
+/// Rapport périodique des paquets orphelins (mode daemon, sans suppression)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrphanConfig {
+    /// Intervalle entre deux rapports, en heures (0 = désactivé)
+    pub report_interval_hours: u64,
+    /// Notifier par Telegram lorsque le nombre d'orphelins augmente
+    pub notify_on_growth: bool,
+}
+
+impl Default for OrphanConfig {
+    fn default() -> Self {
+        Self {
+            report_interval_hours: 24,
+            notify_on_growth: true,
+        }
+    }
+}
+
+/// Avis de sécurité d'Arch Linux
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityConfig {
+    /// Consulter le tracker de sécurité lors des vérifications
+    pub enabled: bool,
+    /// Export JSON du tracker (URL, chemin ou file://)
+    pub advisory_url: String,
+    /// all: toute mise à jour est installée automatiquement; on_security_fix:
+    /// mise à jour complète seulement lorsqu'une mise à jour corrige une vulnérabilité
+    pub auto_update_policy: AutoUpdatePolicy,
+    /// Gravité à partir de laquelle une vulnérabilité sans correctif fait l'objet
+    /// d'une alerte immédiate
+    pub alert_severity: Severity,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            advisory_url: crate::advisory::DEFAULT_ADVISORY_URL.to_string(),
+            auto_update_policy: AutoUpdatePolicy::default(),
+            alert_severity: Severity::Critical,
+        }
+    }
+}
+
+/// Redémarrages après une mise à jour (noyau, services utilisant des fichiers supprimés)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestartConfig {
+    /// Analyser le noyau et les processus après chaque mise à jour
+    pub check_after_upgrade: bool,
+    /// Redémarrer automatiquement les services concernés (mode daemon)
+    pub auto_restart_units: bool,
+    /// Services jamais redémarrés automatiquement (motifs glob)
+    pub never_restart: Vec<String>,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        // Redémarrer ces services fermerait la session graphique ou couperait le daemon
+        Self {
+            check_after_upgrade: true,
+            auto_restart_units: false,
+            never_restart: [
+                "cachypac*.service",
+                "dbus*.service",
+                "systemd-logind.service",
+                "display-manager.service",
+                "gdm.service",
+                "sddm.service",
+                "lightdm.service",
+                "getty@*.service",
+                "user@*.service",
+            ]
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        }
+    }
+}
+
+/// Préchargement des mises à jour dans le cache, sans installation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefetchConfig {
+    /// Mode daemon: télécharger les mises à jour pendant la fenêtre de préchargement
+    pub enabled: bool,
+    /// Début de la fenêtre (HH:MM), indépendante de la fenêtre de maintenance
+    pub window_start: String,
+    /// Fin de la fenêtre (HH:MM)
+    pub window_end: String,
+    /// Débit maximal en Kio/s (absent: illimité)
+    pub rate_limit_kib: Option<u32>,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_start: "01:00".to_string(),
+            window_end: "05:00".to_string(),
+            rate_limit_kib: None,
+        }
+    }
+}
+
+/// État des miroirs: date de synchronisation (`lastsync`) et latence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MirrorsConfig {
+    /// Vérifier les miroirs avant chaque mise à jour automatique
+    pub check_before_upgrade: bool,
+    /// Liste de miroirs analysée et reclassée
+    pub mirrorlist: PathBuf,
+    /// Fichier de date de synchronisation, relatif à la racine de chaque miroir
+    pub lastsync_path: String,
+    /// `lastsync` de référence (absent: le miroir le plus récent)
+    pub reference_url: Option<String>,
+    /// Retard au-delà duquel un miroir est considéré comme obsolète
+    pub max_lag_hours: u64,
+    /// Délai d'attente de chaque miroir en secondes
+    pub timeout: u64,
+    /// Réécrire la liste (miroirs à jour triés par latence) quand un miroir est obsolète
+    pub rank: bool,
+}
+
+impl Default for MirrorsConfig {
+    fn default() -> Self {
+        Self {
+            check_before_upgrade: true,
+            mirrorlist: PathBuf::from(crate::mirrors::DEFAULT_MIRRORLIST),
+            lastsync_path: "lastsync".to_string(),
+            reference_url: None,
+            max_lag_hours: 24,
+            timeout: 10,
+            rank: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuiConfig {
     pub theme: String,
@@ -223,6 +502,8 @@ impl Config {
             debug!("📁 Répertoire de données créé: {:?}", data_dir);
         }
 
+        HoldEngine::new(&self.pacman.hold_rules).context("Règles de retenue invalides")?;
+
         // Validation Telegram
         if self.telegram.enabled {
             if self.telegram.bot_token.is_empty() {
@@ -250,6 +531,7 @@ impl Default for Config {
                 check_interval: 60,
                 auto_update: false,
                 backup_before_update: true,
+                news_feed_url: default_news_feed_url(),
             },
             pacman: PacmanConfig {
                 timeout: 300,
@@ -259,6 +541,22 @@ impl Default for Config {
                 include_aur: false,
                 clean_cache_after: true,
                 check_keyring: true,
+                aur_rpc_url: default_aur_rpc_url(),
+                merge_tool: default_merge_tool(),
+                upgrade_mode: UpgradeMode::default(),
+                allow_partial_upgrades: false,
+                rollback_on_failure: false,
+                log_file: default_pacman_log(),
+                hold_rules: Vec::new(),
+                db_path: default_db_path(),
+                lock_timeout: default_lock_timeout(),
+                remove_stale_lock: false,
+                helper_socket: None,
+                cache_dir: default_cache_dir(),
+                cache_retention: CacheRetention::default(),
+                update_backend: UpdateBackend::default(),
+                pacman_conf: default_pacman_conf(),
+                sync_db_dir: default_sync_db_dir(),
             },
             scheduler: SchedulerConfig {
                 enabled: false,
@@ -284,6 +582,12 @@ impl Default for Config {
                 show_system_tray: true,
                 close_to_tray: true,
             },
+            snapshots: SnapshotConfig::default(),
+            orphans: OrphanConfig::default(),
+            security: SecurityConfig::default(),
+            restart: RestartConfig::default(),
+            prefetch: PrefetchConfig::default(),
+            mirrors: MirrorsConfig::default(),
         }
     }
 }
\ No newline at end of file
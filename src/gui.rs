@@ -1,17 +1,38 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use iced::{
-    widget::{button, column, container, progress_bar, row, scrollable, text, text_input, Column},
-    Application, Command, Element, Length, Settings, Theme,
+    widget::{button, checkbox, column, container, progress_bar, row, scrollable, text, text_input, Column},
+    Application, Command, Element, Length, Settings, Subscription, Theme,
 };
 use iced_aw::tab_bar;
-use tracing::{error, info};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{error, info, warn};
 
 use crate::{
+    advisory::AdvisoryManager,
+    cache::CacheCleanup,
     config::Config,
-    pacman::PacmanManager,
+    hold::HoldRule,
+    news::{NewsItem, NewsManager},
+    pacman::{format_size, format_size_delta, PackageUpdate, PacmanManager},
+    localdb::{LocalDatabase, LocalDbCache, LocalSummary},
+    lock::{LockState, DB_LOCK_FILE},
+    mirrors::{MirrorHealth, MirrorManager},
+    pacnew::{PacnewAction, PacnewFile, PacnewManager},
+    restart::{RestartAnalyzer, RestartReport},
+    pacman_conf::{Mirrorlist, PacmanConf},
+    pacman_log::PacmanLogImporter,
+    orphans::{OrphanManager, OrphanReport},
+    performance::PerformanceConfig,
+    plan::{UpgradeMode, UpgradePlan},
+    prefetch::is_ready_to_install,
+    preview::TransactionPreview,
+    rollback::RollbackManager,
+    snapshot::SnapshotManager,
+    progress::{ProgressReport, TransactionEvent},
     scheduler::SchedulerManager,
     telegram_robust::{RobustTelegramNotifier, TelegramConfig},
-    history::UpdateHistory,
+    history::{HistoryEntry, OperationType, UpdateHistory},
     logs::LogManager,
     i18n::translate,
 };
@@ -21,12 +42,22 @@ pub enum Message {
     TabSelected(TabId),
     CheckUpdates,
     InstallUpdates,
-    UpdatesChecked(Result<Vec<String>, String>),
-    UpdatesInstalled(Result<(), String>),
+    UpgradePlanned(Result<(UpgradePlan, Option<TransactionPreview>), String>),
+    ConfirmUpgrade,
+    CancelUpgrade,
+    ToggleUpdateSelection(String, bool),
+    UpdatesChecked(Result<Vec<PackageUpdate>, String>),
+    UpdatesInstalled(Result<Vec<PacnewFile>, String>),
+    InstallProgress(ProgressReport),
+    DatabaseLockChecked(LockState),
+    RemoveStaleLock,
+    StaleLockRemoved(Result<(), String>),
     ConfigChanged(String, String),
     SaveConfig,
     LoadHistory,
-    HistoryLoaded(Result<Vec<String>, String>),
+    HistoryLoaded(Result<Vec<HistoryEntry>, String>),
+    RollbackEntry(uuid::Uuid),
+    RollbackFinished(Result<String, String>),
     TestTelegram,
     TelegramTested(Result<(), String>),
     DiagnosticTelegram,
@@ -35,6 +66,34 @@ pub enum Message {
     #[allow(dead_code)]
     RefreshLogs,
     LogsRefreshed(Result<Vec<String>, String>),
+    NewsChecked(Result<Vec<NewsItem>, String>),
+    MarkNewsRead,
+    NewsMarkedRead(Result<(), String>),
+    ScanPacnew,
+    PacnewScanned(Result<Vec<PacnewFile>, String>),
+    ShowPacnewDiff(PacnewFile),
+    PacnewDiffLoaded(Result<String, String>),
+    ApplyPacnewAction(PacnewFile, PacnewAction),
+    PacnewActionApplied(Result<(), String>),
+    ScanOrphans,
+    OrphansScanned(Result<OrphanReport, String>),
+    ToggleOrphanSelection(String, bool),
+    RemoveOrphans,
+    ConfirmOrphanRemoval,
+    CancelOrphanRemoval,
+    OrphansRemoved(Result<u64, String>),
+    CacheScanned(Result<CacheCleanup, String>),
+    CleanCache,
+    CacheCleaned(Result<u64, String>),
+    RestartChecked(Result<RestartReport, String>),
+    RestartUnits,
+    UnitsRestarted(Result<Vec<String>, String>),
+    LocalDbLoaded(Result<LocalSummary, String>),
+    /// pacman.conf et nombre de miroirs actifs
+    PacmanConfLoaded(Result<(PacmanConf, usize), String>),
+    MirrorsChecked(Result<MirrorHealth, String>),
+    RankMirrors,
+    MirrorsRanked(Result<std::path::PathBuf, String>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -43,15 +102,19 @@ pub enum TabId {
     Updates,
     Scheduler,
     History,
+    ConfigFiles,
+    Packages,
     Settings,
 }
 
 impl TabId {
-    const ALL: [TabId; 5] = [
+    const ALL: [TabId; 7] = [
         TabId::Dashboard,
         TabId::Updates,
         TabId::Scheduler,
         TabId::History,
+        TabId::ConfigFiles,
+        TabId::Packages,
         TabId::Settings,
     ];
 }
@@ -63,6 +126,8 @@ impl std::fmt::Display for TabId {
             TabId::Updates => format!("📦 {}", translate("menu-updates")),
             TabId::Scheduler => format!("⏰ {}", translate("menu-scheduler")),
             TabId::History => format!("📜 {}", translate("menu-history")),
+            TabId::ConfigFiles => format!("🗂️ {}", translate("menu-config-files")),
+            TabId::Packages => format!("🧹 {}", translate("menu-packages")),
             TabId::Settings => format!("⚙️ {}", translate("menu-settings")),
         };
         write!(f, "{}", text)
@@ -73,13 +138,24 @@ impl std::fmt::Display for TabId {
 pub struct CachyPacApp {
     config: Config,
     current_tab: TabId,
-    available_updates: Vec<String>,
+    available_updates: Vec<PackageUpdate>,
+    /// Paquets choisis pour le mode sélectif
+    selected_updates: HashSet<String>,
+    /// Plan affiché en attente de confirmation
+    pending_plan: Option<UpgradePlan>,
+    /// Aperçu de la transaction du plan en attente
+    pending_preview: Option<TransactionPreview>,
     is_checking_updates: bool,
     is_installing_updates: bool,
+    /// Verrou db.lck présent sans processus pacman actif
+    stale_lock: bool,
+    /// Toutes les mises à jour disponibles sont déjà dans le cache
+    ready_to_install: bool,
     status_message: String,
     progress: f32,
     config_inputs: HashMap<String, String>,
-    history_entries: Vec<String>,
+    history_entries: Vec<HistoryEntry>,
+    is_rolling_back: bool,
     log_entries: Vec<String>,
     pacman_manager: PacmanManager,
     #[allow(dead_code)]
@@ -87,8 +163,41 @@ pub struct CachyPacApp {
     telegram_notifier: Option<RobustTelegramNotifier>,
     update_history: UpdateHistory,
     log_manager: LogManager,
+    install_output: Vec<String>,
+    install_run: u64,
+    progress_receiver: Option<Arc<Mutex<mpsc::UnboundedReceiver<ProgressReport>>>>,
+    unread_news: Vec<NewsItem>,
+    pacnew_manager: PacnewManager,
+    pacnew_files: Vec<PacnewFile>,
+    /// Fichier sélectionné et son diff avec la configuration active
+    pacnew_diff: Option<(PacnewFile, String)>,
+    orphan_manager: OrphanManager,
+    orphan_report: Option<OrphanReport>,
+    /// Paquets choisis pour la suppression
+    selected_orphans: HashSet<String>,
+    /// Suppression affichée en attente de confirmation
+    confirm_orphan_removal: bool,
+    is_removing_orphans: bool,
+    /// Archives du cache au-delà de la politique de conservation
+    cache_cleanup: Option<CacheCleanup>,
+    is_cleaning_cache: bool,
+    /// Redémarrages nécessaires (noyau, services utilisant des fichiers supprimés)
+    restart_report: Option<RestartReport>,
+    is_restarting_units: bool,
+    /// Base locale de pacman, lue sans lancer pacman
+    local_db: LocalDatabase,
+    local_summary: Option<LocalSummary>,
+    /// Réglages de pacman (paquets ignorés, HoldPkg, miroirs)
+    pacman_conf: Option<PacmanConf>,
+    active_mirrors: usize,
+    /// Date de synchronisation et latence des miroirs
+    mirror_health: Option<MirrorHealth>,
+    is_ranking_mirrors: bool,
 }
 
+/// Nombre maximal de lignes conservées dans le panneau de sortie pacman
+const MAX_INSTALL_OUTPUT_LINES: usize = 500;
+
 impl Application for CachyPacApp {
     type Message = Message;
     type Theme = Theme;
@@ -104,6 +213,10 @@ impl Application for CachyPacApp {
         config_inputs.insert("telegram_chat_id".to_string(), config.telegram.chat_id.clone());
 
         let pacman_manager = PacmanManager::new(config.pacman.clone());
+        let pacnew_manager = PacnewManager::new(config.pacman.merge_tool.clone());
+        let orphan_manager = OrphanManager::new(config.pacman.timeout).with_helper(config.pacman.helper_socket.clone());
+        let local_db = LocalDatabase::new(config.pacman.db_path.clone())
+            .with_cache(Arc::new(LocalDbCache::new(PerformanceConfig::default())));
         let scheduler_manager = SchedulerManager::new();
         
         // Utilisation du module Telegram robuste
@@ -138,21 +251,50 @@ impl Application for CachyPacApp {
             config,
             current_tab: TabId::Dashboard,
             available_updates: Vec::new(),
+            selected_updates: HashSet::new(),
+            pending_plan: None,
+            pending_preview: None,
             is_checking_updates: false,
             is_installing_updates: false,
+            stale_lock: false,
+            ready_to_install: false,
             status_message: "CachyPac prêt".to_string(),
             progress: 0.0,
             config_inputs,
             history_entries: Vec::new(),
+            is_rolling_back: false,
             log_entries: Vec::new(),
             pacman_manager,
             scheduler_manager,
             telegram_notifier,
             update_history,
             log_manager,
+            install_output: Vec::new(),
+            install_run: 0,
+            progress_receiver: None,
+            unread_news: Vec::new(),
+            pacnew_manager,
+            pacnew_files: Vec::new(),
+            pacnew_diff: None,
+            orphan_manager,
+            orphan_report: None,
+            selected_orphans: HashSet::new(),
+            confirm_orphan_removal: false,
+            is_removing_orphans: false,
+            cache_cleanup: None,
+            is_cleaning_cache: false,
+            restart_report: None,
+            is_restarting_units: false,
+            local_db,
+            local_summary: None,
+            pacman_conf: None,
+            active_mirrors: 0,
+            mirror_health: None,
+            is_ranking_mirrors: false,
         };
 
-        (app, Command::none())
+        let startup = Command::batch([app.check_news(), app.scan_pacnew(), app.check_restart(), app.load_local_db(), app.load_pacman_conf(), app.check_mirrors()]);
+        (app, startup)
     }
 
     fn title(&self) -> String {
@@ -163,6 +305,9 @@ impl Application for CachyPacApp {
         match message {
             Message::TabSelected(tab) => {
                 self.current_tab = tab;
+                if tab == TabId::Packages && self.orphan_report.is_none() {
+                    return Command::batch([self.scan_orphans(), self.scan_cache()]);
+                }
                 Command::none()
             }
             Message::CheckUpdates => {
@@ -173,8 +318,19 @@ impl Application for CachyPacApp {
                     info!("🔍 Vérification des mises à jour demandée");
                     
                     let pacman_manager = self.pacman_manager.clone();
+                    let advisory_manager = self.config.security.enabled
+                        .then(|| AdvisoryManager::new(self.config.security.advisory_url.clone()));
                     Command::perform(
-                        async move { pacman_manager.check_updates().await },
+                        async move {
+                            let mut updates = pacman_manager.check_updates().await?;
+                            if let Some(advisory_manager) = advisory_manager {
+                                match advisory_manager.fetch().await {
+                                    Ok(database) => database.tag_updates(&mut updates),
+                                    Err(e) => warn!("⚠️ Avis de sécurité indisponibles: {}", e),
+                                }
+                            }
+                            Ok::<_, anyhow::Error>(updates)
+                        },
                         |result| Message::UpdatesChecked(result.map_err(|e| e.to_string())),
                     )
                 } else {
@@ -185,10 +341,22 @@ impl Application for CachyPacApp {
                 self.is_checking_updates = false;
                 match result {
                     Ok(updates) => {
+                        // Les mises à jour retenues par une règle ne sont pas présélectionnées
+                        self.selected_updates = updates
+                            .iter()
+                            .filter(|u| u.hold.is_none())
+                            .map(|u| u.name.clone())
+                            .collect();
+                        self.pending_plan = None;
+                        self.ready_to_install = is_ready_to_install(&updates, &self.config.pacman.cache_dir);
                         self.available_updates = updates;
                         self.status_message = format!("{} mises à jour disponibles", self.available_updates.len());
+                        if self.ready_to_install {
+                            self.status_message.push_str(" (prêt à installer, rien à télécharger)");
+                        }
                         self.progress = 1.0;
                         info!("✅ {} mises à jour trouvées", self.available_updates.len());
+                        return self.check_database_lock();
                     }
                     Err(error) => {
                         self.status_message = format!("Erreur: {}", error);
@@ -200,15 +368,88 @@ impl Application for CachyPacApp {
             }
             Message::InstallUpdates => {
                 if !self.is_installing_updates && !self.available_updates.is_empty() {
+                    self.status_message = "Préparation du plan de mise à jour...".to_string();
+
+                    let pacman_manager = self.pacman_manager.clone();
+                    let updates = self.available_updates.clone();
+                    let selected: Vec<String> = self.selected_updates.iter().cloned().collect();
+                    Command::perform(
+                        async move {
+                            let plan = pacman_manager.plan_upgrade(&updates, &selected).await?;
+                            // L'aperçu est informatif: son échec ne bloque pas la confirmation
+                            let preview = pacman_manager.preview_transaction(&plan)
+                                .await
+                                .inspect_err(|e| warn!("⚠️ Aperçu de la transaction indisponible: {}", e))
+                                .ok();
+                            Ok((plan, preview))
+                        },
+                        |result: anyhow::Result<_>| Message::UpgradePlanned(result.map_err(|e| e.to_string())),
+                    )
+                } else {
+                    Command::none()
+                }
+            }
+            Message::UpgradePlanned(result) => {
+                match result {
+                    Ok((plan, preview)) => {
+                        self.status_message = format!("{}: confirmation requise", plan.mode);
+                        info!("📋 Plan de mise à jour:\n{}", plan);
+                        self.pending_plan = Some(plan);
+                        self.pending_preview = preview;
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors de la préparation du plan: {}", error);
+                    }
+                }
+                Command::none()
+            }
+            Message::CancelUpgrade => {
+                self.pending_plan = None;
+                self.pending_preview = None;
+                self.status_message = "Mise à jour annulée".to_string();
+                Command::none()
+            }
+            Message::ToggleUpdateSelection(name, selected) => {
+                if selected {
+                    self.selected_updates.insert(name);
+                } else {
+                    self.selected_updates.remove(&name);
+                }
+                self.pending_plan = None;
+                Command::none()
+            }
+            Message::ConfirmUpgrade => {
+                if let (false, Some(plan)) = (self.is_installing_updates, self.pending_plan.take()) {
                     self.is_installing_updates = true;
                     self.status_message = "Installation des mises à jour...".to_string();
                     self.progress = 0.0;
                     info!("⬇️ Installation des mises à jour demandée");
+
+                    // Nouveau canal de progression, consommé par la souscription
+                    let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+                    self.progress_receiver = Some(Arc::new(Mutex::new(progress_rx)));
+                    self.install_run += 1;
+                    self.install_output.clear();
                     
                     let pacman_manager = self.pacman_manager.clone();
-                    let updates = self.available_updates.clone();
+                    let snapshot_manager = self.config.general.backup_before_update
+                        .then(|| SnapshotManager::new(self.config.snapshots.clone()));
+                    let mut history = self.update_history.clone();
                     Command::perform(
-                        async move { pacman_manager.install_updates(updates).await },
+                        async move {
+                            let (entry, result) = pacman_manager
+                                .execute_plan_with_snapshots(&plan, OperationType::ManualUpdate, snapshot_manager.as_ref(), Some(progress_tx))
+                                .await;
+                            let recorded = async {
+                                history.load().await?;
+                                history.add_entry(entry).await
+                            };
+                            if let Err(e) = recorded.await {
+                                error!("❌ Erreur lors de l'enregistrement dans l'historique: {}", e);
+                            }
+                            result
+                        },
                         |result| Message::UpdatesInstalled(result.map_err(|e| e.to_string())),
                     )
                 } else {
@@ -218,16 +459,70 @@ impl Application for CachyPacApp {
             Message::UpdatesInstalled(result) => {
                 self.is_installing_updates = false;
                 match result {
-                    Ok(_) => {
-                        self.status_message = "Mises à jour installées avec succès".to_string();
+                    Ok(pacnew_files) => {
+                        self.status_message = if pacnew_files.is_empty() {
+                            "Mises à jour installées avec succès".to_string()
+                        } else {
+                            format!("Mises à jour installées: {} fichiers .pacnew/.pacsave à traiter", pacnew_files.len())
+                        };
                         self.available_updates.clear();
                         self.progress = 1.0;
                         info!("✅ Mises à jour installées avec succès");
+                        let mut commands = vec![self.scan_pacnew(), self.load_local_db()];
+                        if self.config.restart.check_after_upgrade {
+                            commands.push(self.check_restart());
+                        }
+                        if self.pacman_manager.clean_cache_after() {
+                            commands.push(self.clean_cache());
+                        }
+                        Command::batch(commands)
                     }
                     Err(error) => {
                         self.status_message = format!("Erreur d'installation: {}", error);
                         self.progress = 0.0;
                         error!("❌ Erreur d'installation: {}", error);
+                        self.check_database_lock()
+                    }
+                }
+            }
+            Message::DatabaseLockChecked(state) => {
+                self.stale_lock = state == LockState::Stale;
+                if let LockState::Held(holders) = state {
+                    let holders: Vec<String> = holders.iter().map(ToString::to_string).collect();
+                    info!("🔒 Base pacman utilisée par {}", holders.join(", "));
+                }
+                Command::none()
+            }
+            Message::RemoveStaleLock => {
+                let lock = self.pacman_manager.database_lock();
+                Command::perform(
+                    async move { lock.remove_stale().await },
+                    |result| Message::StaleLockRemoved(result.map_err(|e| e.to_string())),
+                )
+            }
+            Message::StaleLockRemoved(result) => {
+                match result {
+                    Ok(()) => {
+                        self.stale_lock = false;
+                        self.status_message = "Verrou orphelin supprimé".to_string();
+                        info!("🔓 Verrou orphelin de la base pacman supprimé");
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Suppression du verrou impossible: {}", error);
+                    }
+                }
+                self.check_database_lock()
+            }
+            Message::InstallProgress(report) => {
+                self.progress = report.fraction;
+                if !matches!(report.event, TransactionEvent::Output(_)) {
+                    self.status_message = report.event.to_string();
+                }
+                if !report.line.trim().is_empty() {
+                    self.install_output.push(report.line);
+                    if self.install_output.len() > MAX_INSTALL_OUTPUT_LINES {
+                        self.install_output.remove(0);
                     }
                 }
                 Command::none()
@@ -304,19 +599,23 @@ impl Application for CachyPacApp {
             }
             Message::LoadHistory => {
                 let mut history = self.update_history.clone();
+                let mut importer = PacmanLogImporter::new(
+                    self.config.pacman.log_file.clone(),
+                    self.config.general.data_dir.clone().into(),
+                );
                 Command::perform(
                     async move {
                         match history.load().await {
                             Ok(_) => {
-                                let entries = history.get_all_entries()
-                                    .iter()
-                                    .map(|e| format!("{}: {} - {}",
-                                        e.timestamp.format("%Y-%m-%d %H:%M"),
-                                        e.operation_type,
-                                        if e.success { "Succès" } else { "Échec" }
-                                    ))
-                                    .collect();
-                                Ok(entries)
+                                // Transactions faites hors de CachyPac
+                                let import_result = match importer.load().await {
+                                    Ok(()) => importer.import(&mut history).await,
+                                    Err(e) => Err(e),
+                                };
+                                if let Err(e) = import_result {
+                                    warn!("⚠️ Impossible d'importer pacman.log: {}", e);
+                                }
+                                Ok(history.get_all_entries().to_vec())
                             }
                             Err(e) => Err(e.to_string())
                         }
@@ -336,6 +635,56 @@ impl Application for CachyPacApp {
                 }
                 Command::none()
             }
+            Message::RollbackEntry(id) => {
+                if self.is_rolling_back {
+                    return Command::none();
+                }
+                self.is_rolling_back = true;
+                self.status_message = "Retour arrière en cours...".to_string();
+                info!("⏪ Retour arrière demandé pour l'opération {}", id);
+
+                let mut history = self.update_history.clone();
+                let rollback_manager = RollbackManager::from_config(&self.config.pacman);
+                Command::perform(
+                    async move {
+                        history.load().await?;
+                        let entry = history.get_entry(id)
+                            .cloned()
+                            .ok_or_else(|| anyhow::anyhow!("Opération {} introuvable dans l'historique", id))?;
+                        let plan = rollback_manager.rollback(&entry, &mut history).await?;
+                        Ok(plan)
+                    },
+                    |result: anyhow::Result<_>| Message::RollbackFinished(
+                        result
+                            .map(|plan| if plan.is_empty() {
+                                "Aucun paquet à restaurer".to_string()
+                            } else if plan.missing.is_empty() {
+                                format!("Retour arrière de {} paquets effectué", plan.targets.len())
+                            } else {
+                                format!(
+                                    "Retour arrière de {} paquets effectué, {} absents du cache",
+                                    plan.targets.len(),
+                                    plan.missing.len()
+                                )
+                            })
+                            .map_err(|e| e.to_string())
+                    ),
+                )
+            }
+            Message::RollbackFinished(result) => {
+                self.is_rolling_back = false;
+                match result {
+                    Ok(message) => {
+                        info!("✅ {}", message);
+                        self.status_message = message;
+                    }
+                    Err(error) => {
+                        error!("❌ Erreur lors du retour arrière: {}", error);
+                        self.status_message = format!("Erreur de retour arrière: {}", error);
+                    }
+                }
+                self.update(Message::LoadHistory)
+            }
             Message::TestTelegram => {
                 if self.telegram_notifier.is_some() {
                     // Créer un nouveau notificateur pour le test async
@@ -357,7 +706,7 @@ impl Application for CachyPacApp {
                                 Err(e) => Err(format!("Configuration invalide: {}", e))
                             }
                         },
-                        |result| Message::TelegramTested(result),
+                        Message::TelegramTested,
                     )
                 } else {
                     // Diagnostic détaillé du problème
@@ -530,10 +879,302 @@ impl Application for CachyPacApp {
                 }
                 Command::none()
             }
+            Message::NewsChecked(result) => {
+                match result {
+                    Ok(unread) => {
+                        info!("📰 {} actualités Arch non lues", unread.len());
+                        self.unread_news = unread;
+                    }
+                    Err(error) => {
+                        error!("❌ Erreur lors de la lecture des actualités Arch: {}", error);
+                    }
+                }
+                Command::none()
+            }
+            Message::MarkNewsRead => {
+                let news = self.unread_news.clone();
+                let feed_url = self.config.general.news_feed_url.clone();
+                let data_dir = std::path::PathBuf::from(&self.config.general.data_dir);
+                Command::perform(
+                    async move {
+                        let mut news_manager = NewsManager::new(feed_url, data_dir);
+                        news_manager.load().await?;
+                        news_manager.mark_read(&news).await
+                    },
+                    |result| Message::NewsMarkedRead(result.map_err(|e| e.to_string())),
+                )
+            }
+            Message::ScanPacnew => self.scan_pacnew(),
+            Message::PacnewScanned(result) => {
+                match result {
+                    Ok(files) => {
+                        // Le diff affiché n'a plus de sens si le fichier a été traité
+                        if let Some((selected, _)) = &self.pacnew_diff {
+                            if !files.contains(selected) {
+                                self.pacnew_diff = None;
+                            }
+                        }
+                        self.pacnew_files = files;
+                    }
+                    Err(error) => {
+                        error!("❌ Erreur lors de la recherche des fichiers .pacnew: {}", error);
+                    }
+                }
+                Command::none()
+            }
+            Message::ShowPacnewDiff(file) => {
+                let pacnew_manager = self.pacnew_manager.clone();
+                self.pacnew_diff = Some((file.clone(), String::new()));
+                Command::perform(
+                    async move { pacnew_manager.diff(&file).await },
+                    |result| Message::PacnewDiffLoaded(result.map_err(|e| e.to_string())),
+                )
+            }
+            Message::PacnewDiffLoaded(result) => {
+                if let Some((_, diff)) = &mut self.pacnew_diff {
+                    *diff = match result {
+                        Ok(diff) if diff.is_empty() => "Fichiers identiques".to_string(),
+                        Ok(diff) => diff,
+                        Err(error) => format!("Erreur: {}", error),
+                    };
+                }
+                Command::none()
+            }
+            Message::ApplyPacnewAction(file, action) => {
+                self.status_message = format!("{}: {}", action, file);
+                let pacnew_manager = self.pacnew_manager.clone();
+                Command::perform(
+                    async move { pacnew_manager.apply(&file, action).await },
+                    |result| Message::PacnewActionApplied(result.map_err(|e| e.to_string())),
+                )
+            }
+            Message::PacnewActionApplied(result) => {
+                match result {
+                    Ok(()) => self.status_message = "Fichier de configuration traité".to_string(),
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors du traitement du fichier .pacnew: {}", error);
+                    }
+                }
+                self.scan_pacnew()
+            }
+            Message::ScanOrphans => self.scan_orphans(),
+            Message::OrphansScanned(result) => {
+                match result {
+                    Ok(report) => {
+                        // Seuls les paquets sans utilité sont présélectionnés
+                        self.selected_orphans = report.unneeded().iter().map(|p| p.name.clone()).collect();
+                        self.confirm_orphan_removal = false;
+                        self.orphan_report = Some(report);
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors de la recherche des orphelins: {}", error);
+                    }
+                }
+                Command::none()
+            }
+            Message::ToggleOrphanSelection(name, selected) => {
+                if selected {
+                    self.selected_orphans.insert(name);
+                } else {
+                    self.selected_orphans.remove(&name);
+                }
+                self.confirm_orphan_removal = false;
+                Command::none()
+            }
+            Message::RemoveOrphans => {
+                self.confirm_orphan_removal = !self.selected_orphans.is_empty();
+                Command::none()
+            }
+            Message::CancelOrphanRemoval => {
+                self.confirm_orphan_removal = false;
+                Command::none()
+            }
+            Message::ConfirmOrphanRemoval => {
+                let Some(report) = self.orphan_report.clone() else {
+                    return Command::none();
+                };
+                if self.is_removing_orphans || !self.confirm_orphan_removal {
+                    return Command::none();
+                }
+                self.is_removing_orphans = true;
+                self.confirm_orphan_removal = false;
+                self.status_message = "Suppression des paquets orphelins...".to_string();
+
+                let names: Vec<String> = report.packages
+                    .iter()
+                    .filter(|p| self.selected_orphans.contains(&p.name))
+                    .map(|p| p.name.clone())
+                    .collect();
+                let orphan_manager = self.orphan_manager.clone();
+                let mut history = self.update_history.clone();
+                Command::perform(
+                    async move {
+                        history.load().await?;
+                        orphan_manager.remove(&report, &names, &mut history).await
+                    },
+                    |result| Message::OrphansRemoved(result.map_err(|e| e.to_string())),
+                )
+            }
+            Message::OrphansRemoved(result) => {
+                self.is_removing_orphans = false;
+                match result {
+                    Ok(freed) => {
+                        self.status_message = format!("Paquets orphelins supprimés, {} libérés", format_size(freed));
+                        info!("✅ {}", self.status_message);
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors de la suppression des orphelins: {}", error);
+                    }
+                }
+                Command::batch([self.scan_orphans(), self.load_local_db()])
+            }
+            Message::CacheScanned(result) => {
+                match result {
+                    Ok(cleanup) => self.cache_cleanup = Some(cleanup),
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors de l'analyse du cache: {}", error);
+                    }
+                }
+                Command::none()
+            }
+            Message::CleanCache => {
+                if self.is_cleaning_cache {
+                    return Command::none();
+                }
+                self.status_message = "Nettoyage du cache des paquets...".to_string();
+                self.clean_cache()
+            }
+            Message::CacheCleaned(result) => {
+                self.is_cleaning_cache = false;
+                match result {
+                    Ok(freed) => {
+                        self.status_message = format!("Cache nettoyé, {} libérés", format_size(freed));
+                        info!("✅ {}", self.status_message);
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors du nettoyage du cache: {}", error);
+                    }
+                }
+                self.scan_cache()
+            }
+            Message::RestartChecked(result) => {
+                match result {
+                    Ok(report) => {
+                        if report.reboot_required() {
+                            warn!("🔁 Redémarrage requis: {}", report.reboot_reasons.join("; "));
+                        }
+                        self.restart_report = Some(report);
+                    }
+                    Err(error) => error!("❌ Erreur lors de l'analyse des redémarrages: {}", error),
+                }
+                Command::none()
+            }
+            Message::LocalDbLoaded(result) => {
+                match result {
+                    Ok(summary) => self.local_summary = Some(summary),
+                    Err(error) => warn!("⚠️ Base locale de pacman illisible: {}", error),
+                }
+                Command::none()
+            }
+            Message::PacmanConfLoaded(result) => {
+                match result {
+                    Ok((pacman_conf, active_mirrors)) => {
+                        self.orphan_manager = self.orphan_manager.clone().with_hold_packages(pacman_conf.hold_pkg.clone());
+                        self.pacman_conf = Some(pacman_conf);
+                        self.active_mirrors = active_mirrors;
+                    }
+                    Err(error) => warn!("⚠️ pacman.conf illisible: {}", error),
+                }
+                Command::none()
+            }
+            Message::MirrorsChecked(result) => {
+                match result {
+                    Ok(health) => self.mirror_health = Some(health),
+                    Err(error) => warn!("⚠️ Miroirs non vérifiés: {}", error),
+                }
+                Command::none()
+            }
+            Message::RankMirrors => {
+                let Some(health) = self.mirror_health.clone() else {
+                    return Command::none();
+                };
+                if self.is_ranking_mirrors {
+                    return Command::none();
+                }
+                self.is_ranking_mirrors = true;
+                self.status_message = "Reclassement des miroirs...".to_string();
+                let mirror_manager = MirrorManager::new(self.config.mirrors.clone()).with_sudo(true);
+                Command::perform(
+                    async move { mirror_manager.rank(&health).await },
+                    |result| Message::MirrorsRanked(result.map_err(|e| e.to_string())),
+                )
+            }
+            Message::MirrorsRanked(result) => {
+                self.is_ranking_mirrors = false;
+                match result {
+                    Ok(backup) => {
+                        self.status_message = format!("Miroirs reclassés (sauvegarde: {})", backup.display());
+                        info!("🌐 Miroirs reclassés, sauvegarde: {}", backup.display());
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors du reclassement des miroirs: {}", error);
+                    }
+                }
+                self.check_mirrors()
+            }
+            Message::RestartUnits => {
+                let Some(report) = self.restart_report.clone() else {
+                    return Command::none();
+                };
+                if self.is_restarting_units {
+                    return Command::none();
+                }
+                self.is_restarting_units = true;
+                self.status_message = "Redémarrage des services...".to_string();
+                let never_restart = self.config.restart.never_restart.clone();
+                let analyzer = RestartAnalyzer::new().with_helper(self.config.pacman.helper_socket.clone());
+                Command::perform(
+                    async move { analyzer.restart_units(&report, &never_restart).await },
+                    |result| Message::UnitsRestarted(result.map_err(|e| e.to_string())),
+                )
+            }
+            Message::UnitsRestarted(result) => {
+                self.is_restarting_units = false;
+                match result {
+                    Ok(units) => {
+                        self.status_message = format!("{} services redémarrés", units.len());
+                        info!("♻️ Services redémarrés: {}", units.join(", "));
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors du redémarrage des services: {}", error);
+                    }
+                }
+                self.check_restart()
+            }
+            Message::NewsMarkedRead(result) => {
+                match result {
+                    Ok(()) => {
+                        self.unread_news.clear();
+                        self.status_message = "Actualités Arch marquées comme lues".to_string();
+                    }
+                    Err(error) => {
+                        self.status_message = format!("Erreur: {}", error);
+                        error!("❌ Erreur lors de l'enregistrement des actualités lues: {}", error);
+                    }
+                }
+                Command::none()
+            }
         }
     }
 
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
         let tabs = TabId::ALL
             .iter()
             .fold(tab_bar::TabBar::new(Message::TabSelected), |tabs, tab| {
@@ -546,6 +1187,8 @@ impl Application for CachyPacApp {
             TabId::Updates => self.updates_view(),
             TabId::Scheduler => self.scheduler_view(),
             TabId::History => self.history_view(),
+            TabId::ConfigFiles => self.config_files_view(),
+            TabId::Packages => self.packages_view(),
             TabId::Settings => self.settings_view(),
         };
 
@@ -565,6 +1208,24 @@ impl Application for CachyPacApp {
             .into()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        // La souscription vit tant que le récepteur de la dernière installation existe
+        self.progress_receiver.as_ref().map_or_else(Subscription::none, |receiver| {
+            iced::subscription::unfold(
+                ("pacman-progress", self.install_run),
+                receiver.clone(),
+                |receiver| async move {
+                    let report = receiver.lock().await.recv().await;
+                    match report {
+                        Some(report) => (Message::InstallProgress(report), receiver),
+                        // Transaction terminée: plus rien à publier
+                        None => std::future::pending().await,
+                    }
+                },
+            )
+        })
+    }
+
     fn theme(&self) -> Theme {
         if self.config.gui.theme == "dark" {
             Theme::Dark
@@ -575,7 +1236,140 @@ impl Application for CachyPacApp {
 }
 
 impl CachyPacApp {
-    fn dashboard_view(&self) -> Element<Message> {
+    /// Lance la lecture du flux d'actualités Arch
+    fn check_news(&self) -> Command<Message> {
+        let feed_url = self.config.general.news_feed_url.clone();
+        let data_dir = std::path::PathBuf::from(&self.config.general.data_dir);
+        Command::perform(
+            async move {
+                let mut news_manager = NewsManager::new(feed_url, data_dir);
+                news_manager.load().await?;
+                news_manager.check_unread().await
+            },
+            |result| Message::NewsChecked(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Vérifie si un verrou orphelin bloque les transactions
+    fn check_database_lock(&self) -> Command<Message> {
+        let lock = self.pacman_manager.database_lock();
+        Command::perform(async move { lock.state().await }, Message::DatabaseLockChecked)
+    }
+
+    /// Lance la recherche des fichiers .pacnew/.pacsave
+    fn scan_pacnew(&self) -> Command<Message> {
+        let pacnew_manager = self.pacnew_manager.clone();
+        Command::perform(
+            async move { pacnew_manager.scan().await },
+            |result| Message::PacnewScanned(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Lance la recherche des paquets orphelins
+    fn scan_orphans(&self) -> Command<Message> {
+        let orphan_manager = self.orphan_manager.clone();
+        Command::perform(
+            async move { orphan_manager.scan().await },
+            |result| Message::OrphansScanned(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Vérifie si un redémarrage du système ou de services est nécessaire
+    fn check_restart(&self) -> Command<Message> {
+        Command::perform(
+            async { RestartAnalyzer::new().analyze().await },
+            |result| Message::RestartChecked(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Lit la base locale pour le tableau de bord
+    fn load_local_db(&self) -> Command<Message> {
+        let local_db = self.local_db.clone();
+        Command::perform(
+            async move { local_db.summary().await },
+            |result| Message::LocalDbLoaded(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Interroge les miroirs pour le tableau de bord
+    fn check_mirrors(&self) -> Command<Message> {
+        let mirror_manager = MirrorManager::new(self.config.mirrors.clone());
+        Command::perform(
+            async move { mirror_manager.check().await },
+            |result| Message::MirrorsChecked(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Lit pacman.conf et ses listes de miroirs pour les paramètres
+    fn load_pacman_conf(&self) -> Command<Message> {
+        let path = self.config.pacman.pacman_conf.clone();
+        Command::perform(
+            async move {
+                let pacman_conf = PacmanConf::load(&path).await?;
+                let mut active_mirrors = 0;
+                for mirrorlist in pacman_conf.mirrorlists() {
+                    match Mirrorlist::load(&mirrorlist).await {
+                        Ok(mirrorlist) => active_mirrors += mirrorlist.servers().count(),
+                        Err(e) => warn!("⚠️ {}", e),
+                    }
+                }
+                Ok::<_, anyhow::Error>((pacman_conf, active_mirrors))
+            },
+            |result| Message::PacmanConfLoaded(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Calcule l'espace récupérable dans le cache des paquets
+    fn scan_cache(&self) -> Command<Message> {
+        let cache_manager = self.pacman_manager.cache_manager();
+        Command::perform(
+            async move { cache_manager.plan().await },
+            |result| Message::CacheScanned(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Nettoie le cache et l'enregistre dans l'historique
+    fn clean_cache(&mut self) -> Command<Message> {
+        self.is_cleaning_cache = true;
+        let pacman_manager = self.pacman_manager.clone();
+        let mut history = self.update_history.clone();
+        Command::perform(
+            async move {
+                history.load().await?;
+                pacman_manager.clean_cache(&mut history).await
+            },
+            |result| Message::CacheCleaned(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Liste des actualités Arch non lues
+    fn news_view(&self) -> Element<'_, Message> {
+        let news_list = self.unread_news
+            .iter()
+            .fold(Column::new().spacing(5), |col, item| {
+                col.push(
+                    column![
+                        text(item.to_string()).size(14),
+                        text(&item.link).size(12),
+                    ]
+                    .spacing(2),
+                )
+            });
+
+        column![
+            text(format!("📰 {} actualités Arch non lues (mises à jour automatiques suspendues)", self.unread_news.len()))
+                .size(16)
+                .style(iced::theme::Text::Color(iced::Color::from_rgb(0.9, 0.6, 0.0))),
+            scrollable(news_list).height(Length::Fixed(120.0)),
+            button("✅ Marquer comme lues")
+                .on_press(Message::MarkNewsRead)
+                .style(iced::theme::Button::Secondary),
+        ]
+        .spacing(5)
+        .into()
+    }
+
+    fn dashboard_view(&self) -> Element<'_, Message> {
         let stats = column![
             text("📊 CachyPac Dashboard").size(24),
             text("").size(10),
@@ -583,6 +1377,14 @@ impl CachyPacApp {
                 text("🔄 Mises à jour disponibles:").size(16),
                 text(self.available_updates.len().to_string()).size(16).style(iced::theme::Text::Color(iced::Color::from_rgb(0.0, 0.8, 0.0)))
             ].spacing(10),
+            row![
+                text("📦 Paquets installés:").size(16),
+                text(self.local_summary.as_ref().map_or_else(|| "…".to_string(), ToString::to_string)).size(16)
+            ].spacing(10),
+            row![
+                text("🌐 Miroirs:").size(16),
+                text(self.mirror_health.as_ref().map_or_else(|| "…".to_string(), ToString::to_string)).size(16)
+            ].spacing(10),
             row![
                 text("⚙️ Planificateur:").size(16),
                 text(if self.config.scheduler.enabled { "Activé" } else { "Désactivé" }).size(16)
@@ -612,18 +1414,84 @@ impl CachyPacApp {
                 .style(iced::theme::Button::Secondary),
         ].spacing(10);
 
-        container(
-            column![stats, text("").size(20), actions]
-                .spacing(10)
-                .align_items(iced::Alignment::Start)
-        )
+        let mut page = column![stats, text("").size(20), actions]
+            .spacing(10)
+            .align_items(iced::Alignment::Start);
+
+        if let Some(report) = self.restart_report.as_ref().filter(|report| !report.is_empty()) {
+            page = page.push(text("").size(10)).push(self.restart_view(report));
+        }
+
+        if let Some(health) = self.mirror_health.as_ref() {
+            if health.stale().chain(health.unreachable()).next().is_some() {
+                page = page.push(text("").size(10)).push(self.mirrors_view(health));
+            }
+        }
+
+        if !self.unread_news.is_empty() {
+            page = page.push(text("").size(10)).push(self.news_view());
+        }
+
+        container(page)
         .padding(20)
         .width(Length::Fill)
         .height(Length::Fill)
         .into()
     }
 
-    fn updates_view(&self) -> Element<Message> {
+    /// Miroirs obsolètes ou injoignables, affichés sur le tableau de bord
+    fn mirrors_view(&self, health: &MirrorHealth) -> Element<'_, Message> {
+        let mut content = column![].spacing(5);
+        if let Some(primary) = health.primary().filter(|_| health.needs_attention()) {
+            content = content.push(
+                text(format!("🌐 Premier miroir inutilisable, risque de mise à jour partielle: {}", primary))
+                    .size(16)
+                    .style(iced::theme::Text::Color(iced::Color::from_rgb(0.8, 0.0, 0.0))),
+            );
+        }
+        for mirror in health.stale().chain(health.unreachable()).take(10) {
+            content = content.push(text(format!("    {}", mirror)).size(12));
+        }
+        content = content.push(
+            button("🏁 Reclasser les miroirs")
+                .on_press_maybe((!self.is_ranking_mirrors).then_some(Message::RankMirrors))
+                .style(iced::theme::Button::Secondary),
+        );
+        content.into()
+    }
+
+    /// Redémarrages nécessaires, affichés sur le tableau de bord
+    fn restart_view(&self, report: &RestartReport) -> Element<'_, Message> {
+        let mut content = column![].spacing(5);
+        for reason in &report.reboot_reasons {
+            content = content.push(
+                text(format!("🔁 Redémarrage requis: {}", reason))
+                    .size(16)
+                    .style(iced::theme::Text::Color(iced::Color::from_rgb(0.8, 0.0, 0.0))),
+            );
+        }
+        if !report.processes.is_empty() {
+            content = content.push(
+                text(format!("♻️ {} processus utilisent des fichiers supprimés ou remplacés", report.processes.len())).size(16),
+            );
+            for process in report.processes.iter().take(10) {
+                content = content.push(text(format!("    {}", process)).size(12));
+            }
+        }
+
+        let units = report.units();
+        if !units.is_empty() {
+            let can_restart = !self.is_restarting_units;
+            content = content.push(
+                button(text(format!("♻️ Redémarrer les services ({})", units.len())))
+                    .on_press_maybe(can_restart.then_some(Message::RestartUnits))
+                    .style(iced::theme::Button::Secondary),
+            );
+        }
+        content.into()
+    }
+
+    fn updates_view(&self) -> Element<'_, Message> {
         let header = text("📦 Gestion des Mises à Jour").size(24);
         
         let content = if self.available_updates.is_empty() {
@@ -635,19 +1503,35 @@ impl CachyPacApp {
                     .style(iced::theme::Button::Primary)
             ]
         } else {
+            let selective = self.config.pacman.upgrade_mode == UpgradeMode::Selective;
             let updates_list = self.available_updates
                 .iter()
                 .fold(Column::new().spacing(5), |col, update| {
-                    col.push(text(format!("• {}", update)).size(14))
+                    let selected = selective.then(|| self.selected_updates.contains(&update.name));
+                    col.push(Self::update_row(update, selected))
                 });
 
+            let download_total: u64 = self.available_updates
+                .iter()
+                .filter_map(|u| u.download_size)
+                .sum();
+            let installed_delta: i64 = self.available_updates
+                .iter()
+                .filter_map(|u| u.installed_size_delta)
+                .sum();
+
             column![
                 text(format!("📋 {} mises à jour disponibles:", self.available_updates.len())).size(16),
+                text(format!(
+                    "⬇️ Téléchargement: {}  •  💽 Taille installée: {}",
+                    if self.ready_to_install { "prêt à installer, rien à télécharger".to_string() } else { format_size(download_total) },
+                    format_size_delta(installed_delta)
+                )).size(14),
                 text("").size(5),
                 scrollable(updates_list).height(Length::Fixed(200.0)),
                 text("").size(10),
                 row![
-                    button("⬇️ Installer toutes les mises à jour")
+                    button(if selective { "⬇️ Installer la sélection" } else { "⬇️ Installer toutes les mises à jour" })
                         .on_press(Message::InstallUpdates)
                         .style(iced::theme::Button::Primary),
                     button("🔍 Revérifier")
@@ -657,18 +1541,178 @@ impl CachyPacApp {
             ]
         };
 
-        container(
-            column![header, text("").size(10), content]
-                .spacing(10)
-                .align_items(iced::Alignment::Start)
-        )
+        let content = match &self.pending_plan {
+            Some(plan) => content.push(Self::plan_view(
+                plan,
+                self.pending_preview.as_ref(),
+                self.config.pacman.allow_partial_upgrades,
+            )),
+            None => content,
+        };
+
+        let content = if self.stale_lock {
+            content.push(
+                row![
+                    text(format!(
+                        "🔒 {} est présent mais aucun pacman n'est actif",
+                        self.config.pacman.db_path.join(DB_LOCK_FILE).display()
+                    ))
+                    .size(14)
+                    .style(iced::theme::Text::Color(iced::Color::from_rgb(0.9, 0.6, 0.0))),
+                    button("🔓 Supprimer le verrou orphelin")
+                        .on_press(Message::RemoveStaleLock)
+                        .style(iced::theme::Button::Destructive),
+                ]
+                .spacing(10),
+            )
+        } else {
+            content
+        };
+
+        let mut page = column![header, text("").size(10), content]
+            .spacing(10)
+            .align_items(iced::Alignment::Start);
+
+        if !self.install_output.is_empty() {
+            let output = self.install_output
+                .iter()
+                .fold(Column::new().spacing(2), |col, line| {
+                    col.push(text(line).size(12).font(iced::Font::MONOSPACE))
+                });
+
+            page = page
+                .push(text("🖥️ Sortie de pacman:").size(16))
+                .push(
+                    scrollable(output)
+                        .height(Length::Fixed(200.0))
+                        .width(Length::Fill)
+                        .direction(scrollable::Direction::Vertical(
+                            scrollable::Properties::new().alignment(scrollable::Alignment::End),
+                        )),
+                );
+        }
+
+        container(page)
         .padding(20)
         .width(Length::Fill)
         .height(Length::Fill)
         .into()
     }
 
-    fn scheduler_view(&self) -> Element<Message> {
+    /// Plan de mise à jour à confirmer avant exécution
+    fn plan_view<'a>(
+        plan: &'a UpgradePlan,
+        preview: Option<&'a TransactionPreview>,
+        allow_partial: bool,
+    ) -> Element<'a, Message> {
+        let mut details = column![
+            text(format!("📋 {} — pacman {}", plan.mode, plan.pacman_args().join(" "))).size(16),
+            text(format!(
+                "{} paquets à mettre à jour, {} laissés de côté",
+                plan.targets.len(),
+                plan.held_back.len()
+            )).size(14),
+        ]
+        .spacing(5);
+
+        for update in plan.held_back.iter().filter(|u| u.hold.is_some()) {
+            if let Some(hold) = &update.hold {
+                details = details.push(text(format!("⏸️ {}: {}", update.name, hold)).size(14));
+            }
+        }
+
+        if !plan.aur.is_empty() {
+            let names: Vec<&str> = plan.aur.iter().map(|u| u.name.as_str()).collect();
+            details = details.push(text(format!(
+                "📦 AUR, à construire avec votre assistant AUR: {}",
+                names.join(", ")
+            )).size(14));
+        }
+
+        if let Some(preview) = preview {
+            details = details.push(text(format!("🔎 {}", preview.summary())).size(14));
+            let changes = preview.installs.iter()
+                .map(|package| format!("➕ {}", package))
+                .chain(preview.replacements.iter().map(|r| format!("🔁 {} remplacé par {}", r.old, r.new)))
+                .chain(preview.removals.iter().map(|name| format!("➖ {}", name)));
+            for change in changes {
+                details = details.push(text(change).size(14));
+            }
+        }
+
+        for warning in &plan.partial_upgrade_warnings {
+            details = details.push(
+                text(format!("⚠️ {}", warning))
+                    .size(14)
+                    .style(iced::theme::Text::Color(iced::Color::from_rgb(0.9, 0.6, 0.0))),
+            );
+        }
+
+        // Une mise à jour partielle non autorisée ne peut pas être confirmée
+        let can_confirm = !plan.is_empty() && (allow_partial || !plan.is_partial());
+        details
+            .push(
+                row![
+                    button("✅ Confirmer")
+                        .on_press_maybe(can_confirm.then_some(Message::ConfirmUpgrade))
+                        .style(iced::theme::Button::Primary),
+                    button("❌ Annuler")
+                        .on_press(Message::CancelUpgrade)
+                        .style(iced::theme::Button::Secondary),
+                ]
+                .spacing(10),
+            )
+            .into()
+    }
+
+    fn update_row(update: &PackageUpdate, selected: Option<bool>) -> Element<'_, Message> {
+        let repository = if update.repository.is_empty() {
+            "?".to_string()
+        } else {
+            update.repository.clone()
+        };
+
+        let name: Element<'_, Message> = match selected {
+            Some(is_selected) => {
+                let name = update.name.clone();
+                checkbox(&update.name, is_selected)
+                    .on_toggle(move |checked| Message::ToggleUpdateSelection(name.clone(), checked))
+                    .size(16)
+                    .text_size(14)
+                    .width(Length::Fixed(220.0))
+                    .into()
+            }
+            None => text(format!("• {}", update.name)).size(14).width(Length::Fixed(220.0)).into(),
+        };
+
+        let details = row![
+            name,
+            text(format!("{} → {}", update.current_version, update.new_version)).size(14).width(Length::Fixed(260.0)),
+            text(repository).size(14).width(Length::Fixed(100.0)),
+            text(update.download_size.map(format_size).unwrap_or_default()).size(14).width(Length::Fixed(100.0)),
+            text(update.installed_size_delta.map(format_size_delta).unwrap_or_default()).size(14),
+        ]
+        .spacing(10);
+
+        let mut lines = column![details];
+        if let Some(fix) = &update.security {
+            lines = lines.push(
+                text(format!("    {}", fix))
+                    .size(12)
+                    .style(iced::theme::Text::Color(iced::Color::from_rgb(0.0, 0.6, 0.3))),
+            );
+        }
+        if let Some(hold) = &update.hold {
+            lines = lines.push(
+                text(format!("    ⏸️ {}", hold))
+                    .size(12)
+                    .style(iced::theme::Text::Color(iced::Color::from_rgb(0.9, 0.6, 0.0))),
+            );
+        }
+        lines.into()
+    }
+
+    fn scheduler_view(&self) -> Element<'_, Message> {
         let header = text("⏰ Planificateur de Tâches").size(24);
         
         let status_text = if self.config.scheduler.enabled { "✅ Activé" } else { "❌ Désactivé" };
@@ -717,7 +1761,29 @@ impl CachyPacApp {
         .into()
     }
 
-    fn history_view(&self) -> Element<Message> {
+    fn history_line(entry: &HistoryEntry) -> String {
+        let mut line = format!("{}: {} - {}",
+            entry.timestamp.format("%Y-%m-%d %H:%M"),
+            entry.operation_type,
+            if entry.success { "Succès" } else { "Échec" }
+        );
+        if !entry.updates.is_empty() {
+            let transitions: Vec<String> = entry.updates
+                .iter()
+                .map(|u| format!("{} {} → {}", u.name, u.current_version, u.new_version))
+                .collect();
+            line.push_str(&format!(" ({})", transitions.join(", ")));
+        }
+        if let Some(freed) = entry.freed_bytes {
+            line.push_str(&format!(", {} libérés", format_size(freed)));
+        }
+        if let Some(restart) = entry.restart.as_ref().filter(|restart| !restart.is_empty()) {
+            line.push_str(&format!(", {}", restart));
+        }
+        line
+    }
+
+    fn history_view(&self) -> Element<'_, Message> {
         let header = text("📜 Historique des Opérations").size(24);
         
         let content = if self.history_entries.is_empty() {
@@ -732,7 +1798,17 @@ impl CachyPacApp {
             let history_list = self.history_entries
                 .iter()
                 .fold(Column::new().spacing(5), |col, entry| {
-                    col.push(text(entry).size(14))
+                    let line = text(Self::history_line(entry)).size(14).width(Length::Fill);
+                    if entry.is_revertible() {
+                        col.push(row![
+                            line,
+                            button("⏪ Revenir en arrière")
+                                .on_press_maybe((!self.is_rolling_back).then_some(Message::RollbackEntry(entry.id)))
+                                .style(iced::theme::Button::Secondary),
+                        ].spacing(10).align_items(iced::Alignment::Center))
+                    } else {
+                        col.push(line)
+                    }
                 });
 
             column![
@@ -757,7 +1833,189 @@ impl CachyPacApp {
         .into()
     }
 
-    fn settings_view(&self) -> Element<Message> {
+    fn config_files_view(&self) -> Element<'_, Message> {
+        let header = text("🗂️ Fichiers .pacnew / .pacsave").size(24);
+
+        let content = if self.pacnew_files.is_empty() {
+            column![
+                text("✅ Aucun fichier de configuration en attente").size(16),
+                text("").size(10),
+                button("🔍 Rechercher")
+                    .on_press(Message::ScanPacnew)
+                    .style(iced::theme::Button::Primary)
+            ]
+        } else {
+            let files_list = self.pacnew_files
+                .iter()
+                .fold(Column::new().spacing(5), |col, file| {
+                    col.push(
+                        row![
+                            text(file.to_string()).size(14).width(Length::Fill),
+                            button("🔍 Diff").on_press(Message::ShowPacnewDiff(file.clone())),
+                            button("Conserver")
+                                .on_press(Message::ApplyPacnewAction(file.clone(), PacnewAction::Keep))
+                                .style(iced::theme::Button::Secondary),
+                            button("Remplacer")
+                                .on_press(Message::ApplyPacnewAction(file.clone(), PacnewAction::Replace))
+                                .style(iced::theme::Button::Destructive),
+                            button("Fusionner")
+                                .on_press(Message::ApplyPacnewAction(file.clone(), PacnewAction::Merge))
+                                .style(iced::theme::Button::Primary),
+                        ]
+                        .spacing(10)
+                        .align_items(iced::Alignment::Center),
+                    )
+                });
+
+            let mut files = column![
+                text(format!("📋 {} fichiers à traiter:", self.pacnew_files.len())).size(16),
+                scrollable(files_list).height(Length::Fixed(150.0)),
+                button("🔄 Actualiser")
+                    .on_press(Message::ScanPacnew)
+                    .style(iced::theme::Button::Secondary),
+            ]
+            .spacing(10);
+
+            if let Some((file, diff)) = &self.pacnew_diff {
+                let diff_lines = diff
+                    .lines()
+                    .fold(Column::new().spacing(1), |col, line| {
+                        let color = match line.chars().next() {
+                            Some('+') => iced::Color::from_rgb(0.0, 0.7, 0.0),
+                            Some('-') => iced::Color::from_rgb(0.8, 0.0, 0.0),
+                            Some('@') => iced::Color::from_rgb(0.2, 0.5, 0.9),
+                            _ => iced::Color::from_rgb(0.6, 0.6, 0.6),
+                        };
+                        col.push(
+                            text(line)
+                                .size(12)
+                                .font(iced::Font::MONOSPACE)
+                                .style(iced::theme::Text::Color(color)),
+                        )
+                    });
+
+                files = files
+                    .push(text(format!("📝 {} → {}", file.original.display(), file.path.display())).size(16))
+                    .push(scrollable(diff_lines).height(Length::Fill).width(Length::Fill));
+            }
+
+            files
+        };
+
+        container(
+            column![header, text("").size(10), content]
+                .spacing(10)
+                .align_items(iced::Alignment::Start)
+        )
+        .padding(20)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
+    }
+
+    fn packages_view(&self) -> Element<'_, Message> {
+        let header = text("🧹 Paquets orphelins et inutiles").size(24);
+
+        let content = match &self.orphan_report {
+            None => column![text("🔍 Recherche des paquets orphelins...").size(16)],
+            Some(report) if report.is_empty() => column![
+                text("✅ Aucun paquet orphelin").size(16),
+                text("").size(10),
+                button("🔍 Rechercher")
+                    .on_press(Message::ScanOrphans)
+                    .style(iced::theme::Button::Primary)
+            ],
+            Some(report) => {
+                let packages_list = report.packages
+                    .iter()
+                    .fold(Column::new().spacing(5), |col, package| {
+                        let name = package.name.clone();
+                        col.push(
+                            row![
+                                checkbox(&package.name, self.selected_orphans.contains(&package.name))
+                                    .on_toggle(move |checked| Message::ToggleOrphanSelection(name.clone(), checked))
+                                    .size(16)
+                                    .text_size(14)
+                                    .width(Length::Fixed(260.0)),
+                                text(&package.version).size(14).width(Length::Fixed(160.0)),
+                                text(package.kind.to_string()).size(14).width(Length::Fixed(200.0)),
+                                text(package.installed_size.map(format_size).unwrap_or_default()).size(14),
+                            ]
+                            .spacing(10),
+                        )
+                    });
+
+                let selected_size = report.installed_size(&self.selected_orphans);
+                let mut details = column![
+                    text(format!("📋 {}", report.summary())).size(16),
+                    scrollable(packages_list).height(Length::Fill),
+                ]
+                .spacing(10);
+
+                details = if self.confirm_orphan_removal {
+                    details
+                        .push(text(format!(
+                            "🗑️ Supprimer {} paquets ({} libérés) avec pacman -Rn ?",
+                            self.selected_orphans.len(),
+                            format_size(selected_size)
+                        )).size(16))
+                        .push(
+                            row![
+                                button("✅ Confirmer")
+                                    .on_press(Message::ConfirmOrphanRemoval)
+                                    .style(iced::theme::Button::Destructive),
+                                button("❌ Annuler")
+                                    .on_press(Message::CancelOrphanRemoval)
+                                    .style(iced::theme::Button::Secondary),
+                            ]
+                            .spacing(10),
+                        )
+                } else {
+                    let can_remove = !self.selected_orphans.is_empty() && !self.is_removing_orphans;
+                    details.push(
+                        row![
+                            button(text(format!("🗑️ Supprimer la sélection ({})", format_size(selected_size))))
+                                .on_press_maybe(can_remove.then_some(Message::RemoveOrphans))
+                                .style(iced::theme::Button::Destructive),
+                            button("🔄 Actualiser")
+                                .on_press(Message::ScanOrphans)
+                                .style(iced::theme::Button::Secondary),
+                        ]
+                        .spacing(10),
+                    )
+                };
+
+                details
+            }
+        };
+
+        let cache = match &self.cache_cleanup {
+            None => row![text("💾 Analyse du cache des paquets...").size(16)],
+            Some(cleanup) => {
+                let can_clean = !cleanup.is_empty() && !self.is_cleaning_cache;
+                row![
+                    text(format!("💾 Cache: {}", cleanup)).size(16),
+                    button(text(format!("🧹 Nettoyer le cache ({})", format_size(cleanup.reclaimable()))))
+                        .on_press_maybe(can_clean.then_some(Message::CleanCache))
+                        .style(iced::theme::Button::Secondary),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center)
+            }
+        };
+
+        container(
+            column![header, text("").size(10), cache, content]
+                .spacing(10)
+                .align_items(iced::Alignment::Start)
+        )
+        .padding(20)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
+    }
+
+    fn settings_view(&self) -> Element<'_, Message> {
         let header = text("⚙️ Paramètres CachyPac").size(24);
         
         let data_dir_input = row![
@@ -808,7 +2066,34 @@ impl CachyPacApp {
                 .style(iced::theme::Button::Secondary),
         ].spacing(10);
 
-        container(
+        // Mises à jour écartées par pacman lui-même ou par les règles de CachyPac
+        let list = |items: &[String]| if items.is_empty() { "aucun".to_string() } else { items.join(", ") };
+        let pacman_rules: Element<Message> = match &self.pacman_conf {
+            Some(pacman_conf) => {
+                let repositories: Vec<String> = pacman_conf.repositories.iter().map(|repo| repo.name.clone()).collect();
+                column![
+                    text(format!("IgnorePkg: {}", list(&pacman_conf.ignore_pkg))),
+                    text(format!("IgnoreGroup: {}", list(&pacman_conf.ignore_group))),
+                    text(format!("HoldPkg (jamais proposés à la suppression): {}", list(&pacman_conf.hold_pkg))),
+                    text(format!("Dépôts: {} ({} miroirs actifs)", list(&repositories), self.active_mirrors)),
+                    text(format!("Téléchargements simultanés: {}", pacman_conf.parallel_downloads.unwrap_or(1))),
+                ]
+                .spacing(5)
+                .into()
+            }
+            None => text(format!("{} illisible", self.config.pacman.pacman_conf.display())).into(),
+        };
+        let hold_rules: Vec<String> = self.config.pacman.hold_rules.iter().map(HoldRule::label).collect();
+        let ignored_packages = column![
+            text("🚫 Ignorés par pacman (pacman.conf)").size(18),
+            pacman_rules,
+            text("⏸️ Écartés par CachyPac").size(18),
+            text(format!("Paquets exclus: {}", list(&self.config.pacman.exclude_packages))),
+            text(format!("Règles de retenue: {}", list(&hold_rules))),
+        ]
+        .spacing(8);
+
+        container(scrollable(
             column![
                 header,
                 text("").size(10),
@@ -818,11 +2103,13 @@ impl CachyPacApp {
                 telegram_token_input,
                 telegram_chat_input,
                 text("").size(20),
-                buttons
+                buttons,
+                text("").size(20),
+                ignored_packages
             ]
             .spacing(15)
             .align_items(iced::Alignment::Start)
-        )
+        ))
         .padding(20)
         .width(Length::Fill)
         .height(Length::Fill)
@@ -856,6 +2143,8 @@ mod tests {
         assert_eq!(TabId::Updates.to_string(), "📦 Mises à jour");
         assert_eq!(TabId::Scheduler.to_string(), "⏰ Planificateur");
         assert_eq!(TabId::History.to_string(), "📜 Historique");
+        assert_eq!(TabId::ConfigFiles.to_string(), "🗂️ Fichiers de configuration");
+        assert_eq!(TabId::Packages.to_string(), "🧹 Paquets");
         assert_eq!(TabId::Settings.to_string(), "⚙️ Paramètres");
     }
 }
\ No newline at end of file
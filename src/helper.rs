@@ -0,0 +1,848 @@
+//! Helper privilégié de CachyPac
+//!
+//! `cachypac-helper` tourne en root et écoute sur une socket Unix. Il n'accepte
+//! que des requêtes typées (transaction d'un plan, nettoyage du cache,
+//! rafraîchissement des bases ou des trousseaux, préchargement, retour arrière
+//! depuis le cache, suppression du verrou orphelin) et construit lui-même la
+//! ligne de commande pacman ou la liste des fichiers à supprimer ou télécharger:
+//! aucun argument libre ni chemin ne traverse la socket. Le client est identifié par les
+//! identifiants de la socket (`SO_PEERCRED`) puis autorisé par appartenance à un
+//! groupe ou par polkit, ce qui permet à l'interface et au daemon de tourner sans
+//! privilèges.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::cache::{CacheManager, CacheRetention, DEFAULT_PACKAGE_CACHE_DIR};
+use crate::localdb::LocalDatabase;
+use crate::lock::{DatabaseLock, DEFAULT_DB_PATH};
+use crate::pacman::{format_size, reinstall_keyrings};
+use crate::pacman_conf::{PacmanConf, DEFAULT_PACMAN_CONF};
+use crate::plan::{transaction_args, UpgradeMode, UpgradePlan};
+use crate::prefetch::PrefetchManager;
+use crate::runner::{CommandOutput, CommandRunner, CommandSpec, OutputLine, SystemRunner};
+
+/// Socket du helper (créée dans le `RuntimeDirectory` du service)
+#[allow(dead_code)]
+pub const DEFAULT_HELPER_SOCKET: &str = "/run/cachypac/helper.sock";
+
+/// Groupe dont les membres peuvent utiliser le helper sans polkit
+pub const DEFAULT_HELPER_GROUP: &str = "cachypac";
+
+/// Taille maximale d'une requête
+#[allow(dead_code)]
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Ligne de la sortie de `CleanCache` donnant l'espace libéré en octets
+pub const FREED_PREFIX: &str = "freed ";
+
+/// Délai d'écriture vers un client qui ne lit plus sa socket
+#[allow(dead_code)]
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Noms de paquets acceptés (jamais d'option pacman déguisée en cible)
+#[allow(dead_code)]
+static PACKAGE_NAME_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9@_+][A-Za-z0-9@._+-]*$").unwrap());
+
+/// Noms d'archives du cache acceptés (jamais de chemin)
+#[allow(dead_code)]
+static ARCHIVE_NAME_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9@_+][A-Za-z0-9@._+:-]*\.pkg\.tar(\.[a-z0-9]+)?$").unwrap());
+
+/// Requête acceptée par le helper
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum HelperRequest {
+    /// Transaction d'un plan de mise à jour
+    Upgrade {
+        mode: UpgradeMode,
+        #[serde(default)]
+        targets: Vec<String>,
+        #[serde(default)]
+        ignored: Vec<String>,
+    },
+    /// Nettoyage du cache selon la politique de conservation
+    CleanCache {
+        #[serde(default)]
+        retention: CacheRetention,
+    },
+    /// Rafraîchissement des bases de synchronisation (`pacman -Sy`)
+    RefreshDatabases,
+    /// Téléchargement des archives d'une mise à jour complète, sans installation
+    Prefetch {
+        #[serde(default)]
+        ignored: Vec<String>,
+        /// Débit maximal en Kio/s
+        #[serde(default)]
+        rate_limit_kib: Option<u32>,
+    },
+    /// Réinstallation des trousseaux installés puis `pacman-key --populate`
+    RefreshKeyrings,
+    /// Retour arrière: installation d'archives du cache (`pacman -U`)
+    Rollback {
+        /// Noms des archives dans le cache du helper
+        #[serde(default)]
+        archives: Vec<String>,
+    },
+    /// Suppression du verrou `db.lck` laissé par une transaction interrompue
+    RemoveStaleLock,
+}
+
+impl HelperRequest {
+    /// Requête exécutant un plan de mise à jour
+    pub fn from_plan(plan: &UpgradePlan) -> Self {
+        HelperRequest::Upgrade {
+            mode: plan.mode,
+            targets: plan.target_names(),
+            ignored: plan.ignored.clone(),
+        }
+    }
+
+    /// Arguments pacman de la requête (sans `pacman`), None si elle n'exécute pas pacman
+    pub fn pacman_args(&self) -> Option<Vec<String>> {
+        match self {
+            HelperRequest::Upgrade { mode, targets, ignored } => Some(transaction_args(*mode, targets, ignored)),
+            HelperRequest::RefreshDatabases => Some(vec!["-Sy".to_string()]),
+            HelperRequest::CleanCache { .. }
+            | HelperRequest::Prefetch { .. }
+            | HelperRequest::RefreshKeyrings
+            | HelperRequest::Rollback { .. }
+            | HelperRequest::RemoveStaleLock => None,
+        }
+    }
+
+    /// Action polkit correspondante (voir `org.cachypac.helper.policy`)
+    #[allow(dead_code)]
+    pub fn polkit_action(&self) -> &'static str {
+        match self {
+            HelperRequest::Upgrade { .. } => "org.cachypac.helper.upgrade",
+            HelperRequest::CleanCache { .. } => "org.cachypac.helper.clean-cache",
+            HelperRequest::RefreshDatabases => "org.cachypac.helper.refresh",
+            HelperRequest::Prefetch { .. } => "org.cachypac.helper.prefetch",
+            HelperRequest::RefreshKeyrings => "org.cachypac.helper.refresh-keyrings",
+            HelperRequest::Rollback { .. } => "org.cachypac.helper.rollback",
+            HelperRequest::RemoveStaleLock => "org.cachypac.helper.remove-stale-lock",
+        }
+    }
+
+    /// Vérifie que chaque nom de paquet ou d'archive est bien un nom, sans option ni chemin
+    #[allow(dead_code)]
+    pub fn validate(&self) -> Result<(), HelperError> {
+        if let HelperRequest::Rollback { archives } = self {
+            if archives.is_empty() {
+                return Err(HelperError::Invalid("aucune archive à installer".to_string()));
+            }
+            if let Some(name) = archives.iter().find(|name| !ARCHIVE_NAME_RE.is_match(name)) {
+                return Err(HelperError::Invalid(format!("nom d'archive invalide: {:?}", name)));
+            }
+        }
+
+        let names: Vec<&String> = match self {
+            HelperRequest::Upgrade { targets, ignored, .. } => targets.iter().chain(ignored).collect(),
+            HelperRequest::Prefetch { ignored, .. } => ignored.iter().collect(),
+            _ => Vec::new(),
+        };
+        if let Some(name) = names.into_iter().find(|name| !PACKAGE_NAME_RE.is_match(name)) {
+            return Err(HelperError::Invalid(format!("nom de paquet invalide: {:?}", name)));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for HelperRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HelperRequest::CleanCache { retention } => write!(f, "nettoyage du cache ({})", retention),
+            HelperRequest::Prefetch { rate_limit_kib: Some(rate), .. } => write!(f, "préchargement des paquets ({} Kio/s)", rate),
+            HelperRequest::Prefetch { .. } => write!(f, "préchargement des paquets"),
+            HelperRequest::RefreshKeyrings => write!(f, "rafraîchissement des trousseaux de clés"),
+            HelperRequest::Rollback { archives } => write!(f, "retour arrière de {} paquets", archives.len()),
+            HelperRequest::RemoveStaleLock => write!(f, "suppression du verrou orphelin"),
+            _ => write!(f, "pacman {}", self.pacman_args().unwrap_or_default().join(" ")),
+        }
+    }
+}
+
+/// Message renvoyé par le helper (une ligne JSON par événement)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HelperEvent {
+    /// Ligne de sortie de pacman
+    Output(OutputLine),
+    /// Transaction terminée
+    Finished(CommandOutput),
+    /// Client non autorisé
+    Denied { reason: String },
+    /// Requête illisible ou refusée par la validation
+    Invalid { reason: String },
+}
+
+/// Refus du helper, distinct des échecs de transaction
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum HelperError {
+    #[error("Requête refusée par cachypac-helper: {0}")]
+    Denied(String),
+    #[error("Requête invalide pour cachypac-helper: {0}")]
+    Invalid(String),
+    #[error("{0} non disponible via cachypac-helper")]
+    Unsupported(String),
+}
+
+/// Identité du client, lue sur la socket
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+    pub uid: u32,
+    pub pid: Option<i32>,
+}
+
+/// Règles d'accès au helper
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPolicy {
+    /// Groupe autorisé sans authentification (None pour désactiver)
+    pub group: Option<String>,
+    /// Consulter polkit (`pkcheck`) pour les autres utilisateurs
+    pub polkit: bool,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self {
+            group: Some(DEFAULT_HELPER_GROUP.to_string()),
+            polkit: true,
+        }
+    }
+}
+
+impl AccessPolicy {
+    /// Autorise le client pour cette requête: root, membre du groupe ou polkit
+    #[allow(dead_code)]
+    pub async fn authorize<R: CommandRunner>(
+        &self,
+        runner: &R,
+        peer: Peer,
+        request: &HelperRequest,
+    ) -> Result<(), HelperError> {
+        if peer.uid == 0 {
+            return Ok(());
+        }
+
+        if let Some(group) = &self.group {
+            let uid = peer.uid.to_string();
+            match runner.run(&CommandSpec::new("id").args(["-Gn", uid.as_str()])).await {
+                Ok(output) if output.success() => {
+                    if output.stdout.split_whitespace().any(|name| name == group) {
+                        debug!("🔐 uid {} autorisé par le groupe {}", peer.uid, group);
+                        return Ok(());
+                    }
+                }
+                Ok(output) => warn!("⚠️ Groupes de l'uid {} illisibles: {}", peer.uid, output.stderr.trim()),
+                Err(e) => warn!("⚠️ Groupes de l'uid {} illisibles: {}", peer.uid, e),
+            }
+        }
+
+        if self.polkit {
+            // pid seul: un autre processus pourrait avoir réutilisé le PID du client
+            let subject = peer.pid.and_then(|pid| {
+                let start_time = process_start_time(pid);
+                if start_time.is_none() {
+                    warn!("⚠️ Heure de démarrage du PID {} illisible, polkit non consulté", pid);
+                }
+                Some(format!("{},{},{}", pid, start_time?, peer.uid))
+            });
+            if let Some(subject) = subject {
+                let command = CommandSpec::new("pkcheck").args([
+                    "--action-id",
+                    request.polkit_action(),
+                    "--process",
+                    subject.as_str(),
+                    "--allow-user-interaction",
+                ]);
+                match runner.run(&command).await {
+                    Ok(output) if output.success() => {
+                        debug!("🔐 uid {} autorisé par polkit ({})", peer.uid, request.polkit_action());
+                        return Ok(());
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("⚠️ pkcheck indisponible: {}", e),
+                }
+            }
+        }
+
+        Err(HelperError::Denied(format!(
+            "uid {} non autorisé pour {}",
+            peer.uid,
+            request.polkit_action()
+        )))
+    }
+}
+
+/// Heure de démarrage d'un processus en ticks depuis le boot (champ 22 de `/proc/<pid>/stat`)
+#[allow(dead_code)]
+fn process_start_time(pid: i32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // Le nom du processus (champ 2) peut contenir espaces et parenthèses
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Écrit un événement, en abandonnant si le client ne lit pas avant `write_timeout`
+#[allow(dead_code)]
+async fn send_event<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    event: &HelperEvent,
+    write_timeout: Duration,
+) -> std::io::Result<()> {
+    let mut payload = serde_json::to_string(event)?;
+    payload.push('\n');
+    tokio::time::timeout(write_timeout, writer.write_all(payload.as_bytes()))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "le client ne lit plus la socket"))?
+}
+
+/// Serveur du helper, exécuté en root
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct HelperServer<R: CommandRunner = SystemRunner> {
+    runner: R,
+    socket: PathBuf,
+    policy: AccessPolicy,
+    /// Cache des paquets nettoyé par `CleanCache`, rempli par `Prefetch` et lu par `Rollback`
+    cache_dir: PathBuf,
+    /// Base pacman dont `RemoveStaleLock` supprime le verrou et qui borne les mises à jour sélectives
+    db_path: PathBuf,
+    /// Délai maximal d'écriture vers le client
+    write_timeout: Duration,
+    /// Une seule transaction pacman à la fois
+    transaction: Arc<Mutex<()>>,
+}
+
+impl HelperServer {
+    #[allow(dead_code)]
+    pub fn new(socket: PathBuf, policy: AccessPolicy) -> Self {
+        Self::with_runner(SystemRunner, socket, policy)
+    }
+}
+
+#[allow(dead_code)]
+impl<R: CommandRunner> HelperServer<R> {
+    pub fn with_runner(runner: R, socket: PathBuf, policy: AccessPolicy) -> Self {
+        Self {
+            runner,
+            socket,
+            policy,
+            cache_dir: PathBuf::from(DEFAULT_PACKAGE_CACHE_DIR),
+            db_path: PathBuf::from(DEFAULT_DB_PATH),
+            write_timeout: CLIENT_WRITE_TIMEOUT,
+            transaction: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Change le cache des paquets (par défaut /var/cache/pacman/pkg)
+    pub fn with_cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = cache_dir;
+        self
+    }
+
+    /// Change la base pacman (par défaut /var/lib/pacman)
+    pub fn with_db_path(mut self, db_path: PathBuf) -> Self {
+        self.db_path = db_path;
+        self
+    }
+
+    /// Change le délai d'écriture vers le client (par défaut 10 s)
+    pub fn with_write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Crée la socket, en remplaçant celle d'une exécution précédente
+    pub async fn bind(&self) -> Result<UnixListener> {
+        if let Some(parent) = self.socket.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context(format!("Impossible de créer {}", parent.display()))?;
+        }
+        if tokio::fs::try_exists(&self.socket).await.unwrap_or(false) {
+            tokio::fs::remove_file(&self.socket)
+                .await
+                .context(format!("Impossible de supprimer l'ancienne socket {}", self.socket.display()))?;
+        }
+
+        let listener = UnixListener::bind(&self.socket)
+            .context(format!("Impossible d'écouter sur {}", self.socket.display()))?;
+        // L'accès est contrôlé requête par requête, pas par les droits du fichier
+        std::fs::set_permissions(&self.socket, std::fs::Permissions::from_mode(0o666))
+            .context(format!("Impossible de modifier les droits de {}", self.socket.display()))?;
+
+        Ok(listener)
+    }
+
+    /// Accepte les clients jusqu'à l'arrêt du processus
+    pub async fn serve(&self, listener: UnixListener) -> Result<()> {
+        info!("🔐 cachypac-helper en écoute sur {}", self.socket.display());
+        loop {
+            let (stream, _) = listener.accept().await.context("Erreur lors de l'acceptation d'un client")?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle(stream).await {
+                    warn!("⚠️ Client cachypac-helper: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Traite une requête: identification, validation, autorisation puis exécution
+    pub async fn handle(&self, stream: UnixStream) -> Result<()> {
+        let credentials = stream.peer_cred().context("Identifiants du client illisibles")?;
+        let peer = Peer { uid: credentials.uid(), pid: credentials.pid() };
+
+        let (reader, mut writer) = stream.into_split();
+        let mut line = String::new();
+        BufReader::new(reader.take(MAX_REQUEST_SIZE))
+            .read_line(&mut line)
+            .await
+            .context("Requête illisible")?;
+
+        let request = match serde_json::from_str::<HelperRequest>(&line)
+            .map_err(|e| HelperError::Invalid(e.to_string()))
+            .and_then(|request| request.validate().map(|()| request))
+        {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("⚠️ Requête invalide de l'uid {}: {}", peer.uid, e);
+                send_event(&mut writer, &HelperEvent::Invalid { reason: e.to_string() }, self.write_timeout).await?;
+                return Ok(());
+            }
+        };
+
+        if let Err(e) = self.policy.authorize(&self.runner, peer, &request).await {
+            warn!("⛔ {}", e);
+            send_event(&mut writer, &HelperEvent::Denied { reason: e.to_string() }, self.write_timeout).await?;
+            return Ok(());
+        }
+
+        let _transaction = self.transaction.lock().await;
+        if let HelperRequest::Upgrade { mode: UpgradeMode::Selective, targets, .. } = &request {
+            if let Err(e) = self.check_installed(targets).await {
+                warn!("⚠️ Requête refusée pour l'uid {}: {}", peer.uid, e);
+                send_event(&mut writer, &HelperEvent::Invalid { reason: e.to_string() }, self.write_timeout).await?;
+                return Ok(());
+            }
+        }
+        info!("🔐 {} demandé par l'uid {}", request, peer.uid);
+
+        let (output, connected) = match (&request, request.pacman_args()) {
+            (HelperRequest::CleanCache { retention }, _) => (self.clean_cache(*retention).await, true),
+            (HelperRequest::Prefetch { ignored, rate_limit_kib }, _) => (self.prefetch(ignored, *rate_limit_kib).await, true),
+            (HelperRequest::RefreshKeyrings, _) => (self.refresh_keyrings().await, true),
+            (HelperRequest::Rollback { archives }, _) => self.rollback(archives, &mut writer).await,
+            (HelperRequest::RemoveStaleLock, _) => (self.remove_stale_lock().await, true),
+            (_, Some(args)) => self.run_pacman(args, &mut writer).await,
+            (_, None) => unreachable!("toutes les requêtes sans arguments pacman sont traitées ci-dessus"),
+        };
+        info!("🔐 {} terminé (code {:?})", request, output.code);
+        if connected {
+            send_event(&mut writer, &HelperEvent::Finished(output), self.write_timeout).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Une mise à jour sélective ne met à jour que des paquets déjà installés, jamais
+    /// n'importe quel paquet des dépôts
+    async fn check_installed(&self, targets: &[String]) -> Result<(), HelperError> {
+        let installed = LocalDatabase::new(self.db_path.clone())
+            .versions()
+            .await
+            .map_err(|e| HelperError::Invalid(format!("{:#}", e)))?;
+        targets
+            .iter()
+            .find(|name| !installed.contains_key(*name))
+            .map_or(Ok(()), |name| Err(HelperError::Invalid(format!("{} n'est pas installé", name))))
+    }
+
+    /// Exécute pacman en relayant sa sortie; renvoie aussi si le client est toujours là
+    async fn run_pacman<W: AsyncWrite + Unpin>(&self, args: Vec<String>, writer: &mut W) -> (CommandOutput, bool) {
+        let command = CommandSpec::new("pacman").env("LC_ALL", "C").args(args);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let forward = async {
+            // Le client peut partir ou cesser de lire en cours de route: la transaction
+            // continue et sa sortie est lue jusqu'au bout, sans plus être relayée
+            let mut connected = true;
+            while let Some(line) = rx.recv().await {
+                if connected {
+                    if let Err(e) = send_event(writer, &HelperEvent::Output(line), self.write_timeout).await {
+                        warn!("⚠️ Client déconnecté, sortie de pacman non relayée: {}", e);
+                        connected = false;
+                    }
+                }
+            }
+            connected
+        };
+        let (result, connected) = tokio::join!(self.runner.run_streaming(&command, tx), forward);
+
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => CommandOutput { code: None, stdout: String::new(), stderr: format!("{:#}", e) },
+        };
+        (output, connected)
+    }
+
+    /// Installe les archives demandées depuis le cache, sans jamais sortir du cache
+    async fn rollback<W: AsyncWrite + Unpin>(&self, archives: &[String], writer: &mut W) -> (CommandOutput, bool) {
+        let mut args = vec!["-U".to_string(), "--noconfirm".to_string()];
+        for archive in archives {
+            let path = self.cache_dir.join(archive);
+            if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                let stderr = format!("{} absent de {}", archive, self.cache_dir.display());
+                return (CommandOutput { code: Some(1), stdout: String::new(), stderr }, true);
+            }
+            args.push(path.to_string_lossy().into_owned());
+        }
+        self.run_pacman(args, writer).await
+    }
+
+    /// Réinstalle les trousseaux; la sortie liste les trousseaux réinstallés
+    async fn refresh_keyrings(&self) -> CommandOutput {
+        match reinstall_keyrings(&self.runner, false).await {
+            Ok(keyrings) => CommandOutput { code: Some(0), stdout: format!("{}\n", keyrings.join(" ")), stderr: String::new() },
+            Err(e) => CommandOutput { code: Some(1), stdout: String::new(), stderr: format!("{:#}", e) },
+        }
+    }
+
+    /// Supprime le verrou de la base, après avoir vérifié qu'aucun processus pacman ne le détient
+    async fn remove_stale_lock(&self) -> CommandOutput {
+        let lock = DatabaseLock::with_runner(self.runner.clone(), &self.db_path).with_sudo(false);
+        match lock.remove_stale().await {
+            Ok(()) => CommandOutput { code: Some(0), stdout: String::new(), stderr: String::new() },
+            Err(e) => CommandOutput { code: Some(1), stdout: String::new(), stderr: format!("{:#}", e) },
+        }
+    }
+
+    /// Supprime les archives hors politique; la sortie liste les fichiers supprimés
+    /// puis l'espace libéré (`freed <octets>`)
+    async fn clean_cache(&self, retention: CacheRetention) -> CommandOutput {
+        let manager = CacheManager::with_runner(self.runner.clone(), self.cache_dir.clone(), retention);
+        let result = match manager.plan().await {
+            Ok(cleanup) => manager.delete(&cleanup).await.map(|freed| (cleanup, freed)),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok((cleanup, freed)) => {
+                let stdout: String = cleanup.remove
+                    .iter()
+                    .map(|package| format!("removed {}\n", package.path.display()))
+                    .chain([format!("{}{}\n", FREED_PREFIX, freed), format!("{}\n", cleanup)])
+                    .collect();
+                CommandOutput { code: Some(0), stdout, stderr: String::new() }
+            }
+            Err(e) => CommandOutput { code: Some(1), stdout: String::new(), stderr: format!("{:#}", e) },
+        }
+    }
+
+    /// Rafraîchit les bases puis télécharge les archives manquantes; la sortie
+    /// liste les archives téléchargées
+    async fn prefetch(&self, ignored: &[String], rate_limit_kib: Option<u32>) -> CommandOutput {
+        let parallel_downloads = PacmanConf::load(Path::new(DEFAULT_PACMAN_CONF))
+            .await
+            .ok()
+            .and_then(|pacman_conf| pacman_conf.parallel_downloads)
+            .unwrap_or(1);
+        let manager = PrefetchManager::with_runner(self.runner.clone(), self.cache_dir.clone(), rate_limit_kib)
+            .with_parallel_downloads(parallel_downloads);
+        let result = async {
+            let refresh = CommandSpec::new("pacman").env("LC_ALL", "C").arg("-Sy");
+            let output = self.runner.run(&refresh).await.context("Impossible d'exécuter pacman -Sy")?;
+            if !output.success() {
+                return Err(anyhow::anyhow!("Échec de pacman -Sy: {}", output.stderr.trim()));
+            }
+            let plan = manager.plan(ignored).await?;
+            manager.download(&plan).await.map(|downloaded| (plan, downloaded))
+        }
+        .await;
+
+        match result {
+            Ok((plan, downloaded)) => {
+                let stdout: String = plan
+                    .missing()
+                    .map(|target| format!("downloaded {}\n", target.file_name()))
+                    .chain(std::iter::once(format!(
+                        "{} archives téléchargées ({})\n",
+                        plan.missing().count(),
+                        format_size(downloaded)
+                    )))
+                    .collect();
+                CommandOutput { code: Some(0), stdout, stderr: String::new() }
+            }
+            Err(e) => CommandOutput { code: Some(1), stdout: String::new(), stderr: format!("{:#}", e) },
+        }
+    }
+}
+
+/// Client du helper, utilisé par l'interface et le daemon
+#[derive(Debug, Clone)]
+pub struct HelperClient {
+    socket: PathBuf,
+}
+
+impl HelperClient {
+    pub fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    /// Envoie une requête et relaie la sortie de pacman jusqu'à la fin de la transaction
+    pub async fn call(
+        &self,
+        request: &HelperRequest,
+        lines: Option<UnboundedSender<OutputLine>>,
+    ) -> Result<CommandOutput> {
+        let stream = UnixStream::connect(&self.socket)
+            .await
+            .context(format!("Impossible de joindre cachypac-helper sur {}", self.socket.display()))?;
+        let (reader, mut writer) = stream.into_split();
+
+        let mut payload = serde_json::to_string(request)?;
+        payload.push('\n');
+        writer.write_all(payload.as_bytes()).await.context("Envoi de la requête impossible")?;
+
+        let mut events = BufReader::new(reader).lines();
+        while let Some(line) = events.next_line().await.context("Réponse de cachypac-helper illisible")? {
+            let event: HelperEvent = serde_json::from_str(&line)
+                .context(format!("Réponse de cachypac-helper invalide: {}", line))?;
+            match event {
+                HelperEvent::Output(output_line) => {
+                    if let Some(lines) = &lines {
+                        let _ = lines.send(output_line);
+                    }
+                }
+                HelperEvent::Finished(output) => return Ok(output),
+                HelperEvent::Denied { reason } => return Err(HelperError::Denied(reason).into()),
+                HelperEvent::Invalid { reason } => return Err(HelperError::Invalid(reason).into()),
+            }
+        }
+
+        Err(anyhow::anyhow!("cachypac-helper a fermé la connexion avant la fin de la transaction"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{OutputStream, ScriptedResponse, ScriptedRunner};
+    use tempfile::TempDir;
+
+    fn upgrade(targets: &[&str]) -> HelperRequest {
+        HelperRequest::Upgrade {
+            mode: UpgradeMode::Selective,
+            targets: targets.iter().map(ToString::to_string).collect(),
+            ignored: Vec::new(),
+        }
+    }
+
+    async fn start(runner: ScriptedRunner, policy: AccessPolicy) -> (TempDir, HelperClient) {
+        let temp_dir = TempDir::new().unwrap();
+        let socket = temp_dir.path().join("run").join("helper.sock");
+        let entry = temp_dir.path().join("db").join("local").join("firefox-91.0-1");
+        std::fs::create_dir_all(&entry).unwrap();
+        std::fs::write(entry.join("desc"), "%NAME%\nfirefox\n\n%VERSION%\n91.0-1\n").unwrap();
+        let server = HelperServer::with_runner(runner, socket.clone(), policy).with_db_path(temp_dir.path().join("db"));
+        let listener = server.bind().await.unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+        (temp_dir, HelperClient::new(socket))
+    }
+
+    #[test]
+    fn test_request_validation() {
+        assert!(upgrade(&["firefox", "lib32-glibc", "gtk2+", "python-pip@2"]).validate().is_ok());
+        for name in ["--dbpath=/tmp", "-Syu", "../firefox", "firefox linux", ""] {
+            assert!(matches!(upgrade(&[name]).validate(), Err(HelperError::Invalid(_))), "{name}");
+        }
+
+        assert_eq!(upgrade(&["firefox"]).pacman_args().unwrap(), vec!["-S", "--needed", "--noconfirm", "firefox"]);
+        assert_eq!(HelperRequest::CleanCache { retention: CacheRetention::default() }.pacman_args(), None);
+
+        let prefetch = HelperRequest::Prefetch { ignored: vec!["--cachedir=/tmp".to_string()], rate_limit_kib: Some(512) };
+        assert!(matches!(prefetch.validate(), Err(HelperError::Invalid(_))));
+        assert_eq!(prefetch.pacman_args(), None);
+        assert_eq!(prefetch.to_string(), "préchargement des paquets (512 Kio/s)");
+
+        let rollback = |archive: &str| HelperRequest::Rollback { archives: vec![archive.to_string()] };
+        assert!(rollback("mesa-1:24.1.0-1-x86_64.pkg.tar.zst").validate().is_ok());
+        for archive in ["../../etc/shadow", "/tmp/evil.pkg.tar.zst", "--dbonly.pkg.tar.zst", "firefox-91.0-1-x86_64"] {
+            assert!(matches!(rollback(archive).validate(), Err(HelperError::Invalid(_))), "{archive}");
+        }
+        assert!(HelperRequest::Rollback { archives: Vec::new() }.validate().is_err());
+
+        let json = serde_json::to_string(&HelperRequest::RefreshDatabases).unwrap();
+        assert_eq!(json, r#"{"request":"refresh_databases"}"#);
+        let request: HelperRequest = serde_json::from_str(r#"{"request":"clean_cache"}"#).unwrap();
+        assert_eq!(request, HelperRequest::CleanCache { retention: CacheRetention::default() });
+    }
+
+    #[tokio::test]
+    async fn test_authorize() {
+        let policy = AccessPolicy::default();
+        let pid = i32::try_from(std::process::id()).unwrap();
+        let peer = Peer { uid: 1000, pid: Some(pid) };
+        let request = HelperRequest::CleanCache { retention: CacheRetention::default() };
+
+        let member = ScriptedRunner::new().on(&["id", "-Gn"], ScriptedResponse::ok("users wheel cachypac\n"));
+        assert!(policy.authorize(&member, peer, &request).await.is_ok());
+        assert_eq!(member.count(&["pkcheck"]), 0);
+
+        let outsider = ScriptedRunner::new()
+            .on(&["id", "-Gn"], ScriptedResponse::ok("users\n"))
+            .on(&["pkcheck"], ScriptedResponse::exit(1, "", "Not authorized."));
+        assert!(matches!(policy.authorize(&outsider, peer, &request).await, Err(HelperError::Denied(_))));
+        assert_eq!(outsider.count(&["pkcheck", "--action-id", "org.cachypac.helper.clean-cache"]), 1);
+        // polkit identifie le processus par pid, heure de démarrage et uid
+        let start_time = process_start_time(pid).unwrap();
+        let subject = format!("{pid},{start_time},1000");
+        assert_eq!(outsider.count(&["pkcheck", "--action-id", "org.cachypac.helper.clean-cache", "--process", subject.as_str()]), 1);
+
+        let authenticated = ScriptedRunner::new()
+            .on(&["id", "-Gn"], ScriptedResponse::ok("users\n"))
+            .on(&["pkcheck"], ScriptedResponse::ok(""));
+        assert!(policy.authorize(&authenticated, peer, &request).await.is_ok());
+
+        // Processus disparu: polkit n'est pas consulté avec un PID seul
+        let vanished = Peer { uid: 1000, pid: Some(i32::MAX) };
+        assert!(matches!(policy.authorize(&authenticated, vanished, &request).await, Err(HelperError::Denied(_))));
+        assert_eq!(authenticated.count(&["pkcheck"]), 1);
+
+        // root n'a besoin d'aucune vérification
+        let root = ScriptedRunner::new();
+        assert!(policy.authorize(&root, Peer { uid: 0, pid: None }, &request).await.is_ok());
+        assert!(root.invocations().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_over_socket() {
+        let runner = ScriptedRunner::new()
+            .on(&["id", "-Gn"], ScriptedResponse::ok("cachypac\n"))
+            .on(&["pacman", "-S"], ScriptedResponse::ok("(1/1) upgrading firefox\n"));
+        let (_temp_dir, client) = start(runner.clone(), AccessPolicy::default()).await;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let output = client.call(&upgrade(&["firefox"]), Some(tx)).await.unwrap();
+        assert!(output.success());
+        assert_eq!(
+            rx.recv().await,
+            Some(OutputLine { stream: OutputStream::Stdout, text: "(1/1) upgrading firefox".to_string() })
+        );
+        assert_eq!(runner.count(&["pacman", "-S", "--needed", "--noconfirm", "firefox"]), 1);
+
+        // Une option déguisée en paquet n'atteint jamais pacman
+        let error = client.call(&upgrade(&["--root=/tmp"]), None).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<HelperError>(), Some(HelperError::Invalid(_))));
+        assert_eq!(runner.count(&["pacman"]), 1);
+
+        // Une mise à jour sélective n'installe jamais un paquet absent de la base locale
+        let error = client.call(&upgrade(&["firefox", "netcat"]), None).await.unwrap_err();
+        assert!(error.to_string().contains("netcat n'est pas installé"));
+        assert_eq!(runner.count(&["pacman"]), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_and_stale_lock_over_socket() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("firefox-91.0-1-x86_64.pkg.tar.zst"), "").unwrap();
+        std::fs::write(temp_dir.path().join("db.lck"), "").unwrap();
+        let runner = ScriptedRunner::new()
+            .on(&["id", "-Gn"], ScriptedResponse::ok("cachypac\n"))
+            .on(&["pgrep"], ScriptedResponse::exit(1, "", ""))
+            .on(&["pacman", "-U"], ScriptedResponse::ok(""));
+        let socket = temp_dir.path().join("helper.sock");
+        let server = HelperServer::with_runner(runner.clone(), socket.clone(), AccessPolicy::default())
+            .with_cache_dir(temp_dir.path().to_path_buf())
+            .with_db_path(temp_dir.path().to_path_buf());
+        let listener = server.bind().await.unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+        let client = HelperClient::new(socket);
+
+        // Les archives sont résolues dans le cache du helper
+        let request = HelperRequest::Rollback { archives: vec!["firefox-91.0-1-x86_64.pkg.tar.zst".to_string()] };
+        assert!(client.call(&request, None).await.unwrap().success());
+        let archive = temp_dir.path().join("firefox-91.0-1-x86_64.pkg.tar.zst");
+        assert_eq!(runner.count(&["pacman", "-U", "--noconfirm", archive.to_str().unwrap()]), 1);
+
+        let missing = HelperRequest::Rollback { archives: vec!["firefox-90.0-1-x86_64.pkg.tar.zst".to_string()] };
+        assert!(!client.call(&missing, None).await.unwrap().success());
+        assert_eq!(runner.count(&["pacman", "-U"]), 1);
+
+        // Le helper supprime le verrou lui-même, sans sudo
+        assert!(client.call(&HelperRequest::RemoveStaleLock, None).await.unwrap().success());
+        assert!(!temp_dir.path().join("db.lck").exists());
+        assert_eq!(runner.count(&["sudo"]), 0);
+    }
+
+    #[tokio::test]
+    async fn test_client_that_stops_reading_releases_the_transaction() {
+        let temp_dir = TempDir::new().unwrap();
+        // Bien plus que le tampon d'une socket Unix
+        let output = "(1/1) upgrading firefox ...................................................\n".repeat(50_000);
+        let runner = ScriptedRunner::new()
+            .on(&["id", "-Gn"], ScriptedResponse::ok("cachypac\n"))
+            .on(&["pacman", "-Syu"], ScriptedResponse::ok(&output));
+        let socket = temp_dir.path().join("helper.sock");
+        let server = HelperServer::with_runner(runner.clone(), socket.clone(), AccessPolicy::default())
+            .with_write_timeout(Duration::from_millis(100));
+        let listener = server.bind().await.unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+        let full = HelperRequest::Upgrade { mode: UpgradeMode::Full, targets: Vec::new(), ignored: Vec::new() };
+
+        // Client qui envoie sa requête puis ne lit jamais la réponse
+        let mut stalled = UnixStream::connect(&socket).await.unwrap();
+        let mut payload = serde_json::to_string(&full).unwrap();
+        payload.push('\n');
+        stalled.write_all(payload.as_bytes()).await.unwrap();
+        while runner.count(&["pacman", "-Syu"]) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let output = tokio::time::timeout(Duration::from_secs(10), HelperClient::new(socket).call(&full, None))
+            .await
+            .expect("la transaction suivante ne doit pas rester bloquée")
+            .unwrap();
+        assert!(output.success());
+        assert_eq!(runner.count(&["pacman", "-Syu"]), 2);
+        drop(stalled);
+    }
+
+    #[tokio::test]
+    async fn test_denied_over_socket() {
+        let runner = ScriptedRunner::new().on(&["pacman"], ScriptedResponse::ok(""));
+        let policy = AccessPolicy { group: None, polkit: false };
+        let (_temp_dir, client) = start(runner.clone(), policy).await;
+
+        let result = client.call(&HelperRequest::RefreshDatabases, None).await;
+        if running_as_root() {
+            assert!(result.is_ok());
+        } else {
+            assert!(matches!(result.unwrap_err().downcast_ref::<HelperError>(), Some(HelperError::Denied(_))));
+            assert_eq!(runner.count(&["pacman"]), 0);
+        }
+    }
+
+    fn running_as_root() -> bool {
+        std::fs::metadata("/proc/self").map(|m| std::os::unix::fs::MetadataExt::uid(&m) == 0).unwrap_or(false)
+    }
+}
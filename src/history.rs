@@ -1,22 +1,74 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use tokio::fs;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use crate::pacman::PackageUpdate;
+use crate::pacnew::PacnewFile;
+use crate::restart::RestartNeeds;
+use crate::snapshot::Snapshot;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
     pub id: Uuid,
     pub timestamp: DateTime<Local>,
     pub operation_type: OperationType,
     pub packages: Vec<String>,
+    /// Détail des transitions de version (vide pour les anciennes entrées)
+    #[serde(default)]
+    pub updates: Vec<PackageUpdate>,
+    /// Fichiers .pacnew/.pacsave créés par l'opération
+    #[serde(default)]
+    pub pacnew_files: Vec<PacnewFile>,
+    /// Instantanés pre/post encadrant l'opération
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+    /// Espace disque libéré (nettoyage du cache, suppression de paquets)
+    #[serde(default)]
+    pub freed_bytes: Option<u64>,
+    /// Redémarrages nécessaires après l'opération
+    #[serde(default)]
+    pub restart: Option<RestartNeeds>,
     pub success: bool,
     pub message: String,
     pub duration: std::time::Duration,
 }
 
+impl HistoryEntry {
+    /// Vrai si l'opération a mis à jour des paquets dont les versions sont connues
+    pub fn is_revertible(&self) -> bool {
+        !self.updates.is_empty()
+            && matches!(self.operation_type, OperationType::ManualUpdate | OperationType::AutoUpdate)
+    }
+
+    /// Vrai si les deux entrées décrivent la même transaction pacman
+    ///
+    /// CachyPac n'enregistre que les paquets qu'il a demandés alors que pacman.log
+    /// contient aussi les dépendances: il suffit qu'un ensemble de transitions
+    /// contienne l'autre, à une heure d'écart au plus.
+    pub fn same_transaction(&self, other: &HistoryEntry) -> bool {
+        if self.updates.is_empty() || other.updates.is_empty() {
+            return false;
+        }
+        if (self.timestamp - other.timestamp).num_minutes().abs() > 60 {
+            return false;
+        }
+
+        let transitions = |entry: &HistoryEntry| -> HashSet<(String, String, String)> {
+            entry.updates
+                .iter()
+                .map(|u| (u.name.clone(), u.current_version.clone(), u.new_version.clone()))
+                .collect()
+        };
+        let (ours, theirs) = (transitions(self), transitions(other));
+        ours.is_subset(&theirs) || theirs.is_subset(&ours)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OperationType {
     ManualUpdate,
@@ -27,6 +79,8 @@ pub enum OperationType {
     SystemMaintenance,
     PackageInstall,
     PackageRemove,
+    Rollback,
+    Prefetch,
 }
 
 impl std::fmt::Display for OperationType {
@@ -40,6 +94,8 @@ impl std::fmt::Display for OperationType {
             OperationType::SystemMaintenance => write!(f, "Maintenance système"),
             OperationType::PackageInstall => write!(f, "Installation de paquet"),
             OperationType::PackageRemove => write!(f, "Suppression de paquet"),
+            OperationType::Rollback => write!(f, "Retour arrière"),
+            OperationType::Prefetch => write!(f, "Préchargement des paquets"),
         }
     }
 }
@@ -151,11 +207,44 @@ impl UpdateHistory {
         Ok(())
     }
 
+    /// Ajoute des entrées importées qui ne sont pas déjà dans l'historique
+    ///
+    /// Les entrées restent triées de la plus récente à la plus ancienne.
+    /// Renvoie le nombre d'entrées ajoutées.
+    pub async fn merge_entries(&mut self, entries: Vec<HistoryEntry>) -> Result<usize> {
+        let mut added = 0;
+        for entry in entries {
+            if self.entries.iter().any(|existing| existing.same_transaction(&entry)) {
+                continue;
+            }
+            self.entries.push(entry);
+            added += 1;
+        }
+
+        if added > 0 {
+            debug!("{} entrées fusionnées dans l'historique CachyPac", added);
+            self.entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+            self.entries.truncate(self.max_entries);
+            self.save().await?;
+        }
+        Ok(added)
+    }
+
     /// Récupère toutes les entrées
     pub fn get_all_entries(&self) -> &[HistoryEntry] {
         &self.entries
     }
 
+    /// Récupère une entrée par son identifiant
+    pub fn get_entry(&self, id: Uuid) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Dernière mise à jour réussie pouvant être annulée
+    pub fn last_update(&self) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.is_revertible())
+    }
+
     /// Récupère les entrées filtrées
     #[allow(dead_code)]
     pub fn get_filtered_entries(&self, filter: &HistoryFilter) -> Vec<&HistoryEntry> {
@@ -326,6 +415,14 @@ mod tests {
             timestamp: Local::now(),
             operation_type: OperationType::ManualUpdate,
             packages: vec!["firefox".to_string(), "chromium".to_string()],
+            updates: vec![
+                PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string()),
+                PackageUpdate::new("chromium".to_string(), "93.0-1".to_string(), "94.0-1".to_string()),
+            ],
+            pacnew_files: Vec::new(),
+            snapshots: Vec::new(),
+            freed_bytes: None,
+            restart: None,
             success: true,
             message: "Test CachyPac update".to_string(),
             duration: std::time::Duration::from_secs(30),
@@ -338,5 +435,9 @@ mod tests {
         assert_eq!(stats.total_operations, 1);
         assert_eq!(stats.successful_operations, 1);
         assert_eq!(stats.total_packages_updated, 2);
+
+        let mut reloaded = UpdateHistory::new(temp_dir.path().to_path_buf());
+        reloaded.load().await.unwrap();
+        assert_eq!(reloaded.get_all_entries()[0].updates[1].new_version, "94.0-1");
     }
 }
\ No newline at end of file
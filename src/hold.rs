@@ -0,0 +1,387 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::pacman::PackageUpdate;
+use crate::plan::parse_package_list;
+use crate::preview::PackageInfo;
+
+/// Règle retenant certaines mises à jour
+///
+/// Tous les sélecteurs renseignés doivent correspondre; une règle sans sélecteur
+/// s'applique à tous les paquets. Sans condition de version, la mise à jour est
+/// retenue tant que la règle n'a pas expiré.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldRule {
+    /// Motif glob sur le nom du paquet ("linux*", "nvidia-?")
+    #[serde(default)]
+    pub package: Option<String>,
+    /// Expression régulière sur le nom du paquet
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// Dépôt de synchronisation (core, extra, aur...), motif glob accepté
+    #[serde(default)]
+    pub repository: Option<String>,
+    /// Groupe de paquets (gnome, base-devel...)
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Ne retenir que les changements de version majeure (ou d'epoch)
+    #[serde(default)]
+    pub major_only: bool,
+    /// Retenir tant que la nouvelle version a été construite il y a moins de N jours
+    #[serde(default)]
+    pub min_age_days: Option<u32>,
+    /// Dernier jour d'application de la règle ("2025-01-31")
+    #[serde(default)]
+    pub expires: Option<NaiveDate>,
+    /// Explication affichée avec la mise à jour retenue
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl HoldRule {
+    /// Description courte de la règle
+    pub fn label(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(package) = &self.package {
+            parts.push(package.clone());
+        }
+        if let Some(regex) = &self.regex {
+            parts.push(format!("/{}/", regex));
+        }
+        if let Some(repository) = &self.repository {
+            parts.push(format!("dépôt {}", repository));
+        }
+        if let Some(group) = &self.group {
+            parts.push(format!("groupe {}", group));
+        }
+
+        if parts.is_empty() {
+            "tous les paquets".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Condition ayant déclenché la retenue
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HoldReason {
+    /// Le paquet correspond à la règle, sans condition de version
+    Pinned,
+    MajorVersionBump { from: String, to: String },
+    /// La nouvelle version a été construite il y a `age_days` jours
+    TooRecent { age_days: i64, min_age_days: u32 },
+}
+
+/// Mise à jour retenue et explication
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hold {
+    pub rule: String,
+    pub reason: HoldReason,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub expires: Option<NaiveDate>,
+}
+
+impl std::fmt::Display for Hold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            HoldReason::Pinned => write!(f, "retenu par la règle « {} »", self.rule)?,
+            HoldReason::MajorVersionBump { from, to } => write!(
+                f,
+                "changement de version majeure {} → {} (règle « {} »)",
+                from, to, self.rule
+            )?,
+            HoldReason::TooRecent { age_days, min_age_days } => write!(
+                f,
+                "version publiée il y a {} jours, {} requis (règle « {} »)",
+                age_days, min_age_days, self.rule
+            )?,
+        }
+        if let Some(expires) = self.expires {
+            write!(f, " jusqu'au {}", expires.format("%d/%m/%Y"))?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " — {}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    rule: HoldRule,
+    package: Option<Regex>,
+    regex: Option<Regex>,
+    repository: Option<Regex>,
+}
+
+/// Évalue les règles de retenue sur les mises à jour disponibles
+#[derive(Debug, Clone, Default)]
+pub struct HoldEngine {
+    rules: Vec<CompiledRule>,
+}
+
+impl HoldEngine {
+    /// Compile les règles; une expression invalide est une erreur de configuration
+    pub fn new(rules: &[HoldRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let glob = |pattern: &Option<String>| -> Result<Option<Regex>> {
+                    pattern
+                        .as_deref()
+                        .map(|p| Regex::new(&glob_to_regex(p)).context(format!("Motif invalide: {}", p)))
+                        .transpose()
+                };
+                Ok(CompiledRule {
+                    package: glob(&rule.package)?,
+                    regex: rule.regex
+                        .as_deref()
+                        .map(|r| Regex::new(r).context(format!("Expression régulière invalide: {}", r)))
+                        .transpose()?,
+                    repository: glob(&rule.repository)?,
+                    rule: rule.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Première règle retenant la mise à jour
+    ///
+    /// `info` contient les champs `pacman -Si` du paquet (groupes, date de construction);
+    /// les conditions qui en dépendent ne s'appliquent pas s'ils sont inconnus.
+    pub fn evaluate(
+        &self,
+        update: &PackageUpdate,
+        info: Option<&std::collections::HashMap<String, String>>,
+        now: DateTime<Local>,
+    ) -> Option<Hold> {
+        self.rules.iter().find_map(|compiled| {
+            let rule = &compiled.rule;
+            if rule.expires.is_some_and(|expires| now.date_naive() > expires) {
+                return None;
+            }
+            if compiled.package.as_ref().is_some_and(|re| !re.is_match(&update.name))
+                || compiled.regex.as_ref().is_some_and(|re| !re.is_match(&update.name))
+                || compiled.repository.as_ref().is_some_and(|re| !re.is_match(&update.repository))
+            {
+                return None;
+            }
+            if let Some(group) = &rule.group {
+                let groups = info
+                    .and_then(|fields| fields.get("Groups"))
+                    .map(|value| parse_package_list(value))
+                    .unwrap_or_default();
+                if !groups.contains(group) {
+                    return None;
+                }
+            }
+
+            let reason = if !rule.major_only && rule.min_age_days.is_none() {
+                HoldReason::Pinned
+            } else if rule.major_only && is_major_bump(&update.current_version, &update.new_version) {
+                HoldReason::MajorVersionBump {
+                    from: update.current_version.clone(),
+                    to: update.new_version.clone(),
+                }
+            } else {
+                let min_age_days = rule.min_age_days?;
+                let built = info
+                    .and_then(|fields| fields.get("Build Date"))
+                    .and_then(|value| parse_build_date(value))?;
+                let age_days = (now - built).num_days();
+                if age_days >= i64::from(min_age_days) {
+                    return None;
+                }
+                HoldReason::TooRecent { age_days, min_age_days }
+            };
+
+            Some(Hold {
+                rule: rule.label(),
+                reason,
+                comment: rule.comment.clone(),
+                expires: rule.expires,
+            })
+        })
+    }
+
+    /// Marque les mises à jour retenues et renvoie leur nombre
+    pub fn apply(&self, updates: &mut [PackageUpdate], sync_info: &PackageInfo, now: DateTime<Local>) -> usize {
+        let mut held = 0;
+        for update in updates.iter_mut() {
+            update.hold = self.evaluate(update, sync_info.get(&update.name), now);
+            if let Some(hold) = &update.hold {
+                info!("⏸️ {} retenu: {}", update.name, hold);
+                held += 1;
+            }
+        }
+        held
+    }
+}
+
+/// Convertit un motif glob (`*`, `?`, `[...]`) en expression régulière ancrée
+pub fn glob_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    let mut in_class = false;
+    for c in pattern.chars() {
+        match c {
+            '*' if !in_class => regex.push_str(".*"),
+            '?' if !in_class => regex.push('.'),
+            '[' if !in_class => {
+                in_class = true;
+                regex.push('[');
+            }
+            ']' if in_class => {
+                in_class = false;
+                regex.push(']');
+            }
+            '!' if in_class && regex.ends_with('[') => regex.push('^'),
+            c if in_class => regex.push(c),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Epoch et premier composant de pkgver ("1:24.2.0-1" → (1, "24"))
+fn major_version(version: &str) -> (u64, &str) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => (epoch.parse().unwrap_or(0), rest),
+        _ => (0, version),
+    };
+    let pkgver = rest.rsplit_once('-').map_or(rest, |(pkgver, _)| pkgver);
+    let major = pkgver
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .next()
+        .unwrap_or(pkgver);
+    (epoch, major)
+}
+
+/// Vrai si l'epoch ou la version majeure change
+pub fn is_major_bump(current: &str, new: &str) -> bool {
+    major_version(current) != major_version(new)
+}
+
+/// Date de construction affichée par `LC_ALL=C pacman -Si` ("Sat Sep 14 10:00:00 2024")
+fn parse_build_date(value: &str) -> Option<DateTime<Local>> {
+    let normalized = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    NaiveDateTime::parse_from_str(&normalized, "%a %b %d %H:%M:%S %Y")
+        .ok()
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn update(name: &str, repository: &str, current: &str, new: &str) -> PackageUpdate {
+        let mut update = PackageUpdate::new(name.to_string(), current.to_string(), new.to_string());
+        update.repository = repository.to_string();
+        update
+    }
+
+    fn info(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields.iter().map(|(k, v)| ((*k).to_string(), (*v).to_string())).collect()
+    }
+
+    #[test]
+    fn test_glob_and_major_version() {
+        let re = Regex::new(&glob_to_regex("linux-[!l]*")).unwrap();
+        assert!(re.is_match("linux-zen"));
+        assert!(!re.is_match("linux-lts"));
+        assert!(!re.is_match("linux"));
+        assert!(!Regex::new(&glob_to_regex("python3.?")).unwrap().is_match("python3.12"));
+
+        assert!(is_major_bump("6.10.9-1", "7.0.1-1"));
+        assert!(is_major_bump("24.2.0-1", "1:24.2.0-1"));
+        assert!(!is_major_bump("1:24.1.0-1", "1:24.2.0-1"));
+    }
+
+    #[test]
+    fn test_hold_rules() {
+        let now = Local.with_ymd_and_hms(2024, 9, 20, 12, 0, 0).unwrap();
+        let engine = HoldEngine::new(&[
+            HoldRule {
+                package: Some("nvidia*".to_string()),
+                major_only: true,
+                comment: Some("attendre le module DKMS".to_string()),
+                ..HoldRule::default()
+            },
+            HoldRule {
+                regex: Some("^linux(-zen)?$".to_string()),
+                expires: NaiveDate::from_ymd_opt(2024, 9, 19),
+                ..HoldRule::default()
+            },
+            HoldRule {
+                group: Some("gnome".to_string()),
+                ..HoldRule::default()
+            },
+            HoldRule {
+                repository: Some("extra".to_string()),
+                min_age_days: Some(3),
+                ..HoldRule::default()
+            },
+        ]).unwrap();
+
+        let major = engine.evaluate(&update("nvidia-dkms", "extra", "550.1-1", "560.1-1"), None, now).unwrap();
+        assert_eq!(major.reason, HoldReason::MajorVersionBump { from: "550.1-1".to_string(), to: "560.1-1".to_string() });
+        assert!(major.to_string().contains("attendre le module DKMS"));
+        assert!(engine.evaluate(&update("nvidia-utils", "core", "550.1-1", "550.2-1"), None, now).is_none());
+
+        // Règle expirée
+        assert!(engine.evaluate(&update("linux", "core", "6.10.9-1", "6.10.10-1"), None, now).is_none());
+
+        let gnome = info(&[("Groups", "gnome")]);
+        let pinned = engine.evaluate(&update("nautilus", "core", "46.1-1", "46.2-1"), Some(&gnome), now).unwrap();
+        assert_eq!(pinned.reason, HoldReason::Pinned);
+        assert_eq!(pinned.rule, "groupe gnome");
+
+        let recent = info(&[("Build Date", "Wed Sep 18 10:00:00 2024")]);
+        let old = info(&[("Build Date", "Sun Sep  1 10:00:00 2024")]);
+        let firefox = update("firefox", "extra", "91.0-1", "92.0-1");
+        assert_eq!(
+            engine.evaluate(&firefox, Some(&recent), now).unwrap().reason,
+            HoldReason::TooRecent { age_days: 2, min_age_days: 3 }
+        );
+        assert!(engine.evaluate(&firefox, Some(&old), now).is_none());
+        // Date de construction inconnue: condition non évaluée
+        assert!(engine.evaluate(&firefox, None, now).is_none());
+
+        assert!(HoldEngine::new(&[HoldRule { regex: Some("(".to_string()), ..HoldRule::default() }]).is_err());
+    }
+
+    #[test]
+    fn test_rules_from_toml() {
+        #[derive(Deserialize)]
+        struct Rules {
+            hold_rules: Vec<HoldRule>,
+        }
+
+        let rules: Rules = toml::from_str(r#"
+            [[hold_rules]]
+            package = "nvidia*"
+            major_only = true
+
+            [[hold_rules]]
+            regex = "^linux(-zen)?$"
+            expires = "2025-01-31"
+        "#).unwrap();
+
+        assert_eq!(rules.hold_rules[0].label(), "nvidia*");
+        assert_eq!(rules.hold_rules[1].expires, NaiveDate::from_ymd_opt(2025, 1, 31));
+        assert_eq!(rules.hold_rules[1].label(), "/^linux(-zen)?$/");
+    }
+}
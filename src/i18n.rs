@@ -203,6 +203,7 @@ impl I18nManager {
                 translations.insert("menu-packages".to_string(), "Paquets".to_string());
                 translations.insert("menu-scheduler".to_string(), "Planificateur".to_string());
                 translations.insert("menu-history".to_string(), "Historique".to_string());
+                translations.insert("menu-config-files".to_string(), "Fichiers de configuration".to_string());
                 translations.insert("menu-settings".to_string(), "Paramètres".to_string());
                 translations.insert("menu-about".to_string(), "À propos".to_string());
                 translations.insert("updates-check".to_string(), "Vérifier les mises à jour".to_string());
@@ -222,6 +223,7 @@ impl I18nManager {
                 translations.insert("menu-packages".to_string(), "Packages".to_string());
                 translations.insert("menu-scheduler".to_string(), "Scheduler".to_string());
                 translations.insert("menu-history".to_string(), "History".to_string());
+                translations.insert("menu-config-files".to_string(), "Config files".to_string());
                 translations.insert("menu-settings".to_string(), "Settings".to_string());
                 translations.insert("menu-about".to_string(), "About".to_string());
                 translations.insert("updates-check".to_string(), "Check for updates".to_string());
@@ -239,6 +241,7 @@ impl I18nManager {
                 translations.insert("menu-updates".to_string(), "Updates".to_string());
                 translations.insert("menu-packages".to_string(), "Pakete".to_string());
                 translations.insert("menu-history".to_string(), "Verlauf".to_string());
+                translations.insert("menu-config-files".to_string(), "Konfigurationsdateien".to_string());
                 translations.insert("menu-settings".to_string(), "Einstellungen".to_string());
                 translations.insert("menu-about".to_string(), "Über".to_string());
                 translations.insert("updates-check".to_string(), "Nach Updates suchen".to_string());
@@ -256,6 +259,7 @@ impl I18nManager {
                 translations.insert("menu-updates".to_string(), "Actualizaciones".to_string());
                 translations.insert("menu-packages".to_string(), "Paquetes".to_string());
                 translations.insert("menu-history".to_string(), "Historial".to_string());
+                translations.insert("menu-config-files".to_string(), "Archivos de configuración".to_string());
                 translations.insert("menu-settings".to_string(), "Configuración".to_string());
                 translations.insert("menu-about".to_string(), "Acerca de".to_string());
                 translations.insert("updates-check".to_string(), "Buscar actualizaciones".to_string());
@@ -23,6 +23,29 @@ pub mod service;
 pub mod performance;
 pub mod i18n;
 pub mod telegram_robust;
+pub mod runner;
+pub mod progress;
+pub mod aur;
+pub mod version;
+pub mod news;
+pub mod pacnew;
+pub mod plan;
+pub mod preview;
+pub mod cache;
+pub mod rollback;
+pub mod snapshot;
+pub mod pacman_log;
+pub mod hold;
+pub mod lock;
+pub mod helper;
+pub mod orphans;
+pub mod advisory;
+pub mod restart;
+pub mod prefetch;
+pub mod localdb;
+pub mod pacman_conf;
+pub mod syncdb;
+pub mod mirrors;
 
 // Ré-exports pour faciliter l'utilisation
 pub use config::Config;
@@ -32,4 +55,6 @@ pub use telegram::TelegramNotifier;
 pub use history::{UpdateHistory, HistoryEntry, OperationType};
 pub use logs::{LogManager, LogLevel};
 pub use service::ServiceManager;
+pub use runner::{CommandRunner, SystemRunner};
+pub use version::{vercmp, Version};
 pub use i18n::{I18nManager, SupportedLanguage, translate, translate_with_args, set_global_language, get_current_language, init_global_i18n};
\ No newline at end of file
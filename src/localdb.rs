@@ -0,0 +1,478 @@
+//! Lecture directe de la base locale de pacman
+//!
+//! Chaque paquet installé possède un répertoire `local/<nom>-<version>/` dont le
+//! fichier `desc` décrit le paquet. Le lire évite de lancer `pacman -Q` pour
+//! chaque question sur les paquets installés; le résultat peut être partagé
+//! via un [`OptimizedCache`].
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+use crate::lock::DEFAULT_DB_PATH;
+use crate::pacman::format_size;
+use crate::performance::OptimizedCache;
+
+/// Raison de l'installation (`%REASON%`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InstallReason {
+    /// Installé à la demande de l'utilisateur
+    #[default]
+    Explicit,
+    /// Installé comme dépendance d'un autre paquet
+    Dependency,
+}
+
+impl std::fmt::Display for InstallReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallReason::Explicit => write!(f, "explicite"),
+            InstallReason::Dependency => write!(f, "dépendance"),
+        }
+    }
+}
+
+/// Vérification faite à l'installation (`%VALIDATION%`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Validation {
+    None,
+    Md5,
+    Sha256,
+    Pgp,
+    Unknown(String),
+}
+
+impl From<&str> for Validation {
+    fn from(value: &str) -> Self {
+        match value {
+            "none" => Validation::None,
+            "md5" => Validation::Md5,
+            "sha256" => Validation::Sha256,
+            "pgp" => Validation::Pgp,
+            other => Validation::Unknown(other.to_string()),
+        }
+    }
+}
+
+/// Paquet installé, tel que décrit par `local/<nom>-<version>/desc`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LocalPackage {
+    pub name: String,
+    /// Version complète (`epoch:pkgver-pkgrel`)
+    pub version: String,
+    pub description: String,
+    pub arch: String,
+    /// Taille installée en octets
+    pub size: u64,
+    pub reason: InstallReason,
+    pub install_date: Option<DateTime<Local>>,
+    pub groups: Vec<String>,
+    /// Dépendances avec leurs contraintes de version (`glibc>=2.38`)
+    pub depends: Vec<String>,
+    /// Dépendances optionnelles (`nom: description`)
+    pub optdepends: Vec<String>,
+    pub provides: Vec<String>,
+    pub validation: Vec<Validation>,
+}
+
+/// Nom d'une dépendance, d'une fourniture ou d'une dépendance optionnelle, sans
+/// contrainte de version ni description (`glibc>=2.38` → `glibc`)
+pub fn dependency_name(entry: &str) -> &str {
+    let entry = entry.split(": ").next().unwrap_or(entry);
+    entry.split(['<', '>', '=']).next().unwrap_or(entry).trim()
+}
+
+impl LocalPackage {
+    /// Noms sous lesquels le paquet satisfait une dépendance (le sien et ses fournitures)
+    pub fn satisfies(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.provides.iter().map(|entry| dependency_name(entry)))
+    }
+}
+
+/// Date d'un champ `%INSTALLDATE%` ou `%BUILDDATE%` (secondes depuis l'epoch)
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Local>> {
+    let secs = value.trim().parse().ok()?;
+    DateTime::from_timestamp(secs, 0).map(|date| date.with_timezone(&Local))
+}
+
+/// Découpe un fichier `desc` (base locale ou de synchronisation) en sections
+///
+/// Les sections (`%NAME%`, `%DEPENDS%`…) sont suivies de leurs valeurs, une par
+/// ligne, jusqu'à une ligne vide.
+pub fn parse_sections(content: &str) -> HashMap<&str, Vec<&str>> {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current = None;
+    for line in content.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            current = None;
+        } else if let Some(section) = line.strip_prefix('%').and_then(|rest| rest.strip_suffix('%')) {
+            current = Some(section);
+            sections.entry(section).or_default();
+        } else if let Some(section) = current {
+            sections.entry(section).or_default().push(line);
+        }
+    }
+    sections
+}
+
+/// Analyse un fichier `desc` de la base locale; les sections inconnues sont ignorées
+pub fn parse_desc(content: &str) -> Result<LocalPackage> {
+    let sections = parse_sections(content);
+    let single = |key: &str| sections.get(key).and_then(|values| values.first()).copied();
+    let list = |key: &str| -> Vec<String> {
+        sections.get(key).map(|values| values.iter().map(ToString::to_string).collect()).unwrap_or_default()
+    };
+
+    let name = single("NAME").context("Section %NAME% absente")?;
+    let version = single("VERSION").context("Section %VERSION% absente")?;
+    Ok(LocalPackage {
+        name: name.to_string(),
+        version: version.to_string(),
+        description: single("DESC").unwrap_or_default().to_string(),
+        arch: single("ARCH").unwrap_or_default().to_string(),
+        size: single("SIZE").and_then(|size| size.parse().ok()).unwrap_or(0),
+        reason: match single("REASON") {
+            Some("1") => InstallReason::Dependency,
+            _ => InstallReason::Explicit,
+        },
+        install_date: single("INSTALLDATE").and_then(parse_timestamp),
+        groups: list("GROUPS"),
+        depends: list("DEPENDS"),
+        optdepends: list("OPTDEPENDS"),
+        provides: list("PROVIDES"),
+        validation: sections
+            .get("VALIDATION")
+            .map(|values| values.iter().map(|value| Validation::from(*value)).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// Dépendances installées qui ne sont plus requises par aucun paquet
+/// (`pacman -Qdt`; avec `include_optional`, aussi celles requises seulement en
+/// dépendance optionnelle, comme `pacman -Qdtt`)
+pub fn unrequired_dependencies(packages: &[LocalPackage], include_optional: bool) -> Vec<&LocalPackage> {
+    let required: HashSet<&str> = packages
+        .iter()
+        .flat_map(|package| package.depends.iter())
+        .map(|entry| dependency_name(entry))
+        .collect();
+    let optional: HashSet<&str> = packages
+        .iter()
+        .flat_map(|package| package.optdepends.iter())
+        .map(|entry| dependency_name(entry))
+        .collect();
+
+    packages
+        .iter()
+        .filter(|package| package.reason == InstallReason::Dependency)
+        .filter(|package| {
+            package.satisfies().all(|name| !required.contains(name) && (include_optional || !optional.contains(name)))
+        })
+        .collect()
+}
+
+/// Vue d'ensemble des paquets installés, pour le tableau de bord
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalSummary {
+    pub installed: usize,
+    pub explicit: usize,
+    /// Dépendances requises par aucun paquet (`pacman -Qdt`)
+    pub orphans: usize,
+    /// Taille installée totale
+    pub size: u64,
+}
+
+impl LocalSummary {
+    pub fn new(packages: &[LocalPackage]) -> Self {
+        Self {
+            installed: packages.len(),
+            explicit: packages.iter().filter(|package| package.reason == InstallReason::Explicit).count(),
+            orphans: unrequired_dependencies(packages, false).len(),
+            size: packages.iter().map(|package| package.size).sum(),
+        }
+    }
+}
+
+impl std::fmt::Display for LocalSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} paquets ({} explicites, {} orphelins), {}",
+            self.installed,
+            self.explicit,
+            self.orphans,
+            format_size(self.size)
+        )
+    }
+}
+
+/// Cache partagé des lectures de la base locale, invalidé par la date de
+/// modification du répertoire `local/`
+pub type LocalDbCache = OptimizedCache<(PathBuf, SystemTime), Arc<Vec<LocalPackage>>>;
+
+/// Lecteur de la base locale de pacman
+#[derive(Clone)]
+pub struct LocalDatabase {
+    /// Base de données de pacman (`DBPath`, contient `local/`)
+    db_path: PathBuf,
+    cache: Option<Arc<LocalDbCache>>,
+}
+
+impl std::fmt::Debug for LocalDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalDatabase")
+            .field("db_path", &self.db_path)
+            .field("cached", &self.cache.is_some())
+            .finish()
+    }
+}
+
+impl Default for LocalDatabase {
+    fn default() -> Self {
+        Self::new(DEFAULT_DB_PATH)
+    }
+}
+
+impl LocalDatabase {
+    /// Lit la base de `db_path` (par défaut /var/lib/pacman)
+    pub fn new(db_path: impl Into<PathBuf>) -> Self {
+        Self {
+            db_path: db_path.into(),
+            cache: None,
+        }
+    }
+
+    /// Lit la base d'un système monté ailleurs (`<root>/var/lib/pacman`)
+    #[allow(dead_code)]
+    pub fn with_root(root: &Path) -> Self {
+        Self::new(root.join(DEFAULT_DB_PATH.trim_start_matches('/')))
+    }
+
+    /// Partage les lectures via un cache
+    pub fn with_cache(mut self, cache: Arc<LocalDbCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    fn local_dir(&self) -> PathBuf {
+        self.db_path.join("local")
+    }
+
+    /// Tous les paquets installés, triés par nom
+    pub async fn packages(&self) -> Result<Arc<Vec<LocalPackage>>> {
+        let local_dir = self.local_dir();
+        let modified = tokio::fs::metadata(&local_dir)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .context(format!("Base locale illisible: {}", local_dir.display()))?;
+        let key = (local_dir.clone(), modified);
+
+        if let Some(cache) = &self.cache {
+            if let Some(packages) = cache.get(&key).await {
+                return Ok(packages);
+            }
+        }
+
+        let packages = Arc::new(
+            tokio::task::spawn_blocking(move || read_local_dir(&local_dir))
+                .await
+                .context("Échec de la lecture de la base locale")??,
+        );
+        if let Some(cache) = &self.cache {
+            cache.insert(key, Arc::clone(&packages)).await;
+        }
+        Ok(packages)
+    }
+
+    /// Paquet installé portant ce nom
+    #[allow(dead_code)]
+    pub async fn package(&self, name: &str) -> Result<Option<LocalPackage>> {
+        Ok(self.packages().await?.iter().find(|package| package.name == name).cloned())
+    }
+
+    /// Noms et versions des paquets installés (équivalent de `pacman -Q`)
+    #[allow(dead_code)]
+    pub async fn versions(&self) -> Result<HashMap<String, String>> {
+        Ok(self
+            .packages()
+            .await?
+            .iter()
+            .map(|package| (package.name.clone(), package.version.clone()))
+            .collect())
+    }
+
+    /// Vue d'ensemble des paquets installés
+    pub async fn summary(&self) -> Result<LocalSummary> {
+        Ok(LocalSummary::new(&self.packages().await?))
+    }
+}
+
+/// Lit tous les `desc` de `local/`; un paquet illisible est signalé puis ignoré
+fn read_local_dir(local_dir: &Path) -> Result<Vec<LocalPackage>> {
+    let entries = std::fs::read_dir(local_dir)
+        .context(format!("Impossible de lire {}", local_dir.display()))?;
+
+    let mut packages: Vec<LocalPackage> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let desc = entry.path().join("desc");
+            let result = std::fs::read_to_string(&desc)
+                .context("lecture impossible")
+                .and_then(|content| parse_desc(&content));
+            match result {
+                Ok(package) => Some(package),
+                Err(e) => {
+                    warn!("⚠️ {} ignoré: {:#}", desc.display(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    debug!("📦 {} paquets dans la base locale {}", packages.len(), local_dir.display());
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::performance::PerformanceConfig;
+    use tempfile::TempDir;
+
+    const FIREFOX_DESC: &str = "\
+%NAME%
+firefox
+
+%VERSION%
+1:92.0-1
+
+%BASE%
+firefox
+
+%DESC%
+Standalone web browser from mozilla.org
+
+%ARCH%
+x86_64
+
+%INSTALLDATE%
+1630500000
+
+%SIZE%
+241172480
+
+%GROUPS%
+browsers
+
+%VALIDATION%
+pgp
+
+%DEPENDS%
+gtk3
+nss>=3.60
+libvpx.so=8-64
+
+%OPTDEPENDS%
+networkmanager: Location detection via available WiFi networks
+
+";
+
+    fn write_package(db_path: &Path, name: &str, version: &str, extra: &str) {
+        let dir = db_path.join("local").join(format!("{name}-{version}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("desc"),
+            format!("%NAME%\n{name}\n\n%VERSION%\n{version}\n\n%SIZE%\n1024\n\n{extra}"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parse_desc() {
+        let package = parse_desc(FIREFOX_DESC).unwrap();
+        assert_eq!(package.name, "firefox");
+        assert_eq!(package.version, "1:92.0-1");
+        assert_eq!(package.arch, "x86_64");
+        assert_eq!(package.size, 241_172_480);
+        assert_eq!(package.reason, InstallReason::Explicit);
+        assert_eq!(package.install_date.unwrap().timestamp(), 1_630_500_000);
+        assert_eq!(package.groups, vec!["browsers"]);
+        assert_eq!(package.validation, vec![Validation::Pgp]);
+        assert_eq!(package.depends, vec!["gtk3", "nss>=3.60", "libvpx.so=8-64"]);
+        let names: Vec<&str> = package.depends.iter().map(|entry| dependency_name(entry)).collect();
+        assert_eq!(names, vec!["gtk3", "nss", "libvpx.so"]);
+        assert_eq!(dependency_name(&package.optdepends[0]), "networkmanager");
+
+        assert!(parse_desc("%VERSION%\n1.0-1\n").is_err());
+    }
+
+    #[test]
+    fn test_unrequired_dependencies() {
+        let package = |name: &str, reason, depends: &[&str], optdepends: &[&str], provides: &[&str]| LocalPackage {
+            name: name.to_string(),
+            version: "1.0-1".to_string(),
+            reason,
+            depends: depends.iter().map(ToString::to_string).collect(),
+            optdepends: optdepends.iter().map(ToString::to_string).collect(),
+            provides: provides.iter().map(ToString::to_string).collect(),
+            ..LocalPackage::default()
+        };
+        let packages = vec![
+            package("firefox", InstallReason::Explicit, &["gtk3", "libvpx.so=8-64"], &["networkmanager: wifi"], &[]),
+            package("gtk2", InstallReason::Dependency, &[], &[], &[]),
+            package("gtk3", InstallReason::Dependency, &[], &[], &[]),
+            package("libvpx", InstallReason::Dependency, &[], &[], &["libvpx.so=8-64"]),
+            package("networkmanager", InstallReason::Dependency, &[], &[], &[]),
+        ];
+
+        let names = |orphans: Vec<&LocalPackage>| orphans.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(unrequired_dependencies(&packages, false)), vec!["gtk2"]);
+        assert_eq!(names(unrequired_dependencies(&packages, true)), vec!["gtk2", "networkmanager"]);
+
+        let summary = LocalSummary::new(&packages);
+        assert_eq!(summary.to_string(), "5 paquets (1 explicites, 1 orphelins), 0 B");
+    }
+
+    #[tokio::test]
+    async fn test_read_local_database() {
+        let temp_dir = TempDir::new().unwrap();
+        write_package(temp_dir.path(), "linux", "6.1.2-1", "");
+        write_package(temp_dir.path(), "gtk2", "2.24.33-3", "%REASON%\n1\n");
+        std::fs::write(temp_dir.path().join("local").join("ALPM_DB_VERSION"), "9\n").unwrap();
+        std::fs::create_dir(temp_dir.path().join("local").join("broken-1.0-1")).unwrap();
+
+        let database = LocalDatabase::new(temp_dir.path());
+        let packages = database.packages().await.unwrap();
+        let names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["gtk2", "linux"]);
+        assert_eq!(database.versions().await.unwrap()["linux"], "6.1.2-1");
+        assert_eq!(database.package("gtk2").await.unwrap().unwrap().reason, InstallReason::Dependency);
+        assert_eq!(database.summary().await.unwrap().orphans, 1);
+
+        let root = TempDir::new().unwrap();
+        write_package(&root.path().join("var/lib/pacman"), "bash", "5.2-1", "");
+        assert_eq!(LocalDatabase::with_root(root.path()).packages().await.unwrap()[0].name, "bash");
+
+        assert!(LocalDatabase::new(temp_dir.path().join("absent")).packages().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_reads() {
+        let temp_dir = TempDir::new().unwrap();
+        write_package(temp_dir.path(), "linux", "6.1.2-1", "");
+        let cache = Arc::new(LocalDbCache::new(PerformanceConfig::default()));
+        let database = LocalDatabase::new(temp_dir.path()).with_cache(Arc::clone(&cache));
+
+        database.packages().await.unwrap();
+        database.packages().await.unwrap();
+        let stats = cache.get_stats().await;
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+    }
+}
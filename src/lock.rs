@@ -0,0 +1,311 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, info, warn};
+
+use crate::helper::{HelperClient, HelperRequest};
+use crate::progress::{ProgressReport, TransactionEvent};
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+
+/// Base de données de pacman
+pub const DEFAULT_DB_PATH: &str = "/var/lib/pacman";
+
+/// Verrou créé par libalpm pendant une transaction
+pub const DB_LOCK_FILE: &str = "db.lck";
+
+/// Processus susceptibles de détenir le verrou (nom exact, pour `pgrep -x`)
+const LOCK_HOLDERS: &str = "pacman|pamac-daemon|packagekitd";
+
+/// Message de pacman lorsque le verrou est déjà pris
+const LOCK_ERROR: &str = "unable to lock database";
+
+/// Processus détenant la base pacman
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub name: String,
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.pid)
+    }
+}
+
+fn format_holders(holders: &[LockHolder]) -> String {
+    if holders.is_empty() {
+        return "un processus inconnu".to_string();
+    }
+    holders.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// État du verrou de la base pacman
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockState {
+    Free,
+    /// Verrou pris par un processus actif (liste vide si les processus n'ont pas pu être listés)
+    Held(Vec<LockHolder>),
+    /// Verrou présent sans aucun processus pacman: reste d'une transaction interrompue
+    Stale,
+}
+
+/// Conflit d'accès à la base pacman, distinct des échecs de transaction
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum LockError {
+    #[error("Base pacman verrouillée par {} depuis plus de {waited}s", format_holders(.holders))]
+    Held { holders: Vec<LockHolder>, waited: u64 },
+    #[error("Verrou {} orphelin: aucun processus pacman actif", .0.display())]
+    Stale(PathBuf),
+    #[error("pacman n'a pas pu verrouiller la base: {0}")]
+    Refused(String),
+}
+
+/// Cherche un conflit de verrou dans la chaîne d'erreurs
+pub fn find_lock_error(error: &anyhow::Error) -> Option<&LockError> {
+    error.chain().find_map(|cause| cause.downcast_ref::<LockError>())
+}
+
+/// Vrai si la sortie de pacman indique que la base est verrouillée
+pub fn is_lock_error(output: &str) -> bool {
+    output.contains(LOCK_ERROR)
+}
+
+/// Surveille le verrou `db.lck` de pacman
+#[derive(Debug, Clone)]
+pub struct DatabaseLock<R: CommandRunner = SystemRunner> {
+    runner: R,
+    path: PathBuf,
+    poll_interval: Duration,
+    /// Supprimer le verrou avec sudo (inutile dans cachypac-helper, déjà root)
+    sudo: bool,
+    /// Socket de cachypac-helper, qui supprime alors le verrou à la place de sudo
+    helper_socket: Option<PathBuf>,
+}
+
+impl DatabaseLock {
+    #[allow(dead_code)]
+    pub fn new(db_path: &Path) -> Self {
+        Self::with_runner(SystemRunner, db_path)
+    }
+}
+
+impl<R: CommandRunner> DatabaseLock<R> {
+    pub fn with_runner(runner: R, db_path: &Path) -> Self {
+        Self {
+            runner,
+            path: db_path.join(DB_LOCK_FILE),
+            poll_interval: Duration::from_secs(2),
+            sudo: true,
+            helper_socket: None,
+        }
+    }
+
+    /// Intervalle entre deux vérifications pendant l'attente
+    #[allow(dead_code)]
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Passe par sudo pour supprimer le verrou (activé par défaut)
+    pub fn with_sudo(mut self, sudo: bool) -> Self {
+        self.sudo = sudo;
+        self
+    }
+
+    /// Délègue la suppression du verrou à cachypac-helper
+    pub fn with_helper(mut self, helper_socket: Option<PathBuf>) -> Self {
+        self.helper_socket = helper_socket;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Processus pacman actifs (`pgrep -l -x`), None s'ils n'ont pas pu être listés
+    async fn holders(&self) -> Option<Vec<LockHolder>> {
+        let output = self.runner
+            .run(&CommandSpec::new("pgrep").args(["-l", "-x", LOCK_HOLDERS]))
+            .await
+            .ok()?;
+
+        // pgrep renvoie 1 lorsqu'aucun processus ne correspond
+        match output.code {
+            Some(0 | 1) => Some(
+                output.stdout
+                    .lines()
+                    .filter_map(|line| line.split_once(' '))
+                    .filter_map(|(pid, name)| Some(LockHolder { pid: pid.parse().ok()?, name: name.trim().to_string() }))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Vérifie la présence du verrou et l'activité de son propriétaire
+    pub async fn state(&self) -> LockState {
+        if !tokio::fs::try_exists(&self.path).await.unwrap_or(false) {
+            return LockState::Free;
+        }
+
+        match self.holders().await {
+            Some(holders) if holders.is_empty() => LockState::Stale,
+            Some(holders) => LockState::Held(holders),
+            None => {
+                warn!("⚠️ Impossible de lister les processus pacman, verrou {} supposé actif", self.path.display());
+                LockState::Held(Vec::new())
+            }
+        }
+    }
+
+    /// Attend la libération du verrou en publiant la progression de l'attente
+    pub async fn wait(
+        &self,
+        timeout: Duration,
+        progress: Option<&UnboundedSender<ProgressReport>>,
+    ) -> Result<(), LockError> {
+        let start = Instant::now();
+        loop {
+            let holders = match self.state().await {
+                LockState::Free => {
+                    if !start.elapsed().is_zero() {
+                        debug!("🔓 Base pacman libérée après {}s", start.elapsed().as_secs());
+                    }
+                    return Ok(());
+                }
+                LockState::Stale => return Err(LockError::Stale(self.path.clone())),
+                LockState::Held(holders) => holders,
+            };
+
+            let waited = start.elapsed().as_secs();
+            if start.elapsed() >= timeout {
+                return Err(LockError::Held { holders, waited });
+            }
+
+            let event = TransactionEvent::WaitingForLock {
+                holders: holders.iter().map(ToString::to_string).collect(),
+                waited,
+            };
+            if waited == 0 {
+                info!("🔒 {}", event);
+            }
+            if let Some(progress) = progress {
+                let _ = progress.send(ProgressReport { line: event.to_string(), event, fraction: 0.0 });
+            }
+
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Supprime un verrou orphelin, uniquement si aucun processus pacman n'est actif
+    pub async fn remove_stale(&self) -> anyhow::Result<()> {
+        match self.state().await {
+            LockState::Free => return Ok(()),
+            LockState::Held(holders) => {
+                return Err(anyhow::anyhow!(
+                    "Verrou toujours détenu par {}, suppression refusée",
+                    format_holders(&holders)
+                ));
+            }
+            LockState::Stale => {}
+        }
+
+        warn!("🔓 Suppression du verrou orphelin {}", self.path.display());
+        let output = if let Some(socket) = &self.helper_socket {
+            // Le helper vérifie lui-même que le verrou est toujours orphelin
+            HelperClient::new(socket.clone()).call(&HelperRequest::RemoveStaleLock, None).await?
+        } else if self.sudo {
+            self.runner
+                .run(&CommandSpec::new("sudo").args(["rm", "-f"]).arg(&self.path.to_string_lossy()))
+                .await?
+        } else {
+            return match tokio::fs::remove_file(&self.path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(anyhow::Error::new(e).context(format!("Impossible de supprimer {}", self.path.display())))
+                }
+                _ => Ok(()),
+            };
+        };
+        if !output.success() {
+            return Err(anyhow::anyhow!("Impossible de supprimer {}: {}", self.path.display(), output.stderr.trim()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_lock_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = ScriptedRunner::new().on(&["pgrep"], ScriptedResponse::ok("4242 pacman\n"));
+        let lock = DatabaseLock::with_runner(runner.clone(), temp_dir.path());
+        assert_eq!(lock.state().await, LockState::Free);
+        // Le verrou absent ne nécessite pas de lister les processus
+        assert!(runner.invocations().is_empty());
+
+        std::fs::write(lock.path(), "").unwrap();
+        assert_eq!(lock.state().await, LockState::Held(vec![LockHolder { pid: 4242, name: "pacman".to_string() }]));
+
+        let stale = DatabaseLock::with_runner(ScriptedRunner::new().on(&["pgrep"], ScriptedResponse::exit(1, "", "")), temp_dir.path());
+        assert_eq!(stale.state().await, LockState::Stale);
+
+        // pgrep indisponible: le verrou est considéré comme actif
+        let unknown = DatabaseLock::with_runner(ScriptedRunner::new().on(&["pgrep"], ScriptedResponse::exit(127, "", "")), temp_dir.path());
+        assert_eq!(unknown.state().await, LockState::Held(Vec::new()));
+        assert!(unknown.remove_stale().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = ScriptedRunner::new().on(&["pgrep"], ScriptedResponse::ok("4242 pacman\n"));
+        let lock = DatabaseLock::with_runner(runner, temp_dir.path()).with_poll_interval(Duration::from_millis(10));
+        std::fs::write(lock.path(), "").unwrap();
+
+        let error = lock.wait(Duration::from_millis(30), None).await.unwrap_err();
+        assert!(matches!(error, LockError::Held { ref holders, .. } if holders.len() == 1));
+        assert!(error.to_string().contains("pacman (4242)"));
+
+        let path = lock.path().to_path_buf();
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            std::fs::remove_file(path).unwrap();
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        lock.wait(Duration::from_secs(5), Some(&tx)).await.unwrap();
+        release.await.unwrap();
+        assert!(matches!(rx.recv().await.unwrap().event, TransactionEvent::WaitingForLock { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_remove_stale_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = ScriptedRunner::new()
+            .on(&["pgrep"], ScriptedResponse::exit(1, "", ""))
+            .on(&["sudo", "rm"], ScriptedResponse::ok(""));
+        let lock = DatabaseLock::with_runner(runner.clone(), temp_dir.path());
+        std::fs::write(lock.path(), "").unwrap();
+
+        assert_eq!(lock.wait(Duration::from_secs(1), None).await, Err(LockError::Stale(lock.path().to_path_buf())));
+        lock.remove_stale().await.unwrap();
+        assert_eq!(runner.count(&["sudo", "rm", "-f"]), 1);
+
+        // Sans sudo (cachypac-helper), le verrou est supprimé directement
+        let direct = DatabaseLock::with_runner(runner.clone(), temp_dir.path()).with_sudo(false);
+        direct.remove_stale().await.unwrap();
+        assert!(!direct.path().exists());
+        assert_eq!(runner.count(&["sudo", "rm", "-f"]), 1);
+
+        let error = anyhow::Error::new(LockError::Refused("error: failed to init transaction".to_string())).context("Échec");
+        assert!(matches!(find_lock_error(&error), Some(LockError::Refused(_))));
+        assert!(is_lock_error("error: failed to init transaction (unable to lock database)"));
+    }
+}
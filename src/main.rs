@@ -10,7 +10,7 @@
 use anyhow::Result;
 use clap::{Arg, Command};
 use std::path::PathBuf;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 mod config;
 mod gui;
@@ -22,14 +22,51 @@ mod history;
 mod logs;
 mod service;
 mod i18n;
+mod runner;
+mod progress;
+mod aur;
+mod version;
+mod news;
+mod pacnew;
+mod plan;
+mod preview;
+mod cache;
+mod rollback;
+mod snapshot;
+mod pacman_log;
+mod hold;
+mod lock;
+mod helper;
+mod orphans;
+mod advisory;
+mod restart;
+mod prefetch;
+mod localdb;
+mod pacman_conf;
+mod syncdb;
+mod mirrors;
+// Seul le cache générique sert au binaire (lectures de la base locale)
+#[allow(dead_code)]
+mod performance;
 
 use config::Config;
 use pacman::PacmanManager;
 use scheduler::SchedulerManager;
 use telegram_robust::{RobustTelegramNotifier, TelegramConfig};
 use history::UpdateHistory;
+use pacman_log::PacmanLogImporter;
+use lock::{find_lock_error, DatabaseLock, LockState};
+use progress::{ProgressReport, TransactionEvent};
 use logs::LogManager;
+use news::NewsManager;
 use service::ServiceManager;
+use snapshot::SnapshotManager;
+use rollback::RollbackManager;
+use orphans::{OrphanManager, OrphanTracker};
+use pacman_conf::PacmanConf;
+use advisory::{AdvisoryManager, AutoUpdatePolicy};
+use mirrors::MirrorManager;
+use restart::{RestartAnalyzer, RestartNeeds};
 
 fn main() -> Result<()> {
     // Initialisation du système de logging
@@ -72,6 +109,55 @@ fn main() -> Result<()> {
             .long("service-status")
             .action(clap::ArgAction::SetTrue)
             .help("Afficher le statut du service"))
+        .arg(Arg::new("mark-news-read")
+            .long("mark-news-read")
+            .action(clap::ArgAction::SetTrue)
+            .help("Marquer les actualités Arch comme lues (débloque les mises à jour automatiques)"))
+        .arg(Arg::new("dry-run")
+            .long("dry-run")
+            .action(clap::ArgAction::SetTrue)
+            .help("Afficher ce que ferait la mise à jour sans rien installer"))
+        .arg(Arg::new("rollback")
+            .long("rollback")
+            .value_name("ID")
+            .help("Revenir aux versions précédant une mise à jour (identifiant d'historique ou \"last\")"))
+        .arg(Arg::new("unlock")
+            .long("unlock")
+            .action(clap::ArgAction::SetTrue)
+            .help("Supprimer le verrou de la base pacman laissé par un pacman interrompu"))
+        .arg(Arg::new("orphans")
+            .long("orphans")
+            .action(clap::ArgAction::SetTrue)
+            .help("Lister les paquets orphelins, optionnels ou absents des dépôts"))
+        .arg(Arg::new("remove-orphans")
+            .long("remove-orphans")
+            .value_name("PAQUET")
+            .num_args(0..)
+            .help("Supprimer après confirmation les paquets orphelins (ou les paquets indiqués parmi ceux listés par --orphans)"))
+        .arg(Arg::new("audit")
+            .long("audit")
+            .action(clap::ArgAction::SetTrue)
+            .help("Lister les vulnérabilités connues des paquets installés (avis de sécurité Arch)"))
+        .arg(Arg::new("check-restart")
+            .long("check-restart")
+            .action(clap::ArgAction::SetTrue)
+            .help("Indiquer si un redémarrage du système ou de services est nécessaire"))
+        .arg(Arg::new("prefetch")
+            .long("prefetch")
+            .action(clap::ArgAction::SetTrue)
+            .help("Télécharger les mises à jour dans le cache sans les installer"))
+        .arg(Arg::new("clean-cache")
+            .long("clean-cache")
+            .action(clap::ArgAction::SetTrue)
+            .help("Supprimer après confirmation les archives du cache au-delà de la politique de conservation"))
+        .arg(Arg::new("mirrors")
+            .long("mirrors")
+            .action(clap::ArgAction::SetTrue)
+            .help("Vérifier la date de synchronisation et la latence des miroirs"))
+        .arg(Arg::new("rank-mirrors")
+            .long("rank-mirrors")
+            .action(clap::ArgAction::SetTrue)
+            .help("Réécrire la liste de miroirs (sauvegardée) avec les miroirs à jour triés par latence"))
         .get_matches();
 
     // Chargement de la configuration
@@ -118,6 +204,63 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    if matches.get_flag("mark-news-read") {
+        tokio::runtime::Runtime::new()?.block_on(mark_news_read(&config))?;
+        return Ok(());
+    }
+
+    if matches.get_flag("dry-run") {
+        tokio::runtime::Runtime::new()?.block_on(dry_run(&config))?;
+        return Ok(());
+    }
+
+    if let Some(target) = matches.get_one::<String>("rollback") {
+        tokio::runtime::Runtime::new()?.block_on(rollback_update(&config, target))?;
+        return Ok(());
+    }
+
+    if matches.get_flag("unlock") {
+        tokio::runtime::Runtime::new()?.block_on(remove_stale_lock(&config))?;
+        return Ok(());
+    }
+
+    if matches.get_flag("orphans") {
+        tokio::runtime::Runtime::new()?.block_on(list_orphans(&config))?;
+        return Ok(());
+    }
+
+    if let Some(names) = matches.get_many::<String>("remove-orphans") {
+        let names: Vec<String> = names.cloned().collect();
+        tokio::runtime::Runtime::new()?.block_on(remove_orphans(&config, &names))?;
+        return Ok(());
+    }
+
+    if matches.get_flag("audit") {
+        tokio::runtime::Runtime::new()?.block_on(audit(&config))?;
+        return Ok(());
+    }
+
+    if matches.get_flag("check-restart") {
+        let report = tokio::runtime::Runtime::new()?.block_on(RestartAnalyzer::new().analyze())?;
+        print!("{}", report);
+        return Ok(());
+    }
+
+    if matches.get_flag("prefetch") {
+        tokio::runtime::Runtime::new()?.block_on(prefetch_updates(&config))?;
+        return Ok(());
+    }
+
+    if matches.get_flag("clean-cache") {
+        tokio::runtime::Runtime::new()?.block_on(clean_cache(&config))?;
+        return Ok(());
+    }
+
+    if matches.get_flag("mirrors") || matches.get_flag("rank-mirrors") {
+        tokio::runtime::Runtime::new()?.block_on(check_mirrors(&config, matches.get_flag("rank-mirrors")))?;
+        return Ok(());
+    }
+
     // Mode daemon ou interface graphique
     if matches.get_flag("daemon") {
         info!("🔧 Lancement en mode daemon");
@@ -164,11 +307,25 @@ async fn run_daemon_mode(config: Config) -> Result<()> {
     
     let data_dir = PathBuf::from(&config.general.data_dir);
     let mut update_history = UpdateHistory::new(data_dir.clone());
-    let mut log_manager = LogManager::new(data_dir);
+    let mut log_manager = LogManager::new(data_dir.clone());
+    let mut news_manager = NewsManager::new(config.general.news_feed_url.clone(), data_dir.clone());
+    let mut log_importer = PacmanLogImporter::new(config.pacman.log_file.clone(), data_dir.clone());
+    let orphan_manager = orphan_manager(&config).await;
+    let mut orphan_tracker = OrphanTracker::new(data_dir);
+    let mut notified_news = std::collections::HashSet::new();
+    let mut notified_mirrors = std::collections::HashSet::new();
+    let advisory_manager = AdvisoryManager::new(config.security.advisory_url.clone());
+    let mut alerted_vulnerabilities = std::collections::HashSet::new();
 
     // Chargement des données
     update_history.load().await?;
     log_manager.load().await?;
+    news_manager.load().await?;
+    log_importer.load().await?;
+    if let Err(e) = orphan_tracker.load().await {
+        warn!("⚠️ Dernier rapport d'orphelins illisible: {}", e);
+    }
+    import_pacman_log(&mut log_importer, &mut update_history).await;
 
     info!("✅ Composants initialisés en mode daemon");
 
@@ -181,22 +338,71 @@ async fn run_daemon_mode(config: Config) -> Result<()> {
     // Boucle principale du daemon
     loop {
         tokio::time::sleep(tokio::time::Duration::from_secs(config.general.check_interval * 60)).await;
-        
+        import_pacman_log(&mut log_importer, &mut update_history).await;
+        report_orphans(&config, &orphan_manager, &mut orphan_tracker, telegram_notifier.as_mut()).await;
+
+        if config.prefetch.enabled {
+            match scheduler_manager.is_in_maintenance_window(&config.prefetch.window_start, &config.prefetch.window_end) {
+                Ok(true) => {
+                    if let Err(e) = pacman_manager.prefetch(config.prefetch.rate_limit_kib, &mut update_history).await {
+                        warn!("⚠️ Préchargement des mises à jour impossible: {:#}", e);
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => warn!("⚠️ Fenêtre de préchargement invalide: {}", e),
+            }
+        }
+
         match pacman_manager.check_updates().await {
-            Ok(updates) => {
+            Ok(mut updates) => {
+                audit_security(
+                    &config,
+                    &pacman_manager,
+                    &advisory_manager,
+                    &mut updates,
+                    &mut alerted_vulnerabilities,
+                    telegram_notifier.as_mut(),
+                )
+                .await;
+
                 if !updates.is_empty() {
                     info!("🔄 {} mises à jour disponibles", updates.len());
                     
                     if let Some(ref mut notifier) = telegram_notifier {
                         // Formater le message pour les mises à jour disponibles
-                        let message = format!(
+                        let mut message = format!(
                             "🔄 <b>CachyPac - {} mises à jour disponibles</b>\n\n{}",
                             updates.len(),
                             updates.iter().take(10).enumerate()
-                                .map(|(i, pkg)| format!("{}. <code>{}</code>", i + 1, pkg))
+                                .map(|(i, update)| format!(
+                                    "{}. <code>{}</code> {} → {}{}",
+                                    i + 1, update.name, update.current_version, update.new_version,
+                                    update.security.as_ref().map(|fix| format!(" {}", fix)).unwrap_or_default()
+                                ))
                                 .collect::<Vec<_>>()
                                 .join("\n")
                         );
+
+                        let held: Vec<String> = updates
+                            .iter()
+                            .filter_map(|update| update.hold.as_ref().map(|hold| format!("<code>{}</code> {}", update.name, hold)))
+                            .collect();
+                        if !held.is_empty() {
+                            message.push_str("\n\n⏸️ <b>Mises à jour retenues:</b>\n");
+                            message.push_str(&held.join("\n"));
+                        }
+
+                        match transaction_preview(&pacman_manager, &updates).await {
+                            Ok(preview) => {
+                                message.push_str("\n\n🔎 ");
+                                message.push_str(&preview.summary());
+                            }
+                            Err(e) => warn!("⚠️ Aperçu de la transaction indisponible: {}", e),
+                        }
+
+                        if prefetch::is_ready_to_install(&updates, &config.pacman.cache_dir) {
+                            message.push_str("\n\n📥 Prêt à installer, rien à télécharger");
+                        }
                         
                         // Utiliser le module robuste avec retry automatique
                         if let Err(e) = notifier.send_message_with_retry(&message).await {
@@ -211,7 +417,12 @@ async fn run_daemon_mode(config: Config) -> Result<()> {
                         id: uuid::Uuid::new_v4(),
                         timestamp: chrono::Local::now(),
                         operation_type: history::OperationType::CheckUpdates,
-                        packages: updates.clone(),
+                        packages: updates.iter().map(|u| u.name.clone()).collect(),
+                        updates: updates.clone(),
+                        pacnew_files: Vec::new(),
+                        snapshots: Vec::new(),
+                        freed_bytes: None,
+                        restart: None,
                         success: true,
                         duration: std::time::Duration::from_secs(0),
                         message: "Vérification des mises à jour réussie".to_string(),
@@ -220,6 +431,16 @@ async fn run_daemon_mode(config: Config) -> Result<()> {
                     if let Err(e) = update_history.add_entry(entry).await {
                         error!("❌ Erreur lors de l'enregistrement dans l'historique: {}", e);
                     }
+
+                    let policy = config.security.auto_update_policy;
+                    if config.general.auto_update && !policy.should_update(&updates) {
+                        info!("🛡️ Aucun correctif de sécurité: mise à jour automatique complète reportée");
+                    } else if config.general.auto_update
+                        && !unread_news_blocks_update(&news_manager, &mut notified_news, telegram_notifier.as_mut()).await
+                        && !stale_mirrors_block_update(&config, &mut notified_mirrors, telegram_notifier.as_mut()).await
+                    {
+                        run_auto_update(&config, &pacman_manager, &updates, &mut update_history, telegram_notifier.as_mut()).await;
+                    }
                 }
             }
             Err(e) => {
@@ -234,6 +455,637 @@ async fn run_daemon_mode(config: Config) -> Result<()> {
     }
 }
 
+/// Aperçu de la transaction qui installerait toutes les mises à jour disponibles
+async fn transaction_preview(
+    pacman_manager: &PacmanManager,
+    updates: &[pacman::PackageUpdate],
+) -> Result<preview::TransactionPreview> {
+    let selected: Vec<String> = updates
+        .iter()
+        .filter(|u| u.hold.is_none())
+        .map(|u| u.name.clone())
+        .collect();
+    let plan = pacman_manager.plan_upgrade(updates, &selected).await?;
+    pacman_manager.preview_transaction(&plan).await
+}
+
+/// Marque les correctifs de sécurité parmi les mises à jour et alerte, une seule
+/// fois par avis, des vulnérabilités graves sans correctif installable
+async fn audit_security(
+    config: &Config,
+    pacman_manager: &PacmanManager,
+    advisory_manager: &AdvisoryManager,
+    updates: &mut [pacman::PackageUpdate],
+    alerted: &mut std::collections::HashSet<String>,
+    telegram_notifier: Option<&mut RobustTelegramNotifier>,
+) {
+    if !config.security.enabled {
+        return;
+    }
+
+    let database = match advisory_manager.fetch().await {
+        Ok(database) => database,
+        Err(e) => {
+            warn!("⚠️ Avis de sécurité indisponibles: {}", e);
+            return;
+        }
+    };
+    database.tag_updates(updates);
+
+    let installed = match pacman_manager.installed_packages().await {
+        Ok(installed) => installed,
+        Err(e) => {
+            warn!("⚠️ Paquets installés illisibles: {}", e);
+            return;
+        }
+    };
+    let critical: Vec<_> = database
+        .vulnerabilities(&installed, updates)
+        .into_iter()
+        .filter(|v| v.is_unfixed() && v.advisory.severity >= config.security.alert_severity)
+        .filter(|v| alerted.insert(format!("{} {}", v.package, v.advisory.name)))
+        .collect();
+    if critical.is_empty() {
+        return;
+    }
+
+    for vulnerability in &critical {
+        error!("🚨 {}", vulnerability);
+    }
+    let Some(notifier) = telegram_notifier else {
+        return;
+    };
+    let message = format!(
+        "🚨 <b>CachyPac - {} vulnérabilités sans correctif</b>\n\n{}",
+        critical.len(),
+        critical.iter()
+            .map(|v| format!("• <code>{}</code> {}", v.package, v.advisory))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    if let Err(e) = notifier.send_message_with_retry(&message).await {
+        error!("❌ Erreur notification Telegram (après retry): {}", e);
+    }
+}
+
+/// Vérifie les actualités Arch: toute actualité non lue bloque la mise à jour automatique
+async fn unread_news_blocks_update(
+    news_manager: &NewsManager,
+    notified_news: &mut std::collections::HashSet<String>,
+    telegram_notifier: Option<&mut RobustTelegramNotifier>,
+) -> bool {
+    let unread = match news_manager.check_unread().await {
+        Ok(unread) => unread,
+        Err(e) => {
+            warn!("⚠️ Actualités Arch indisponibles, mise à jour automatique suspendue: {}", e);
+            return true;
+        }
+    };
+
+    if unread.is_empty() {
+        return false;
+    }
+
+    warn!("⏸️ Mise à jour automatique bloquée: {} actualités Arch non lues", unread.len());
+    for item in &unread {
+        warn!("📰 {} ({})", item, item.link);
+    }
+
+    // Ne notifier chaque actualité qu'une seule fois
+    let new_items: Vec<_> = unread.iter().filter(|item| notified_news.insert(item.id.clone())).collect();
+    if let (Some(notifier), false) = (telegram_notifier, new_items.is_empty()) {
+        let message = format!(
+            "📰 <b>CachyPac - Actualités Arch non lues</b>\n\n{}\n\nMise à jour automatique suspendue jusqu'à lecture (cachypac --mark-news-read).",
+            new_items.iter()
+                .map(|item| format!("• {}\n{}", item, item.link))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        if let Err(e) = notifier.send_message_with_retry(&message).await {
+            error!("❌ Erreur notification Telegram (après retry): {}", e);
+        }
+    }
+
+    true
+}
+
+/// Marque toutes les actualités Arch actuelles comme lues
+async fn mark_news_read(config: &Config) -> Result<()> {
+    let mut news_manager = NewsManager::new(
+        config.general.news_feed_url.clone(),
+        PathBuf::from(&config.general.data_dir),
+    );
+    news_manager.load().await?;
+
+    let unread = news_manager.check_unread().await?;
+    for item in &unread {
+        println!("📰 {}\n   {}", item, item.link);
+    }
+    news_manager.mark_read(&unread).await
+}
+
+/// Affiche le plan et l'aperçu de la transaction sans rien installer
+async fn dry_run(config: &Config) -> Result<()> {
+    let pacman_manager = PacmanManager::new(config.pacman.clone());
+    let updates = pacman_manager.check_updates().await?;
+    if updates.is_empty() {
+        println!("✅ Système à jour");
+        return Ok(());
+    }
+
+    let selected: Vec<String> = updates
+        .iter()
+        .filter(|u| u.hold.is_none())
+        .map(|u| u.name.clone())
+        .collect();
+    let plan = pacman_manager.plan_upgrade(&updates, &selected).await?;
+    print!("{}", plan);
+
+    let preview = pacman_manager.preview_transaction(&plan).await?;
+    if preview.is_empty() {
+        println!("ℹ️ pacman ne prévoit aucune transaction: la base de synchronisation n'est peut-être pas à jour (pacman -Sy)");
+    } else {
+        print!("{}", preview);
+    }
+    if prefetch::is_ready_to_install(&updates, &config.pacman.cache_dir) {
+        println!("📥 Prêt à installer, rien à télécharger");
+    }
+    Ok(())
+}
+
+/// Ajoute à l'historique les transactions faites hors de CachyPac
+async fn import_pacman_log(importer: &mut PacmanLogImporter, update_history: &mut UpdateHistory) {
+    if let Err(e) = importer.import(update_history).await {
+        warn!("⚠️ Impossible d'importer pacman.log: {}", e);
+    }
+}
+
+/// Rapport périodique des orphelins: notifie seulement lorsque leur nombre augmente
+async fn report_orphans(
+    config: &Config,
+    orphan_manager: &OrphanManager,
+    orphan_tracker: &mut OrphanTracker,
+    telegram_notifier: Option<&mut RobustTelegramNotifier>,
+) {
+    let now = chrono::Local::now();
+    if !orphan_tracker.is_due(config.orphans.report_interval_hours, now) {
+        return;
+    }
+
+    let report = match orphan_manager.scan().await {
+        Ok(report) => report,
+        Err(e) => {
+            warn!("⚠️ Recherche des orphelins impossible: {}", e);
+            return;
+        }
+    };
+    let previous = match orphan_tracker.record(&report, now).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            warn!("⚠️ Impossible d'enregistrer le rapport d'orphelins: {}", e);
+            return;
+        }
+    };
+
+    let (Some(previous), Some(notifier), true) = (previous, telegram_notifier, config.orphans.notify_on_growth) else {
+        return;
+    };
+    let message = format!(
+        "🧹 <b>CachyPac - {} paquets orphelins</b> ({} au dernier rapport)\n\n{}\n\n{}\n\nSuppression après vérification: cachypac --remove-orphans",
+        report.unneeded().len(),
+        previous,
+        report.summary(),
+        report.unneeded()
+            .iter()
+            .take(20)
+            .map(|package| format!("• <code>{}</code> {}", package.name, package.version))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    if let Err(e) = notifier.send_message_with_retry(&message).await {
+        error!("❌ Erreur notification Telegram (après retry): {}", e);
+    }
+}
+
+/// Gestionnaire des orphelins, sans les paquets protégés par HoldPkg
+async fn orphan_manager(config: &Config) -> OrphanManager {
+    let hold_packages = match PacmanConf::load(&config.pacman.pacman_conf).await {
+        Ok(pacman_conf) => pacman_conf.hold_pkg,
+        Err(e) => {
+            warn!("⚠️ HoldPkg non appliqué: {:#}", e);
+            Vec::new()
+        }
+    };
+    OrphanManager::new(config.pacman.timeout)
+        .with_hold_packages(hold_packages)
+        .with_helper(config.pacman.helper_socket.clone())
+}
+
+/// Affiche le rapport des paquets orphelins
+async fn list_orphans(config: &Config) -> Result<()> {
+    let report = orphan_manager(config).await.scan().await?;
+    print!("{}", report);
+    Ok(())
+}
+
+/// Supprime des orphelins après confirmation: tous les orphelins sans argument,
+/// sinon les paquets indiqués s'ils figurent dans le rapport
+async fn remove_orphans(config: &Config, names: &[String]) -> Result<()> {
+    let orphan_manager = orphan_manager(config).await;
+    let report = orphan_manager.scan().await?;
+
+    let selected: Vec<String> = if names.is_empty() {
+        report.unneeded().iter().map(|package| package.name.clone()).collect()
+    } else {
+        if let Some(unknown) = names.iter().find(|name| !report.packages.iter().any(|p| &p.name == *name)) {
+            return Err(anyhow::anyhow!("{} n'est pas un paquet orphelin (voir cachypac --orphans)", unknown));
+        }
+        names.to_vec()
+    };
+    if selected.is_empty() {
+        println!("✅ Aucun paquet orphelin à supprimer");
+        return Ok(());
+    }
+
+    println!("🗑️ Paquets à supprimer ({}):", pacman::format_size(report.installed_size(&selected)));
+    for package in report.packages.iter().filter(|p| selected.contains(&p.name)) {
+        println!("  {}", package);
+    }
+    print!("Confirmer la suppression ? [o/N] ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "o" | "O" | "oui" | "y" | "yes") {
+        println!("❌ Suppression annulée");
+        return Ok(());
+    }
+
+    let mut update_history = UpdateHistory::new(PathBuf::from(&config.general.data_dir));
+    update_history.load().await?;
+    let freed = orphan_manager.remove(&report, &selected, &mut update_history).await?;
+    println!("✅ {} paquets supprimés, {} libérés", selected.len(), pacman::format_size(freed));
+    Ok(())
+}
+
+/// Affiche les vulnérabilités des paquets installés, comme `arch-audit`
+async fn audit(config: &Config) -> Result<()> {
+    let pacman_manager = PacmanManager::new(config.pacman.clone());
+    let database = AdvisoryManager::new(config.security.advisory_url.clone()).fetch().await?;
+    let installed = pacman_manager.installed_packages().await?;
+    // Sans checkupdates, les correctifs en attente ne sont simplement pas signalés
+    let updates = pacman_manager.check_updates().await.unwrap_or_else(|e| {
+        warn!("⚠️ Mises à jour en attente inconnues: {}", e);
+        Vec::new()
+    });
+
+    let vulnerabilities = database.vulnerabilities(&installed, &updates);
+    if vulnerabilities.is_empty() {
+        println!("✅ Aucune vulnérabilité connue parmi les {} paquets installés", installed.len());
+        return Ok(());
+    }
+
+    let unfixed = vulnerabilities.iter().filter(|v| v.is_unfixed()).count();
+    println!("🛡️ {} vulnérabilités ({} sans correctif en attente):", vulnerabilities.len(), unfixed);
+    for vulnerability in &vulnerabilities {
+        println!("  {}", vulnerability);
+    }
+    Ok(())
+}
+
+/// Nettoie le cache des paquets après avoir affiché l'espace récupérable
+async fn clean_cache(config: &Config) -> Result<()> {
+    let pacman_manager = PacmanManager::new(config.pacman.clone());
+    let cleanup = pacman_manager.cache_manager().plan().await?;
+    if cleanup.is_empty() {
+        println!("✅ Cache conforme à la politique de conservation ({})", config.pacman.cache_retention);
+        return Ok(());
+    }
+
+    println!("🧹 {}:", cleanup);
+    for package in &cleanup.remove {
+        println!("  {} {} ({})", package.name, package.version, pacman::format_size(package.size));
+    }
+    print!("Confirmer la suppression ? [o/N] ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "o" | "O" | "oui" | "y" | "yes") {
+        println!("❌ Nettoyage annulé");
+        return Ok(());
+    }
+
+    let mut update_history = UpdateHistory::new(PathBuf::from(&config.general.data_dir));
+    update_history.load().await?;
+    let freed = pacman_manager.clean_cache(&mut update_history).await?;
+    println!("✅ Cache nettoyé, {} libérés", pacman::format_size(freed));
+    Ok(())
+}
+
+/// Télécharge les mises à jour dans le cache sans les installer
+async fn prefetch_updates(config: &Config) -> Result<()> {
+    let pacman_manager = PacmanManager::new(config.pacman.clone());
+    let mut update_history = UpdateHistory::new(PathBuf::from(&config.general.data_dir));
+    update_history.load().await?;
+
+    let plan = pacman_manager.prefetch(config.prefetch.rate_limit_kib, &mut update_history).await?;
+    if plan.missing().next().is_none() {
+        println!("✅ {}", plan);
+    } else {
+        println!(
+            "📥 {} archives téléchargées ({}): prêt à installer",
+            plan.missing().count(),
+            pacman::format_size(plan.download_size())
+        );
+    }
+    Ok(())
+}
+
+/// Affiche l'état des miroirs et, sur demande, reclasse la liste
+async fn check_mirrors(config: &Config, rank: bool) -> Result<()> {
+    let mirror_manager = MirrorManager::new(config.mirrors.clone()).with_sudo(true);
+    let health = mirror_manager.check().await?;
+    for mirror in &health.mirrors {
+        println!("  {}", mirror);
+    }
+    println!("🌐 {}", health);
+
+    if rank {
+        let backup = mirror_manager.rank(&health).await?;
+        println!("✅ {} reclassée, ancienne liste: {}", config.mirrors.mirrorlist.display(), backup.display());
+    } else if health.needs_attention() {
+        println!("⚠️ Le premier miroir est inutilisable: cachypac --rank-mirrors pour reclasser la liste");
+    }
+    Ok(())
+}
+
+/// Vérifie les miroirs avant une mise à jour automatique: un premier miroir
+/// obsolète ou injoignable la bloque, sauf si la liste peut être reclassée
+async fn stale_mirrors_block_update(
+    config: &Config,
+    notified_mirrors: &mut std::collections::HashSet<String>,
+    telegram_notifier: Option<&mut RobustTelegramNotifier>,
+) -> bool {
+    if !config.mirrors.check_before_upgrade {
+        return false;
+    }
+    let mirror_manager = MirrorManager::new(config.mirrors.clone()).with_sudo(true);
+    let health = match mirror_manager.check().await {
+        Ok(health) => health,
+        Err(e) => {
+            warn!("⚠️ Miroirs non vérifiés: {:#}", e);
+            return false;
+        }
+    };
+    for mirror in health.stale().chain(health.unreachable()) {
+        warn!("🌐 {}", mirror);
+    }
+    let Some(primary) = health.primary().filter(|_| health.needs_attention()) else {
+        return false;
+    };
+
+    let (blocked, action) = if config.mirrors.rank {
+        match mirror_manager.rank(&health).await {
+            Ok(backup) => (false, format!("Liste de miroirs reclassée (ancienne liste: {}).", backup.display())),
+            Err(e) => (true, format!("Reclassement impossible, mise à jour automatique suspendue: {:#}", e)),
+        }
+    } else {
+        (true, "Mise à jour automatique suspendue (cachypac --rank-mirrors pour reclasser la liste).".to_string())
+    };
+    warn!("🌐 Premier miroir inutilisable: {}. {}", primary, action);
+
+    // Ne notifier chaque miroir qu'une seule fois
+    if let (Some(notifier), true) = (telegram_notifier, notified_mirrors.insert(primary.url.clone())) {
+        let message = format!(
+            "🌐 <b>CachyPac - Miroir inutilisable</b>\n\n{}\n{}\n\n{}",
+            primary, health, action
+        );
+        if let Err(e) = notifier.send_message_with_retry(&message).await {
+            error!("❌ Erreur notification Telegram (après retry): {}", e);
+        }
+    }
+
+    blocked
+}
+
+/// Supprime le verrou db.lck s'il n'est détenu par aucun processus pacman
+async fn remove_stale_lock(config: &Config) -> Result<()> {
+    let lock = DatabaseLock::new(&config.pacman.db_path).with_helper(config.pacman.helper_socket.clone());
+    match lock.state().await {
+        LockState::Free => println!("🔓 La base pacman n'est pas verrouillée"),
+        LockState::Held(holders) => {
+            let holders: Vec<String> = holders.iter().map(ToString::to_string).collect();
+            return Err(anyhow::anyhow!("Base pacman utilisée par {}, verrou conservé", holders.join(", ")));
+        }
+        LockState::Stale => {
+            lock.remove_stale().await?;
+            println!("🔓 Verrou orphelin {} supprimé", lock.path().display());
+        }
+    }
+    Ok(())
+}
+
+/// Annule une mise à jour passée en réinstallant les versions du cache
+async fn rollback_update(config: &Config, target: &str) -> Result<()> {
+    let mut update_history = UpdateHistory::new(PathBuf::from(&config.general.data_dir));
+    update_history.load().await?;
+
+    let entry = if target == "last" {
+        update_history.last_update()
+    } else {
+        let id = uuid::Uuid::parse_str(target)
+            .map_err(|e| anyhow::anyhow!("Identifiant d'historique invalide {}: {}", target, e))?;
+        update_history.get_entry(id)
+    }
+    .cloned()
+    .ok_or_else(|| anyhow::anyhow!("Aucune mise à jour trouvée pour {}", target))?;
+
+    let plan = RollbackManager::from_config(&config.pacman)
+        .rollback(&entry, &mut update_history)
+        .await?;
+    print!("{}", plan);
+    Ok(())
+}
+
+/// Installe automatiquement les mises à jour en journalisant la progression de pacman
+async fn run_auto_update(
+    config: &Config,
+    pacman_manager: &PacmanManager,
+    updates: &[pacman::PackageUpdate],
+    update_history: &mut UpdateHistory,
+    telegram_notifier: Option<&mut RobustTelegramNotifier>,
+) {
+    info!("⬇️ Installation automatique de {} mises à jour", updates.len());
+
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel::<ProgressReport>();
+    let progress_logger = tokio::spawn(async move {
+        while let Some(report) = progress_rx.recv().await {
+            match report.event {
+                TransactionEvent::Output(line) => debug!("pacman: {}", line),
+                TransactionEvent::Warning(_) => warn!("[{:>3.0}%] {}", report.fraction * 100.0, report.event),
+                TransactionEvent::Error(_) => error!("[{:>3.0}%] {}", report.fraction * 100.0, report.event),
+                _ => info!("[{:>3.0}%] {}", report.fraction * 100.0, report.event),
+            }
+        }
+    });
+
+    let selected: Vec<String> = updates
+        .iter()
+        .filter(|u| u.hold.is_none())
+        .map(|u| u.name.clone())
+        .collect();
+    let plan = match pacman_manager.plan_upgrade(updates, &selected).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!("❌ Impossible de planifier la mise à jour automatique: {}", e);
+            return;
+        }
+    };
+    info!("📋 Plan de mise à jour:\n{}", plan);
+
+    let snapshot_manager = config.general.backup_before_update
+        .then(|| SnapshotManager::new(config.snapshots.clone()));
+    let (mut entry, result) = pacman_manager
+        .execute_plan_with_snapshots(&plan, history::OperationType::AutoUpdate, snapshot_manager.as_ref(), Some(progress_tx))
+        .await;
+    let _ = progress_logger.await;
+
+    // Un conflit de verrou n'a rien modifié: la mise à jour est simplement reportée
+    let lock_error = result.as_ref().err().and_then(find_lock_error);
+    let trigger = match config.security.auto_update_policy {
+        AutoUpdatePolicy::OnSecurityFix => " (mise à jour complète déclenchée par un correctif de sécurité)",
+        AutoUpdatePolicy::All => "",
+    };
+    let message = match (&result, lock_error) {
+        (Ok(_), _) => format!("{} mises à jour installées automatiquement{}", plan.targets.len(), trigger),
+        (Err(_), Some(lock_error)) => format!("Mise à jour automatique reportée: {}", lock_error),
+        (Err(e), None) => format!("Échec de la mise à jour automatique: {}", e),
+    };
+    let pacnew_files = entry.pacnew_files.clone();
+    let restart = if result.is_ok() && config.restart.check_after_upgrade {
+        check_restart(config).await
+    } else {
+        None
+    };
+
+    entry.message = message.clone();
+    entry.restart = restart.clone();
+    if let Err(e) = update_history.add_entry(entry.clone()).await {
+        error!("❌ Erreur lors de l'enregistrement dans l'historique: {}", e);
+    }
+
+    // Une transaction interrompue (hook en échec...) peut laisser des paquets à moitié mis à jour
+    let rollback = if result.is_err() && lock_error.is_none() && config.pacman.rollback_on_failure {
+        warn!("⏪ Retour arrière automatique de l'opération {}", entry.id);
+        Some(
+            RollbackManager::from_config(&config.pacman)
+                .rollback(&entry, update_history)
+                .await
+                .inspect_err(|e| error!("❌ Échec du retour arrière: {}", e))
+        )
+    } else {
+        None
+    };
+
+    // Nettoyage enregistré comme une opération distincte de la mise à jour
+    if result.is_ok() && pacman_manager.clean_cache_after() {
+        if let Err(e) = pacman_manager.clean_cache(update_history).await {
+            warn!("⚠️ Nettoyage du cache: {}", e);
+        }
+    }
+
+    if result.is_ok() {
+        info!("✅ {}", message);
+    } else {
+        error!("❌ {}", message);
+    }
+
+    if let Some(notifier) = telegram_notifier {
+        let notify = if result.is_ok() { config.telegram.notify_on_success } else { config.telegram.notify_on_errors };
+        if notify {
+            let icon = match (&result, lock_error) {
+                (Ok(_), _) => "✅",
+                (Err(_), Some(_)) => "🔒",
+                (Err(_), None) => "❌",
+            };
+            if let Err(e) = notifier.send_message_with_retry(&format!("{} <b>CachyPac</b> - {}", icon, message)).await {
+                error!("❌ Erreur notification Telegram (après retry): {}", e);
+            }
+        }
+
+        if let Some(rollback) = &rollback {
+            let message = match rollback {
+                Ok(plan) if plan.is_empty() => "⏪ <b>CachyPac</b> - Aucun paquet modifié, retour arrière inutile".to_string(),
+                Ok(plan) => format!("⏪ <b>CachyPac</b> - Retour arrière de {} paquets effectué", plan.targets.len()),
+                Err(e) => format!("❌ <b>CachyPac</b> - Échec du retour arrière: {}", e),
+            };
+            if let Err(e) = notifier.send_message_with_retry(&message).await {
+                error!("❌ Erreur notification Telegram (après retry): {}", e);
+            }
+        }
+
+        if let Some(restart) = &restart {
+            let icon = if restart.reboot_reasons.is_empty() { "♻️" } else { "🔁" };
+            let mut message = format!("{} <b>CachyPac</b> - {}", icon, restart);
+            let pending: Vec<&String> = restart.units.iter().filter(|unit| !restart.restarted.contains(unit)).collect();
+            if !pending.is_empty() {
+                message.push_str(&format!(
+                    "\n\n{}",
+                    pending.iter().map(|unit| format!("• <code>{}</code>", unit)).collect::<Vec<_>>().join("\n")
+                ));
+            }
+            if let Err(e) = notifier.send_message_with_retry(&message).await {
+                error!("❌ Erreur notification Telegram (après retry): {}", e);
+            }
+        }
+
+        if !pacnew_files.is_empty() {
+            let summary = format!(
+                "🗂️ <b>CachyPac - {} fichiers de configuration à traiter</b>\n\n{}",
+                pacnew_files.len(),
+                pacnew_files.iter()
+                    .map(|file| format!("• <code>{}</code>", file.path.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            if let Err(e) = notifier.send_message_with_retry(&summary).await {
+                error!("❌ Erreur notification Telegram (après retry): {}", e);
+            }
+        }
+    }
+}
+
+/// Analyse les redémarrages nécessaires après une mise à jour et redémarre
+/// les services concernés si la politique l'autorise
+async fn check_restart(config: &Config) -> Option<RestartNeeds> {
+    let analyzer = RestartAnalyzer::new().with_helper(config.pacman.helper_socket.clone());
+    let report = match analyzer.analyze().await {
+        Ok(report) => report,
+        Err(e) => {
+            warn!("⚠️ Analyse des redémarrages impossible: {}", e);
+            return None;
+        }
+    };
+    if report.is_empty() {
+        return None;
+    }
+    for reason in &report.reboot_reasons {
+        warn!("🔁 Redémarrage requis: {}", reason);
+    }
+
+    let restarted = if config.restart.auto_restart_units {
+        analyzer
+            .restart_units(&report, &config.restart.never_restart)
+            .await
+            .unwrap_or_else(|e| {
+                error!("❌ {}", e);
+                Vec::new()
+            })
+    } else {
+        Vec::new()
+    };
+    Some(report.needs(restarted))
+}
+
 fn run_gui_mode(config: Config) -> Result<()> {
     info!("🖥️ Mode interface graphique activé");
     
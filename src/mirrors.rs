@@ -0,0 +1,410 @@
+//! État et classement des miroirs
+//!
+//! Chaque miroir actif de la liste est interrogé sur son fichier `lastsync`,
+//! date Unix de sa dernière synchronisation. Un miroir en retard sert des bases
+//! plus anciennes que celles des autres miroirs: pacman installe alors un
+//! mélange de versions (mise à jour partielle) qui ressemble à un bug de CachyPac.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::config::MirrorsConfig;
+use crate::pacman_conf::Mirrorlist;
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+
+/// Liste de miroirs de pacman
+pub const DEFAULT_MIRRORLIST: &str = "/etc/pacman.d/mirrorlist";
+
+/// Racine d'un miroir: l'URL `Server` avant `$repo` ou `$arch`
+///
+/// "https://mirror.example/archlinux/$repo/os/$arch" → "https://mirror.example/archlinux"
+pub fn mirror_root(server: &str) -> &str {
+    let end = ["$repo", "$arch"]
+        .iter()
+        .filter_map(|variable| server.find(variable))
+        .min()
+        .unwrap_or(server.len());
+    server[..end].trim_end_matches('/')
+}
+
+/// État d'un miroir par rapport à la référence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MirrorState {
+    UpToDate,
+    /// Synchronisé `lag_hours` heures avant la référence
+    Stale { lag_hours: i64 },
+    /// Joignable, sans `lastsync` lisible
+    Unknown,
+    Unreachable(String),
+}
+
+impl std::fmt::Display for MirrorState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorState::UpToDate => write!(f, "à jour"),
+            MirrorState::Stale { lag_hours } => write!(f, "en retard de {} h", lag_hours),
+            MirrorState::Unknown => write!(f, "date de synchronisation inconnue"),
+            MirrorState::Unreachable(error) => write!(f, "injoignable ({})", error),
+        }
+    }
+}
+
+/// Résultat de l'analyse d'un miroir
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorStatus {
+    /// URL `Server` telle qu'écrite dans la liste
+    pub url: String,
+    /// Temps de réponse à la requête `lastsync`
+    pub latency: Option<Duration>,
+    pub lastsync: Option<DateTime<Utc>>,
+    pub state: MirrorState,
+}
+
+impl MirrorStatus {
+    /// Miroir utilisable: à jour, ou joignable sans date de synchronisation
+    pub fn is_usable(&self) -> bool {
+        matches!(self.state, MirrorState::UpToDate | MirrorState::Unknown)
+    }
+}
+
+impl std::fmt::Display for MirrorStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", mirror_root(&self.url), self.state)?;
+        if let Some(lastsync) = self.lastsync {
+            write!(f, ", synchronisé le {}", lastsync.with_timezone(&Local).format("%d/%m/%Y %H:%M"))?;
+        }
+        if let Some(latency) = self.latency {
+            write!(f, " ({} ms)", latency.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+/// État de tous les miroirs actifs, dans l'ordre de la liste
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MirrorHealth {
+    pub mirrors: Vec<MirrorStatus>,
+    /// Date de synchronisation de référence
+    pub reference: Option<DateTime<Utc>>,
+}
+
+impl MirrorHealth {
+    pub fn stale(&self) -> impl Iterator<Item = &MirrorStatus> {
+        self.mirrors.iter().filter(|mirror| matches!(mirror.state, MirrorState::Stale { .. }))
+    }
+
+    pub fn unreachable(&self) -> impl Iterator<Item = &MirrorStatus> {
+        self.mirrors.iter().filter(|mirror| matches!(mirror.state, MirrorState::Unreachable(_)))
+    }
+
+    /// Premier miroir de la liste, le seul utilisé par pacman s'il répond
+    pub fn primary(&self) -> Option<&MirrorStatus> {
+        self.mirrors.first()
+    }
+
+    /// Vrai si pacman téléchargerait depuis un miroir obsolète ou injoignable
+    pub fn needs_attention(&self) -> bool {
+        self.primary().is_some_and(|mirror| !mirror.is_usable())
+    }
+
+    /// Miroirs utilisables, les miroirs à jour d'abord, triés par latence
+    pub fn ranked(&self) -> Vec<&MirrorStatus> {
+        let mut ranked: Vec<&MirrorStatus> = self.mirrors.iter().filter(|mirror| mirror.is_usable()).collect();
+        ranked.sort_by_key(|mirror| (mirror.state != MirrorState::UpToDate, mirror.latency));
+        ranked
+    }
+}
+
+impl std::fmt::Display for MirrorHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let up_to_date = self.mirrors.iter().filter(|mirror| mirror.state == MirrorState::UpToDate).count();
+        write!(
+            f,
+            "{} miroirs: {} à jour, {} en retard, {} injoignables",
+            self.mirrors.len(),
+            up_to_date,
+            self.stale().count(),
+            self.unreachable().count()
+        )?;
+        if let Some(latency) = self.ranked().first().and_then(|mirror| mirror.latency) {
+            write!(f, ", le plus rapide répond en {} ms", latency.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+/// Liste reclassée: miroirs utilisables triés par latence, les autres désactivés
+///
+/// Les miroirs déjà désactivés dans `original` sont conservés, commentés, à la fin.
+pub fn render_ranked(health: &MirrorHealth, original: &Mirrorlist, now: DateTime<Local>) -> String {
+    let mut content = format!(
+        "## Liste de miroirs classée par CachyPac le {}\n\
+         ## Miroirs à jour triés par latence; miroirs obsolètes ou injoignables désactivés\n\n",
+        now.format("%d/%m/%Y %H:%M")
+    );
+    for mirror in health.ranked() {
+        content.push_str(&format!("Server = {}\n", mirror.url));
+    }
+    for mirror in health.mirrors.iter().filter(|mirror| !mirror.is_usable()) {
+        content.push_str(&format!("#Server = {}  # {}\n", mirror.url, mirror.state));
+    }
+    for entry in original.entries.iter().filter(|entry| !entry.enabled) {
+        content.push_str(&format!("#Server = {}\n", entry.url));
+    }
+    content
+}
+
+/// Sauvegarde horodatée placée à côté de la liste
+fn backup_path(mirrorlist: &Path, now: DateTime<Local>) -> PathBuf {
+    let mut backup = mirrorlist.to_path_buf().into_os_string();
+    backup.push(format!(".cachypac-{}.bak", now.format("%Y%m%d-%H%M%S")));
+    PathBuf::from(backup)
+}
+
+/// Réponse d'un miroir à la requête `lastsync`
+struct Probe {
+    latency: Option<Duration>,
+    lastsync: Option<DateTime<Utc>>,
+    error: Option<String>,
+}
+
+async fn probe(client: &reqwest::Client, url: &str) -> Probe {
+    let start = Instant::now();
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            let error = if e.is_timeout() { "délai dépassé".to_string() } else { e.to_string() };
+            return Probe { latency: None, lastsync: None, error: Some(error) };
+        }
+    };
+    let latency = Some(start.elapsed());
+
+    let status = response.status();
+    if status.is_server_error() {
+        return Probe { latency, lastsync: None, error: Some(format!("HTTP {}", status)) };
+    }
+    // Un miroir sans lastsync (404) reste utilisable, sa date est inconnue
+    let lastsync = if status.is_success() {
+        response
+            .text()
+            .await
+            .ok()
+            .and_then(|body| body.trim().parse::<i64>().ok())
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+    } else {
+        None
+    };
+    Probe { latency, lastsync, error: None }
+}
+
+/// Analyse et reclassement de la liste de miroirs
+#[derive(Debug, Clone)]
+pub struct MirrorManager<R: CommandRunner = SystemRunner> {
+    runner: R,
+    config: MirrorsConfig,
+    /// Écrire la liste avec sudo (processus sans droits sur /etc/pacman.d)
+    sudo: bool,
+}
+
+impl MirrorManager {
+    pub fn new(config: MirrorsConfig) -> Self {
+        Self::with_runner(SystemRunner, config)
+    }
+}
+
+impl<R: CommandRunner> MirrorManager<R> {
+    pub fn with_runner(runner: R, config: MirrorsConfig) -> Self {
+        Self { runner, config, sudo: false }
+    }
+
+    /// Passe par sudo pour réécrire la liste
+    pub fn with_sudo(mut self, sudo: bool) -> Self {
+        self.sudo = sudo;
+        self
+    }
+
+    /// Interroge tous les miroirs actifs en parallèle
+    pub async fn check(&self) -> Result<MirrorHealth> {
+        let mirrorlist = Mirrorlist::load(&self.config.mirrorlist).await?;
+        let servers: Vec<String> = mirrorlist.servers().map(ToString::to_string).collect();
+        if servers.is_empty() {
+            return Err(anyhow::anyhow!("Aucun miroir actif dans {}", self.config.mirrorlist.display()));
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(self.config.timeout))
+            .user_agent(concat!("CachyPac/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("Impossible de créer le client HTTP")?;
+
+        let mut probes = JoinSet::new();
+        for (index, server) in servers.iter().enumerate() {
+            let client = client.clone();
+            let url = format!("{}/{}", mirror_root(server), self.config.lastsync_path.trim_start_matches('/'));
+            probes.spawn(async move {
+                debug!("🌐 Analyse de {}", url);
+                (index, probe(&client, &url).await)
+            });
+        }
+        let mut results: Vec<Option<Probe>> = servers.iter().map(|_| None).collect();
+        while let Some(joined) = probes.join_next().await {
+            let (index, result) = joined.context("Échec de l'analyse d'un miroir")?;
+            results[index] = Some(result);
+        }
+
+        let newest = results.iter().flatten().filter_map(|result| result.lastsync).max();
+        let reference = match &self.config.reference_url {
+            Some(url) => probe(&client, url).await.lastsync.or_else(|| {
+                warn!("⚠️ Référence de synchronisation illisible: {}", url);
+                newest
+            }),
+            None => newest,
+        };
+
+        let max_lag = chrono::Duration::hours(i64::try_from(self.config.max_lag_hours).unwrap_or(i64::MAX / 3600));
+        let mirrors = servers
+            .into_iter()
+            .zip(results.into_iter().flatten())
+            .map(|(url, result)| {
+                let state = match (result.error, result.lastsync, reference) {
+                    (Some(error), _, _) => MirrorState::Unreachable(error),
+                    (None, None, _) => MirrorState::Unknown,
+                    (None, Some(lastsync), Some(reference)) if reference - lastsync > max_lag => {
+                        MirrorState::Stale { lag_hours: (reference - lastsync).num_hours() }
+                    }
+                    (None, Some(_), _) => MirrorState::UpToDate,
+                };
+                MirrorStatus { url, latency: result.latency, lastsync: result.lastsync, state }
+            })
+            .collect();
+
+        let health = MirrorHealth { mirrors, reference };
+        info!("🌐 {}", health);
+        Ok(health)
+    }
+
+    /// Réécrit la liste avec les miroirs utilisables triés par latence
+    ///
+    /// La liste actuelle est d'abord copiée à côté (`mirrorlist.cachypac-<date>.bak`);
+    /// renvoie le chemin de cette sauvegarde.
+    pub async fn rank(&self, health: &MirrorHealth) -> Result<PathBuf> {
+        if health.ranked().is_empty() {
+            return Err(anyhow::anyhow!("Aucun miroir utilisable: liste de miroirs conservée"));
+        }
+
+        let path = &self.config.mirrorlist;
+        let original = Mirrorlist::load(path).await?;
+        let now = Local::now();
+        let content = render_ranked(health, &original, now);
+        let backup = backup_path(path, now);
+
+        if self.sudo {
+            let staged = std::env::temp_dir().join(format!("cachypac-mirrorlist-{}", uuid::Uuid::new_v4()));
+            tokio::fs::write(&staged, &content)
+                .await
+                .context(format!("Impossible d'écrire {}", staged.display()))?;
+            let result = async {
+                self.run_sudo(CommandSpec::new("sudo").args(["cp", "-p", "--"]).arg(&path.to_string_lossy()).arg(&backup.to_string_lossy()))
+                    .await?;
+                self.run_sudo(
+                    CommandSpec::new("sudo")
+                        .args(["install", "-m", "644", "--"])
+                        .arg(&staged.to_string_lossy())
+                        .arg(&path.to_string_lossy()),
+                )
+                .await
+            }
+            .await;
+            let _ = tokio::fs::remove_file(&staged).await;
+            result?;
+        } else {
+            tokio::fs::copy(path, &backup)
+                .await
+                .context(format!("Impossible de sauvegarder {}", path.display()))?;
+            let mut part = path.clone().into_os_string();
+            part.push(".part");
+            tokio::fs::write(&part, &content)
+                .await
+                .context(format!("Impossible d'écrire {}", path.display()))?;
+            tokio::fs::rename(&part, path)
+                .await
+                .context(format!("Impossible d'écrire {}", path.display()))?;
+        }
+
+        info!("🌐 Liste de miroirs reclassée, ancienne liste: {}", backup.display());
+        Ok(backup)
+    }
+
+    async fn run_sudo(&self, cmd: CommandSpec) -> Result<()> {
+        let output = self.runner.run(&cmd).await.context(format!("Impossible d'exécuter {}", cmd))?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de {}: {}", cmd, output.stderr.trim()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(url: &str, latency_ms: Option<u64>, state: MirrorState) -> MirrorStatus {
+        MirrorStatus {
+            url: url.to_string(),
+            latency: latency_ms.map(Duration::from_millis),
+            lastsync: None,
+            state,
+        }
+    }
+
+    #[test]
+    fn test_mirror_root() {
+        assert_eq!(mirror_root("https://mirror.example/archlinux/$repo/os/$arch"), "https://mirror.example/archlinux");
+        assert_eq!(mirror_root("https://cdn.example/repo/$arch/$repo"), "https://cdn.example/repo");
+        assert_eq!(mirror_root("https://static.example/"), "https://static.example");
+    }
+
+    #[test]
+    fn test_render_ranked() {
+        let health = MirrorHealth {
+            mirrors: vec![
+                status("https://stale.example/$repo/os/$arch", Some(20), MirrorState::Stale { lag_hours: 72 }),
+                status("https://slow.example/$repo/os/$arch", Some(300), MirrorState::UpToDate),
+                status("https://nodate.example/$repo/os/$arch", Some(10), MirrorState::Unknown),
+                status("https://fast.example/$repo/os/$arch", Some(40), MirrorState::UpToDate),
+                status("https://down.example/$repo/os/$arch", None, MirrorState::Unreachable("délai dépassé".to_string())),
+            ],
+            reference: None,
+        };
+        assert!(health.needs_attention());
+        assert_eq!(
+            health.to_string(),
+            "5 miroirs: 2 à jour, 1 en retard, 1 injoignables, le plus rapide répond en 40 ms"
+        );
+
+        let original = Mirrorlist::parse("#Server = https://disabled.example/$repo/os/$arch\n");
+        let now = Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let content = render_ranked(&health, &original, now);
+        assert!(content.starts_with("## Liste de miroirs classée par CachyPac le 01/05/2024 12:00\n"));
+
+        let ranked = Mirrorlist::parse(&content);
+        assert_eq!(
+            ranked.servers().collect::<Vec<_>>(),
+            vec![
+                "https://fast.example/$repo/os/$arch",
+                "https://slow.example/$repo/os/$arch",
+                "https://nodate.example/$repo/os/$arch",
+            ]
+        );
+        assert_eq!(ranked.entries.len(), 6);
+        assert!(content.contains("#Server = https://stale.example/$repo/os/$arch  # en retard de 72 h\n"));
+        assert_eq!(
+            backup_path(Path::new("/etc/pacman.d/mirrorlist"), now),
+            PathBuf::from("/etc/pacman.d/mirrorlist.cachypac-20240501-120000.bak")
+        );
+    }
+}
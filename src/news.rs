@@ -0,0 +1,287 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+/// Flux RSS officiel des actualités Arch Linux
+pub const DEFAULT_NEWS_FEED_URL: &str = "https://archlinux.org/feeds/news/";
+
+static ITEM_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<item(?:\s[^>]*)?>(.*?)</item>").unwrap());
+
+/// Actualité publiée sur archlinux.org
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewsItem {
+    pub title: String,
+    pub link: String,
+    /// Identifiant stable de l'article (guid, sinon lien)
+    pub id: String,
+    pub published: Option<DateTime<Local>>,
+    pub description: String,
+}
+
+impl std::fmt::Display for NewsItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(published) = self.published {
+            write!(f, "[{}] ", published.format("%Y-%m-%d"))?;
+        }
+        write!(f, "{}", self.title)
+    }
+}
+
+/// Suivi des actualités Arch et des articles déjà lus
+#[derive(Debug)]
+pub struct NewsManager {
+    feed_url: String,
+    data_dir: PathBuf,
+    seen: HashSet<String>,
+}
+
+impl NewsManager {
+    pub fn new(feed_url: String, data_dir: PathBuf) -> Self {
+        Self {
+            feed_url,
+            data_dir,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Charge la liste des articles déjà lus
+    pub async fn load(&mut self) -> Result<()> {
+        let seen_file = self.get_seen_file_path();
+
+        if !seen_file.exists() {
+            debug!("Aucune actualité Arch lue pour le moment");
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(&seen_file)
+            .await
+            .context("Impossible de lire le fichier des actualités lues")?;
+
+        self.seen = serde_json::from_str(&content)
+            .context("Erreur lors du parsing des actualités lues")?;
+
+        debug!("{} actualités Arch déjà lues", self.seen.len());
+        Ok(())
+    }
+
+    /// Sauvegarde la liste des articles déjà lus
+    pub async fn save(&self) -> Result<()> {
+        let seen_file = self.get_seen_file_path();
+
+        if let Some(parent) = seen_file.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .context("Impossible de créer le répertoire de données")?;
+        }
+
+        let mut seen: Vec<&String> = self.seen.iter().collect();
+        seen.sort();
+        let content = serde_json::to_string_pretty(&seen)
+            .context("Erreur lors de la sérialisation des actualités lues")?;
+
+        fs::write(&seen_file, content)
+            .await
+            .context("Impossible d'écrire le fichier des actualités lues")?;
+
+        Ok(())
+    }
+
+    /// Télécharge (ou lit localement) le flux et le parse
+    pub async fn fetch(&self) -> Result<Vec<NewsItem>> {
+        debug!("📰 Lecture du flux d'actualités: {}", self.feed_url);
+
+        let content = if self.feed_url.starts_with("http://") || self.feed_url.starts_with("https://") {
+            reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .user_agent(concat!("CachyPac/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .context("Impossible de créer le client HTTP")?
+                .get(&self.feed_url)
+                .send()
+                .await
+                .context("Impossible de télécharger les actualités Arch")?
+                .error_for_status()
+                .context("Réponse HTTP invalide pour les actualités Arch")?
+                .text()
+                .await
+                .context("Impossible de lire les actualités Arch")?
+        } else {
+            let path = self.feed_url.strip_prefix("file://").unwrap_or(&self.feed_url);
+            fs::read_to_string(path)
+                .await
+                .context(format!("Impossible de lire le flux d'actualités: {}", path))?
+        };
+
+        parse_feed(&content)
+    }
+
+    /// Articles du flux qui n'ont pas encore été lus
+    pub fn unread(&self, items: &[NewsItem]) -> Vec<NewsItem> {
+        items
+            .iter()
+            .filter(|item| !self.seen.contains(&item.id))
+            .cloned()
+            .collect()
+    }
+
+    /// Récupère le flux et renvoie les articles non lus
+    pub async fn check_unread(&self) -> Result<Vec<NewsItem>> {
+        let items = self.fetch().await?;
+        let unread = self.unread(&items);
+
+        if unread.is_empty() {
+            info!("📰 Aucune actualité Arch non lue");
+        } else {
+            warn!("📰 {} actualités Arch non lues", unread.len());
+        }
+
+        Ok(unread)
+    }
+
+    /// Marque les articles comme lus et sauvegarde
+    pub async fn mark_read(&mut self, items: &[NewsItem]) -> Result<()> {
+        self.seen.extend(items.iter().map(|item| item.id.clone()));
+        self.save().await?;
+        info!("✅ {} actualités Arch marquées comme lues", items.len());
+        Ok(())
+    }
+
+    fn get_seen_file_path(&self) -> PathBuf {
+        self.data_dir.join("news_seen.json")
+    }
+}
+
+/// Parse un flux RSS 2.0 (format des actualités archlinux.org)
+pub fn parse_feed(xml: &str) -> Result<Vec<NewsItem>> {
+    if !xml.contains("<rss") && !xml.contains("<channel") {
+        return Err(anyhow::anyhow!("Le flux d'actualités n'est pas un flux RSS"));
+    }
+
+    let items = ITEM_RE
+        .captures_iter(xml)
+        .filter_map(|captures| {
+            let body = captures.get(1)?.as_str();
+            let title = element_text(body, "title").unwrap_or_default();
+            let link = element_text(body, "link").unwrap_or_default();
+            let id = element_text(body, "guid")
+                .filter(|guid| !guid.is_empty())
+                .unwrap_or_else(|| if link.is_empty() { title.clone() } else { link.clone() });
+
+            if id.is_empty() {
+                warn!("⚠️ Actualité sans identifiant ignorée");
+                return None;
+            }
+
+            let published = element_text(body, "pubDate")
+                .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+                .map(|date| date.with_timezone(&Local));
+
+            Some(NewsItem {
+                title,
+                link,
+                id,
+                published,
+                description: element_text(body, "description").unwrap_or_default(),
+            })
+        })
+        .collect();
+
+    Ok(items)
+}
+
+/// Contenu texte d'un élément, CDATA et entités décodés
+fn element_text(body: &str, name: &str) -> Option<String> {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+
+    let start = body.find(&open)?;
+    let content_start = start + body[start..].find('>')? + 1;
+    if body[start..content_start].ends_with("/>") {
+        return Some(String::new());
+    }
+    let content_end = content_start + body[content_start..].find(&close)?;
+    let raw = body[content_start..content_end].trim();
+
+    let text = raw
+        .strip_prefix("<![CDATA[")
+        .and_then(|r| r.strip_suffix("]]>"))
+        .map_or_else(|| decode_entities(raw), str::to_string);
+
+    Some(text.trim().to_string())
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel><title>Arch Linux: Recent news updates</title>
+<item><title>Manual intervention for pacman 7.0.0 and local repositories required</title><link>https://archlinux.org/news/manual-intervention-for-pacman-700-and-local-repositories-required/</link><description>&lt;p&gt;Use &lt;code&gt;pacman -Syu&lt;/code&gt; &amp;amp; check&lt;/p&gt;</description><dc:creator xmlns:dc="http://purl.org/dc/elements/1.1/">Morten Linderud</dc:creator><pubDate>Sat, 14 Sep 2024 09:05:45 +0000</pubDate><guid isPermaLink="false">tag:archlinux.org,2024-09-14:/news/manual-intervention-for-pacman-700-and-local-repositories-required/</guid></item>
+<item><title><![CDATA[The sshd service needs a restart after upgrading to openssh-9.8p1]]></title><link>https://archlinux.org/news/the-sshd-service-needs-a-restart-after-upgrading-to-openssh-98p1/</link><description></description><pubDate>Mon, 01 Jul 2024 18:05:08 +0000</pubDate></item>
+</channel></rss>"#;
+
+    #[test]
+    fn test_parse_feed() {
+        let items = parse_feed(FEED).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "Manual intervention for pacman 7.0.0 and local repositories required");
+        assert!(items[0].id.starts_with("tag:archlinux.org,2024-09-14"));
+        assert_eq!(items[0].description, "<p>Use <code>pacman -Syu</code> &amp; check</p>");
+        assert_eq!(
+            items[0].published.unwrap().with_timezone(&chrono::Utc).to_rfc3339(),
+            "2024-09-14T09:05:45+00:00"
+        );
+        assert_eq!(items[1].title, "The sshd service needs a restart after upgrading to openssh-9.8p1");
+        // Sans guid, le lien sert d'identifiant
+        assert_eq!(items[1].id, items[1].link);
+
+        assert!(parse_feed("<html></html>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unread_tracking() {
+        let temp_dir = TempDir::new().unwrap();
+        let feed_path = temp_dir.path().join("news.xml");
+        std::fs::write(&feed_path, FEED).unwrap();
+
+        let mut manager = NewsManager::new(
+            format!("file://{}", feed_path.display()),
+            temp_dir.path().to_path_buf(),
+        );
+        manager.load().await.unwrap();
+
+        let unread = manager.check_unread().await.unwrap();
+        assert_eq!(unread.len(), 2);
+
+        manager.mark_read(&unread[..1]).await.unwrap();
+
+        // Les articles lus sont persistés dans le répertoire de données
+        let mut reloaded = NewsManager::new(
+            feed_path.to_string_lossy().to_string(),
+            temp_dir.path().to_path_buf(),
+        );
+        reloaded.load().await.unwrap();
+        let unread = reloaded.check_unread().await.unwrap();
+        assert_eq!(unread.len(), 1);
+        assert!(unread[0].title.starts_with("The sshd service"));
+    }
+}
@@ -0,0 +1,506 @@
+//! Paquets orphelins et inutiles
+//!
+//! Repère les dépendances qui ne sont plus requises (`pacman -Qdtq`, y compris
+//! celles libérées en cascade), les paquets requis seulement en dépendance
+//! optionnelle et les paquets absents de tous les dépôts de synchronisation.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tokio::time::timeout;
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+use crate::helper::HelperError;
+use crate::history::{HistoryEntry, OperationType, UpdateHistory};
+use crate::pacman::{format_size, parse_package_info, parse_size, PackageUpdate};
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+
+/// Motif de découverte des paquets orphelins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanKind {
+    /// Dépendance requise par aucun paquet (`pacman -Qdtq`)
+    Orphan,
+    /// Dépendance qui ne sert qu'à des orphelins
+    Recursive,
+    /// Dépendance requise seulement en dépendance optionnelle (`pacman -Qdttq`)
+    OptionalOnly,
+    /// Paquet absent de tous les dépôts de synchronisation (`pacman -Qmq`)
+    Foreign,
+}
+
+impl OrphanKind {
+    /// Vrai si le paquet peut être supprimé sans rien casser
+    ///
+    /// Les dépendances optionnelles et les paquets étrangers (AUR, paquets
+    /// retirés des dépôts) restent à la discrétion de l'utilisateur.
+    pub fn is_unneeded(self) -> bool {
+        matches!(self, OrphanKind::Orphan | OrphanKind::Recursive)
+    }
+}
+
+impl std::fmt::Display for OrphanKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrphanKind::Orphan => write!(f, "orphelin"),
+            OrphanKind::Recursive => write!(f, "orphelin en cascade"),
+            OrphanKind::OptionalOnly => write!(f, "dépendance optionnelle"),
+            OrphanKind::Foreign => write!(f, "absent des dépôts"),
+        }
+    }
+}
+
+/// Paquet installé candidat à la suppression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanPackage {
+    pub name: String,
+    pub version: String,
+    pub kind: OrphanKind,
+    pub installed_size: Option<u64>,
+}
+
+impl std::fmt::Display for OrphanPackage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ({}", self.name, self.version, self.kind)?;
+        if let Some(size) = self.installed_size {
+            write!(f, ", {}", format_size(size))?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Résultat d'une recherche d'orphelins
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrphanReport {
+    /// Paquets triés par motif puis par nom
+    pub packages: Vec<OrphanPackage>,
+}
+
+impl OrphanReport {
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    pub fn count(&self, kind: OrphanKind) -> usize {
+        self.packages.iter().filter(|package| package.kind == kind).count()
+    }
+
+    /// Paquets supprimables sans rien casser (orphelins et orphelins en cascade)
+    pub fn unneeded(&self) -> Vec<&OrphanPackage> {
+        self.packages.iter().filter(|package| package.kind.is_unneeded()).collect()
+    }
+
+    /// Taille installée cumulée des paquets choisis
+    pub fn installed_size<'a, I>(&self, names: I) -> u64
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let names: HashSet<&String> = names.into_iter().collect();
+        self.packages
+            .iter()
+            .filter(|package| names.contains(&package.name))
+            .filter_map(|package| package.installed_size)
+            .sum()
+    }
+
+    /// Résumé d'une ligne ("3 orphelins (120.00 MiB), 2 dépendances optionnelles")
+    pub fn summary(&self) -> String {
+        let unneeded = self.unneeded();
+        if self.is_empty() {
+            return "Aucun paquet orphelin".to_string();
+        }
+
+        let size: u64 = unneeded.iter().filter_map(|package| package.installed_size).sum();
+        let mut parts = vec![format!("{} orphelins ({})", unneeded.len(), format_size(size))];
+        let optional = self.count(OrphanKind::OptionalOnly);
+        if optional > 0 {
+            parts.push(format!("{} dépendances optionnelles", optional));
+        }
+        let foreign = self.count(OrphanKind::Foreign);
+        if foreign > 0 {
+            parts.push(format!("{} absents des dépôts", foreign));
+        }
+        parts.join(", ")
+    }
+}
+
+impl std::fmt::Display for OrphanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "🧹 {}", self.summary())?;
+        for package in &self.packages {
+            writeln!(f, "  {}", package)?;
+        }
+        Ok(())
+    }
+}
+
+/// Recherche et suppression des paquets orphelins
+#[derive(Debug, Clone)]
+pub struct OrphanManager<R: CommandRunner = SystemRunner> {
+    runner: R,
+    timeout: Duration,
+    /// `HoldPkg` de pacman.conf: jamais proposés à la suppression
+    hold_packages: Vec<String>,
+    /// Socket de cachypac-helper: la suppression n'y est pas proposée, sudo n'est pas utilisé
+    helper_socket: Option<PathBuf>,
+}
+
+impl OrphanManager {
+    pub fn new(timeout_secs: u64) -> Self {
+        Self::with_runner(SystemRunner, timeout_secs)
+    }
+}
+
+impl<R: CommandRunner> OrphanManager<R> {
+    pub fn with_runner(runner: R, timeout_secs: u64) -> Self {
+        Self {
+            runner,
+            timeout: Duration::from_secs(timeout_secs),
+            hold_packages: Vec::new(),
+            helper_socket: None,
+        }
+    }
+
+    /// Paquets protégés par `HoldPkg`, que pacman refuserait de supprimer
+    pub fn with_hold_packages(mut self, hold_packages: Vec<String>) -> Self {
+        self.hold_packages = hold_packages;
+        self
+    }
+
+    /// Refuse la suppression plutôt que de passer par sudo lorsque cachypac-helper est configuré
+    pub fn with_helper(mut self, helper_socket: Option<PathBuf>) -> Self {
+        self.helper_socket = helper_socket;
+        self
+    }
+
+    async fn pacman(&self, args: &[&str]) -> Result<String> {
+        let cmd = CommandSpec::new("pacman").env("LC_ALL", "C").args(args.iter().copied());
+        let output = timeout(self.timeout, self.runner.run(&cmd))
+            .await
+            .context("Timeout lors de la recherche des orphelins")?
+            .context("Impossible d'exécuter pacman")?;
+
+        // pacman renvoie 1 lorsqu'aucun paquet ne correspond à la requête
+        match output.code {
+            Some(0 | 1) => Ok(output.stdout),
+            _ => Err(anyhow::anyhow!("Échec de {}: {}", cmd, output.stderr.trim())),
+        }
+    }
+
+    async fn names(&self, args: &[&str]) -> Result<Vec<String>> {
+        Ok(self.pacman(args).await?.split_whitespace().map(ToString::to_string).collect())
+    }
+
+    /// Recherche tous les paquets orphelins ou inutiles, avec leur taille installée
+    pub async fn scan(&self) -> Result<OrphanReport> {
+        info!("🧹 Recherche des paquets orphelins");
+
+        let orphans = self.names(&["-Qdtq"]).await?;
+        // -tt inclut aussi les paquets qui ne sont requis qu'en dépendance optionnelle
+        let optional_only = self.names(&["-Qdttq"]).await?;
+        // Simulation de suppression récursive: dépendances libérées par les orphelins
+        let recursive = if orphans.is_empty() {
+            Vec::new()
+        } else {
+            let mut args = vec!["-Rsp", "--print-format", "%n"];
+            args.extend(orphans.iter().map(String::as_str));
+            self.names(&args).await?
+        };
+        let foreign = self.names(&["-Qmq"]).await?;
+
+        // Un paquet n'apparaît qu'une fois, sous son motif le plus fort
+        let mut seen = HashSet::new();
+        let candidates: Vec<(String, OrphanKind)> = [
+            (orphans, OrphanKind::Orphan),
+            (recursive, OrphanKind::Recursive),
+            (optional_only, OrphanKind::OptionalOnly),
+            (foreign, OrphanKind::Foreign),
+        ]
+        .into_iter()
+        .flat_map(|(names, kind)| names.into_iter().map(move |name| (name, kind)))
+        .filter(|(name, _)| seen.insert(name.clone()))
+        .filter(|(name, _)| {
+            let held = self.hold_packages.contains(name);
+            if held {
+                debug!("🔒 {} protégé par HoldPkg", name);
+            }
+            !held
+        })
+        .collect();
+
+        if candidates.is_empty() {
+            return Ok(OrphanReport::default());
+        }
+
+        let names: Vec<&str> = candidates.iter().map(|(name, _)| name.as_str()).collect();
+        let mut args = vec!["-Qi"];
+        args.extend(&names);
+        let info = parse_package_info(&self.pacman(&args).await?);
+
+        let mut packages: Vec<OrphanPackage> = candidates
+            .into_iter()
+            .map(|(name, kind)| {
+                let fields = info.get(&name);
+                OrphanPackage {
+                    version: fields.and_then(|f| f.get("Version")).cloned().unwrap_or_default(),
+                    installed_size: fields.and_then(|f| f.get("Installed Size")).and_then(|s| parse_size(s)),
+                    name,
+                    kind,
+                }
+            })
+            .collect();
+        packages.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)));
+
+        let report = OrphanReport { packages };
+        info!("🧹 {}", report.summary());
+        Ok(report)
+    }
+
+    /// Supprime les paquets en une seule transaction (`pacman -Rn`)
+    ///
+    /// Sans `-s`: seuls les paquets passés en revue sont supprimés.
+    pub async fn execute(&self, names: &[String]) -> Result<()> {
+        if names.is_empty() {
+            return Err(anyhow::anyhow!("Aucun paquet à supprimer"));
+        }
+        if self.helper_socket.is_some() {
+            return Err(HelperError::Unsupported("La suppression des paquets orphelins".to_string()).into());
+        }
+
+        let cmd = CommandSpec::new("sudo")
+            .args(["pacman", "-Rn", "--noconfirm"])
+            .args(names)
+            .env("LC_ALL", "C");
+
+        info!("🗑️ Suppression de {} paquets: {}", names.len(), names.join(", "));
+        let output = timeout(self.timeout * 2, self.runner.run(&cmd))
+            .await
+            .context("Timeout lors de la suppression des paquets")?
+            .context("Impossible d'exécuter pacman -R")?;
+
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de la suppression: {}", output.stderr.trim()));
+        }
+
+        Ok(())
+    }
+
+    /// Supprime les paquets choisis dans le rapport et l'enregistre dans l'historique
+    pub async fn remove(&self, report: &OrphanReport, names: &[String], history: &mut UpdateHistory) -> Result<u64> {
+        let start_time = Instant::now();
+        let removed: Vec<&OrphanPackage> = report.packages.iter().filter(|p| names.contains(&p.name)).collect();
+        let freed = report.installed_size(names);
+        let result = self.execute(names).await;
+
+        let message = match &result {
+            Ok(()) => format!("Suppression de {} paquets orphelins ({} libérés)", names.len(), format_size(freed)),
+            Err(e) => format!("Échec de la suppression des paquets orphelins: {}", e),
+        };
+        let record = HistoryEntry {
+            id: Uuid::new_v4(),
+            timestamp: Local::now(),
+            operation_type: OperationType::PackageRemove,
+            packages: names.to_vec(),
+            updates: removed
+                .iter()
+                .map(|package| PackageUpdate::new(package.name.clone(), package.version.clone(), String::new()))
+                .collect(),
+            pacnew_files: Vec::new(),
+            snapshots: Vec::new(),
+            freed_bytes: result.is_ok().then_some(freed),
+            restart: None,
+            success: result.is_ok(),
+            message,
+            duration: start_time.elapsed(),
+        };
+        if let Err(e) = history.add_entry(record).await {
+            error!("❌ Erreur lors de l'enregistrement dans l'historique: {}", e);
+        }
+
+        result.map(|()| freed)
+    }
+}
+
+/// Dernier rapport d'orphelins, pour ne notifier qu'en cas d'augmentation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanState {
+    pub checked_at: Option<DateTime<Local>>,
+    /// Nombre d'orphelins (y compris en cascade) au dernier rapport
+    pub count: usize,
+}
+
+/// Rapport périodique des orphelins (sans suppression)
+#[derive(Debug)]
+pub struct OrphanTracker {
+    data_dir: PathBuf,
+    state: OrphanState,
+}
+
+impl OrphanTracker {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            state: OrphanState::default(),
+        }
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.data_dir.join("orphans_report.json")
+    }
+
+    #[allow(dead_code)]
+    pub fn state(&self) -> &OrphanState {
+        &self.state
+    }
+
+    /// Charge le dernier rapport
+    pub async fn load(&mut self) -> Result<()> {
+        let path = self.state_path();
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(&path)
+            .await
+            .context("Impossible de lire le dernier rapport d'orphelins")?;
+        self.state = serde_json::from_str(&content)
+            .context("Erreur lors du parsing du dernier rapport d'orphelins")?;
+        Ok(())
+    }
+
+    /// Vrai si le dernier rapport date de plus de `interval_hours` (0: jamais)
+    pub fn is_due(&self, interval_hours: u64, now: DateTime<Local>) -> bool {
+        let Some(interval) = i64::try_from(interval_hours).ok().and_then(chrono::Duration::try_hours) else {
+            return false;
+        };
+        interval_hours > 0 && self.state.checked_at.is_none_or(|checked_at| now - checked_at >= interval)
+    }
+
+    /// Enregistre un rapport et renvoie le nombre précédent si les orphelins ont augmenté
+    pub async fn record(&mut self, report: &OrphanReport, now: DateTime<Local>) -> Result<Option<usize>> {
+        let previous = self.state.count;
+        let count = report.unneeded().len();
+        self.state = OrphanState { checked_at: Some(now), count };
+
+        if let Some(parent) = self.state_path().parent() {
+            fs::create_dir_all(parent)
+                .await
+                .context("Impossible de créer le répertoire de données")?;
+        }
+        let content = serde_json::to_string_pretty(&self.state)
+            .context("Erreur lors de la sérialisation du rapport d'orphelins")?;
+        fs::write(self.state_path(), content)
+            .await
+            .context("Impossible d'écrire le rapport d'orphelins")?;
+
+        debug!("🧹 Orphelins: {} (précédemment {})", count, previous);
+        Ok((count > previous).then_some(previous))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
+    use tempfile::TempDir;
+
+    fn scripted() -> ScriptedRunner {
+        ScriptedRunner::new()
+            .on(&["pacman", "-Qdtq"], ScriptedResponse::ok("gtk2\npython-nose\n"))
+            .on(&["pacman", "-Qdttq"], ScriptedResponse::ok("gtk2\npython-nose\nxdg-utils\n"))
+            .on(&["pacman", "-Rsp"], ScriptedResponse::ok("gtk2\npython-nose\nlibxcomposite\n"))
+            .on(&["pacman", "-Qmq"], ScriptedResponse::ok("python-nose\nyay\n"))
+            .on(&["pacman", "-Qi"], ScriptedResponse::ok(
+                "Name            : gtk2\nVersion         : 2.24.33-3\nInstalled Size  : 20.00 MiB\n\n\
+                 Name            : python-nose\nVersion         : 1.3.7-13\nInstalled Size  : 1.00 MiB\n\n\
+                 Name            : libxcomposite\nVersion         : 0.4.6-1\nInstalled Size  : 512.00 KiB\n\n\
+                 Name            : xdg-utils\nVersion         : 1.2.1-1\nInstalled Size  : 300.00 KiB\n\n\
+                 Name            : yay\nVersion         : 12.3.5-1\nInstalled Size  : 8.00 MiB\n"
+            ))
+    }
+
+    #[tokio::test]
+    async fn test_scan_orphans() {
+        let runner = scripted();
+        let report = OrphanManager::with_runner(runner.clone(), 5).scan().await.unwrap();
+
+        let kinds: Vec<(&str, OrphanKind)> = report.packages.iter().map(|p| (p.name.as_str(), p.kind)).collect();
+        assert_eq!(kinds, vec![
+            ("gtk2", OrphanKind::Orphan),
+            ("python-nose", OrphanKind::Orphan),
+            ("libxcomposite", OrphanKind::Recursive),
+            ("xdg-utils", OrphanKind::OptionalOnly),
+            ("yay", OrphanKind::Foreign),
+        ]);
+        assert_eq!(report.packages[0].version, "2.24.33-3");
+        assert_eq!(report.packages[2].installed_size, Some(512 * 1024));
+        assert_eq!(report.unneeded().len(), 3);
+        assert_eq!(report.summary(), "3 orphelins (21.50 MiB), 1 dépendances optionnelles, 1 absents des dépôts");
+        assert_eq!(runner.count(&["pacman", "-Rsp", "--print-format", "%n", "gtk2", "python-nose"]), 1);
+
+        // Aucun orphelin: pacman renvoie 1 sans rien afficher
+        let empty = ScriptedRunner::new().on(&["pacman"], ScriptedResponse::exit(1, "", ""));
+        let report = OrphanManager::with_runner(empty.clone(), 5).scan().await.unwrap();
+        assert!(report.is_empty());
+        assert_eq!(empty.count(&["pacman", "-Rsp"]), 0);
+
+        // HoldPkg: jamais proposé à la suppression
+        let report = OrphanManager::with_runner(scripted(), 5)
+            .with_hold_packages(vec!["yay".to_string()])
+            .scan()
+            .await
+            .unwrap();
+        assert!(report.packages.iter().all(|p| p.name != "yay"));
+    }
+
+    #[tokio::test]
+    async fn test_remove_records_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = scripted().on(&["sudo", "pacman", "-Rn"], ScriptedResponse::ok(""));
+        let manager = OrphanManager::with_runner(runner.clone(), 5);
+        let report = manager.scan().await.unwrap();
+        let mut history = UpdateHistory::new(temp_dir.path().to_path_buf());
+
+        let names = vec!["gtk2".to_string(), "libxcomposite".to_string()];
+        let freed = manager.remove(&report, &names, &mut history).await.unwrap();
+        assert_eq!(freed, 20 * 1024 * 1024 + 512 * 1024);
+        assert_eq!(runner.count(&["sudo", "pacman", "-Rn", "--noconfirm", "gtk2", "libxcomposite"]), 1);
+
+        // cachypac-helper ne supprime pas de paquets: refus plutôt que sudo
+        let error = manager.clone().with_helper(Some(PathBuf::from("/run/cachypac/helper.sock"))).execute(&names).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<HelperError>(), Some(HelperError::Unsupported(_))));
+        assert_eq!(runner.count(&["sudo"]), 1);
+
+        let entry = &history.get_all_entries()[0];
+        assert!(matches!(entry.operation_type, OperationType::PackageRemove));
+        assert!(entry.success);
+        assert_eq!(entry.updates[0].current_version, "2.24.33-3");
+        assert_eq!(entry.updates[0].new_version, "");
+        assert_eq!(entry.freed_bytes, Some(freed));
+    }
+
+    #[tokio::test]
+    async fn test_tracker_notifies_on_growth() {
+        let temp_dir = TempDir::new().unwrap();
+        let report = OrphanManager::with_runner(scripted(), 5).scan().await.unwrap();
+        let now = Local::now();
+
+        let mut tracker = OrphanTracker::new(temp_dir.path().to_path_buf());
+        assert!(tracker.is_due(24, now));
+        assert!(!tracker.is_due(0, now));
+        assert_eq!(tracker.record(&report, now).await.unwrap(), Some(0));
+        assert!(!tracker.is_due(24, now + chrono::Duration::hours(23)));
+        assert!(tracker.is_due(24, now + chrono::Duration::hours(24)));
+
+        // Même nombre d'orphelins: rien à signaler, état conservé après rechargement
+        let mut reloaded = OrphanTracker::new(temp_dir.path().to_path_buf());
+        reloaded.load().await.unwrap();
+        assert_eq!(reloaded.state().count, 3);
+        assert_eq!(reloaded.record(&report, now).await.unwrap(), None);
+    }
+}
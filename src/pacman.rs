@@ -1,123 +1,622 @@
 use anyhow::{Context, Result};
+use chrono::Local;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Duration;
-use tokio::process::Command;
+use tokio::sync::mpsc::{self, UnboundedSender};
 use tokio::time::timeout;
 use tracing::{debug, info, warn};
 
+use crate::advisory::SecurityFix;
+use crate::aur::{parse_foreign_packages, AurClient};
+use crate::cache::{CacheCleanup, CacheManager};
 use crate::config::PacmanConfig;
+use crate::helper::{HelperClient, HelperError, HelperRequest, FREED_PREFIX};
+use crate::history::{HistoryEntry, OperationType, UpdateHistory};
+use crate::hold::{Hold, HoldEngine};
+use crate::localdb::LocalDatabase;
+use crate::lock::{find_lock_error, is_lock_error, DatabaseLock, LockError};
+use crate::pacman_conf::PacmanConf;
+use crate::pacnew::{parse_pacman_line, scan_config_files, PacnewFile};
+use crate::plan::{find_partial_upgrades, parse_package_list, PackageRelations, UpgradeMode, UpgradePlan};
+use crate::prefetch::{PrefetchManager, PrefetchPlan};
+use crate::preview::{build_preview, removal_candidates, PackageInfo, TransactionPreview, PRINT_FORMAT};
+use crate::progress::{ProgressParser, ProgressReport};
+use crate::runner::{CommandOutput, CommandRunner, CommandSpec, OutputLine, SystemRunner};
+use crate::snapshot::SnapshotManager;
+use crate::syncdb::{SyncDbManager, UpdateBackend};
+use crate::version::vercmp;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Trousseaux de clés mis à jour avant le reste du système
+pub const KEYRING_PACKAGES: &[&str] = &["archlinux-keyring", "cachyos-keyring", "chaotic-keyring"];
+
+/// Messages de pacman indiquant un problème de signature PGP
+const SIGNATURE_ERRORS: &[&str] = &[
+    "invalid or corrupted package (PGP signature)",
+    "signature from",
+    "could not be looked up remotely",
+    "required key missing from keyring",
+    "missing required signature",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PackageUpdate {
     pub name: String,
     pub current_version: String,
     pub new_version: String,
+    /// Dépôt de synchronisation (vide si inconnu)
     pub repository: String,
-    pub size: Option<String>,
+    /// Taille de téléchargement en octets
+    pub download_size: Option<u64>,
+    /// Variation de la taille installée en octets (nouvelle - actuelle)
+    pub installed_size_delta: Option<i64>,
+    /// Règle de retenue applicable à la mise à jour
+    #[serde(default)]
+    pub hold: Option<Hold>,
+    /// Vulnérabilités corrigées par la mise à jour (avis de sécurité Arch)
+    #[serde(default)]
+    pub security: Option<SecurityFix>,
+}
+
+impl PackageUpdate {
+    pub fn new(name: String, current_version: String, new_version: String) -> Self {
+        Self {
+            name,
+            current_version,
+            new_version,
+            repository: String::new(),
+            download_size: None,
+            installed_size_delta: None,
+            hold: None,
+            security: None,
+        }
+    }
+
+    /// Vrai pour une mise à jour AUR, que pacman ne peut pas installer
+    pub fn is_aur(&self) -> bool {
+        self.repository == "aur"
+    }
+}
+
+impl std::fmt::Display for PackageUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} -> {}", self.name, self.current_version, self.new_version)?;
+        if !self.repository.is_empty() {
+            write!(f, " [{}]", self.repository)?;
+        }
+        if let Some(size) = self.download_size {
+            write!(f, " ({})", format_size(size))?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
-pub struct PacmanManager {
+pub struct PacmanManager<R: CommandRunner = SystemRunner> {
     config: PacmanConfig,
+    runner: R,
+    /// Répertoire parcouru pour les fichiers .pacnew/.pacsave
+    config_root: PathBuf,
 }
 
 impl PacmanManager {
     pub fn new(config: PacmanConfig) -> Self {
-        Self { config }
+        Self::with_runner(config, SystemRunner)
+    }
+}
+
+impl<R: CommandRunner> PacmanManager<R> {
+    /// Crée un gestionnaire utilisant un exécuteur de commandes spécifique
+    pub fn with_runner(config: PacmanConfig, runner: R) -> Self {
+        Self {
+            config,
+            runner,
+            config_root: PathBuf::from("/etc"),
+        }
+    }
+
+    /// Change le répertoire parcouru après une mise à jour (par défaut /etc)
+    #[allow(dead_code)]
+    pub fn with_config_root(mut self, config_root: PathBuf) -> Self {
+        self.config_root = config_root;
+        self
     }
 
     /// Vérifie les mises à jour disponibles
-    pub async fn check_updates(&self) -> Result<Vec<String>> {
+    pub async fn check_updates(&self) -> Result<Vec<PackageUpdate>> {
         info!("🔍 Vérification des mises à jour disponibles avec CachyPac");
-        
-        // Vérifier si checkupdates est disponible
-        let check_cmd = Command::new("which")
-            .arg("checkupdates")
-            .output()
-            .await;
-            
-        if check_cmd.is_err() || !check_cmd.unwrap().status.success() {
-            warn!("⚠️ DIAGNOSTIC: La commande 'checkupdates' n'est pas disponible!");
-            warn!("⚠️ DIAGNOSTIC: Installez 'pacman-contrib' avec: sudo pacman -S pacman-contrib");
-            return Err(anyhow::anyhow!("checkupdates non trouvé - installez pacman-contrib"));
-        }
-        
-        debug!("✅ DIAGNOSTIC: checkupdates trouvé, continuation...");
 
-        let mut cmd = Command::new("checkupdates");
-        
+        let (mut updates, sync_info) = match self.config.update_backend {
+            UpdateBackend::Native => self.sync_db_manager().check_updates().await?,
+            UpdateBackend::Checkupdates => {
+                if !self.has_checkupdates().await {
+                    warn!("⚠️ Installez 'pacman-contrib' avec: sudo pacman -S pacman-contrib");
+                    return Err(anyhow::anyhow!("checkupdates non trouvé - installez pacman-contrib"));
+                }
+                self.run_checkupdates().await?
+            }
+            UpdateBackend::Auto if self.has_checkupdates().await => self.run_checkupdates().await?,
+            UpdateBackend::Auto => {
+                info!("ℹ️ checkupdates absent: vérification native des bases de synchronisation");
+                self.sync_db_manager().check_updates().await?
+            }
+        };
+
         if self.config.include_aur {
-            cmd.arg("--aur");
+            match self.check_aur_updates().await {
+                Ok(aur_updates) => updates.extend(aur_updates),
+                Err(e) => warn!("⚠️ Vérification AUR impossible: {}", e),
+            }
+        }
+
+        // pacman -Syu n'installera pas les paquets ignorés par pacman.conf
+        match PacmanConf::load(&self.config.pacman_conf).await {
+            Ok(pacman_conf) => updates.retain(|update| {
+                let groups = sync_info
+                    .get(&update.name)
+                    .and_then(|fields| fields.get("Groups"))
+                    .map(|value| parse_package_list(value))
+                    .unwrap_or_default();
+                match pacman_conf.ignored(&update.name, &groups) {
+                    Some(rule) => {
+                        debug!("⏭️ {} ignoré par pacman.conf ({})", update.name, rule);
+                        false
+                    }
+                    None => true,
+                }
+            }),
+            Err(e) => warn!("⚠️ IgnorePkg/IgnoreGroup non appliqués: {:#}", e),
+        }
+
+        match HoldEngine::new(&self.config.hold_rules) {
+            Ok(engine) if !engine.is_empty() => {
+                let held = engine.apply(&mut updates, &sync_info, Local::now());
+                debug!("⏸️ {} mises à jour retenues par les règles", held);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("⚠️ Règles de retenue invalides: {}", e),
         }
 
+        info!("📦 {} mises à jour trouvées", updates.len());
+        Ok(updates)
+    }
+
+    /// Vérifie si checkupdates (pacman-contrib) est installé
+    async fn has_checkupdates(&self) -> bool {
+        let found = self.runner
+            .run(&CommandSpec::new("which").arg("checkupdates"))
+            .await
+            .is_ok_and(|output| output.success());
+        if !found {
+            warn!("⚠️ DIAGNOSTIC: La commande 'checkupdates' n'est pas disponible!");
+        }
+        found
+    }
+
+    /// Vérificateur natif: bases de synchronisation téléchargées dans un répertoire privé
+    pub fn sync_db_manager(&self) -> SyncDbManager {
+        SyncDbManager::new(
+            self.config.pacman_conf.clone(),
+            self.config.sync_db_dir.clone(),
+            LocalDatabase::new(self.config.db_path.clone()),
+            self.config.timeout,
+        )
+    }
+
+    /// Mises à jour selon checkupdates, complétées par pacman -Si / -Qi
+    async fn run_checkupdates(&self) -> Result<(Vec<PackageUpdate>, PackageInfo)> {
+        let cmd = CommandSpec::new("checkupdates");
+
         let output = timeout(
             Duration::from_secs(self.config.timeout),
-            cmd.output()
+            self.runner.run(&cmd)
         )
         .await
         .context("Timeout lors de la vérification des mises à jour")?
         .context("Impossible d'exécuter checkupdates")?;
 
-        if !output.status.success() && output.status.code() != Some(2) {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Erreur checkupdates: {}", error));
+        if !output.success() && output.code != Some(2) {
+            return Err(anyhow::anyhow!("Erreur checkupdates: {}", output.stderr));
         }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let updates = self.parse_updates(&stdout)?;
+        let mut updates = parse_updates(&output.stdout);
 
-        info!("📦 {} mises à jour trouvées", updates.len());
-        Ok(updates)
+        let sync_info = match self.fill_package_details(&mut updates).await {
+            Ok(sync_info) => sync_info,
+            Err(e) => {
+                warn!("⚠️ Détails des paquets indisponibles: {}", e);
+                PackageInfo::new()
+            }
+        };
+
+        Ok((updates, sync_info))
     }
 
-    /// Parse la sortie de checkupdates
-    fn parse_updates(&self, output: &str) -> Result<Vec<String>> {
-        let mut updates = Vec::new();
+    /// Compare les paquets étrangers installés (pacman -Qm) avec l'AUR
+    pub async fn check_aur_updates(&self) -> Result<Vec<PackageUpdate>> {
+        info!("🔍 Vérification des mises à jour AUR");
+
+        let output = timeout(
+            Duration::from_secs(self.config.timeout),
+            self.runner.run(&CommandSpec::new("pacman").env("LC_ALL", "C").arg("-Qm"))
+        )
+        .await
+        .context("Timeout lors de la lecture des paquets étrangers")?
+        .context("Impossible d'exécuter pacman")?;
+
+        // pacman -Qm renvoie 1 lorsqu'aucun paquet étranger n'est installé
+        let foreign = parse_foreign_packages(&output.stdout);
+        if foreign.is_empty() {
+            return Ok(Vec::new());
+        }
 
-        for line in output.lines() {
-            if line.trim().is_empty() {
+        let client = AurClient::new(&self.config.aur_rpc_url, self.config.timeout)?;
+        let names: Vec<String> = foreign.iter().map(|(name, _)| name.clone()).collect();
+        let remote: HashMap<String, String> = client
+            .info(&names)
+            .await?
+            .into_iter()
+            .map(|package| (package.name, package.version))
+            .collect();
+
+        let mut updates = Vec::new();
+        for (name, current_version) in foreign {
+            let Some(new_version) = remote.get(&name) else {
+                debug!("❔ Paquet absent de l'AUR: {}", name);
                 continue;
+            };
+
+            if vercmp(&current_version, new_version).is_lt() {
+                let mut update = PackageUpdate::new(name, current_version, new_version.clone());
+                update.repository = "aur".to_string();
+                updates.push(update);
             }
+        }
 
-            // Format simple: nom_paquet version_actuelle -> nouvelle_version
-            if let Some(package_name) = line.split_whitespace().next() {
-                // Vérifier si le paquet est exclu
-                if self.config.exclude_packages.contains(&package_name.to_string()) {
-                    debug!("⏭️ Paquet exclu: {}", package_name);
-                    continue;
-                }
+        info!("📦 {} mises à jour AUR trouvées", updates.len());
+        Ok(updates)
+    }
+
+    /// Complète les mises à jour avec le dépôt et les tailles (pacman -Si / -Qi)
+    ///
+    /// Renvoie les champs `pacman -Si`, utilisés ensuite par les règles de retenue.
+    async fn fill_package_details(&self, updates: &mut [PackageUpdate]) -> Result<PackageInfo> {
+        if updates.is_empty() {
+            return Ok(PackageInfo::new());
+        }
 
-                updates.push(package_name.to_string());
-            } else {
-                warn!("⚠️ Ligne non parsée: {}", line);
+        let names: Vec<&str> = updates.iter().map(|u| u.name.as_str()).collect();
+        let sync_info = parse_package_info(&self.query_package_info("-Si", &names).await?);
+        let local_info = parse_package_info(&self.query_package_info("-Qi", &names).await?);
+
+        for update in updates.iter_mut() {
+            let Some(sync) = sync_info.get(&update.name) else {
+                continue;
+            };
+
+            if let Some(repository) = sync.get("Repository") {
+                update.repository.clone_from(repository);
+            }
+            update.download_size = sync.get("Download Size").and_then(|s| parse_size(s));
+
+            let new_size = sync.get("Installed Size").and_then(|s| parse_size(s));
+            let current_size = local_info
+                .get(&update.name)
+                .and_then(|local| local.get("Installed Size"))
+                .and_then(|s| parse_size(s));
+            if let (Some(new_size), Some(current_size)) = (new_size, current_size) {
+                update.installed_size_delta = i64::try_from(new_size)
+                    .ok()
+                    .zip(i64::try_from(current_size).ok())
+                    .map(|(new, current)| new - current);
             }
         }
 
-        Ok(updates)
+        Ok(sync_info)
+    }
+
+    /// Interroge pacman (-Si ou -Qi) pour une liste de paquets
+    async fn query_package_info(&self, operation: &str, names: &[&str]) -> Result<String> {
+        let cmd = CommandSpec::new("pacman")
+            .env("LC_ALL", "C")
+            .arg(operation)
+            .args(names);
+
+        let output = timeout(
+            Duration::from_secs(self.config.timeout),
+            self.runner.run(&cmd)
+        )
+        .await
+        .context("Timeout lors de la lecture des informations des paquets")?
+        .context("Impossible d'exécuter pacman")?;
+
+        // pacman renvoie une erreur si un seul paquet est introuvable,
+        // mais affiche tout de même les autres
+        Ok(output.stdout)
+    }
+
+    /// Aperçu de la transaction d'un plan (`pacman -Sp --print-format`), sans rien installer
+    ///
+    /// Les cibles proviennent de la base de synchronisation locale: l'aperçu peut
+    /// différer de `checkupdates` si elle n'a pas été rafraîchie.
+    pub async fn preview_transaction(&self, plan: &UpgradePlan) -> Result<TransactionPreview> {
+        if plan.is_empty() {
+            return Ok(TransactionPreview::default());
+        }
+
+        let cmd = CommandSpec::new("pacman")
+            .env("LC_ALL", "C")
+            .args(plan.preview_args())
+            .args(["--print-format", PRINT_FORMAT]);
+        let output = timeout(
+            Duration::from_secs(self.config.timeout),
+            self.runner.run(&cmd)
+        )
+        .await
+        .context("Timeout lors de la simulation de la transaction")?
+        .context("Impossible d'exécuter pacman")?;
+
+        if !output.success() {
+            return Err(anyhow::anyhow!("Erreur lors de la simulation: {}", output.stderr.trim()));
+        }
+
+        let installed = self.installed_packages().await?;
+        let targets: Vec<&str> = output.stdout
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        let sync_info = if targets.is_empty() {
+            HashMap::new()
+        } else {
+            parse_package_info(&self.query_package_info("-Si", &targets).await?)
+        };
+
+        let mut local_names: Vec<String> = removal_candidates(&sync_info, &installed);
+        local_names.extend(targets.iter().filter(|name| installed.contains_key(**name)).map(ToString::to_string));
+        let local_names: Vec<&str> = local_names.iter().map(String::as_str).collect();
+        let local_info = if local_names.is_empty() {
+            HashMap::new()
+        } else {
+            parse_package_info(&self.query_package_info("-Qi", &local_names).await?)
+        };
+
+        let preview = build_preview(&output.stdout, &installed, &sync_info, &local_info);
+        info!("🔎 Aperçu de la transaction: {}", preview.summary());
+        Ok(preview)
     }
 
-    /// Installe les mises à jour
-    pub async fn install_updates(&self, packages: Vec<String>) -> Result<()> {
-        if packages.is_empty() {
+    /// Paquets installés et leurs versions (`pacman -Q`)
+    pub async fn installed_packages(&self) -> Result<HashMap<String, String>> {
+        let output = timeout(
+            Duration::from_secs(self.config.timeout),
+            self.runner.run(&CommandSpec::new("pacman").env("LC_ALL", "C").arg("-Q"))
+        )
+        .await
+        .context("Timeout lors de la lecture des paquets installés")?
+        .context("Impossible d'exécuter pacman")?;
+
+        Ok(output.stdout
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(name, version)| (name.to_string(), version.trim().to_string()))
+            .collect())
+    }
+
+    /// Installe les mises à jour et renvoie les nouveaux fichiers .pacnew/.pacsave
+    #[allow(dead_code)]
+    pub async fn install_updates(&self, updates: &[PackageUpdate]) -> Result<Vec<PacnewFile>> {
+        self.install_updates_with_progress(updates, None).await
+    }
+
+    /// Planifie puis installe les mises à jour en publiant la progression de pacman
+    pub async fn install_updates_with_progress(
+        &self,
+        updates: &[PackageUpdate],
+        progress: Option<UnboundedSender<ProgressReport>>,
+    ) -> Result<Vec<PacnewFile>> {
+        let selected: Vec<String> = updates
+            .iter()
+            .filter(|u| u.hold.is_none())
+            .map(|u| u.name.clone())
+            .collect();
+        let plan = self.plan_upgrade(updates, &selected).await?;
+        info!("📋 Plan de mise à jour:\n{}", plan);
+        self.execute_plan(&plan, progress).await
+    }
+
+    /// Construit le plan de mise à jour selon le mode configuré
+    ///
+    /// En mode sélectif, les dépendances des paquets choisis sont comparées
+    /// aux mises à jour laissées de côté pour repérer les mises à jour partielles.
+    pub async fn plan_upgrade(&self, available: &[PackageUpdate], selected: &[String]) -> Result<UpgradePlan> {
+        let mut plan = UpgradePlan::new(
+            self.config.upgrade_mode,
+            available,
+            selected,
+            &self.config.exclude_packages,
+        );
+
+        if plan.mode == UpgradeMode::Selective && !plan.held_back.is_empty() && !plan.is_empty() {
+            let targets: Vec<&str> = plan.targets.iter().map(|u| u.name.as_str()).collect();
+            let installed: Vec<&str> = plan.targets.iter()
+                .chain(&plan.held_back)
+                .map(|u| u.name.as_str())
+                .collect();
+            let sync_info = parse_package_info(&self.query_package_info("-Si", &targets).await?);
+            let local_info = parse_package_info(&self.query_package_info("-Qi", &installed).await?);
+
+            let relations = |info: &HashMap<String, HashMap<String, String>>| {
+                info.iter()
+                    .map(|(name, fields)| (name.clone(), PackageRelations::from_info(fields)))
+                    .collect::<HashMap<String, PackageRelations>>()
+            };
+
+            plan.partial_upgrade_warnings = find_partial_upgrades(&plan, &relations(&sync_info), &relations(&local_info));
+        }
+
+        Ok(plan)
+    }
+
+    /// Exécute un plan de mise à jour et renvoie les nouveaux fichiers .pacnew/.pacsave
+    pub async fn execute_plan(
+        &self,
+        plan: &UpgradePlan,
+        progress: Option<UnboundedSender<ProgressReport>>,
+    ) -> Result<Vec<PacnewFile>> {
+        if !plan.aur.is_empty() {
+            let names: Vec<&str> = plan.aur.iter().map(|u| u.name.as_str()).collect();
+            info!("📦 Mises à jour AUR à construire avec votre assistant AUR: {}", names.join(", "));
+        }
+
+        if plan.is_empty() {
             info!("✅ Aucune mise à jour à installer");
-            return Ok(());
+            return Ok(Vec::new());
+        }
+
+        if plan.is_partial() {
+            for warning in &plan.partial_upgrade_warnings {
+                warn!("⚠️ Mise à jour partielle: {}", warning);
+            }
+            if !self.config.allow_partial_upgrades {
+                return Err(anyhow::anyhow!(
+                    "Mise à jour partielle refusée ({} dépendances désynchronisées), sélectionnez les paquets liés ou utilisez une mise à jour complète",
+                    plan.partial_upgrade_warnings.len()
+                ));
+            }
+        }
+
+        info!("🔧 Installation de {} mises à jour avec CachyPac ({})", plan.targets.len(), plan.mode);
+
+        let existing_pacnew = self.scan_config_root().await?;
+        let mut pacnew_files = Vec::new();
+
+        // Les trousseaux passent d'abord, dans leur propre transaction
+        if self.config.check_keyring {
+            let keyrings: Vec<String> = plan.targets
+                .iter()
+                .map(|update| update.name.clone())
+                .filter(|name| KEYRING_PACKAGES.contains(&name.as_str()))
+                .collect();
+            if !keyrings.is_empty() {
+                info!("🔑 Mise à jour prioritaire des trousseaux: {}", keyrings.join(", "));
+                // `-Sy` puis `-S <trousseaux>` n'est sûr que suivi de la mise à jour complète
+                if plan.mode != UpgradeMode::Selective {
+                    let output = self.run_privileged(&HelperRequest::RefreshDatabases, None)
+                        .await
+                        .context("Impossible de rafraîchir les bases de paquets")?;
+                    if !output.success() {
+                        return Err(anyhow::anyhow!("Échec du rafraîchissement des bases: {}", output.stderr.trim()));
+                    }
+                }
+                let request = HelperRequest::Upgrade {
+                    mode: UpgradeMode::Selective,
+                    targets: keyrings,
+                    ignored: Vec::new(),
+                };
+                pacnew_files.extend(
+                    self.install_with_retry(&request, progress.as_ref())
+                        .await
+                        .context("Échec de la mise à jour des trousseaux de clés")?
+                );
+            }
         }
 
-        info!("🔧 Installation de {} mises à jour avec CachyPac", packages.len());
+        pacnew_files.extend(self.install_with_retry(&HelperRequest::from_plan(plan), progress.as_ref()).await?);
+        info!("✅ Mises à jour installées avec succès");
 
+        // Fichiers signalés par pacman et nouveaux fichiers trouvés dans /etc
+        pacnew_files.extend(
+            self.scan_config_root()
+                .await?
+                .into_iter()
+                .filter(|file| !existing_pacnew.contains(file))
+        );
+        pacnew_files.sort();
+        pacnew_files.dedup_by(|a, b| a.path == b.path);
+        for file in &pacnew_files {
+            warn!("🗂️ Nouveau fichier {}: {}", file.kind, file.path.display());
+        }
+
+        Ok(pacnew_files)
+    }
+
+    /// Exécute un plan entre deux instantanés (si `snapshots` est fourni) et
+    /// renvoie l'entrée d'historique de l'opération, à enregistrer par l'appelant
+    ///
+    /// Partagé par le daemon et l'interface pour que l'historique conserve les
+    /// instantanés, les versions et les fichiers .pacnew de chaque mise à jour.
+    pub async fn execute_plan_with_snapshots<S: CommandRunner>(
+        &self,
+        plan: &UpgradePlan,
+        operation_type: OperationType,
+        snapshots: Option<&SnapshotManager<S>>,
+        progress: Option<UnboundedSender<ProgressReport>>,
+    ) -> (HistoryEntry, Result<Vec<PacnewFile>>) {
+        let start_time = std::time::Instant::now();
+        let install = self.execute_plan(plan, progress);
+        let (snapshots, result) = match snapshots {
+            Some(manager) => manager.with_snapshots(&format!("{} paquets", plan.targets.len()), install).await,
+            None => (Vec::new(), install.await),
+        };
+
+        let message = match &result {
+            Ok(_) => format!("{} mises à jour installées", plan.targets.len()),
+            Err(e) => format!("Échec de la mise à jour: {}", e),
+        };
+        let entry = HistoryEntry {
+            id: uuid::Uuid::new_v4(),
+            timestamp: Local::now(),
+            operation_type,
+            packages: plan.target_names(),
+            updates: plan.targets.clone(),
+            pacnew_files: result.as_ref().map(Vec::clone).unwrap_or_default(),
+            snapshots,
+            freed_bytes: None,
+            restart: None,
+            success: result.is_ok(),
+            duration: start_time.elapsed(),
+            message,
+        };
+
+        (entry, result)
+    }
+
+    /// Fichiers .pacnew/.pacsave présents sous la racine de configuration
+    async fn scan_config_root(&self) -> Result<Vec<PacnewFile>> {
+        let root = self.config_root.clone();
+        tokio::task::spawn_blocking(move || scan_config_files(&root))
+            .await
+            .context("Échec du parcours des fichiers de configuration")
+    }
+
+    /// Exécute une transaction avec nouvelles tentatives, en rafraîchissant
+    /// les trousseaux une fois si pacman signale une erreur de signature
+    ///
+    /// Un verrou pris par un autre pacman est attendu sans consommer de tentative.
+    async fn install_with_retry(
+        &self,
+        request: &HelperRequest,
+        progress: Option<&UnboundedSender<ProgressReport>>,
+    ) -> Result<Vec<PacnewFile>> {
         let mut retry_count = 0;
+        let mut lock_conflicts = 0;
+        let mut keyring_refreshed = false;
         loop {
-            match self.try_install_updates(&packages).await {
-                Ok(_) => {
-                    info!("✅ Mises à jour installées avec succès");
-                    
-                    // Nettoyer le cache si configuré
-                    if self.config.clean_cache_after {
-                        self.clean_cache().await?;
+            self.wait_for_database(progress).await?;
+
+            match self.try_install_updates(request, progress).await {
+                Ok(pacnew_files) => return Ok(pacnew_files),
+                Err(e) if find_lock_error(&e).is_some() => {
+                    // Un autre processus a pris le verrou entre la vérification et le lancement
+                    lock_conflicts += 1;
+                    if lock_conflicts > self.config.retry_count {
+                        return Err(e);
                     }
-                    
-                    return Ok(());
+                    warn!("🔒 {}", e);
                 }
+                // Un refus du helper ne changera pas d'une tentative à l'autre
+                Err(e) if e.chain().any(<dyn std::error::Error>::is::<HelperError>) => return Err(e),
                 Err(e) => {
                     retry_count += 1;
                     if retry_count >= self.config.retry_count {
@@ -125,55 +624,403 @@ impl PacmanManager {
                     }
                     
                     warn!("⚠️ Tentative {}/{} échouée: {}", retry_count, self.config.retry_count, e);
+
+                    if self.config.check_keyring && !keyring_refreshed && is_signature_error(&format!("{:#}", e)) {
+                        keyring_refreshed = true;
+                        if let Err(e) = self.refresh_keyrings().await {
+                            warn!("⚠️ Rafraîchissement des trousseaux impossible: {}", e);
+                        }
+                    }
+
                     tokio::time::sleep(Duration::from_secs(self.config.retry_delay)).await;
                 }
             }
         }
     }
 
-    /// Tente d'installer les mises à jour
-    async fn try_install_updates(&self, packages: &[String]) -> Result<()> {
-        let mut cmd = Command::new("sudo");
-        cmd.args(&["pacman", "-Su", "--noconfirm"]);
-        
-        // Ajouter les paquets spécifiques
-        for package in packages {
-            cmd.arg(package);
+    /// Attend que la base pacman soit libre, en supprimant le verrou orphelin si configuré
+    async fn wait_for_database(&self, progress: Option<&UnboundedSender<ProgressReport>>) -> Result<()> {
+        let lock = self.database_lock();
+        match lock.wait(Duration::from_secs(self.config.lock_timeout), progress).await {
+            Err(LockError::Stale(_)) if self.config.remove_stale_lock => lock.remove_stale().await,
+            result => Ok(result?),
         }
+    }
 
-        let output = timeout(
+    /// Verrou de la base pacman configurée
+    pub fn database_lock(&self) -> DatabaseLock<R> {
+        DatabaseLock::with_runner(self.runner.clone(), &self.config.db_path)
+            .with_helper(self.config.helper_socket.clone())
+    }
+
+    /// Nettoyage du cache configuré (répertoire et politique de conservation)
+    pub fn cache_manager(&self) -> CacheManager<R> {
+        CacheManager::with_runner(self.runner.clone(), self.config.cache_dir.clone(), self.config.cache_retention)
+    }
+
+    /// Nettoyage à effectuer après une installation, si `clean_cache_after` est activé
+    pub fn clean_cache_after(&self) -> bool {
+        self.config.clean_cache_after
+    }
+
+    /// Réinstalle les trousseaux présents puis recharge les clés dans pacman-key
+    pub async fn refresh_keyrings(&self) -> Result<()> {
+        warn!("🔑 Erreur de signature détectée, rafraîchissement des trousseaux de clés");
+
+        let refresh = async {
+            if self.config.helper_socket.is_none() {
+                return reinstall_keyrings(&self.runner, true).await;
+            }
+            let output = self.run_privileged(&HelperRequest::RefreshKeyrings, None).await?;
+            if !output.success() {
+                return Err(anyhow::anyhow!("Échec du rafraîchissement des trousseaux: {}", output.stderr.trim()));
+            }
+            Ok(output.stdout.split_whitespace().map(ToString::to_string).collect())
+        };
+        let keyrings = timeout(Duration::from_secs(self.config.timeout * 2), refresh)
+            .await
+            .context("Timeout lors du rafraîchissement des trousseaux")??;
+
+        info!("✅ Trousseaux rafraîchis: {}", keyrings.join(", "));
+        Ok(())
+    }
+
+    /// Tente une transaction pacman
+    async fn try_install_updates(
+        &self,
+        request: &HelperRequest,
+        progress: Option<&UnboundedSender<ProgressReport>>,
+    ) -> Result<Vec<PacnewFile>> {
+        // Les lignes de pacman sont analysées au fil de l'eau pendant l'exécution
+        let (line_tx, mut line_rx) = mpsc::unbounded_channel::<OutputLine>();
+        let forward = async {
+            let mut parser = ProgressParser::new();
+            let mut pacnew_files = Vec::new();
+            while let Some(line) = line_rx.recv().await {
+                pacnew_files.extend(parse_pacman_line(&line.text));
+                let report = parser.parse_line(&line.text);
+                debug!("pacman: {}", report.line);
+                if let Some(progress) = progress {
+                    let _ = progress.send(report);
+                }
+            }
+            pacnew_files
+        };
+        let run = timeout(
             Duration::from_secs(self.config.timeout * 2),
-            cmd.output()
-        )
-        .await
-        .context("Timeout lors de l'installation")?
-        .context("Impossible d'exécuter pacman")?;
+            self.run_privileged(request, Some(line_tx))
+        );
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Erreur pacman: {}", error));
+        let (output, pacnew_files) = tokio::join!(run, forward);
+        let output = output
+            .context("Timeout lors de l'installation")?
+            .context("Impossible d'exécuter pacman")?;
+
+        if !output.success() {
+            if is_lock_error(&output.stderr) {
+                return Err(LockError::Refused(output.stderr.trim().to_string()).into());
+            }
+            return Err(anyhow::anyhow!("Erreur pacman: {}", output.stderr));
         }
 
-        Ok(())
+        Ok(pacnew_files)
+    }
+
+    /// Exécute une transaction pacman privilégiée: via cachypac-helper si une
+    /// socket est configurée, sinon avec sudo
+    async fn run_privileged(
+        &self,
+        request: &HelperRequest,
+        lines: Option<UnboundedSender<OutputLine>>,
+    ) -> Result<CommandOutput> {
+        if let Some(socket) = &self.config.helper_socket {
+            return HelperClient::new(socket.clone()).call(request, lines).await;
+        }
+
+        let Some(args) = request.pacman_args() else {
+            return Err(anyhow::anyhow!("{} nécessite cachypac-helper", request));
+        };
+        let cmd = CommandSpec::new("sudo")
+            .env("LC_ALL", "C")
+            .arg("pacman")
+            .args(args);
+        match lines {
+            Some(lines) => self.runner.run_streaming(&cmd, lines).await,
+            None => self.runner.run(&cmd).await,
+        }
+    }
+
+    /// Supprime les archives du cache hors politique de conservation et
+    /// l'enregistre dans l'historique; renvoie l'espace libéré
+    pub async fn clean_cache(&self, history: &mut UpdateHistory) -> Result<u64> {
+        let start_time = std::time::Instant::now();
+        let manager = self.cache_manager();
+        let cleanup = manager.plan().await.context("Impossible d'analyser le cache des paquets")?;
+        if cleanup.is_empty() {
+            info!("🧹 Cache déjà conforme à la politique de conservation");
+            return Ok(0);
+        }
+        info!("🧹 Nettoyage du cache: {}", cleanup);
+
+        let result = self.delete_cache_archives(&manager, &cleanup).await;
+        let message = match &result {
+            Ok(freed) => format!("Nettoyage du cache: {} archives supprimées ({} libérés)", cleanup.remove.len(), format_size(*freed)),
+            Err(e) => format!("Échec du nettoyage du cache: {}", e),
+        };
+        let mut packages: Vec<String> = cleanup.remove.iter().map(|package| package.name.clone()).collect();
+        packages.dedup();
+        let entry = HistoryEntry {
+            id: uuid::Uuid::new_v4(),
+            timestamp: Local::now(),
+            operation_type: OperationType::CleanCache,
+            packages,
+            updates: Vec::new(),
+            pacnew_files: Vec::new(),
+            snapshots: Vec::new(),
+            freed_bytes: result.as_ref().ok().copied(),
+            restart: None,
+            success: result.is_ok(),
+            message,
+            duration: start_time.elapsed(),
+        };
+        if let Err(e) = history.add_entry(entry).await {
+            warn!("⚠️ Erreur lors de l'enregistrement dans l'historique: {}", e);
+        }
+
+        result
+    }
+
+    /// Supprime les archives: via cachypac-helper, qui refait le calcul en root et
+    /// renvoie l'espace réellement libéré, ou avec sudo
+    async fn delete_cache_archives(&self, manager: &CacheManager<R>, cleanup: &CacheCleanup) -> Result<u64> {
+        if self.config.helper_socket.is_none() {
+            return manager.delete_with_sudo(cleanup).await;
+        }
+
+        let request = HelperRequest::CleanCache { retention: self.config.cache_retention };
+        let output = self.run_privileged(&request, None).await.context("Impossible de nettoyer le cache")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec du nettoyage du cache: {}", output.stderr.trim()));
+        }
+        output.stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(FREED_PREFIX)?.trim().parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("Espace libéré absent de la réponse de cachypac-helper"))
     }
 
-    /// Nettoie le cache Pacman
-    async fn clean_cache(&self) -> Result<()> {
-        info!("🧹 Nettoyage du cache Pacman");
+    /// Télécharge dans le cache les archives de la prochaine mise à jour complète,
+    /// sans rien installer (`pacman -Syuw`), et l'enregistre dans l'historique
+    ///
+    /// Les paquets écartés par le plan (exclusions, règles de retenue) ne sont
+    /// pas téléchargés. Renvoie le plan de préchargement établi
+    /// avant les téléchargements.
+    pub async fn prefetch(&self, rate_limit_kib: Option<u32>, history: &mut UpdateHistory) -> Result<PrefetchPlan> {
+        let start_time = std::time::Instant::now();
+        let updates = self.check_updates().await?;
+        let ignored = UpgradePlan::new(UpgradeMode::FullWithIgnores, &updates, &[], &self.config.exclude_packages).ignored;
 
-        let output = Command::new("sudo")
-            .args(&["pacman", "-Sc", "--noconfirm"])
-            .output()
+        let output = self.run_privileged(&HelperRequest::RefreshDatabases, None)
             .await
-            .context("Impossible de nettoyer le cache")?;
+            .context("Impossible de rafraîchir les bases de paquets")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec du rafraîchissement des bases: {}", output.stderr.trim()));
+        }
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            warn!("⚠️ Avertissement nettoyage: {}", error);
+        let parallel_downloads = PacmanConf::load(&self.config.pacman_conf)
+            .await
+            .ok()
+            .and_then(|pacman_conf| pacman_conf.parallel_downloads)
+            .unwrap_or(1);
+        let manager = PrefetchManager::with_runner(self.runner.clone(), self.config.cache_dir.clone(), rate_limit_kib)
+            .with_sudo(self.config.helper_socket.is_none())
+            .with_parallel_downloads(parallel_downloads);
+        let plan = manager.plan(&ignored).await.context("Impossible de lister les archives à télécharger")?;
+        if plan.missing().next().is_none() {
+            return Ok(plan);
         }
+        info!("📥 Préchargement: {}", plan);
 
-        Ok(())
+        // Le helper refait le calcul en root avant de télécharger
+        let result = if self.config.helper_socket.is_some() {
+            let request = HelperRequest::Prefetch { ignored, rate_limit_kib };
+            match self.run_privileged(&request, None).await {
+                Ok(output) if output.success() => Ok(plan.download_size()),
+                Ok(output) => Err(anyhow::anyhow!("Échec du préchargement: {}", output.stderr.trim())),
+                Err(e) => Err(e.context("Impossible de précharger les paquets")),
+            }
+        } else {
+            manager.download(&plan).await
+        };
+
+        let message = match &result {
+            Ok(downloaded) => format!(
+                "Préchargement: {} archives téléchargées ({})",
+                plan.missing().count(),
+                format_size(*downloaded)
+            ),
+            Err(e) => format!("Échec du préchargement: {}", e),
+        };
+        let entry = HistoryEntry {
+            id: uuid::Uuid::new_v4(),
+            timestamp: Local::now(),
+            operation_type: OperationType::Prefetch,
+            packages: plan.missing().map(|target| target.name.clone()).collect(),
+            updates: Vec::new(),
+            pacnew_files: Vec::new(),
+            snapshots: Vec::new(),
+            freed_bytes: None,
+            restart: None,
+            success: result.is_ok(),
+            message,
+            duration: start_time.elapsed(),
+        };
+        if let Err(e) = history.add_entry(entry).await {
+            warn!("⚠️ Erreur lors de l'enregistrement dans l'historique: {}", e);
+        }
+
+        result.map(|_| plan)
+    }
+}
+
+/// Parse la sortie de checkupdates ("nom version_actuelle -> nouvelle_version")
+fn parse_updates(output: &str) -> Vec<PackageUpdate> {
+    let mut updates = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let [name, current, "->", new, ..] = fields.as_slice() {
+            updates.push(PackageUpdate::new(
+                (*name).to_string(),
+                (*current).to_string(),
+                (*new).to_string(),
+            ));
+        } else {
+            warn!("⚠️ Ligne non parsée: {}", line);
+        }
+    }
+
+    updates
+}
+
+/// Parse la sortie de `pacman -Si`/`-Qi` en blocs clé/valeur indexés par nom de paquet
+pub fn parse_package_info(output: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut packages = HashMap::new();
+    let mut current: HashMap<String, String> = HashMap::new();
+    let mut last_key: Option<String> = None;
+
+    for line in output.lines().chain(std::iter::once("")) {
+        if line.trim().is_empty() {
+            if let Some(name) = current.get("Name").cloned() {
+                // Un paquet peut exister dans plusieurs dépôts: garder le premier
+                packages.entry(name).or_insert_with(|| std::mem::take(&mut current));
+            }
+            current.clear();
+            last_key = None;
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            // Continuation d'une valeur sur plusieurs lignes
+            if let Some(value) = last_key.as_ref().and_then(|k| current.get_mut(k)) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(" : ") {
+            let key = key.trim().to_string();
+            current.insert(key.clone(), value.trim().to_string());
+            last_key = Some(key);
+        }
+    }
+
+    packages
+}
+
+/// Convertit une taille pacman ("12.34 MiB") en octets
+pub fn parse_size(value: &str) -> Option<u64> {
+    let mut parts = value.split_whitespace();
+    let number: f64 = parts.next()?.replace(',', ".").parse().ok()?;
+    let multiplier = match parts.next().unwrap_or("B") {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    if number < 0.0 {
+        return None;
     }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Some((number * multiplier).round() as u64)
+}
+
+/// Formate une taille en octets pour l'affichage
+#[allow(clippy::cast_precision_loss)]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.2} {}", UNITS[unit])
+    }
+}
+
+/// Vrai si la sortie de pacman signale un problème de signature PGP
+pub fn is_signature_error(output: &str) -> bool {
+    SIGNATURE_ERRORS.iter().any(|pattern| output.contains(pattern))
+}
+
+/// Réinstalle les trousseaux présents puis recharge les clés dans pacman-key;
+/// renvoie les trousseaux réinstallés (`sudo` est inutile dans cachypac-helper)
+pub async fn reinstall_keyrings<R: CommandRunner>(runner: &R, sudo: bool) -> Result<Vec<String>> {
+    // pacman -Qq renvoie 1 si un des trousseaux n'est pas installé
+    let installed = runner
+        .run(&CommandSpec::new("pacman").arg("-Qq").args(KEYRING_PACKAGES))
+        .await
+        .context("Impossible de lister les trousseaux installés")?;
+    let keyrings: Vec<String> = installed.stdout.split_whitespace().map(ToString::to_string).collect();
+    if keyrings.is_empty() {
+        return Err(anyhow::anyhow!("Aucun trousseau de clés installé"));
+    }
+
+    let privileged = |program: &str| {
+        if sudo {
+            CommandSpec::new("sudo").arg(program)
+        } else {
+            CommandSpec::new(program)
+        }
+    };
+    for cmd in [
+        privileged("pacman").env("LC_ALL", "C").args(["-S", "--noconfirm"]).args(&keyrings),
+        privileged("pacman-key").arg("--populate"),
+    ] {
+        let output = runner.run(&cmd).await.context(format!("Impossible d'exécuter {}", cmd))?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de {}: {}", cmd, output.stderr.trim()));
+        }
+    }
+
+    Ok(keyrings)
+}
+
+/// Formate une variation de taille signée ("+1.20 MiB", "-512 B")
+pub fn format_size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{sign}{}", format_size(delta.unsigned_abs()))
 }
 
 #[cfg(test)]
@@ -182,24 +1029,46 @@ mod tests {
 
     #[test]
     fn test_parse_updates() {
-        let config = PacmanConfig {
-            timeout: 300,
-            retry_count: 3,
-            retry_delay: 5,
-            exclude_packages: vec!["excluded-package".to_string()],
-            include_aur: false,
-            clean_cache_after: true,
-            check_keyring: true,
-        };
+        let output = "firefox 91.0-1 -> 92.0-1\nchromium 93.0-1 -> 94.0-1\nligne invalide";
+        let updates = parse_updates(output);
 
-        let manager = PacmanManager::new(config);
-        
-        let output = "firefox 91.0-1 -> 92.0-1\nchromium 93.0-1 -> 94.0-1\nexcluded-package 1.0-1 -> 2.0-1";
-        let updates = manager.parse_updates(output).unwrap();
-        
         assert_eq!(updates.len(), 2);
-        assert!(updates.contains(&"firefox".to_string()));
-        assert!(updates.contains(&"chromium".to_string()));
-        assert!(!updates.contains(&"excluded-package".to_string()));
+        assert_eq!(updates[0], PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string()));
+        assert_eq!(updates[1].name, "chromium");
+        assert_eq!(updates[1].current_version, "93.0-1");
+        assert_eq!(updates[1].new_version, "94.0-1");
+    }
+
+    #[test]
+    fn test_parse_package_info() {
+        let output = "Repository      : extra\nName            : firefox\nVersion         : 92.0-1\nDepends On      : gtk3  libxt\n                  nss\nDownload Size   : 60.12 MiB\nInstalled Size  : 230.50 MiB\n\nRepository      : core\nName            : linux\nDownload Size   : 512.00 KiB\n";
+        let info = parse_package_info(output);
+
+        assert_eq!(info.len(), 2);
+        assert_eq!(info["firefox"]["Repository"], "extra");
+        assert_eq!(info["firefox"]["Depends On"], "gtk3  libxt nss");
+        assert_eq!(info["linux"]["Download Size"], "512.00 KiB");
+    }
+
+    #[test]
+    fn test_parse_and_format_size() {
+        assert_eq!(parse_size("512 B"), Some(512));
+        assert_eq!(parse_size("1.50 KiB"), Some(1536));
+        assert_eq!(parse_size("2.00 MiB"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_size("n/a"), None);
+        assert_eq!(parse_size("3 PB"), None);
+
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size_delta(-2 * 1024 * 1024), "-2.00 MiB");
+        assert_eq!(format_size_delta(0), "+0 B");
+    }
+
+    #[test]
+    fn test_is_signature_error() {
+        assert!(is_signature_error("error: archlinux-keyring: signature from \"Jane <jane@archlinux.org>\" is unknown trust"));
+        assert!(is_signature_error("Erreur pacman: error: failed to commit transaction (invalid or corrupted package (PGP signature))"));
+        assert!(is_signature_error("error: key \"ABCDEF\" could not be looked up remotely"));
+        assert!(!is_signature_error("error: failed to commit transaction (conflicting files)"));
     }
 }
\ No newline at end of file
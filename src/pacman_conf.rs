@@ -0,0 +1,385 @@
+//! Lecture de pacman.conf et des listes de miroirs
+//!
+//! Les réglages de pacman qui concernent CachyPac sont lus ici: paquets et
+//! groupes ignorés, paquets protégés (`HoldPkg`), téléchargements simultanés,
+//! dépôts et miroirs (`Server`, directement ou via `Include`). Comme pacman,
+//! les fichiers inclus prolongent la section en cours.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::hold::glob_to_regex;
+
+/// Configuration de pacman
+pub const DEFAULT_PACMAN_CONF: &str = "/etc/pacman.conf";
+
+/// Profondeur maximale des `Include` imbriqués
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Dépôt de synchronisation déclaré dans pacman.conf
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repository {
+    pub name: String,
+    /// Miroirs dans l'ordre de pacman (`$repo` et `$arch` non remplacés)
+    pub servers: Vec<String>,
+    /// Fichiers inclus dans la section (listes de miroirs)
+    pub includes: Vec<PathBuf>,
+}
+
+/// Remplace `$repo` et `$arch` dans l'URL d'un miroir
+pub fn expand_server(server: &str, repository: &str, arch: &str) -> String {
+    server.replace("$repo", repository).replace("$arch", arch)
+}
+
+impl Repository {
+    fn new(name: &str) -> Self {
+        Self { name: name.to_string(), servers: Vec::new(), includes: Vec::new() }
+    }
+
+    /// URL de la base de synchronisation (`<dépôt>.db`) sur chaque miroir
+    pub fn db_urls(&self, arch: &str) -> Vec<String> {
+        self.servers
+            .iter()
+            .map(|server| {
+                let base = expand_server(server, &self.name, arch);
+                format!("{}/{}.db", base.trim_end_matches('/'), self.name)
+            })
+            .collect()
+    }
+}
+
+/// Règle de pacman.conf qui écarte une mise à jour
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacmanIgnore {
+    /// Motif `IgnorePkg` correspondant au paquet
+    Package(String),
+    /// Groupe `IgnoreGroup` du paquet
+    Group(String),
+}
+
+impl std::fmt::Display for PacmanIgnore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PacmanIgnore::Package(pattern) => write!(f, "IgnorePkg = {}", pattern),
+            PacmanIgnore::Group(group) => write!(f, "IgnoreGroup = {}", group),
+        }
+    }
+}
+
+/// Réglages de pacman.conf
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacmanConf {
+    /// `Architecture` (`auto` ou absente: celle de la machine)
+    pub architecture: Option<String>,
+    /// `IgnorePkg`: motifs glob des paquets jamais mis à jour par `pacman -Syu`
+    pub ignore_pkg: Vec<String>,
+    /// `IgnoreGroup`: groupes jamais mis à jour par `pacman -Syu`
+    pub ignore_group: Vec<String>,
+    /// `HoldPkg`: paquets que pacman refuse de supprimer sans confirmation
+    pub hold_pkg: Vec<String>,
+    /// `ParallelDownloads` (absent: un téléchargement à la fois)
+    pub parallel_downloads: Option<u32>,
+    /// Dépôts dans l'ordre de priorité de pacman
+    pub repositories: Vec<Repository>,
+}
+
+impl PacmanConf {
+    /// Lit pacman.conf et les fichiers inclus
+    pub async fn load(path: &Path) -> Result<Self> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || Self::load_blocking(&path))
+            .await
+            .context("Échec de la lecture de pacman.conf")?
+    }
+
+    fn load_blocking(path: &Path) -> Result<Self> {
+        let mut conf = Self::default();
+        let mut section = None;
+        conf.read_file(path, &mut section, 0)?;
+        debug!("⚙️ {} dépôts dans {}", conf.repositories.len(), path.display());
+        Ok(conf)
+    }
+
+    /// Analyse un fichier; `section` est la section courante, que les fichiers
+    /// inclus prolongent comme le fait pacman
+    fn read_file(&mut self, path: &Path, section: &mut Option<String>, depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(anyhow::anyhow!("Include trop imbriqués: {}", path.display()));
+        }
+        let content = std::fs::read_to_string(path).context(format!("Impossible de lire {}", path.display()))?;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                let name = name.trim();
+                *section = Some(name.to_string());
+                if name != "options" {
+                    self.repositories.push(Repository::new(name));
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let list = || value.split_whitespace().map(ToString::to_string);
+            match (section.as_deref(), key) {
+                (Some("options"), "Architecture") => {
+                    self.architecture = value.split_whitespace().next().map(ToString::to_string);
+                }
+                (Some("options"), "IgnorePkg") => self.ignore_pkg.extend(list()),
+                (Some("options"), "IgnoreGroup") => self.ignore_group.extend(list()),
+                (Some("options"), "HoldPkg") => self.hold_pkg.extend(list()),
+                (Some("options"), "ParallelDownloads") => match value.parse::<u32>() {
+                    Ok(count) if count > 0 => self.parallel_downloads = Some(count),
+                    _ => warn!("⚠️ ParallelDownloads invalide dans {}: {}", path.display(), value),
+                },
+                (_, "Include") => {
+                    for included in expand_include(value)? {
+                        if let Some(repository) = self.current_repository(section.as_deref()) {
+                            repository.includes.push(included.clone());
+                        }
+                        self.read_file(&included, section, depth + 1)?;
+                    }
+                }
+                (Some(_), "Server") => {
+                    if let Some(repository) = self.current_repository(section.as_deref()) {
+                        repository.servers.push(value.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn current_repository(&mut self, section: Option<&str>) -> Option<&mut Repository> {
+        let name = section.filter(|name| *name != "options")?;
+        self.repositories.iter_mut().rev().find(|repo| repo.name == name)
+    }
+
+    /// Architecture substituée à `$arch`
+    pub fn architecture(&self) -> &str {
+        match self.architecture.as_deref() {
+            Some(arch) if arch != "auto" => arch,
+            _ => std::env::consts::ARCH,
+        }
+    }
+
+    /// Règle `IgnorePkg` ou `IgnoreGroup` qui écarte le paquet des mises à jour
+    pub fn ignored(&self, name: &str, groups: &[String]) -> Option<PacmanIgnore> {
+        self.ignore_pkg
+            .iter()
+            .find(|pattern| Regex::new(&glob_to_regex(pattern)).is_ok_and(|re| re.is_match(name)))
+            .map(|pattern| PacmanIgnore::Package(pattern.clone()))
+            .or_else(|| {
+                self.ignore_group
+                    .iter()
+                    .find(|group| groups.contains(group))
+                    .map(|group| PacmanIgnore::Group(group.clone()))
+            })
+    }
+
+    /// Fichiers de miroirs inclus par les dépôts, sans doublon
+    pub fn mirrorlists(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for path in self.repositories.iter().flat_map(|repo| &repo.includes) {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+        paths
+    }
+}
+
+/// Fichiers désignés par `Include`, triés comme le fait pacman
+///
+/// Les jokers ne sont acceptés que dans le nom du fichier ("/etc/pacman.d/*.conf");
+/// un motif sans correspondance n'est pas une erreur.
+fn expand_include(value: &str) -> Result<Vec<PathBuf>> {
+    let path = PathBuf::from(value);
+    let Some(file_name) = path.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+        return Ok(vec![path]);
+    };
+    if !file_name.contains(['*', '?', '[']) {
+        return Ok(vec![path]);
+    }
+
+    let pattern = Regex::new(&glob_to_regex(&file_name)).context(format!("Motif Include invalide: {}", value))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut matches: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter(|entry| pattern.is_match(&entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(e) => {
+            warn!("⚠️ Include sans correspondance: {} ({})", value, e);
+            Vec::new()
+        }
+    };
+    matches.sort();
+    Ok(matches)
+}
+
+/// Miroir d'une liste de miroirs, actif ou commenté
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorEntry {
+    pub url: String,
+    /// `false` pour une ligne `#Server = ...`
+    pub enabled: bool,
+    /// Dernier titre de section ("## France") précédant le miroir
+    pub region: Option<String>,
+}
+
+/// Liste de miroirs (/etc/pacman.d/mirrorlist)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mirrorlist {
+    pub entries: Vec<MirrorEntry>,
+}
+
+impl Mirrorlist {
+    pub fn parse(content: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut region = None;
+        for line in content.lines() {
+            let line = line.trim();
+            let (enabled, directive) = match line.strip_prefix('#') {
+                Some(rest) => (false, rest.trim()),
+                None => (true, line),
+            };
+            if let Some((key, value)) = directive.split_once('=') {
+                if key.trim() == "Server" {
+                    let url = value.split('#').next().unwrap_or_default().trim();
+                    if !url.is_empty() {
+                        entries.push(MirrorEntry { url: url.to_string(), enabled, region: region.clone() });
+                    }
+                    continue;
+                }
+            }
+            // "## France": titre de région (reflector, archlinux.org/mirrorlist)
+            if let Some(title) = line.strip_prefix("##") {
+                let title = title.trim();
+                if !title.is_empty() && !title.contains(':') {
+                    region = Some(title.to_string());
+                }
+            }
+        }
+        Self { entries }
+    }
+
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .context(format!("Impossible de lire {}", path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// Miroirs actifs, dans l'ordre du fichier
+    pub fn servers(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter(|entry| entry.enabled).map(|entry| entry.url.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_load_repositories() {
+        let temp_dir = TempDir::new().unwrap();
+        let mirrorlist = temp_dir.path().join("mirrorlist");
+        std::fs::write(
+            &mirrorlist,
+            "## France\nServer = https://mirror.example/$repo/os/$arch\n#Server = https://disabled.example/$repo/os/$arch\n",
+        )
+        .unwrap();
+        let conf = temp_dir.path().join("pacman.conf");
+        std::fs::write(
+            &conf,
+            format!(
+                "[options]\nArchitecture = auto\nHoldPkg = pacman glibc\n\n\
+                 #[testing]\n#Include = {mirrorlist}\n\n\
+                 [core]\nInclude = {mirrorlist}\n\n\
+                 [cachyos-v3]\nServer = https://cachyos.example/$arch_v3/$repo/ # miroir principal\nInclude = {mirrorlist}\n",
+                mirrorlist = mirrorlist.display()
+            ),
+        )
+        .unwrap();
+
+        let conf = PacmanConf::load(&conf).await.unwrap();
+        let names: Vec<&str> = conf.repositories.iter().map(|repo| repo.name.as_str()).collect();
+        assert_eq!(names, vec!["core", "cachyos-v3"]);
+        assert_eq!(conf.architecture(), std::env::consts::ARCH);
+        assert_eq!(
+            conf.repositories[1].db_urls("x86_64"),
+            vec![
+                "https://cachyos.example/x86_64_v3/cachyos-v3/cachyos-v3.db",
+                "https://mirror.example/cachyos-v3/os/x86_64/cachyos-v3.db",
+            ]
+        );
+        assert_eq!(conf.mirrorlists(), vec![mirrorlist]);
+
+        assert!(PacmanConf::load(&temp_dir.path().join("absent.conf")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_options_and_glob_include() {
+        let temp_dir = TempDir::new().unwrap();
+        let conf_dir = temp_dir.path().join("pacman.d");
+        std::fs::create_dir_all(&conf_dir).unwrap();
+        std::fs::write(conf_dir.join("20-ignore.conf"), "IgnoreGroup = gnome\n").unwrap();
+        std::fs::write(conf_dir.join("10-ignore.conf"), "IgnorePkg = nvidia-*\n").unwrap();
+        std::fs::write(conf_dir.join("notes.txt"), "IgnorePkg = firefox\n").unwrap();
+
+        let conf = temp_dir.path().join("pacman.conf");
+        std::fs::write(
+            &conf,
+            format!(
+                "[options]\nHoldPkg = pacman glibc\nIgnorePkg = linux linux-headers\nParallelDownloads = 5\n\
+                 Include = {}/*.conf\n\n[extra]\nServer = https://mirror.example/$repo/os/$arch\n",
+                conf_dir.display()
+            ),
+        )
+        .unwrap();
+
+        let conf = PacmanConf::load(&conf).await.unwrap();
+        assert_eq!(conf.ignore_pkg, vec!["linux", "linux-headers", "nvidia-*"]);
+        assert_eq!(conf.ignore_group, vec!["gnome"]);
+        assert_eq!(conf.hold_pkg, vec!["pacman", "glibc"]);
+        assert_eq!(conf.parallel_downloads, Some(5));
+        assert!(conf.repositories[0].includes.is_empty());
+
+        assert_eq!(conf.ignored("nvidia-utils", &[]), Some(PacmanIgnore::Package("nvidia-*".to_string())));
+        assert_eq!(
+            conf.ignored("gedit", &["gnome".to_string()]),
+            Some(PacmanIgnore::Group("gnome".to_string()))
+        );
+        assert_eq!(conf.ignored("firefox", &[]), None);
+        assert_eq!(PacmanIgnore::Package("linux".to_string()).to_string(), "IgnorePkg = linux");
+    }
+
+    #[test]
+    fn test_parse_mirrorlist() {
+        let mirrorlist = Mirrorlist::parse(
+            "##\n## Arch Linux repository mirrorlist\n## Generated on 2024-05-01\n##\n\n\
+             ## France\nServer = https://fr.mirror.example/$repo/os/$arch\n\
+             #Server = https://old.mirror.example/$repo/os/$arch\n\n\
+             ## Germany\n#Server = https://de.mirror.example/$repo/os/$arch\n",
+        );
+
+        assert_eq!(mirrorlist.entries.len(), 3);
+        assert_eq!(mirrorlist.servers().collect::<Vec<_>>(), vec!["https://fr.mirror.example/$repo/os/$arch"]);
+        assert_eq!(mirrorlist.entries[0].region.as_deref(), Some("France"));
+        assert!(!mirrorlist.entries[1].enabled);
+        assert_eq!(mirrorlist.entries[2].region.as_deref(), Some("Germany"));
+    }
+}
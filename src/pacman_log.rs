@@ -0,0 +1,501 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::history::{HistoryEntry, OperationType, UpdateHistory};
+use crate::pacman::PackageUpdate;
+
+/// Journal de pacman
+pub const DEFAULT_PACMAN_LOG: &str = "/var/log/pacman.log";
+
+/// "[2024-09-14T10:00:31+0200] [ALPM] message" (l'étiquette est absente des très anciens journaux)
+static LINE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[([^\]]+)\](?: \[([A-Z-]+)\])? (.*)$").unwrap());
+
+/// "upgraded foo (1.0-1 -> 1.1-1)", "installed bar (2.0-1)"
+static ACTION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(installed|upgraded|downgraded|reinstalled|removed) (\S+) \((.+?)(?: -> (.+?))?\)$").unwrap()
+});
+
+static RUNNING_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^Running '(.*)'$").unwrap());
+
+/// Action de libalpm sur un paquet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageAction {
+    Installed,
+    Upgraded,
+    Downgraded,
+    Reinstalled,
+    Removed,
+}
+
+/// Ligne `[ALPM]` décrivant une action sur un paquet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    pub action: PackageAction,
+    pub name: String,
+    /// Version avant l'opération (absente pour une installation)
+    pub old_version: Option<String>,
+    /// Version après l'opération (absente pour une suppression)
+    pub new_version: Option<String>,
+}
+
+impl LogEvent {
+    fn parse(message: &str) -> Option<Self> {
+        let captures = ACTION_RE.captures(message)?;
+        let action = match &captures[1] {
+            "installed" => PackageAction::Installed,
+            "upgraded" => PackageAction::Upgraded,
+            "downgraded" => PackageAction::Downgraded,
+            "reinstalled" => PackageAction::Reinstalled,
+            _ => PackageAction::Removed,
+        };
+        let first = captures[3].to_string();
+        let second = captures.get(4).map(|m| m.as_str().to_string());
+
+        let (old_version, new_version) = match action {
+            PackageAction::Installed => (None, Some(first)),
+            PackageAction::Removed => (Some(first), None),
+            PackageAction::Reinstalled => (Some(first.clone()), Some(first)),
+            PackageAction::Upgraded | PackageAction::Downgraded => (Some(first), second),
+        };
+
+        Some(Self {
+            action,
+            name: captures[2].to_string(),
+            old_version,
+            new_version,
+        })
+    }
+}
+
+/// Transaction reconstituée à partir du journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogTransaction {
+    pub started: DateTime<Local>,
+    /// Fin de la transaction, None si elle a été interrompue
+    pub completed: Option<DateTime<Local>>,
+    /// Commande ayant lancé la transaction ("pacman -Syu", "paru -S ...")
+    pub command: Option<String>,
+    pub events: Vec<LogEvent>,
+}
+
+impl LogTransaction {
+    /// Convertit la transaction en entrée d'historique
+    pub fn to_history_entry(&self) -> HistoryEntry {
+        let has_version_change = self.events
+            .iter()
+            .any(|e| matches!(e.action, PackageAction::Upgraded | PackageAction::Downgraded));
+        let operation_type = if has_version_change {
+            OperationType::ManualUpdate
+        } else if self.events.iter().all(|e| e.action == PackageAction::Removed) {
+            OperationType::PackageRemove
+        } else {
+            OperationType::PackageInstall
+        };
+
+        let duration = self.completed
+            .and_then(|completed| (completed - self.started).to_std().ok())
+            .unwrap_or_default();
+        let message = match &self.command {
+            Some(command) => format!("Importé de pacman.log: {}", command),
+            None => "Importé de pacman.log".to_string(),
+        };
+
+        HistoryEntry {
+            id: Uuid::new_v4(),
+            timestamp: self.started,
+            operation_type,
+            packages: self.events.iter().map(|e| e.name.clone()).collect(),
+            updates: self.events
+                .iter()
+                .map(|e| PackageUpdate::new(
+                    e.name.clone(),
+                    e.old_version.clone().unwrap_or_default(),
+                    e.new_version.clone().unwrap_or_default(),
+                ))
+                .collect(),
+            pacnew_files: Vec::new(),
+            snapshots: Vec::new(),
+            freed_bytes: None,
+            restart: None,
+            success: self.completed.is_some(),
+            message,
+            duration,
+        }
+    }
+}
+
+/// Résultat du parsing d'un extrait de journal
+#[derive(Debug, Default)]
+pub struct ParsedLog {
+    pub transactions: Vec<LogTransaction>,
+    /// Octets traités; une transaction encore ouverte en fin de journal n'est pas consommée
+    pub consumed: usize,
+}
+
+/// Date d'une ligne du journal (format ISO 8601 actuel ou "AAAA-MM-JJ HH:MM" historique)
+fn parse_timestamp(value: &str) -> Option<DateTime<Local>> {
+    if let Ok(date) = DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%z") {
+        return Some(date.with_timezone(&Local));
+    }
+
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .ok()
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+}
+
+/// Regroupe les lignes du journal en transactions
+///
+/// Les actions hors d'un bloc "transaction started/completed" (anciens journaux)
+/// forment une transaction implicite, close à la commande suivante.
+pub fn parse_log(content: &str) -> ParsedLog {
+    let mut parsed = ParsedLog::default();
+    let mut current: Option<(LogTransaction, bool, usize)> = None;
+    let mut command: Option<(String, usize)> = None;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        if !line.ends_with('\n') {
+            // Ligne en cours d'écriture: relue au prochain import
+            offset = line_start;
+            break;
+        }
+
+        let Some(captures) = LINE_RE.captures(line.trim_end()) else {
+            continue;
+        };
+        let Some(timestamp) = parse_timestamp(&captures[1]) else {
+            continue;
+        };
+        // Les très anciens journaux n'ont pas d'étiquette
+        let tag = captures.get(2).map_or("", |m| m.as_str());
+        let message = &captures[3];
+
+        match (tag, message) {
+            ("PACMAN" | "", message) if RUNNING_RE.is_match(message) => {
+                // Une transaction implicite se termine avec la commande suivante
+                if let Some((transaction, false, _)) = current.take() {
+                    parsed.transactions.push(transaction);
+                }
+                command = Some((RUNNING_RE.captures(message).unwrap()[1].to_string(), line_start));
+            }
+            ("ALPM" | "", "transaction started") => {
+                if let Some((transaction, _, _)) = current.take() {
+                    parsed.transactions.push(transaction);
+                }
+                let (command, start) = command
+                    .take()
+                    .map_or((None, line_start), |(command, start)| (Some(command), start));
+                current = Some((LogTransaction {
+                    started: timestamp,
+                    completed: None,
+                    command,
+                    events: Vec::new(),
+                }, true, start));
+            }
+            ("ALPM" | "", "transaction completed") => {
+                if let Some((mut transaction, _, _)) = current.take() {
+                    transaction.completed = Some(timestamp);
+                    parsed.transactions.push(transaction);
+                }
+            }
+            ("ALPM" | "", "transaction interrupted" | "transaction failed") => {
+                if let Some((transaction, _, _)) = current.take() {
+                    parsed.transactions.push(transaction);
+                }
+            }
+            ("ALPM" | "", message) => {
+                if let Some(event) = LogEvent::parse(message) {
+                    let (transaction, _, _) = current.get_or_insert_with(|| (LogTransaction {
+                        started: timestamp,
+                        completed: Some(timestamp),
+                        command: command.take().map(|(command, _)| command),
+                        events: Vec::new(),
+                    }, false, line_start));
+                    transaction.events.push(event);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    parsed.consumed = match current {
+        // Transaction explicite non terminée: reprise depuis son début
+        Some((_, true, start)) => start,
+        Some((transaction, false, _)) => {
+            parsed.transactions.push(transaction);
+            offset
+        }
+        None => offset,
+    };
+    parsed.transactions.retain(|transaction| !transaction.events.is_empty());
+
+    parsed
+}
+
+/// Position de lecture du journal, persistée entre deux imports
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct ImportState {
+    offset: u64,
+    /// Première ligne du journal lu, pour détecter sa rotation
+    first_line: String,
+}
+
+/// Importe les transactions de pacman.log dans l'historique de CachyPac
+#[derive(Debug)]
+pub struct PacmanLogImporter {
+    log_path: PathBuf,
+    data_dir: PathBuf,
+    state: ImportState,
+}
+
+impl PacmanLogImporter {
+    pub fn new(log_path: PathBuf, data_dir: PathBuf) -> Self {
+        Self {
+            log_path,
+            data_dir,
+            state: ImportState::default(),
+        }
+    }
+
+    /// Charge la position du dernier import
+    pub async fn load(&mut self) -> Result<()> {
+        let state_file = self.get_state_file_path();
+        if !state_file.exists() {
+            debug!("Aucun import de pacman.log pour le moment");
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(&state_file)
+            .await
+            .context("Impossible de lire l'état d'import de pacman.log")?;
+        self.state = serde_json::from_str(&content)
+            .context("Erreur lors du parsing de l'état d'import de pacman.log")?;
+
+        Ok(())
+    }
+
+    async fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.data_dir)
+            .await
+            .context("Impossible de créer le répertoire de données")?;
+        let content = serde_json::to_string_pretty(&self.state)
+            .context("Erreur lors de la sérialisation de l'état d'import")?;
+        fs::write(self.get_state_file_path(), content)
+            .await
+            .context("Impossible d'écrire l'état d'import de pacman.log")?;
+        Ok(())
+    }
+
+    /// Lit les nouvelles lignes du journal et ajoute les transactions inconnues à l'historique
+    ///
+    /// Renvoie le nombre d'entrées ajoutées.
+    pub async fn import(&mut self, history: &mut UpdateHistory) -> Result<usize> {
+        let first_line = read_first_line(&self.log_path).await?;
+        let length = fs::metadata(&self.log_path)
+            .await
+            .context(format!("Impossible de lire {}", self.log_path.display()))?
+            .len();
+
+        let mut transactions = Vec::new();
+        let rotated = !self.state.first_line.is_empty()
+            && (first_line != self.state.first_line || length < self.state.offset);
+
+        if rotated {
+            // Terminer la lecture de l'ancien journal avant de reprendre au début du nouveau
+            if let Some(previous) = self.find_rotated_log().await {
+                info!("🔄 Rotation de pacman.log détectée, fin de lecture de {}", previous.display());
+                let (content, _) = read_from(&previous, self.state.offset).await?;
+                transactions.extend(parse_log(&content).transactions);
+            } else {
+                warn!("⚠️ Rotation de pacman.log détectée, ancien journal introuvable");
+            }
+            self.state.offset = 0;
+        }
+        self.state.first_line = first_line;
+
+        let (content, start) = read_from(&self.log_path, self.state.offset).await?;
+        let parsed = parse_log(&content);
+        transactions.extend(parsed.transactions);
+        self.state.offset = start + parsed.consumed as u64;
+
+        let entries = transactions.iter().map(LogTransaction::to_history_entry).collect();
+        let added = history.merge_entries(entries).await?;
+        self.save().await?;
+
+        if added > 0 {
+            info!("📥 {} transactions importées depuis {}", added, self.log_path.display());
+        }
+        Ok(added)
+    }
+
+    /// Journal renommé par logrotate commençant par la première ligne déjà lue
+    async fn find_rotated_log(&self) -> Option<PathBuf> {
+        let directory = self.log_path.parent()?;
+        let name = self.log_path.file_name()?.to_str()?;
+        let mut entries = fs::read_dir(directory).await.ok()?;
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let candidate = entry.file_name().to_string_lossy().to_string();
+            // Les journaux compressés ne sont pas relus
+            if candidate == name || !candidate.starts_with(name) || [".gz", ".xz", ".zst", ".bz2"].iter().any(|ext| candidate.ends_with(ext)) {
+                continue;
+            }
+            if read_first_line(&path).await.ok().as_deref() == Some(self.state.first_line.as_str()) {
+                return Some(path);
+            }
+        }
+
+        None
+    }
+
+    fn get_state_file_path(&self) -> PathBuf {
+        self.data_dir.join("pacman_log_import.json")
+    }
+}
+
+async fn read_first_line(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .await
+        .context(format!("Impossible d'ouvrir {}", path.display()))?;
+    let mut buffer = vec![0; 512];
+    let read = file.read(&mut buffer).await?;
+    buffer.truncate(read);
+
+    let content = String::from_utf8_lossy(&buffer);
+    Ok(content.lines().next().unwrap_or_default().to_string())
+}
+
+/// Contenu du fichier à partir de `offset` (ramené à 0 s'il dépasse la taille)
+async fn read_from(path: &Path, offset: u64) -> Result<(String, u64)> {
+    let mut file = fs::File::open(path)
+        .await
+        .context(format!("Impossible d'ouvrir {}", path.display()))?;
+    let length = file.metadata().await?.len();
+    let offset = if offset > length { 0 } else { offset };
+
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer).await?;
+
+    Ok((String::from_utf8_lossy(&buffer).into_owned(), offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/pacman.log");
+
+    #[test]
+    fn test_parse_log_fixture() {
+        let parsed = parse_log(FIXTURE);
+
+        assert_eq!(parsed.transactions.len(), 4);
+
+        let install = &parsed.transactions[0];
+        assert_eq!(install.command.as_deref(), Some("pacman -S htop"));
+        assert_eq!(install.events[0].action, PackageAction::Installed);
+        assert_eq!(install.events[0].new_version.as_deref(), Some("2.2.0-1"));
+
+        let upgrade = &parsed.transactions[1];
+        assert_eq!(upgrade.events.len(), 4);
+        assert_eq!(upgrade.events[1].old_version.as_deref(), Some("1:24.1.0-1"));
+        assert_eq!(upgrade.events[1].new_version.as_deref(), Some("1:24.2.0-1"));
+        assert_eq!(upgrade.events[3].action, PackageAction::Removed);
+        let entry = upgrade.to_history_entry();
+        assert!(matches!(entry.operation_type, OperationType::ManualUpdate));
+        assert_eq!(entry.duration, std::time::Duration::from_secs(9));
+        assert_eq!(entry.updates[2].current_version, "");
+        assert_eq!(entry.updates[3].new_version, "");
+        assert_eq!(entry.message, "Importé de pacman.log: pacman -Syu");
+
+        assert_eq!(parsed.transactions[2].events[0].action, PackageAction::Downgraded);
+        assert!(matches!(parsed.transactions[3].to_history_entry().operation_type, OperationType::PackageRemove));
+
+        // La dernière transaction n'est pas terminée: elle sera relue avec sa commande
+        let pending = FIXTURE.find("[2024-09-17T08:00:00+0200]").unwrap();
+        assert_eq!(parsed.consumed, pending);
+    }
+
+    #[test]
+    fn test_parse_log_without_markers() {
+        let log = "[2012-05-01 10:00] Running 'pacman -Syu'\n\
+            [2012-05-01 10:01] upgraded bash (4.2.028-1 -> 4.2.029-1)\n\
+            [2012-05-01 10:01] upgraded glibc (2.15-10 -> 2.15-11)\n\
+            [2012-05-02 09:00] [PACMAN] Running 'pacman -S git'\n\
+            [2012-05-02 09:01] installed git (1.7.10-1)\n\
+            [2012-05-02 09:01] upgraded";
+
+        let parsed = parse_log(log);
+        assert_eq!(parsed.transactions.len(), 2);
+        assert_eq!(parsed.transactions[0].events.len(), 2);
+        assert_eq!(parsed.transactions[1].command.as_deref(), Some("pacman -S git"));
+        // La ligne incomplète n'est pas consommée
+        assert_eq!(parsed.consumed, log.rfind("[2012-05-02 09:01] upgraded").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_incremental_import_and_deduplication() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("pacman.log");
+        let complete = FIXTURE.find("[2024-09-15T09:12:00+0200]").unwrap();
+        std::fs::write(&log_path, &FIXTURE[..complete]).unwrap();
+
+        let mut history = UpdateHistory::new(temp_dir.path().to_path_buf());
+        let mut importer = PacmanLogImporter::new(log_path.clone(), temp_dir.path().to_path_buf());
+        assert_eq!(importer.import(&mut history).await.unwrap(), 2);
+
+        // Reprise depuis la position enregistrée, la transaction ouverte n'est pas importée
+        std::fs::write(&log_path, FIXTURE).unwrap();
+        let mut importer = PacmanLogImporter::new(log_path.clone(), temp_dir.path().to_path_buf());
+        importer.load().await.unwrap();
+        assert_eq!(importer.import(&mut history).await.unwrap(), 2);
+        assert_eq!(history.get_all_entries().len(), 4);
+
+        // Un import complet depuis le début ne crée pas de doublons
+        let mut fresh = PacmanLogImporter::new(log_path, temp_dir.path().join("autre"));
+        assert_eq!(fresh.import(&mut history).await.unwrap(), 0);
+        assert_eq!(history.get_all_entries()[0].message, "Importé de pacman.log: pacman -Rns yelp");
+    }
+
+    #[tokio::test]
+    async fn test_import_across_log_rotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_dir = temp_dir.path().join("log");
+        std::fs::create_dir(&log_dir).unwrap();
+        let log_path = log_dir.join("pacman.log");
+        let rotated = include_str!("../tests/fixtures/pacman.log.1");
+        let first_transaction = rotated.find("[2024-08-02T07:30:00+0200]").unwrap();
+        std::fs::write(&log_path, &rotated[..first_transaction]).unwrap();
+
+        let mut history = UpdateHistory::new(temp_dir.path().to_path_buf());
+        let mut importer = PacmanLogImporter::new(log_path.clone(), temp_dir.path().to_path_buf());
+        assert_eq!(importer.import(&mut history).await.unwrap(), 1);
+
+        // Des lignes ont été ajoutées avant la rotation par logrotate
+        std::fs::write(log_dir.join("pacman.log.1"), rotated).unwrap();
+        std::fs::write(log_dir.join("pacman.log.2.gz"), "").unwrap();
+        std::fs::write(&log_path, include_str!("../tests/fixtures/pacman.log.rotated")).unwrap();
+
+        assert_eq!(importer.import(&mut history).await.unwrap(), 2);
+        let packages: Vec<&str> = history.get_all_entries().iter().map(|e| e.packages[0].as_str()).collect();
+        assert_eq!(packages, vec!["curl", "vim", "glibc"]);
+
+        assert_eq!(importer.import(&mut history).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,536 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tracing::{debug, info, warn};
+
+use crate::runner::{CommandOutput, CommandRunner, CommandSpec, SystemRunner};
+
+/// Lignes de contexte autour de chaque bloc du diff unifié
+const DIFF_CONTEXT: usize = 3;
+
+/// Au-delà, le diff ligne à ligne est remplacé par un remplacement complet
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+static PACMAN_CONFIG_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^warning: (\S+) (?:installed|saved) as (\S+\.pac(?:new|save)(?:\.\d+)?)$").unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum PacnewKind {
+    /// Nouvelle version proposée par le paquet, non appliquée
+    Pacnew,
+    /// Ancienne configuration sauvegardée lors d'une suppression ou d'un remplacement
+    Pacsave,
+}
+
+impl std::fmt::Display for PacnewKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PacnewKind::Pacnew => write!(f, ".pacnew"),
+            PacnewKind::Pacsave => write!(f, ".pacsave"),
+        }
+    }
+}
+
+/// Fichier .pacnew/.pacsave en attente de traitement
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct PacnewFile {
+    /// Chemin du fichier .pacnew/.pacsave
+    pub path: PathBuf,
+    /// Fichier de configuration actif correspondant
+    pub original: PathBuf,
+    pub kind: PacnewKind,
+}
+
+impl PacnewFile {
+    /// Reconnaît un chemin .pacnew, .pacsave ou .pacsave.N
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+
+        let (stem, kind) = if let Some(stem) = name.strip_suffix(".pacnew") {
+            (stem, PacnewKind::Pacnew)
+        } else {
+            let (stem, suffix) = name.rsplit_once(".pacsave")?;
+            if !(suffix.is_empty()
+                || suffix.strip_prefix('.').is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())))
+            {
+                return None;
+            }
+            (stem, PacnewKind::Pacsave)
+        };
+
+        if stem.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            path: path.to_path_buf(),
+            original: path.with_file_name(stem),
+            kind,
+        })
+    }
+}
+
+impl std::fmt::Display for PacnewFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+/// Décision de l'utilisateur pour un fichier .pacnew/.pacsave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacnewAction {
+    /// Conserver la configuration active et supprimer le fichier en attente
+    Keep,
+    /// Remplacer la configuration active par le fichier en attente
+    Replace,
+    /// Fusionner avec l'outil configuré (via sudoedit), puis supprimer le fichier
+    /// en attente si la configuration active a changé
+    Merge,
+}
+
+impl std::fmt::Display for PacnewAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PacnewAction::Keep => write!(f, "Conserver"),
+            PacnewAction::Replace => write!(f, "Remplacer"),
+            PacnewAction::Merge => write!(f, "Fusionner"),
+        }
+    }
+}
+
+/// Détecte et traite les fichiers .pacnew/.pacsave
+#[derive(Debug, Clone)]
+pub struct PacnewManager<R: CommandRunner = SystemRunner> {
+    runner: R,
+    root: PathBuf,
+    merge_tool: String,
+}
+
+impl PacnewManager {
+    pub fn new(merge_tool: String) -> Self {
+        Self::with_runner(SystemRunner, PathBuf::from("/etc"), merge_tool)
+    }
+}
+
+impl<R: CommandRunner> PacnewManager<R> {
+    pub fn with_runner(runner: R, root: PathBuf, merge_tool: String) -> Self {
+        Self {
+            runner,
+            root,
+            merge_tool,
+        }
+    }
+
+    /// Parcourt le répertoire de configuration à la recherche de fichiers en attente
+    pub async fn scan(&self) -> Result<Vec<PacnewFile>> {
+        let root = self.root.clone();
+        tokio::task::spawn_blocking(move || scan_config_files(&root))
+            .await
+            .context("Échec du parcours des fichiers de configuration")
+    }
+
+    /// Diff unifié entre la configuration active et le fichier en attente
+    ///
+    /// Une configuration active absente est traitée comme vide; un fichier
+    /// réservé à root est lu avec sudo, comme pour `checksum`.
+    pub async fn diff(&self, file: &PacnewFile) -> Result<String> {
+        let live = match self.read_config(&file.original).await {
+            Ok(live) => live,
+            Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) => {
+                String::new()
+            }
+            Err(e) => return Err(e.context(format!("Impossible de lire {}", file.original.display()))),
+        };
+        let pending = self.read_config(&file.path)
+            .await
+            .context(format!("Impossible de lire {}", file.path.display()))?;
+
+        Ok(unified_diff(
+            &live,
+            &pending,
+            &file.original.to_string_lossy(),
+            &file.path.to_string_lossy(),
+        ))
+    }
+
+    /// Applique la décision de l'utilisateur
+    pub async fn apply(&self, file: &PacnewFile, action: PacnewAction) -> Result<()> {
+        info!("🗂️ {} {}", action, file);
+
+        let path = file.path.to_string_lossy().to_string();
+        let original = file.original.to_string_lossy().to_string();
+
+        match action {
+            PacnewAction::Keep => {
+                self.run_privileged(&["rm", "-f", "--", &path]).await?;
+            }
+            PacnewAction::Replace => {
+                self.run_privileged(&["mv", "-f", "--", &path, &original]).await?;
+            }
+            PacnewAction::Merge => {
+                let before = self.checksum(&original).await?;
+                self.merge(&original, &path).await?;
+                if self.checksum(&original).await? == before {
+                    return Err(anyhow::anyhow!(
+                        "Fusion non confirmée: {} inchangé, {} conservé",
+                        original,
+                        path
+                    ));
+                }
+                self.run_privileged(&["rm", "-f", "--", &path]).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lance l'outil de fusion sans privilèges, à la manière de sudoedit
+    ///
+    /// L'outil travaille sur des copies temporaires; sudoedit ne réécrit
+    /// dans /etc que les fichiers effectivement modifiés.
+    async fn merge(&self, original: &str, pacnew: &str) -> Result<()> {
+        let cmd = CommandSpec::new("sudoedit")
+            .args(["--", original, pacnew])
+            .env("SUDO_EDITOR", &self.merge_tool);
+        let output = self.runner
+            .run(&cmd)
+            .await
+            .context(format!("Impossible d'exécuter {}", cmd))?;
+
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de {}: {}", cmd, output.stderr.trim()));
+        }
+
+        Ok(())
+    }
+
+    /// Contenu d'un fichier de configuration, lu avec sudo s'il est réservé à root
+    async fn read_config(&self, path: &Path) -> Result<String> {
+        match tokio::fs::read_to_string(path).await {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                let path = path.to_string_lossy();
+                Ok(self.run_privileged(&["cat", "--", &path]).await?.stdout)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Empreinte d'un fichier de configuration, lisible ou non par l'utilisateur
+    async fn checksum(&self, path: &str) -> Result<String> {
+        let output = self.run_privileged(&["sha256sum", "--", path]).await?;
+        output.stdout
+            .split_whitespace()
+            .next()
+            .map(ToString::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Empreinte de {} illisible", path))
+    }
+
+    async fn run_privileged(&self, args: &[&str]) -> Result<CommandOutput> {
+        let cmd = CommandSpec::new("sudo").args(args.iter().copied());
+        let output = self.runner
+            .run(&cmd)
+            .await
+            .context(format!("Impossible d'exécuter {}", cmd))?;
+
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de {}: {}", cmd, output.stderr.trim()));
+        }
+
+        Ok(output)
+    }
+}
+
+/// Extrait un fichier .pacnew/.pacsave d'un avertissement de pacman
+///
+/// Exemples: "warning: /etc/pacman.conf installed as /etc/pacman.conf.pacnew",
+/// "warning: /etc/foo.conf saved as /etc/foo.conf.pacsave"
+pub fn parse_pacman_line(line: &str) -> Option<PacnewFile> {
+    let captures = PACMAN_CONFIG_RE.captures(line.trim())?;
+    let mut file = PacnewFile::from_path(Path::new(&captures[2]))?;
+    file.original = PathBuf::from(&captures[1]);
+    Some(file)
+}
+
+/// Parcours récursif (sans suivre les liens symboliques) d'un répertoire
+pub fn scan_config_files(root: &Path) -> Vec<PacnewFile> {
+    let mut found = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Répertoire ignoré {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if let Some(file) = PacnewFile::from_path(&entry.path()) {
+                found.push(file);
+            }
+        }
+    }
+
+    found.sort();
+    if !found.is_empty() {
+        warn!("🗂️ {} fichiers .pacnew/.pacsave dans {}", found.len(), root.display());
+    }
+    found
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffOp<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Script d'édition ligne à ligne (plus longue sous-séquence commune)
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffOp<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<DiffOp> = old[..prefix].iter().map(|line| DiffOp::Equal(line)).collect();
+
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        ops.extend(old_mid.iter().map(|line| DiffOp::Delete(line)));
+        ops.extend(new_mid.iter().map(|line| DiffOp::Insert(line)));
+    } else {
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                ops.push(DiffOp::Equal(old_mid[i]));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+                ops.push(DiffOp::Delete(old_mid[i]));
+                i += 1;
+            } else {
+                ops.push(DiffOp::Insert(new_mid[j]));
+                j += 1;
+            }
+        }
+    }
+
+    ops.extend(old[old.len() - suffix..].iter().map(|line| DiffOp::Equal(line)));
+    ops
+}
+
+/// Diff unifié (format `diff -u`) entre deux contenus texte
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, DiffOp::Equal(_)))
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Position dans chaque fichier avant chaque opération
+    let mut old_pos = Vec::with_capacity(ops.len() + 1);
+    let mut new_pos = Vec::with_capacity(ops.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for op in &ops {
+        old_pos.push(o);
+        new_pos.push(n);
+        match op {
+            DiffOp::Equal(_) => {
+                o += 1;
+                n += 1;
+            }
+            DiffOp::Delete(_) => o += 1,
+            DiffOp::Insert(_) => n += 1,
+        }
+    }
+    old_pos.push(o);
+    new_pos.push(n);
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    let mut index = 0;
+    while index < changes.len() {
+        let start = changes[index].saturating_sub(DIFF_CONTEXT);
+        let mut last = changes[index];
+        while index + 1 < changes.len() && changes[index + 1] <= last + 2 * DIFF_CONTEXT + 1 {
+            index += 1;
+            last = changes[index];
+        }
+        let end = (last + DIFF_CONTEXT + 1).min(ops.len());
+
+        let hunk_range = |pos: &[usize]| {
+            let count = pos[end] - pos[start];
+            let first = if count == 0 { pos[start] } else { pos[start] + 1 };
+            format!("{},{}", first, count)
+        };
+        let _ = writeln!(out, "@@ -{} +{} @@", hunk_range(&old_pos), hunk_range(&new_pos));
+
+        for op in &ops[start..end] {
+            let (prefix, line) = match op {
+                DiffOp::Equal(line) => (' ', line),
+                DiffOp::Delete(line) => ('-', line),
+                DiffOp::Insert(line) => ('+', line),
+            };
+            out.push(prefix);
+            out.push_str(line);
+            out.push('\n');
+        }
+
+        index += 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pacnew_file_from_path() {
+        let file = PacnewFile::from_path(Path::new("/etc/pacman.conf.pacnew")).unwrap();
+        assert_eq!(file.original, PathBuf::from("/etc/pacman.conf"));
+        assert_eq!(file.kind, PacnewKind::Pacnew);
+
+        let file = PacnewFile::from_path(Path::new("/etc/ssh/sshd_config.pacsave.2")).unwrap();
+        assert_eq!(file.original, PathBuf::from("/etc/ssh/sshd_config"));
+        assert_eq!(file.kind, PacnewKind::Pacsave);
+
+        assert!(PacnewFile::from_path(Path::new("/etc/pacman.conf")).is_none());
+        assert!(PacnewFile::from_path(Path::new("/etc/foo.pacsave.bak")).is_none());
+    }
+
+    #[test]
+    fn test_parse_pacman_line() {
+        let file = parse_pacman_line("warning: /etc/pacman.d/mirrorlist installed as /etc/pacman.d/mirrorlist.pacnew").unwrap();
+        assert_eq!(file.path, PathBuf::from("/etc/pacman.d/mirrorlist.pacnew"));
+        assert_eq!(file.original, PathBuf::from("/etc/pacman.d/mirrorlist"));
+
+        let file = parse_pacman_line("warning: /etc/foo.conf saved as /etc/foo.conf.pacsave").unwrap();
+        assert_eq!(file.kind, PacnewKind::Pacsave);
+
+        assert!(parse_pacman_line("warning: linux: local (6.1-1) is newer than core (6.0-1)").is_none());
+    }
+
+    #[test]
+    fn test_scan_config_files() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("pacman.d")).unwrap();
+        std::fs::write(temp_dir.path().join("pacman.conf"), "").unwrap();
+        std::fs::write(temp_dir.path().join("pacman.conf.pacnew"), "").unwrap();
+        std::fs::write(temp_dir.path().join("pacman.d/mirrorlist.pacsave"), "").unwrap();
+
+        let files = scan_config_files(temp_dir.path());
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, temp_dir.path().join("pacman.conf.pacnew"));
+        assert_eq!(files[1].kind, PacnewKind::Pacsave);
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+
+        let diff = unified_diff(old, new, "old", "new");
+
+        assert_eq!(
+            diff,
+            "--- old\n+++ new\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,3 +10,4 @@\n j\n k\n l\n+m\n"
+        );
+        assert_eq!(unified_diff(old, old, "old", "new"), "");
+        assert_eq!(unified_diff("", "x\n", "old", "new"), "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n");
+    }
+
+    #[tokio::test]
+    async fn test_apply_actions() {
+        let runner = ScriptedRunner::new()
+            .on(&["sudo"], ScriptedResponse::ok(""))
+            .on(&["sudo", "sha256sum"], ScriptedResponse::ok("aaaa  /etc/pacman.conf\n"))
+            .on(&["sudo", "sha256sum"], ScriptedResponse::ok("bbbb  /etc/pacman.conf\n"))
+            .on(&["sudoedit"], ScriptedResponse::ok(""));
+        let manager = PacnewManager::with_runner(runner.clone(), PathBuf::from("/etc"), "meld".to_string());
+        let file = PacnewFile::from_path(Path::new("/etc/pacman.conf.pacnew")).unwrap();
+
+        manager.apply(&file, PacnewAction::Replace).await.unwrap();
+        manager.apply(&file, PacnewAction::Merge).await.unwrap();
+
+        let invocations: Vec<String> = runner.invocations().iter().map(ToString::to_string).collect();
+        assert_eq!(invocations, vec![
+            "sudo mv -f -- /etc/pacman.conf.pacnew /etc/pacman.conf",
+            "sudo sha256sum -- /etc/pacman.conf",
+            "sudoedit -- /etc/pacman.conf /etc/pacman.conf.pacnew",
+            "sudo sha256sum -- /etc/pacman.conf",
+            "sudo rm -f -- /etc/pacman.conf.pacnew",
+        ]);
+        assert_eq!(runner.invocations()[2].env, vec![("SUDO_EDITOR".to_string(), "meld".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_diff_reports_unreadable_original() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("foo.conf.pacnew"), "x\n").unwrap();
+        let manager = PacnewManager::with_runner(ScriptedRunner::new(), temp_dir.path().to_path_buf(), "meld".to_string());
+        let file = PacnewFile::from_path(&temp_dir.path().join("foo.conf.pacnew")).unwrap();
+
+        // Configuration active absente: tout le fichier est nouveau
+        assert!(manager.diff(&file).await.unwrap().ends_with("@@ -0,0 +1,1 @@\n+x\n"));
+
+        // Illisible: une erreur plutôt qu'un original vide
+        std::fs::create_dir(temp_dir.path().join("foo.conf")).unwrap();
+        assert!(manager.diff(&file).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_merge_keeps_pacnew() {
+        let runner = ScriptedRunner::new()
+            .on(&["sudo", "sha256sum"], ScriptedResponse::ok("aaaa  /etc/pacman.conf\n"))
+            .on(&["sudoedit"], ScriptedResponse::ok(""));
+        let manager = PacnewManager::with_runner(runner.clone(), PathBuf::from("/etc"), "meld".to_string());
+        let file = PacnewFile::from_path(Path::new("/etc/pacman.conf.pacnew")).unwrap();
+
+        let error = manager.apply(&file, PacnewAction::Merge).await.unwrap_err();
+
+        assert!(error.to_string().contains("Fusion non confirmée"));
+        assert_eq!(runner.count(&["sudo", "rm"]), 0);
+    }
+}
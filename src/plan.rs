@@ -0,0 +1,468 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::pacman::PackageUpdate;
+use crate::version::vercmp;
+
+/// Stratégie de mise à jour du système
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradeMode {
+    /// `pacman -Syu`: tout le système, sans exception (`exclude_packages` compris)
+    Full,
+    /// `pacman -Syu --ignore ...`: tout le système sauf `exclude_packages`
+    /// et les mises à jour retenues par les règles
+    #[default]
+    FullWithIgnores,
+    /// `pacman -S --needed <paquets>`: uniquement les paquets choisis et non exclus, depuis
+    /// les bases déjà synchronisées (refusée si des dépendances seraient désynchronisées)
+    Selective,
+}
+
+impl std::fmt::Display for UpgradeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpgradeMode::Full => write!(f, "Mise à jour complète"),
+            UpgradeMode::FullWithIgnores => write!(f, "Mise à jour complète avec exclusions"),
+            UpgradeMode::Selective => write!(f, "Mise à jour sélective"),
+        }
+    }
+}
+
+/// Plan de mise à jour, présenté avant exécution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpgradePlan {
+    pub mode: UpgradeMode,
+    /// Paquets qui seront mis à jour
+    pub targets: Vec<PackageUpdate>,
+    /// Mises à jour disponibles laissées de côté (exclusions ou non sélectionnées)
+    pub held_back: Vec<PackageUpdate>,
+    /// Paquets passés à `--ignore`
+    pub ignored: Vec<String>,
+    /// Dépendances qui resteraient partiellement mises à jour
+    pub partial_upgrade_warnings: Vec<String>,
+    /// Mises à jour AUR, hors transaction pacman (à construire avec un assistant AUR)
+    #[serde(default)]
+    pub aur: Vec<PackageUpdate>,
+}
+
+impl UpgradePlan {
+    /// Construit le plan à partir des mises à jour disponibles
+    ///
+    /// `selected` n'est utilisé qu'en mode sélectif. Les mises à jour AUR
+    /// sont mises à part: pacman ne sait pas les installer.
+    pub fn new(mode: UpgradeMode, available: &[PackageUpdate], selected: &[String], exclude: &[String]) -> Self {
+        let (aur, available): (Vec<PackageUpdate>, Vec<PackageUpdate>) =
+            available.iter().cloned().partition(PackageUpdate::is_aur);
+
+        let ignored: Vec<String> = match mode {
+            UpgradeMode::FullWithIgnores => {
+                let mut ignored = exclude.to_vec();
+                for update in available.iter().filter(|update| update.hold.is_some()) {
+                    if !ignored.contains(&update.name) {
+                        ignored.push(update.name.clone());
+                    }
+                }
+                ignored
+            }
+            UpgradeMode::Full | UpgradeMode::Selective => Vec::new(),
+        };
+
+        let (targets, held_back) = available.into_iter().partition(|update| match mode {
+            UpgradeMode::Full => true,
+            UpgradeMode::FullWithIgnores => !ignored.contains(&update.name),
+            UpgradeMode::Selective => selected.contains(&update.name) && !exclude.contains(&update.name),
+        });
+
+        Self {
+            mode,
+            targets,
+            held_back,
+            ignored,
+            partial_upgrade_warnings: Vec::new(),
+            aur,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Vrai si le plan laisserait des dépendances désynchronisées
+    pub fn is_partial(&self) -> bool {
+        !self.partial_upgrade_warnings.is_empty()
+    }
+
+    /// Noms des paquets ciblés
+    pub fn target_names(&self) -> Vec<String> {
+        self.targets.iter().map(|update| update.name.clone()).collect()
+    }
+
+    /// Arguments pacman de la transaction (sans `sudo pacman`)
+    pub fn pacman_args(&self) -> Vec<String> {
+        transaction_args(self.mode, &self.target_names(), &self.ignored)
+    }
+
+    /// Arguments pacman de la simulation (`-p`), sans `--print-format`
+    pub fn preview_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = match self.mode {
+            UpgradeMode::Full | UpgradeMode::FullWithIgnores => vec!["-Sup".to_string()],
+            UpgradeMode::Selective => vec!["-Sp".to_string(), "--needed".to_string()],
+        };
+
+        if !self.ignored.is_empty() {
+            args.push("--ignore".to_string());
+            args.push(self.ignored.join(","));
+        }
+        if self.mode == UpgradeMode::Selective {
+            args.extend(self.target_names());
+        }
+
+        args
+    }
+}
+
+impl std::fmt::Display for UpgradePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} — pacman {}", self.mode, self.pacman_args().join(" "))?;
+        writeln!(f, "{} paquets à mettre à jour:", self.targets.len())?;
+        for update in &self.targets {
+            writeln!(f, "  {}", update)?;
+        }
+        if !self.held_back.is_empty() {
+            writeln!(f, "{} mises à jour laissées de côté:", self.held_back.len())?;
+            for update in &self.held_back {
+                match &update.hold {
+                    Some(hold) => writeln!(f, "  {} ⏸️ {}", update, hold)?,
+                    None => writeln!(f, "  {}", update)?,
+                }
+            }
+        }
+        if !self.aur.is_empty() {
+            writeln!(f, "{} mises à jour AUR à construire avec votre assistant AUR:", self.aur.len())?;
+            for update in &self.aur {
+                writeln!(f, "  {}", update)?;
+            }
+        }
+        for warning in &self.partial_upgrade_warnings {
+            writeln!(f, "⚠️ {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Arguments pacman d'une transaction de mise à jour
+///
+/// Les cibles ne sont passées qu'en mode sélectif, qui ne rafraîchit pas les bases:
+/// `-Sy <paquets>` serait une mise à jour partielle.
+pub fn transaction_args(mode: UpgradeMode, targets: &[String], ignored: &[String]) -> Vec<String> {
+    let mut args: Vec<String> = match mode {
+        UpgradeMode::Full | UpgradeMode::FullWithIgnores => vec!["-Syu".to_string()],
+        UpgradeMode::Selective => vec!["-S".to_string(), "--needed".to_string()],
+    };
+    args.push("--noconfirm".to_string());
+
+    if !ignored.is_empty() {
+        args.push("--ignore".to_string());
+        args.push(ignored.join(","));
+    }
+    if mode == UpgradeMode::Selective {
+        args.extend(targets.iter().cloned());
+    }
+
+    args
+}
+
+/// Dépendances et fournitures d'une version de paquet (`pacman -Si`/`-Qi`),
+/// contraintes de version comprises ("nss>=3.90", "libfoo.so=1-64")
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageRelations {
+    pub depends: Vec<String>,
+    pub provides: Vec<String>,
+}
+
+impl PackageRelations {
+    /// Lit les champs "Depends On" et "Provides" d'une fiche pacman
+    pub fn from_info(fields: &HashMap<String, String>) -> Self {
+        let list = |field: &str| {
+            fields
+                .get(field)
+                .map(|value| {
+                    value.split_whitespace()
+                        .filter(|item| *item != "None")
+                        .map(ToString::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        Self {
+            depends: list("Depends On"),
+            provides: list("Provides"),
+        }
+    }
+}
+
+/// Relations de dépendance qui traversent la frontière sélection / non-sélection
+///
+/// `sync` décrit la nouvelle version des paquets sélectionnés, `local` la version
+/// installée des paquets sélectionnés et laissés de côté. Les dépendances sont
+/// résolues par nom et par fourniture (sonames compris); une contrainte de version
+/// qui ne serait plus satisfaite est signalée explicitement.
+pub fn find_partial_upgrades(
+    plan: &UpgradePlan,
+    sync: &HashMap<String, PackageRelations>,
+    local: &HashMap<String, PackageRelations>,
+) -> Vec<String> {
+    let none = PackageRelations::default();
+    let sync_of = |name: &str| sync.get(name).unwrap_or(&none);
+    let local_of = |name: &str| local.get(name).unwrap_or(&none);
+    let mut warnings = Vec::new();
+
+    // Dépendances des paquets sélectionnés fournies par un paquet laissé de côté
+    for target in &plan.targets {
+        for dependency in &sync_of(&target.name).depends {
+            let resolved = plan.targets.iter().any(|other| {
+                satisfies(&other.name, &other.new_version, &sync_of(&other.name).provides, dependency)
+            });
+            if resolved {
+                continue;
+            }
+
+            for held in &plan.held_back {
+                let installed = local_of(&held.name);
+                if !provides_name(&held.name, &installed.provides, dependency) {
+                    continue;
+                }
+                if satisfies(&held.name, &held.current_version, &installed.provides, dependency) {
+                    warnings.push(format!(
+                        "{} dépend de {}, dont la mise à jour n'est pas sélectionnée",
+                        target.name, held.name
+                    ));
+                } else {
+                    warnings.push(format!(
+                        "{} requiert {}, non satisfait par {} {} installé",
+                        target.name, dependency, held.name, held.current_version
+                    ));
+                }
+            }
+        }
+    }
+
+    // Paquets laissés de côté qui dépendent d'un paquet sélectionné
+    for held in &plan.held_back {
+        for dependency in &local_of(&held.name).depends {
+            for target in &plan.targets {
+                let upgraded = sync_of(&target.name);
+                if !provides_name(&target.name, &local_of(&target.name).provides, dependency)
+                    && !provides_name(&target.name, &upgraded.provides, dependency)
+                {
+                    continue;
+                }
+                if satisfies(&target.name, &target.new_version, &upgraded.provides, dependency) {
+                    warnings.push(format!(
+                        "{} dépend de {} mais ne sera pas mis à jour",
+                        held.name, target.name
+                    ));
+                } else {
+                    warnings.push(format!(
+                        "{} requiert {}, non satisfait par {} {}",
+                        held.name, dependency, target.name, target.new_version
+                    ));
+                }
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Découpe une dépendance pacman en nom et contrainte éventuelle ("glibc>=2.38")
+fn split_dependency(item: &str) -> (&str, Option<(&str, &str)>) {
+    let Some(index) = item.find(['<', '>', '=']) else {
+        return (item, None);
+    };
+    let (name, constraint) = item.split_at(index);
+    let operator_len = if constraint[1..].starts_with('=') { 2 } else { 1 };
+    let (operator, version) = constraint.split_at(operator_len);
+    (name, Some((operator, version)))
+}
+
+/// Vrai si `dependency` désigne ce paquet, par son nom ou une de ses fournitures
+fn provides_name(name: &str, provides: &[String], dependency: &str) -> bool {
+    let (wanted, _) = split_dependency(dependency);
+    wanted == name || provides.iter().any(|provide| split_dependency(provide).0 == wanted)
+}
+
+/// Vrai si la version `version` du paquet satisfait `dependency`, comme libalpm:
+/// une fourniture sans version ne satisfait pas une dépendance versionnée
+fn satisfies(name: &str, version: &str, provides: &[String], dependency: &str) -> bool {
+    let (wanted, constraint) = split_dependency(dependency);
+    let matches = |version: &str, (operator, required): (&str, &str)| {
+        let ordering = vercmp(version, required);
+        match operator {
+            "<" => ordering == Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" => ordering == Ordering::Greater,
+            ">=" => ordering != Ordering::Less,
+            _ => ordering == Ordering::Equal,
+        }
+    };
+
+    if wanted == name && constraint.is_none_or(|constraint| matches(version, constraint)) {
+        return true;
+    }
+    provides.iter().any(|provide| {
+        let (provided_name, provided_version) = split_dependency(provide);
+        provided_name == wanted
+            && constraint.is_none_or(|constraint| {
+                provided_version.is_some_and(|(_, version)| matches(version, constraint))
+            })
+    })
+}
+
+/// Extrait les noms d'une liste pacman ("glibc>=2.38  sh  libfoo.so=1-64"), "None" compris
+pub fn parse_package_list(value: &str) -> Vec<String> {
+    value
+        .split_whitespace()
+        .filter(|item| *item != "None")
+        .map(|item| {
+            item.split(['<', '>', '=', ':'])
+                .next()
+                .unwrap_or(item)
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hold::{Hold, HoldReason};
+
+    fn update(name: &str) -> PackageUpdate {
+        PackageUpdate::new(name.to_string(), "1.0-1".to_string(), "1.1-1".to_string())
+    }
+
+    #[test]
+    fn test_plan_modes() {
+        let available = vec![update("linux"), update("firefox"), update("nvidia-dkms")];
+        let exclude = vec!["nvidia-dkms".to_string()];
+
+        let full = UpgradePlan::new(UpgradeMode::Full, &available, &[], &exclude);
+        assert_eq!(full.targets.len(), 3);
+        assert_eq!(full.pacman_args(), vec!["-Syu", "--noconfirm"]);
+
+        let ignoring = UpgradePlan::new(UpgradeMode::FullWithIgnores, &available, &[], &exclude);
+        assert_eq!(ignoring.targets.len(), 2);
+        assert_eq!(ignoring.held_back[0].name, "nvidia-dkms");
+        assert_eq!(ignoring.pacman_args(), vec!["-Syu", "--noconfirm", "--ignore", "nvidia-dkms"]);
+
+        let selected = vec!["firefox".to_string(), "nvidia-dkms".to_string()];
+        let selective = UpgradePlan::new(UpgradeMode::Selective, &available, &selected, &exclude);
+        assert_eq!(selective.targets, vec![update("firefox")]);
+        assert_eq!(selective.pacman_args(), vec!["-S", "--needed", "--noconfirm", "firefox"]);
+        assert_eq!(ignoring.preview_args(), vec!["-Sup", "--ignore", "nvidia-dkms"]);
+        assert_eq!(selective.preview_args(), vec!["-Sp", "--needed", "firefox"]);
+    }
+
+    #[test]
+    fn test_plan_with_held_updates() {
+        let mut held = update("linux");
+        held.hold = Some(Hold {
+            rule: "linux*".to_string(),
+            reason: HoldReason::Pinned,
+            comment: None,
+            expires: None,
+        });
+        let available = vec![held, update("firefox")];
+
+        let ignoring = UpgradePlan::new(UpgradeMode::FullWithIgnores, &available, &[], &["nvidia-dkms".to_string()]);
+        assert_eq!(ignoring.ignored, vec!["nvidia-dkms", "linux"]);
+        assert_eq!(ignoring.targets, vec![update("firefox")]);
+        assert!(ignoring.to_string().contains("linux 1.0-1 -> 1.1-1 ⏸️ retenu par la règle « linux* »"));
+
+        // Le mode complet ne fait aucune exception
+        assert_eq!(UpgradePlan::new(UpgradeMode::Full, &available, &[], &[]).targets.len(), 2);
+    }
+
+    #[test]
+    fn test_plan_sets_aur_updates_aside() {
+        let mut aur = update("yay");
+        aur.repository = "aur".to_string();
+        aur.hold = Some(Hold {
+            rule: "yay".to_string(),
+            reason: HoldReason::Pinned,
+            comment: None,
+            expires: None,
+        });
+        let available = vec![update("firefox"), aur.clone()];
+
+        for mode in [UpgradeMode::Full, UpgradeMode::FullWithIgnores, UpgradeMode::Selective] {
+            let selected = vec!["firefox".to_string(), "yay".to_string()];
+            let plan = UpgradePlan::new(mode, &available, &selected, &[]);
+            assert_eq!(plan.targets, vec![update("firefox")]);
+            assert!(plan.held_back.is_empty());
+            assert!(plan.ignored.is_empty());
+            assert!(!plan.pacman_args().iter().any(|arg| arg.contains("yay")));
+            assert_eq!(plan.aur, vec![aur.clone()]);
+        }
+
+        let plan = UpgradePlan::new(UpgradeMode::Full, &available, &[], &[]);
+        assert!(plan.to_string().contains("1 mises à jour AUR à construire avec votre assistant AUR"));
+    }
+
+    #[test]
+    fn test_find_partial_upgrades() {
+        let available = vec![update("firefox"), update("nss"), update("python"), update("python-yaml")];
+        let selected = vec!["firefox".to_string(), "python".to_string()];
+        let plan = UpgradePlan::new(UpgradeMode::Selective, &available, &selected, &[]);
+
+        let relations = |depends: &str, provides: &str| PackageRelations {
+            depends: depends.split_whitespace().map(ToString::to_string).collect(),
+            provides: provides.split_whitespace().map(ToString::to_string).collect(),
+        };
+        let sync = HashMap::from([
+            ("firefox".to_string(), relations("gtk3  nss>=1.1", "")),
+            ("python".to_string(), relations("glibc  openssl", "")),
+        ]);
+        let local = HashMap::from([
+            ("python-yaml".to_string(), relations("python  libyaml", "")),
+        ]);
+
+        let warnings = find_partial_upgrades(&plan, &sync, &local);
+
+        assert_eq!(warnings, vec![
+            "firefox requiert nss>=1.1, non satisfait par nss 1.0-1 installé",
+            "python-yaml dépend de python mais ne sera pas mis à jour",
+        ]);
+    }
+
+    #[test]
+    fn test_find_partial_upgrades_through_provides() {
+        let available = vec![update("libfoo"), update("bar"), update("baz")];
+        let plan = UpgradePlan::new(UpgradeMode::Selective, &available, &["libfoo".to_string()], &[]);
+
+        let relations = |depends: &str, provides: &str| PackageRelations {
+            depends: depends.split_whitespace().map(ToString::to_string).collect(),
+            provides: provides.split_whitespace().map(ToString::to_string).collect(),
+        };
+        let sync = HashMap::from([("libfoo".to_string(), relations("", "libfoo.so=2-64"))]);
+        let local = HashMap::from([
+            ("libfoo".to_string(), relations("", "libfoo.so=1-64")),
+            ("bar".to_string(), relations("libfoo.so=1-64", "")),
+            ("baz".to_string(), relations("libfoo>=1.0", "")),
+        ]);
+
+        let warnings = find_partial_upgrades(&plan, &sync, &local);
+
+        // Le soname disparaît, la contrainte versionnée reste satisfaite
+        assert_eq!(warnings, vec![
+            "bar requiert libfoo.so=1-64, non satisfait par libfoo 1.1-1",
+            "baz dépend de libfoo mais ne sera pas mis à jour",
+        ]);
+        assert!(satisfies("libfoo", "1.1-1", &[], "libfoo>=1.1"));
+        assert!(!satisfies("libfoo", "1.1-1", &["libbar".to_string()], "libbar=1"));
+    }
+}
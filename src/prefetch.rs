@@ -0,0 +1,357 @@
+//! Préchargement des paquets
+//!
+//! Télécharge dans le cache les archives d'une mise à jour complète, sans rien
+//! installer (sémantique de `pacman -Syuw`), éventuellement avec un débit
+//! limité: les téléchargements peuvent ainsi avoir lieu la nuit et
+//! l'installation plus tard, après validation.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::cache::scan_package_cache;
+use crate::pacman::{format_size, PackageUpdate};
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+
+/// Format de `pacman -Sup`: nom, version, taille et emplacement de l'archive
+const PREFETCH_FORMAT: &str = "%n %v %s %l";
+
+/// Archive d'une mise à jour à précharger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefetchTarget {
+    pub name: String,
+    pub version: String,
+    /// Taille de l'archive en octets
+    pub size: u64,
+    /// Miroir d'où télécharger l'archive (`file://` si elle est déjà en cache)
+    pub url: String,
+    pub cached: bool,
+}
+
+impl PrefetchTarget {
+    /// Nom du fichier dans le cache
+    pub fn file_name(&self) -> &str {
+        self.url.rsplit('/').next().unwrap_or(&self.url)
+    }
+}
+
+/// Archives nécessaires à la prochaine mise à jour
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefetchPlan {
+    pub targets: Vec<PrefetchTarget>,
+}
+
+impl PrefetchPlan {
+    /// Archives encore à télécharger
+    pub fn missing(&self) -> impl Iterator<Item = &PrefetchTarget> {
+        self.targets.iter().filter(|target| !target.cached)
+    }
+
+    /// Volume restant à télécharger
+    pub fn download_size(&self) -> u64 {
+        self.missing().map(|target| target.size).sum()
+    }
+
+    /// Tout est dans le cache: la mise à jour peut s'installer sans réseau
+    #[allow(dead_code)]
+    pub fn is_ready(&self) -> bool {
+        !self.targets.is_empty() && self.missing().next().is_none()
+    }
+}
+
+impl std::fmt::Display for PrefetchPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let missing = self.missing().count();
+        if self.targets.is_empty() {
+            write!(f, "Aucune mise à jour à télécharger")
+        } else if missing == 0 {
+            write!(f, "Prêt à installer, rien à télécharger ({} paquets en cache)", self.targets.len())
+        } else {
+            write!(
+                f,
+                "{} paquets à télécharger ({}), {} déjà en cache",
+                missing,
+                format_size(self.download_size()),
+                self.targets.len() - missing
+            )
+        }
+    }
+}
+
+/// Analyse la sortie de `pacman -Sup --print-format "%n %v %s %l"`
+pub fn parse_prefetch_list(output: &str, cache_dir: &Path) -> Vec<PrefetchTarget> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (name, version, size, url) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+            let size = size.parse().ok()?;
+            let mut target = PrefetchTarget {
+                name: name.to_string(),
+                version: version.to_string(),
+                size,
+                url: url.to_string(),
+                cached: false,
+            };
+            // pacman indique file:// pour les archives déjà dans son cache
+            target.cached = url.starts_with("file://") || cache_dir.join(target.file_name()).exists();
+            Some(target)
+        })
+        .collect()
+}
+
+/// Mises à jour dont l'archive n'est pas encore dans le cache
+///
+/// Les paquets AUR et les mises à jour retenues ne sont pas concernés.
+pub fn missing_from_cache<'a>(updates: &'a [PackageUpdate], cache_dir: &Path) -> Vec<&'a PackageUpdate> {
+    let cached = scan_package_cache(cache_dir);
+    updates
+        .iter()
+        .filter(|update| update.repository != "aur" && update.hold.is_none())
+        .filter(|update| {
+            !cached
+                .iter()
+                .any(|package| package.name == update.name && package.version == update.new_version)
+        })
+        .collect()
+}
+
+/// Toutes les mises à jour installables sont déjà téléchargées
+pub fn is_ready_to_install(updates: &[PackageUpdate], cache_dir: &Path) -> bool {
+    updates.iter().any(|update| update.repository != "aur" && update.hold.is_none())
+        && missing_from_cache(updates, cache_dir).is_empty()
+}
+
+/// Téléchargement des archives dans le cache des paquets
+#[derive(Debug, Clone)]
+pub struct PrefetchManager<R: CommandRunner = SystemRunner> {
+    runner: R,
+    cache_dir: PathBuf,
+    /// Débit maximal en Kio/s (None: illimité)
+    rate_limit_kib: Option<u32>,
+    /// Écrire dans le cache avec sudo (processus sans droits sur le cache)
+    sudo: bool,
+    /// Archives téléchargées ensemble par un même curl
+    parallel_downloads: usize,
+}
+
+impl PrefetchManager {
+    #[allow(dead_code)]
+    pub fn new(cache_dir: PathBuf, rate_limit_kib: Option<u32>) -> Self {
+        Self::with_runner(SystemRunner, cache_dir, rate_limit_kib)
+    }
+}
+
+impl<R: CommandRunner> PrefetchManager<R> {
+    pub fn with_runner(runner: R, cache_dir: PathBuf, rate_limit_kib: Option<u32>) -> Self {
+        Self {
+            runner,
+            cache_dir,
+            rate_limit_kib,
+            sudo: false,
+            parallel_downloads: 1,
+        }
+    }
+
+    /// Téléchargements simultanés, comme `ParallelDownloads` de pacman.conf
+    pub fn with_parallel_downloads(mut self, parallel_downloads: u32) -> Self {
+        self.parallel_downloads = parallel_downloads.max(1) as usize;
+        self
+    }
+
+    /// Passe par sudo pour écrire dans le cache
+    pub fn with_sudo(mut self, sudo: bool) -> Self {
+        self.sudo = sudo;
+        self
+    }
+
+    /// Commande éventuellement préfixée par sudo
+    fn command(&self, program: &str) -> CommandSpec {
+        if self.sudo {
+            CommandSpec::new("sudo").arg(program)
+        } else {
+            CommandSpec::new(program)
+        }
+    }
+
+    /// Archives d'une mise à jour complète d'après les bases de synchronisation locales
+    pub async fn plan(&self, ignored: &[String]) -> Result<PrefetchPlan> {
+        let mut cmd = CommandSpec::new("pacman")
+            .env("LC_ALL", "C")
+            .args(["-Sup", "--print-format", PREFETCH_FORMAT]);
+        if !ignored.is_empty() {
+            cmd = cmd.args(["--ignore", ignored.join(",").as_str()]);
+        }
+
+        let output = self.runner.run(&cmd).await.context("Impossible d'exécuter pacman -Sup")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de pacman -Sup: {}", output.stderr.trim()));
+        }
+
+        let plan = PrefetchPlan { targets: parse_prefetch_list(&output.stdout, &self.cache_dir) };
+        info!("📥 {}", plan);
+        Ok(plan)
+    }
+
+    /// Télécharge les archives manquantes; renvoie le volume téléchargé
+    ///
+    /// Chaque archive est écrite dans un `.part` renommé une fois complet: pacman
+    /// ne voit jamais d'archive tronquée et un téléchargement interrompu reprend.
+    /// Les archives sont téléchargées par lots de `parallel_downloads`.
+    pub async fn download(&self, plan: &PrefetchPlan) -> Result<u64> {
+        let targets: Vec<&PrefetchTarget> = plan.missing().collect();
+        let mut downloaded = 0;
+        for batch in targets.chunks(self.parallel_downloads) {
+            let mut cmd = self.command("curl")
+                .args(["--fail", "--location", "--silent", "--show-error", "--continue-at", "-"]);
+            if batch.len() > 1 {
+                cmd = cmd.args(["--parallel".to_string(), "--parallel-max".to_string(), batch.len().to_string()]);
+            }
+            // --limit-rate s'applique à chaque transfert: le débit est partagé
+            if let Some(rate) = self.rate_limit_kib {
+                let rate = (rate / u32::try_from(batch.len()).unwrap_or(u32::MAX)).max(1);
+                cmd = cmd.args(["--limit-rate".to_string(), format!("{}k", rate)]);
+            }
+            for target in batch {
+                let part = self.part_path(target);
+                cmd = cmd.arg("--output").arg(&part.to_string_lossy()).arg(target.url.as_str());
+            }
+
+            let output = self.runner.run(&cmd).await.context("Impossible d'exécuter curl")?;
+            if !output.success() {
+                let names: Vec<&str> = batch.iter().map(|target| target.file_name()).collect();
+                return Err(anyhow::anyhow!(
+                    "Échec du téléchargement de {}: {}",
+                    names.join(", "),
+                    output.stderr.trim()
+                ));
+            }
+
+            for target in batch {
+                let part = self.part_path(target);
+                let path = self.cache_dir.join(target.file_name());
+                if self.sudo {
+                    let cmd = self.command("mv")
+                        .args(["-f", "--"])
+                        .arg(&part.to_string_lossy())
+                        .arg(&path.to_string_lossy());
+                    let output = self.runner.run(&cmd).await.context("Impossible d'exécuter mv")?;
+                    if !output.success() {
+                        return Err(anyhow::anyhow!("Impossible de placer {}: {}", path.display(), output.stderr.trim()));
+                    }
+                } else {
+                    tokio::fs::rename(&part, &path)
+                        .await
+                        .context(format!("Impossible de placer {}", path.display()))?;
+                }
+
+                debug!("📥 {} {} ({})", target.name, target.version, format_size(target.size));
+                downloaded += target.size;
+            }
+        }
+
+        Ok(downloaded)
+    }
+
+    fn part_path(&self, target: &PrefetchTarget) -> PathBuf {
+        let mut part = self.cache_dir.join(target.file_name()).into_os_string();
+        part.push(".part");
+        PathBuf::from(part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
+    use tempfile::TempDir;
+
+    const PRINT_OUTPUT: &str = "\
+firefox 92.0-1 61234567 https://mirror.example/extra/os/x86_64/firefox-92.0-1-x86_64.pkg.tar.zst
+linux 6.1.1.arch1-1 140000000 file:///var/cache/pacman/pkg/linux-6.1.1.arch1-1-x86_64.pkg.tar.zst
+gtk2 2.24.33-3 7000000 https://mirror.example/extra/os/x86_64/gtk2-2.24.33-3-x86_64.pkg.tar.zst
+";
+
+    #[test]
+    fn test_parse_prefetch_list() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("gtk2-2.24.33-3-x86_64.pkg.tar.zst"), "").unwrap();
+
+        let plan = PrefetchPlan { targets: parse_prefetch_list(PRINT_OUTPUT, temp_dir.path()) };
+        let cached: Vec<(&str, bool)> = plan.targets.iter().map(|t| (t.name.as_str(), t.cached)).collect();
+        assert_eq!(cached, vec![("firefox", false), ("linux", true), ("gtk2", true)]);
+        assert_eq!(plan.targets[0].file_name(), "firefox-92.0-1-x86_64.pkg.tar.zst");
+        assert_eq!(plan.download_size(), 61_234_567);
+        assert!(!plan.is_ready());
+        assert_eq!(plan.to_string(), "1 paquets à télécharger (58.40 MiB), 2 déjà en cache");
+
+        assert!(!PrefetchPlan::default().is_ready());
+        assert!(parse_prefetch_list(":: Synchronizing package databases...\n", temp_dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_ready_to_install() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut updates = vec![
+            PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string()),
+            PackageUpdate::new("yay".to_string(), "12.0-1".to_string(), "12.1-1".to_string()),
+        ];
+        updates[1].repository = "aur".to_string();
+        assert!(!is_ready_to_install(&updates, temp_dir.path()));
+        assert_eq!(missing_from_cache(&updates, temp_dir.path()).len(), 1);
+
+        // Une autre version en cache ne suffit pas
+        std::fs::write(temp_dir.path().join("firefox-91.0-1-x86_64.pkg.tar.zst"), "").unwrap();
+        assert!(!is_ready_to_install(&updates, temp_dir.path()));
+
+        std::fs::write(temp_dir.path().join("firefox-92.0-1-x86_64.pkg.tar.zst"), "").unwrap();
+        assert!(is_ready_to_install(&updates, temp_dir.path()));
+        assert!(!is_ready_to_install(&updates[1..], temp_dir.path()));
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_download() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = ScriptedRunner::new()
+            .on(&["pacman", "-Sup"], ScriptedResponse::ok(PRINT_OUTPUT))
+            .on(&["sudo", "curl"], ScriptedResponse::ok(""))
+            .on(&["sudo", "mv"], ScriptedResponse::ok(""));
+        let manager = PrefetchManager::with_runner(runner.clone(), temp_dir.path().to_path_buf(), Some(512)).with_sudo(true);
+
+        let plan = manager.plan(&["linux".to_string(), "gtk2".to_string()]).await.unwrap();
+        assert_eq!(runner.count(&["pacman", "-Sup", "--print-format", PREFETCH_FORMAT, "--ignore", "linux,gtk2"]), 1);
+        assert_eq!(manager.download(&plan).await.unwrap(), 61_234_567 + 7_000_000);
+
+        let part = temp_dir.path().join("firefox-92.0-1-x86_64.pkg.tar.zst.part");
+        assert_eq!(
+            runner.count(&[
+                "sudo", "curl", "--fail", "--location", "--silent", "--show-error", "--continue-at", "-",
+                "--limit-rate", "512k", "--output", part.to_str().unwrap(),
+                "https://mirror.example/extra/os/x86_64/firefox-92.0-1-x86_64.pkg.tar.zst",
+            ]),
+            1
+        );
+        assert_eq!(runner.count(&["sudo", "curl"]), 2);
+        assert_eq!(runner.count(&["sudo", "mv", "-f", "--", part.to_str().unwrap()]), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_parallel_download() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = ScriptedRunner::new()
+            .on(&["pacman", "-Sup"], ScriptedResponse::ok(PRINT_OUTPUT))
+            .on(&["curl"], ScriptedResponse::ok(""));
+        let manager = PrefetchManager::with_runner(runner.clone(), temp_dir.path().to_path_buf(), Some(512))
+            .with_parallel_downloads(5);
+        let plan = manager.plan(&[]).await.unwrap();
+        for target in plan.missing() {
+            std::fs::write(temp_dir.path().join(format!("{}.part", target.file_name())), "").unwrap();
+        }
+
+        assert_eq!(manager.download(&plan).await.unwrap(), 61_234_567 + 7_000_000);
+        assert_eq!(runner.count(&["curl"]), 1);
+        let invocation = runner.invocations().remove(1);
+        assert!(invocation.to_string().contains("--parallel --parallel-max 2 --limit-rate 256k"));
+        assert!(temp_dir.path().join("gtk2-2.24.33-3-x86_64.pkg.tar.zst").exists());
+    }
+}
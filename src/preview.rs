@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::pacman::{format_size, format_size_delta, parse_size, PackageUpdate};
+use crate::plan::parse_package_list;
+
+/// Format demandé à `pacman -Sp --print-format`: nom, version, dépôt, taille de téléchargement
+pub const PRINT_FORMAT: &str = "%n %v %r %s";
+
+/// Champs `pacman -Si`/`-Qi` indexés par nom de paquet
+pub type PackageInfo = HashMap<String, HashMap<String, String>>;
+
+/// Paquet téléchargé par la transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviewPackage {
+    pub name: String,
+    pub version: String,
+    pub repository: String,
+    pub download_size: u64,
+    pub installed_size: Option<u64>,
+}
+
+impl std::fmt::Display for PreviewPackage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} {}", self.repository, self.name, self.version)
+    }
+}
+
+/// Paquet installé remplacé par un nouveau paquet (champ `Replaces`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replacement {
+    pub old: String,
+    pub new: PreviewPackage,
+}
+
+/// Ce que pacman ferait, sans rien installer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionPreview {
+    pub upgrades: Vec<PackageUpdate>,
+    /// Nouveaux paquets (dépendances ajoutées)
+    pub installs: Vec<PreviewPackage>,
+    pub replacements: Vec<Replacement>,
+    /// Paquets installés supprimés pour cause de conflit
+    pub removals: Vec<String>,
+    pub download_size: u64,
+    pub installed_size_delta: i64,
+}
+
+impl TransactionPreview {
+    pub fn is_empty(&self) -> bool {
+        self.upgrades.is_empty() && self.installs.is_empty() && self.replacements.is_empty() && self.removals.is_empty()
+    }
+
+    /// Résumé sur une ligne (notifications)
+    pub fn summary(&self) -> String {
+        format!(
+            "{} mises à jour, {} installations, {} remplacements, {} suppressions — téléchargement {}, taille installée {}",
+            self.upgrades.len(),
+            self.installs.len(),
+            self.replacements.len(),
+            self.removals.len(),
+            format_size(self.download_size),
+            format_size_delta(self.installed_size_delta)
+        )
+    }
+}
+
+impl std::fmt::Display for TransactionPreview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        for update in &self.upgrades {
+            writeln!(f, "  ⬆️ {}", update)?;
+        }
+        for package in &self.installs {
+            writeln!(f, "  ➕ {}", package)?;
+        }
+        for replacement in &self.replacements {
+            writeln!(f, "  🔁 {} remplacé par {}", replacement.old, replacement.new)?;
+        }
+        for name in &self.removals {
+            writeln!(f, "  ➖ {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Paquets installés que la transaction pourrait retirer (Replaces / Conflicts With)
+pub fn removal_candidates(sync_info: &PackageInfo, installed: &HashMap<String, String>) -> Vec<String> {
+    let candidates: BTreeSet<String> = sync_info
+        .values()
+        .flat_map(|fields| {
+            ["Replaces", "Conflicts With"]
+                .into_iter()
+                .filter_map(|field| fields.get(field))
+                .flat_map(|value| parse_package_list(value))
+        })
+        .filter(|name| installed.contains_key(name))
+        .collect();
+
+    candidates.into_iter().collect()
+}
+
+/// Construit l'aperçu à partir de la sortie de `pacman -Sp --print-format PRINT_FORMAT`
+///
+/// `installed` provient de `pacman -Q`, `sync_info` de `pacman -Si` sur les cibles et
+/// `local_info` de `pacman -Qi` sur les paquets installés concernés.
+pub fn build_preview(
+    printed: &str,
+    installed: &HashMap<String, String>,
+    sync_info: &PackageInfo,
+    local_info: &PackageInfo,
+) -> TransactionPreview {
+    let installed_size = |info: &PackageInfo, name: &str| {
+        info.get(name)
+            .and_then(|fields| fields.get("Installed Size"))
+            .and_then(|size| parse_size(size))
+    };
+    let local_size = |name: &str| installed_size(local_info, name).and_then(|size| i64::try_from(size).ok()).unwrap_or(0);
+
+    let targets: Vec<PreviewPackage> = printed
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [name, version, repository, size] = fields.as_slice() else {
+                return None;
+            };
+            Some(PreviewPackage {
+                name: (*name).to_string(),
+                version: (*version).to_string(),
+                repository: (*repository).to_string(),
+                download_size: size.parse().ok()?,
+                installed_size: installed_size(sync_info, name),
+            })
+        })
+        .collect();
+    let target_names: BTreeSet<&str> = targets.iter().map(|p| p.name.as_str()).collect();
+
+    let mut preview = TransactionPreview::default();
+    let mut removed: BTreeSet<String> = BTreeSet::new();
+
+    for package in &targets {
+        preview.download_size += package.download_size;
+        preview.installed_size_delta += package.installed_size.and_then(|size| i64::try_from(size).ok()).unwrap_or(0);
+
+        if let Some(current_version) = installed.get(&package.name) {
+            let mut update = PackageUpdate::new(package.name.clone(), current_version.clone(), package.version.clone());
+            update.repository.clone_from(&package.repository);
+            update.download_size = Some(package.download_size);
+            update.installed_size_delta = package.installed_size
+                .and_then(|size| i64::try_from(size).ok())
+                .map(|size| size - local_size(&package.name));
+            preview.installed_size_delta -= local_size(&package.name);
+            preview.upgrades.push(update);
+            continue;
+        }
+
+        let replaced: Vec<String> = sync_info
+            .get(&package.name)
+            .and_then(|fields| fields.get("Replaces"))
+            .map(|value| parse_package_list(value))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|old| installed.contains_key(old) && !target_names.contains(old.as_str()))
+            .collect();
+
+        if replaced.is_empty() {
+            preview.installs.push(package.clone());
+        }
+        for old in replaced {
+            if removed.insert(old.clone()) {
+                preview.installed_size_delta -= local_size(&old);
+            }
+            preview.replacements.push(Replacement { old, new: package.clone() });
+        }
+    }
+
+    // Les conflits avec des paquets installés entraînent leur suppression
+    for package in &targets {
+        let conflicts = sync_info
+            .get(&package.name)
+            .and_then(|fields| fields.get("Conflicts With"))
+            .map(|value| parse_package_list(value))
+            .unwrap_or_default();
+        for name in conflicts {
+            if installed.contains_key(&name) && !target_names.contains(name.as_str()) && removed.insert(name.clone()) {
+                preview.installed_size_delta -= local_size(&name);
+                preview.removals.push(name);
+            }
+        }
+    }
+
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(entries: &[(&str, &[(&str, &str)])]) -> PackageInfo {
+        entries
+            .iter()
+            .map(|(name, fields)| {
+                let fields = fields.iter().map(|(k, v)| ((*k).to_string(), (*v).to_string())).collect();
+                ((*name).to_string(), fields)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_preview() {
+        let printed = "firefox 92.0-1 extra 60000000\n\
+            pipewire-jack 1:1.2.3-1 extra 100000\n\
+            libnew 1.0-1 core 2048\n\
+            ligne inattendue\n";
+        let installed: HashMap<String, String> = [("firefox", "91.0-1"), ("jack2", "1.9.22-1"), ("pulseaudio", "17.0-1")]
+            .iter()
+            .map(|(n, v)| ((*n).to_string(), (*v).to_string()))
+            .collect();
+        let sync_info = info(&[
+            ("firefox", &[("Installed Size", "240.00 MiB"), ("Replaces", "None"), ("Conflicts With", "None")]),
+            ("pipewire-jack", &[("Installed Size", "1.00 MiB"), ("Replaces", "jack2"), ("Conflicts With", "jack  jack2  pulseaudio")]),
+            ("libnew", &[("Installed Size", "8.00 KiB"), ("Replaces", "None"), ("Conflicts With", "None")]),
+        ]);
+        assert_eq!(removal_candidates(&sync_info, &installed), vec!["jack2", "pulseaudio"]);
+
+        let local_info = info(&[
+            ("firefox", &[("Installed Size", "230.00 MiB")]),
+            ("jack2", &[("Installed Size", "2.00 MiB")]),
+            ("pulseaudio", &[("Installed Size", "4.00 MiB")]),
+        ]);
+
+        let preview = build_preview(printed, &installed, &sync_info, &local_info);
+
+        assert_eq!(preview.upgrades.len(), 1);
+        assert_eq!(preview.upgrades[0].current_version, "91.0-1");
+        assert_eq!(preview.upgrades[0].installed_size_delta, Some(10 * 1024 * 1024));
+        assert_eq!(preview.installs.len(), 1);
+        assert_eq!(preview.installs[0].name, "libnew");
+        assert_eq!(preview.replacements.len(), 1);
+        assert_eq!(preview.replacements[0].old, "jack2");
+        assert_eq!(preview.removals, vec!["pulseaudio"]);
+        assert_eq!(preview.download_size, 60_102_048);
+        // +10 MiB (firefox) +1 MiB -2 MiB (jack2) +8 KiB -4 MiB (pulseaudio)
+        assert_eq!(preview.installed_size_delta, 5 * 1024 * 1024 + 8 * 1024);
+        assert!(preview.summary().starts_with("1 mises à jour, 1 installations, 1 remplacements, 1 suppressions"));
+    }
+}
@@ -0,0 +1,479 @@
+//! Suivi en temps réel des transactions pacman
+//!
+//! Les lignes produites par pacman sont analysées une à une et traduites en
+//! événements typés (synchronisation, téléchargements, vérifications,
+//! installation N/M, hooks) accompagnés d'une estimation de l'avancement.
+
+use serde::{Deserialize, Serialize};
+
+/// Étapes de vérification effectuées par pacman avant l'installation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionStep {
+    ResolvingDependencies,
+    CheckingKeyring,
+    CheckingIntegrity,
+    LoadingPackageFiles,
+    CheckingConflicts,
+    CheckingDiskSpace,
+    ProcessingChanges,
+}
+
+impl std::fmt::Display for TransactionStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ResolvingDependencies => write!(f, "Résolution des dépendances"),
+            Self::CheckingKeyring => write!(f, "Vérification du trousseau de clés"),
+            Self::CheckingIntegrity => write!(f, "Vérification de l'intégrité des paquets"),
+            Self::LoadingPackageFiles => write!(f, "Chargement des fichiers des paquets"),
+            Self::CheckingConflicts => write!(f, "Recherche de conflits de fichiers"),
+            Self::CheckingDiskSpace => write!(f, "Vérification de l'espace disque"),
+            Self::ProcessingChanges => write!(f, "Application des changements"),
+        }
+    }
+}
+
+/// Action appliquée à un paquet pendant la transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PackageAction {
+    Installing,
+    Upgrading,
+    Reinstalling,
+    Downgrading,
+    Removing,
+}
+
+impl PackageAction {
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "installing" => Some(Self::Installing),
+            "upgrading" => Some(Self::Upgrading),
+            "reinstalling" => Some(Self::Reinstalling),
+            "downgrading" => Some(Self::Downgrading),
+            "removing" => Some(Self::Removing),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PackageAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Installing => write!(f, "Installation"),
+            Self::Upgrading => write!(f, "Mise à jour"),
+            Self::Reinstalling => write!(f, "Réinstallation"),
+            Self::Downgrading => write!(f, "Rétrogradation"),
+            Self::Removing => write!(f, "Suppression"),
+        }
+    }
+}
+
+/// Événement typé extrait de la sortie de pacman
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionEvent {
+    /// ":: Synchronizing package databases..."
+    SyncStarted,
+    /// Téléchargement ou vérification d'une base de synchronisation
+    DatabaseSync { repository: String },
+    /// "Packages (N) ..."
+    PackageCount(usize),
+    /// ":: Retrieving packages..."
+    DownloadStarted,
+    /// Téléchargement d'un fichier de paquet
+    Download { package: String, current: usize, total: usize },
+    Step(TransactionStep),
+    /// "(N/M) upgrading foo"
+    Package { action: PackageAction, package: String, current: usize, total: usize },
+    /// ":: Running pre/post-transaction hooks..."
+    HooksStarted { post: bool },
+    /// "(N/M) Arming ConditionNeedsUpdate..."
+    Hook { description: String, current: usize, total: usize },
+    /// Attente de la libération de la base pacman (`db.lck`)
+    WaitingForLock { holders: Vec<String>, waited: u64 },
+    Warning(String),
+    Error(String),
+    /// Ligne non reconnue
+    Output(String),
+}
+
+impl std::fmt::Display for TransactionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SyncStarted => write!(f, "Synchronisation des bases de données"),
+            Self::DatabaseSync { repository } => write!(f, "Base {repository}"),
+            Self::PackageCount(count) => write!(f, "{count} paquets dans la transaction"),
+            Self::DownloadStarted => write!(f, "Téléchargement des paquets"),
+            Self::Download { package, current, total } => {
+                write!(f, "Téléchargement ({current}/{total}) {package}")
+            }
+            Self::Step(step) => write!(f, "{step}"),
+            Self::Package { action, package, current, total } => {
+                write!(f, "{action} ({current}/{total}) {package}")
+            }
+            Self::HooksStarted { post: false } => write!(f, "Exécution des hooks de pré-transaction"),
+            Self::HooksStarted { post: true } => write!(f, "Exécution des hooks de post-transaction"),
+            Self::Hook { description, current, total } => {
+                write!(f, "Hook ({current}/{total}) {description}")
+            }
+            Self::WaitingForLock { holders, waited } if holders.is_empty() => {
+                write!(f, "Base pacman verrouillée, attente ({waited}s)")
+            }
+            Self::WaitingForLock { holders, waited } => {
+                write!(f, "Base pacman verrouillée par {}, attente ({waited}s)", holders.join(", "))
+            }
+            Self::Warning(message) => write!(f, "⚠️ {message}"),
+            Self::Error(message) => write!(f, "❌ {message}"),
+            Self::Output(line) => write!(f, "{line}"),
+        }
+    }
+}
+
+/// Rapport de progression publié pour chaque ligne de pacman
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressReport {
+    pub event: TransactionEvent,
+    /// Avancement estimé de la transaction, entre 0 et 1
+    pub fraction: f32,
+    /// Ligne brute émise par pacman
+    pub line: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Preparing,
+    Syncing,
+    Downloading,
+    Checking,
+    Installing,
+    PreHooks,
+    PostHooks,
+}
+
+/// Analyseur incrémental de la sortie de pacman
+#[derive(Debug, Clone)]
+pub struct ProgressParser {
+    phase: Phase,
+    package_count: usize,
+    downloads: usize,
+    installed: usize,
+    fraction: f32,
+}
+
+impl Default for ProgressParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressParser {
+    pub fn new() -> Self {
+        Self {
+            phase: Phase::Preparing,
+            package_count: 0,
+            downloads: 0,
+            installed: 0,
+            fraction: 0.0,
+        }
+    }
+
+    /// Avancement estimé après la dernière ligne analysée
+    #[allow(dead_code)]
+    pub fn fraction(&self) -> f32 {
+        self.fraction
+    }
+
+    /// Analyse une ligne et produit le rapport correspondant
+    pub fn parse_line(&mut self, line: &str) -> ProgressReport {
+        let event = self.parse_event(line);
+        self.update_fraction(&event);
+
+        ProgressReport {
+            event,
+            fraction: self.fraction,
+            line: line.to_string(),
+        }
+    }
+
+    fn parse_event(&mut self, line: &str) -> TransactionEvent {
+        let trimmed = line.trim();
+
+        if let Some(message) = trimmed.strip_prefix("error:") {
+            return TransactionEvent::Error(message.trim().to_string());
+        }
+        if let Some(message) = trimmed.strip_prefix("warning:") {
+            return TransactionEvent::Warning(message.trim().to_string());
+        }
+
+        if let Some(header) = trimmed.strip_prefix("::") {
+            let header = header.trim();
+            if header.starts_with("Synchronizing package databases") {
+                self.phase = Phase::Syncing;
+                return TransactionEvent::SyncStarted;
+            }
+            if header.starts_with("Retrieving packages") {
+                self.phase = Phase::Downloading;
+                return TransactionEvent::DownloadStarted;
+            }
+            if header.starts_with("Processing package changes") {
+                self.phase = Phase::Installing;
+                return TransactionEvent::Step(TransactionStep::ProcessingChanges);
+            }
+            if header.starts_with("Running pre-transaction hooks") {
+                self.phase = Phase::PreHooks;
+                return TransactionEvent::HooksStarted { post: false };
+            }
+            if header.starts_with("Running post-transaction hooks") {
+                self.phase = Phase::PostHooks;
+                return TransactionEvent::HooksStarted { post: true };
+            }
+            if self.phase == Phase::Syncing {
+                self.phase = Phase::Preparing;
+            }
+            return TransactionEvent::Output(trimmed.to_string());
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("Packages (") {
+            if let Some(count) = rest.split(')').next().and_then(|n| n.parse().ok()) {
+                self.package_count = count;
+                return TransactionEvent::PackageCount(count);
+            }
+        }
+
+        if let Some(step) = Self::parse_step(trimmed) {
+            self.phase = if step == TransactionStep::ResolvingDependencies {
+                Phase::Preparing
+            } else {
+                Phase::Checking
+            };
+            return TransactionEvent::Step(step);
+        }
+
+        if let Some((current, total, text)) = parse_counter(trimmed) {
+            return self.parse_counted(current, total, text);
+        }
+
+        // Sortie non interactive: " firefox-92.0-1-x86_64 downloading..."
+        // ou "downloading firefox-92.0-1-x86_64.pkg.tar.zst..."
+        let download_target = trimmed
+            .strip_suffix(" downloading...")
+            .or_else(|| trimmed.strip_prefix("downloading ").map(|t| t.trim_end_matches("...")));
+        if let Some(target) = download_target {
+            let target = target.trim().to_string();
+            if self.phase == Phase::Syncing || target.ends_with(".db") {
+                return TransactionEvent::DatabaseSync {
+                    repository: target.trim_end_matches(".db").to_string(),
+                };
+            }
+            self.phase = Phase::Downloading;
+            self.downloads += 1;
+            return TransactionEvent::Download {
+                package: target,
+                current: self.downloads,
+                total: self.package_count.max(self.downloads),
+            };
+        }
+
+        if self.phase == Phase::Syncing {
+            if let Some(repository) = trimmed.strip_suffix(" is up to date") {
+                return TransactionEvent::DatabaseSync {
+                    repository: repository.to_string(),
+                };
+            }
+        }
+
+        // Sortie non interactive sans compteur: "upgrading firefox..."
+        if let Some((word, package)) = trimmed.split_once(' ') {
+            if let Some(action) = PackageAction::parse(word) {
+                self.installed += 1;
+                return self.package_event(action, package.trim_end_matches("..."), self.installed, 0);
+            }
+        }
+
+        TransactionEvent::Output(trimmed.to_string())
+    }
+
+    fn parse_counted(&mut self, current: usize, total: usize, text: &str) -> TransactionEvent {
+        if let Some((word, package)) = text.split_once(' ') {
+            if let Some(action) = PackageAction::parse(word) {
+                self.installed = current;
+                return self.package_event(action, package.trim_end_matches("..."), current, total);
+            }
+        }
+
+        if matches!(self.phase, Phase::PreHooks | Phase::PostHooks) {
+            return TransactionEvent::Hook {
+                description: text.trim_end_matches("...").to_string(),
+                current,
+                total,
+            };
+        }
+
+        // "(1/3) checking package integrity" et équivalents
+        if let Some(step) = Self::parse_step(text) {
+            self.phase = Phase::Checking;
+            return TransactionEvent::Step(step);
+        }
+
+        TransactionEvent::Output(format!("({current}/{total}) {text}"))
+    }
+
+    fn package_event(&mut self, action: PackageAction, package: &str, current: usize, total: usize) -> TransactionEvent {
+        self.phase = Phase::Installing;
+        let total = if total == 0 { self.package_count.max(current) } else { total };
+        self.package_count = self.package_count.max(total);
+
+        TransactionEvent::Package {
+            action,
+            package: package.trim().to_string(),
+            current,
+            total,
+        }
+    }
+
+    fn parse_step(text: &str) -> Option<TransactionStep> {
+        let text = text.trim_end_matches("...");
+        match text {
+            "resolving dependencies" => Some(TransactionStep::ResolvingDependencies),
+            "checking keyring" | "checking keys in keyring" => Some(TransactionStep::CheckingKeyring),
+            "checking package integrity" => Some(TransactionStep::CheckingIntegrity),
+            "loading package files" => Some(TransactionStep::LoadingPackageFiles),
+            "checking for file conflicts" => Some(TransactionStep::CheckingConflicts),
+            "checking available disk space" => Some(TransactionStep::CheckingDiskSpace),
+            _ => None,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn update_fraction(&mut self, event: &TransactionEvent) {
+        let ratio = |current: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                (current as f32 / total as f32).min(1.0)
+            }
+        };
+
+        let fraction = match event {
+            TransactionEvent::SyncStarted | TransactionEvent::DatabaseSync { .. } => 0.02,
+            TransactionEvent::DownloadStarted => 0.05,
+            TransactionEvent::Download { current, total, .. } => 0.05 + 0.40 * ratio(*current, *total),
+            TransactionEvent::Step(TransactionStep::ProcessingChanges) => 0.50,
+            TransactionEvent::Step(TransactionStep::ResolvingDependencies) => 0.03,
+            TransactionEvent::Step(_) => 0.45,
+            TransactionEvent::Package { current, total, .. } => 0.50 + 0.40 * ratio(*current, *total),
+            TransactionEvent::HooksStarted { post: false } => 0.50,
+            TransactionEvent::HooksStarted { post: true } => 0.90,
+            TransactionEvent::Hook { current, total, .. } if self.phase == Phase::PostHooks => {
+                0.90 + 0.10 * ratio(*current, *total)
+            }
+            _ => self.fraction,
+        };
+
+        // L'avancement ne recule jamais
+        self.fraction = self.fraction.max(fraction);
+    }
+}
+
+/// Découpe un préfixe "(N/M) texte"
+fn parse_counter(line: &str) -> Option<(usize, usize, &str)> {
+    let rest = line.strip_prefix('(')?;
+    let (counter, text) = rest.split_once(')')?;
+    let (current, total) = counter.trim().split_once('/')?;
+
+    Some((
+        current.trim().parse().ok()?,
+        total.trim().parse().ok()?,
+        text.trim(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACMAN_OUTPUT: &str = ":: Synchronizing package databases...
+ core downloading...
+ extra is up to date
+:: Starting full system upgrade...
+resolving dependencies...
+looking for conflicting packages...
+
+Packages (2) firefox-92.0-1  linux-6.1.2-1
+
+Total Download Size:   190.00 MiB
+:: Retrieving packages...
+ firefox-92.0-1-x86_64 downloading...
+ linux-6.1.2-1-x86_64 downloading...
+(2/2) checking keys in keyring
+(2/2) checking package integrity
+(2/2) loading package files
+(2/2) checking for file conflicts
+(2/2) checking available disk space
+:: Running pre-transaction hooks...
+(1/1) Creating snapshot...
+:: Processing package changes...
+(1/2) upgrading firefox
+warning: /etc/firefox.conf installed as /etc/firefox.conf.pacnew
+(2/2) upgrading linux
+:: Running post-transaction hooks...
+(1/2) Arming ConditionNeedsUpdate...
+(2/2) Updating module dependencies...";
+
+    #[test]
+    fn test_parse_full_transaction() {
+        let mut parser = ProgressParser::new();
+        let reports: Vec<ProgressReport> = PACMAN_OUTPUT.lines().map(|l| parser.parse_line(l)).collect();
+        let events: Vec<&TransactionEvent> = reports.iter().map(|r| &r.event).collect();
+
+        assert_eq!(events[0], &TransactionEvent::SyncStarted);
+        assert_eq!(events[1], &TransactionEvent::DatabaseSync { repository: "core".to_string() });
+        assert_eq!(events[2], &TransactionEvent::DatabaseSync { repository: "extra".to_string() });
+        assert!(events.contains(&&TransactionEvent::PackageCount(2)));
+        assert!(events.contains(&&TransactionEvent::Download {
+            package: "linux-6.1.2-1-x86_64".to_string(),
+            current: 2,
+            total: 2,
+        }));
+        assert!(events.contains(&&TransactionEvent::Step(TransactionStep::CheckingIntegrity)));
+        assert!(events.contains(&&TransactionEvent::Hook {
+            description: "Creating snapshot".to_string(),
+            current: 1,
+            total: 1,
+        }));
+        assert!(events.contains(&&TransactionEvent::Package {
+            action: PackageAction::Upgrading,
+            package: "firefox".to_string(),
+            current: 1,
+            total: 2,
+        }));
+        assert!(events.contains(&&TransactionEvent::Warning(
+            "/etc/firefox.conf installed as /etc/firefox.conf.pacnew".to_string()
+        )));
+        assert_eq!(events.last().unwrap(), &&TransactionEvent::Hook {
+            description: "Updating module dependencies".to_string(),
+            current: 2,
+            total: 2,
+        });
+
+        // L'avancement est monotone et se termine à 100%
+        assert!(reports.windows(2).all(|w| w[0].fraction <= w[1].fraction));
+        assert!((parser.fraction() - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_parse_uncounted_output() {
+        let mut parser = ProgressParser::new();
+        parser.parse_line("Packages (3) a-1 b-1 c-1");
+        parser.parse_line("downloading a-1-x86_64.pkg.tar.zst...");
+
+        let report = parser.parse_line("upgrading a...");
+        assert_eq!(report.event, TransactionEvent::Package {
+            action: PackageAction::Upgrading,
+            package: "a".to_string(),
+            current: 1,
+            total: 3,
+        });
+        assert_eq!(parser.parse_line("error: failed to commit transaction").event,
+            TransactionEvent::Error("failed to commit transaction".to_string()));
+        assert_eq!(parser.parse_line("quelque chose").event, TransactionEvent::Output("quelque chose".to_string()));
+    }
+}
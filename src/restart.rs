@@ -0,0 +1,433 @@
+//! Détection des redémarrages nécessaires après une mise à jour
+//!
+//! Deux situations sont repérées: le noyau en cours d'exécution n'a plus ses
+//! modules (`/usr/lib/modules/$(uname -r)` supprimé par la mise à jour), et des
+//! processus exécutent encore du code supprimé ou remplacé sur le disque
+//! (`/proc/*/maps`). Ces processus sont rattachés à leur unité systemd pour
+//! pouvoir être redémarrés.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use tracing::{debug, info, warn};
+
+use crate::helper::HelperError;
+use crate::hold::glob_to_regex;
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+
+/// Modules des noyaux installés, relatif à la racine
+const MODULES_DIR: &str = "usr/lib/modules";
+
+/// Seul le code des paquets compte: les fichiers temporaires et mémoires partagées sont ignorés
+const WATCHED_PREFIXES: [&str; 2] = ["/usr/", "/opt/"];
+
+/// Ligne de `/proc/<pid>/maps`: adresse, droits, offset, périphérique, inode, chemin
+static MAPS_LINE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\S+\s+(\S+)\s+\S+\s+\S+\s+(\d+)\s+(/.*)$").unwrap());
+
+/// Processus exécutant des fichiers supprimés ou remplacés
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleProcess {
+    pub pid: u32,
+    pub name: String,
+    /// Unité systemd du processus (service système ou utilisateur)
+    pub unit: Option<String>,
+    pub user_unit: bool,
+    pub files: Vec<String>,
+}
+
+impl std::fmt::Display for StaleProcess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.pid)?;
+        if let Some(unit) = &self.unit {
+            write!(f, " [{}{}]", if self.user_unit { "utilisateur: " } else { "" }, unit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Résultat de l'analyse après une mise à jour
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestartReport {
+    pub running_kernel: String,
+    /// Noyaux dont les modules sont installés
+    pub installed_kernels: Vec<String>,
+    pub reboot_reasons: Vec<String>,
+    pub processes: Vec<StaleProcess>,
+}
+
+impl RestartReport {
+    pub fn reboot_required(&self) -> bool {
+        !self.reboot_reasons.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reboot_reasons.is_empty() && self.processes.is_empty()
+    }
+
+    /// Services système à redémarrer, sans doublon
+    pub fn units(&self) -> Vec<String> {
+        self.processes
+            .iter()
+            .filter(|process| !process.user_unit)
+            .filter_map(|process| process.unit.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Résumé conservé dans l'historique
+    pub fn needs(&self, restarted: Vec<String>) -> RestartNeeds {
+        RestartNeeds {
+            reboot_reasons: self.reboot_reasons.clone(),
+            units: self.units(),
+            processes: self.processes.len(),
+            restarted,
+        }
+    }
+}
+
+impl std::fmt::Display for RestartReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "✅ Aucun redémarrage nécessaire (noyau {})", self.running_kernel);
+        }
+        for reason in &self.reboot_reasons {
+            writeln!(f, "🔁 Redémarrage requis: {}", reason)?;
+        }
+        if !self.processes.is_empty() {
+            writeln!(f, "♻️ {} processus utilisent des fichiers supprimés ou remplacés:", self.processes.len())?;
+            for process in &self.processes {
+                writeln!(f, "  {}", process)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Redémarrages nécessaires, enregistrés avec l'opération qui les a provoqués
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestartNeeds {
+    pub reboot_reasons: Vec<String>,
+    /// Services système utilisant des fichiers supprimés
+    pub units: Vec<String>,
+    /// Nombre de processus concernés, services compris
+    pub processes: usize,
+    /// Services redémarrés automatiquement
+    #[serde(default)]
+    pub restarted: Vec<String>,
+}
+
+impl RestartNeeds {
+    pub fn is_empty(&self) -> bool {
+        self.reboot_reasons.is_empty() && self.processes == 0
+    }
+}
+
+impl std::fmt::Display for RestartNeeds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if !self.reboot_reasons.is_empty() {
+            parts.push(format!("redémarrage requis ({})", self.reboot_reasons.join("; ")));
+        }
+        let pending: Vec<&String> = self.units.iter().filter(|unit| !self.restarted.contains(unit)).collect();
+        if !pending.is_empty() {
+            parts.push(format!("{} services à redémarrer", pending.len()));
+        }
+        if !self.restarted.is_empty() {
+            parts.push(format!("{} services redémarrés", self.restarted.len()));
+        }
+        let others = self.processes.saturating_sub(self.units.len());
+        if pending.is_empty() && others > 0 {
+            parts.push(format!("{} processus à relancer", others));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Unité systemd d'un processus, d'après `/proc/<pid>/cgroup`
+fn parse_cgroup_unit(cgroup: &str) -> Option<(String, bool)> {
+    // cgroup v2: une seule ligne "0::/system.slice/sshd.service"
+    let path = cgroup.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let unit = path.rsplit('/').find(|component| component.ends_with(".service"))?;
+    let user_unit = path.split('/').any(|component| component.starts_with("user@"));
+    Some((unit.to_string(), user_unit))
+}
+
+/// Analyse les redémarrages nécessaires
+#[derive(Debug, Clone)]
+pub struct RestartAnalyzer<R: CommandRunner = SystemRunner> {
+    runner: R,
+    /// Racine du système (contient proc/ et usr/lib/modules)
+    root: PathBuf,
+    /// Socket de cachypac-helper: les redémarrages n'y sont pas proposés, sudo n'est pas utilisé
+    helper_socket: Option<PathBuf>,
+}
+
+impl RestartAnalyzer {
+    pub fn new() -> Self {
+        Self::with_runner(SystemRunner, PathBuf::from("/"))
+    }
+}
+
+impl Default for RestartAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: CommandRunner> RestartAnalyzer<R> {
+    pub fn with_runner(runner: R, root: PathBuf) -> Self {
+        Self { runner, root, helper_socket: None }
+    }
+
+    /// Refuse les redémarrages plutôt que de passer par sudo lorsque cachypac-helper est configuré
+    pub fn with_helper(mut self, helper_socket: Option<PathBuf>) -> Self {
+        self.helper_socket = helper_socket;
+        self
+    }
+
+    /// Chemin absolu du système, résolu sous la racine analysée
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    /// Version du noyau en cours d'exécution (`uname -r`)
+    async fn running_kernel(&self) -> Result<String> {
+        let output = self.runner
+            .run(&CommandSpec::new("uname").arg("-r"))
+            .await
+            .context("Impossible d'exécuter uname")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de uname -r: {}", output.stderr.trim()));
+        }
+        Ok(output.stdout.trim().to_string())
+    }
+
+    /// Noyaux installés: répertoires de modules contenant une image `vmlinuz`
+    fn installed_kernels(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(self.root.join(MODULES_DIR)) else {
+            return Vec::new();
+        };
+        let mut kernels: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().join("vmlinuz").is_file())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        kernels.sort();
+        kernels
+    }
+
+    /// Fichiers exécutables supprimés ou remplacés, mappés par un processus
+    fn stale_files(&self, maps: &str) -> Vec<String> {
+        let mut files = BTreeSet::new();
+        for captures in maps.lines().filter_map(|line| MAPS_LINE_RE.captures(line)) {
+            if !captures[1].contains('x') {
+                continue;
+            }
+            let path = &captures[3];
+            let (path, deleted) = match path.strip_suffix(" (deleted)") {
+                Some(path) => (path, true),
+                None => (path, false),
+            };
+            if !WATCHED_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+                continue;
+            }
+
+            // Un fichier remplacé garde son chemin mais change d'inode
+            let replaced = || {
+                let inode: u64 = captures[2].parse().unwrap_or(0);
+                std::fs::metadata(self.resolve(path)).is_ok_and(|metadata| inode != 0 && metadata.ino() != inode)
+            };
+            if deleted || replaced() {
+                files.insert(path.to_string());
+            }
+        }
+        files.into_iter().collect()
+    }
+
+    /// Parcourt `/proc` à la recherche des processus concernés
+    fn stale_processes(&self) -> Vec<StaleProcess> {
+        let proc_dir = self.root.join("proc");
+        let Ok(entries) = std::fs::read_dir(&proc_dir) else {
+            warn!("⚠️ Impossible de lire {}", proc_dir.display());
+            return Vec::new();
+        };
+
+        let mut processes: Vec<StaleProcess> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| Some((entry.file_name().to_str()?.parse::<u32>().ok()?, entry.path())))
+            .filter_map(|(pid, dir)| {
+                // Les processus d'autres utilisateurs sont illisibles sans droits
+                let maps = std::fs::read_to_string(dir.join("maps")).ok()?;
+                let files = self.stale_files(&maps);
+                if files.is_empty() {
+                    return None;
+                }
+                let name = std::fs::read_to_string(dir.join("comm")).unwrap_or_default().trim().to_string();
+                let unit = std::fs::read_to_string(dir.join("cgroup")).ok().and_then(|cgroup| parse_cgroup_unit(&cgroup));
+                Some(StaleProcess {
+                    pid,
+                    name,
+                    user_unit: unit.as_ref().is_some_and(|(_, user)| *user),
+                    unit: unit.map(|(unit, _)| unit),
+                    files,
+                })
+            })
+            .collect();
+        processes.sort_by_key(|process| process.pid);
+        processes
+    }
+
+    /// Analyse complète: noyau puis processus
+    pub async fn analyze(&self) -> Result<RestartReport> {
+        let running_kernel = self.running_kernel().await?;
+        let analyzer = self.clone();
+        let (installed_kernels, processes) = tokio::task::spawn_blocking(move || {
+            (analyzer.installed_kernels(), analyzer.stale_processes())
+        })
+        .await
+        .context("Échec de l'analyse des processus")?;
+
+        let mut reboot_reasons = Vec::new();
+        if !installed_kernels.contains(&running_kernel) {
+            reboot_reasons.push(format!(
+                "noyau {} en cours d'exécution, modules installés: {}",
+                running_kernel,
+                if installed_kernels.is_empty() { "aucun".to_string() } else { installed_kernels.join(", ") }
+            ));
+        }
+        // systemd ne peut pas être redémarré comme un service
+        if let Some(init) = processes.iter().find(|process| process.pid == 1) {
+            reboot_reasons.push(format!("{} (PID 1) utilise des bibliothèques remplacées", init.name));
+        }
+
+        let report = RestartReport { running_kernel, installed_kernels, reboot_reasons, processes };
+        if report.is_empty() {
+            debug!("✅ Aucun redémarrage nécessaire");
+        } else {
+            info!("🔁 {}", report.needs(Vec::new()));
+        }
+        Ok(report)
+    }
+
+    /// Redémarre les services concernés, hors motifs exclus; renvoie les services redémarrés
+    pub async fn restart_units(&self, report: &RestartReport, never_restart: &[String]) -> Result<Vec<String>> {
+        let excluded: Vec<Regex> = never_restart
+            .iter()
+            .map(|pattern| Regex::new(&glob_to_regex(pattern)).context(format!("Motif invalide: {}", pattern)))
+            .collect::<Result<_>>()?;
+        let units: Vec<String> = report.units()
+            .into_iter()
+            .filter(|unit| !excluded.iter().any(|re| re.is_match(unit)))
+            .collect();
+        if units.is_empty() {
+            return Ok(units);
+        }
+        if self.helper_socket.is_some() {
+            return Err(HelperError::Unsupported("Le redémarrage des services".to_string()).into());
+        }
+
+        info!("♻️ Redémarrage des services: {}", units.join(", "));
+        let output = self.runner
+            .run(&CommandSpec::new("sudo").args(["systemctl", "try-restart", "--"]).args(&units))
+            .await
+            .context("Impossible d'exécuter systemctl")?;
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec du redémarrage des services: {}", output.stderr.trim()));
+        }
+        Ok(units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn process(root: &Path, pid: u32, name: &str, cgroup: &str, maps: &str) {
+        write(root, &format!("proc/{pid}/comm"), &format!("{name}\n"));
+        write(root, &format!("proc/{pid}/cgroup"), cgroup);
+        write(root, &format!("proc/{pid}/maps"), maps);
+    }
+
+    #[test]
+    fn test_parse_cgroup_unit() {
+        assert_eq!(parse_cgroup_unit("0::/system.slice/sshd.service\n"), Some(("sshd.service".to_string(), false)));
+        assert_eq!(
+            parse_cgroup_unit("0::/user.slice/user-1000.slice/user@1000.service/app.slice/pipewire.service\n"),
+            Some(("pipewire.service".to_string(), true))
+        );
+        assert_eq!(parse_cgroup_unit("0::/user.slice/user-1000.slice/session-2.scope\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_analyze() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write(root, "usr/lib/modules/6.10.2-arch1-1/vmlinuz", "");
+        write(root, "usr/lib/modules/extramodules-6.10-arch/nvidia.ko", "");
+        write(root, "usr/lib/libssl.so.3", "");
+        let inode = std::fs::metadata(root.join("usr/lib/libssl.so.3")).unwrap().ino();
+
+        process(root, 1, "systemd", "0::/init.scope\n",
+            "55d0c0a00000-55d0c0a20000 r-xp 00000000 00:1f 1234 /usr/lib/libc.so.6 (deleted)\n");
+        process(root, 412, "sshd", "0::/system.slice/sshd.service\n", &format!(
+            "7f0000000000-7f0000100000 r-xp 00000000 00:1f 99999999 /usr/lib/libssl.so.3\n\
+             7f0000200000-7f0000300000 r--p 00000000 00:1f 4321 /usr/share/locale/locale-archive (deleted)\n\
+             7f0000400000-7f0000500000 r-xp 00000000 00:1f {inode} /usr/lib/libssl.so.3\n"
+        ));
+        process(root, 900, "nginx", "0::/system.slice/nginx.service\n",
+            &format!("7f0000000000-7f0000100000 r-xp 00000000 00:1f {inode} /usr/lib/libssl.so.3\n"));
+        process(root, 1500, "pipewire", "0::/user.slice/user-1000.slice/user@1000.service/app.slice/pipewire.service\n",
+            "7f0000000000-7f0000100000 r-xp 00000000 00:01 77 /memfd:pipewire (deleted)\n\
+             7f0000100000-7f0000200000 r-xp 00000000 00:1f 78 /usr/bin/pipewire (deleted)\n");
+
+        let runner = ScriptedRunner::new()
+            .on(&["uname", "-r"], ScriptedResponse::ok("6.9.7-arch1-1\n"))
+            .on(&["sudo", "systemctl"], ScriptedResponse::ok(""));
+        let analyzer = RestartAnalyzer::with_runner(runner.clone(), root.to_path_buf());
+        let report = analyzer.analyze().await.unwrap();
+
+        assert_eq!(report.installed_kernels, vec!["6.10.2-arch1-1"]);
+        assert!(report.reboot_required());
+        assert!(report.reboot_reasons[0].contains("6.9.7-arch1-1"));
+        assert!(report.reboot_reasons[1].starts_with("systemd (PID 1)"));
+        // Les données non exécutables et les mémoires anonymes sont ignorées
+        let pids: Vec<u32> = report.processes.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, vec![1, 412, 1500]);
+        assert_eq!(report.processes[1].files, vec!["/usr/lib/libssl.so.3"]);
+        assert_eq!(report.units(), vec!["sshd.service"]);
+
+        let restarted = analyzer.restart_units(&report, &["ssh*".to_string()]).await.unwrap();
+        assert!(restarted.is_empty());
+        let restarted = analyzer.restart_units(&report, &[]).await.unwrap();
+        assert_eq!(restarted, vec!["sshd.service"]);
+        assert_eq!(runner.count(&["sudo", "systemctl", "try-restart", "--", "sshd.service"]), 1);
+
+        // cachypac-helper ne redémarre pas de services: refus plutôt que sudo
+        let error = analyzer.clone()
+            .with_helper(Some(PathBuf::from("/run/cachypac/helper.sock")))
+            .restart_units(&report, &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(error.downcast_ref::<HelperError>(), Some(HelperError::Unsupported(_))));
+        assert_eq!(runner.count(&["sudo"]), 1);
+
+        let needs = report.needs(restarted);
+        assert_eq!(needs.processes, 3);
+        assert!(needs.to_string().contains("1 services redémarrés"));
+    }
+}
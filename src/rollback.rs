@@ -0,0 +1,360 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::cache::{scan_package_cache, CachedPackage};
+use crate::config::PacmanConfig;
+use crate::helper::{HelperClient, HelperRequest};
+use crate::history::{HistoryEntry, OperationType, UpdateHistory};
+use crate::localdb::LocalDatabase;
+use crate::pacman::PackageUpdate;
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+
+/// Paquet à ramener à sa version précédente
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollbackTarget {
+    pub name: String,
+    /// Version installée par la mise à jour
+    pub from_version: String,
+    /// Version d'avant la mise à jour
+    pub to_version: String,
+    pub file: PathBuf,
+}
+
+/// Retour arrière calculé pour une entrée d'historique
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollbackPlan {
+    /// Entrée d'historique annulée
+    pub entry_id: Uuid,
+    pub targets: Vec<RollbackTarget>,
+    /// Versions précédentes absentes du cache
+    pub missing: Vec<PackageUpdate>,
+}
+
+impl RollbackPlan {
+    /// Vrai si l'opération n'a laissé aucun paquet dans sa nouvelle version
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty() && self.missing.is_empty()
+    }
+
+    /// Transitions inverses, pour l'entrée d'historique du retour arrière
+    fn reverted_updates(&self) -> Vec<PackageUpdate> {
+        self.targets
+            .iter()
+            .map(|target| PackageUpdate::new(
+                target.name.clone(),
+                target.from_version.clone(),
+                target.to_version.clone(),
+            ))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for RollbackPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Aucun paquet à restaurer");
+        }
+        writeln!(f, "Retour arrière de {} paquets:", self.targets.len())?;
+        for target in &self.targets {
+            writeln!(f, "  {} {} → {}", target.name, target.from_version, target.to_version)?;
+        }
+        if !self.missing.is_empty() {
+            writeln!(f, "{} versions absentes du cache:", self.missing.len())?;
+            for update in &self.missing {
+                writeln!(f, "  {} {}", update.name, update.current_version)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ramène les paquets d'une mise à jour passée à leurs versions précédentes
+#[derive(Debug, Clone)]
+pub struct RollbackManager<R: CommandRunner = SystemRunner> {
+    runner: R,
+    cache_dir: PathBuf,
+    local_db: LocalDatabase,
+    /// Socket de cachypac-helper, qui installe alors les archives à la place de sudo
+    helper_socket: Option<PathBuf>,
+}
+
+impl RollbackManager {
+    /// Cache, base locale et helper de la configuration pacman
+    pub fn from_config(config: &PacmanConfig) -> Self {
+        Self::with_runner(SystemRunner, config.cache_dir.clone())
+            .with_local_db(LocalDatabase::new(config.db_path.clone()))
+            .with_helper(config.helper_socket.clone())
+    }
+}
+
+impl<R: CommandRunner> RollbackManager<R> {
+    pub fn with_runner(runner: R, cache_dir: PathBuf) -> Self {
+        Self {
+            runner,
+            cache_dir,
+            local_db: LocalDatabase::default(),
+            helper_socket: None,
+        }
+    }
+
+    /// Base locale consultée pour les versions installées
+    pub fn with_local_db(mut self, local_db: LocalDatabase) -> Self {
+        self.local_db = local_db;
+        self
+    }
+
+    /// Délègue l'installation des archives à cachypac-helper
+    pub fn with_helper(mut self, helper_socket: Option<PathBuf>) -> Self {
+        self.helper_socket = helper_socket;
+        self
+    }
+
+    /// Cherche dans le cache la version précédente de chaque paquet mis à jour
+    ///
+    /// Seuls les paquets encore installés dans la version apportée par l'opération
+    /// sont restaurés: une transaction interrompue n'en a modifié qu'une partie.
+    pub async fn plan(&self, entry: &HistoryEntry) -> Result<RollbackPlan> {
+        if entry.updates.is_empty() {
+            return Err(anyhow::anyhow!(
+                "L'entrée {} ne contient pas le détail des versions, retour arrière impossible",
+                entry.id
+            ));
+        }
+
+        let cache_dir = self.cache_dir.clone();
+        let cached: Vec<CachedPackage> = tokio::task::spawn_blocking(move || scan_package_cache(&cache_dir))
+            .await
+            .context("Échec du parcours du cache des paquets")?;
+
+        let installed = self.local_db
+            .versions()
+            .await
+            .context("Impossible de lire les versions installées")?;
+
+        let mut targets = Vec::new();
+        let mut missing = Vec::new();
+        // Les paquets installés par l'opération n'ont pas de version précédente
+        for update in entry.updates.iter().filter(|u| !u.current_version.is_empty()) {
+            if installed.get(&update.name) != Some(&update.new_version) {
+                debug!("⏭️ {} n'est pas installé en version {}, ignoré", update.name, update.new_version);
+                continue;
+            }
+            match cached.iter().find(|p| p.name == update.name && p.version == update.current_version) {
+                Some(package) => targets.push(RollbackTarget {
+                    name: update.name.clone(),
+                    from_version: update.new_version.clone(),
+                    to_version: update.current_version.clone(),
+                    file: package.path.clone(),
+                }),
+                None => missing.push(update.clone()),
+            }
+        }
+
+        Ok(RollbackPlan {
+            entry_id: entry.id,
+            targets,
+            missing,
+        })
+    }
+
+    /// Installe les versions précédentes en une seule transaction
+    pub async fn execute(&self, plan: &RollbackPlan) -> Result<()> {
+        if plan.targets.is_empty() {
+            return Err(anyhow::anyhow!("Aucune version précédente disponible dans le cache"));
+        }
+
+        for update in &plan.missing {
+            warn!("⚠️ {} {} absent du cache, non restauré", update.name, update.current_version);
+        }
+
+        info!("⏪ Retour arrière de {} paquets", plan.targets.len());
+        let output = if let Some(socket) = &self.helper_socket {
+            // Le helper ne reçoit que les noms des archives, résolus dans son propre cache
+            let archives = plan.targets
+                .iter()
+                .filter_map(|target| target.file.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+            HelperClient::new(socket.clone()).call(&HelperRequest::Rollback { archives }, None).await?
+        } else {
+            let cmd = CommandSpec::new("sudo")
+                .args(["pacman", "-U", "--noconfirm"])
+                .args(plan.targets.iter().map(|target| target.file.to_string_lossy()))
+                .env("LC_ALL", "C");
+            self.runner
+                .run(&cmd)
+                .await
+                .context("Impossible d'exécuter pacman -U")?
+        };
+
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec du retour arrière: {}", output.stderr.trim()));
+        }
+
+        info!("✅ Retour arrière terminé");
+        Ok(())
+    }
+
+    /// Calcule et exécute le retour arrière, puis l'enregistre dans l'historique
+    pub async fn rollback(&self, entry: &HistoryEntry, history: &mut UpdateHistory) -> Result<RollbackPlan> {
+        let start_time = Instant::now();
+        let plan = self.plan(entry).await?;
+        if plan.is_empty() {
+            info!("✅ Aucun paquet modifié par l'opération {}, retour arrière inutile", entry.id);
+            return Ok(plan);
+        }
+        let result = self.execute(&plan).await;
+
+        let mut message = match &result {
+            Ok(()) => format!("Retour arrière de {} paquets (opération {})", plan.targets.len(), entry.id),
+            Err(e) => format!("Échec du retour arrière de l'opération {}: {}", entry.id, e),
+        };
+        if !plan.missing.is_empty() {
+            let missing: Vec<&str> = plan.missing.iter().map(|u| u.name.as_str()).collect();
+            let _ = write!(message, ", absents du cache: {}", missing.join(", "));
+        }
+
+        let record = HistoryEntry {
+            id: Uuid::new_v4(),
+            timestamp: Local::now(),
+            operation_type: OperationType::Rollback,
+            packages: plan.targets.iter().map(|t| t.name.clone()).collect(),
+            updates: plan.reverted_updates(),
+            pacnew_files: Vec::new(),
+            snapshots: Vec::new(),
+            freed_bytes: None,
+            restart: None,
+            success: result.is_ok(),
+            message,
+            duration: start_time.elapsed(),
+        };
+        if let Err(e) = history.add_entry(record).await {
+            error!("❌ Erreur lors de l'enregistrement dans l'historique: {}", e);
+        }
+
+        result.map(|()| plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn local_db(db_path: &Path, installed: &[(&str, &str)]) -> LocalDatabase {
+        for (name, version) in installed {
+            let dir = db_path.join("local").join(format!("{name}-{version}"));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("desc"), format!("%NAME%\n{name}\n\n%VERSION%\n{version}\n")).unwrap();
+        }
+        LocalDatabase::new(db_path)
+    }
+
+    fn entry(updates: Vec<PackageUpdate>) -> HistoryEntry {
+        HistoryEntry {
+            id: Uuid::new_v4(),
+            timestamp: Local::now(),
+            operation_type: OperationType::ManualUpdate,
+            packages: updates.iter().map(|u| u.name.clone()).collect(),
+            updates,
+            pacnew_files: Vec::new(),
+            snapshots: Vec::new(),
+            freed_bytes: None,
+            restart: None,
+            success: true,
+            message: String::new(),
+            duration: std::time::Duration::from_secs(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rollback_plan_and_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("pkg");
+        std::fs::create_dir(&cache_dir).unwrap();
+        for name in ["firefox-91.0-1-x86_64.pkg.tar.zst", "firefox-92.0-1-x86_64.pkg.tar.zst", "mesa-1:24.1.0-1-x86_64.pkg.tar.zst"] {
+            std::fs::write(cache_dir.join(name), "").unwrap();
+        }
+
+        let runner = ScriptedRunner::new().on(&["sudo", "pacman", "-U"], ScriptedResponse::ok(""));
+        // archlinux-keyring n'a pas été mis à jour par la transaction interrompue
+        let installed = [
+            ("firefox", "92.0-1"),
+            ("mesa", "1:24.2.0-1"),
+            ("yay", "12.3.5-1"),
+            ("archlinux-keyring", "20240101-1"),
+        ];
+        let manager = RollbackManager::with_runner(runner.clone(), cache_dir.clone())
+            .with_local_db(local_db(&temp_dir.path().join("db"), &installed));
+        let source = entry(vec![
+            PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string()),
+            PackageUpdate::new("mesa".to_string(), "1:24.1.0-1".to_string(), "1:24.2.0-1".to_string()),
+            PackageUpdate::new("yay".to_string(), "12.3.4-1".to_string(), "12.3.5-1".to_string()),
+            PackageUpdate::new("archlinux-keyring".to_string(), "20240101-1".to_string(), "20240201-1".to_string()),
+        ]);
+
+        let mut history = UpdateHistory::new(temp_dir.path().to_path_buf());
+        let plan = manager.rollback(&source, &mut history).await.unwrap();
+
+        assert_eq!(plan.targets.len(), 2);
+        assert_eq!(plan.targets[0].to_version, "91.0-1");
+        assert_eq!(plan.missing.len(), 1);
+        assert_eq!(plan.missing[0].name, "yay");
+
+        // Une seule transaction pour tous les paquets
+        let invocations = runner.invocations();
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].args[3..], [
+            cache_dir.join("firefox-91.0-1-x86_64.pkg.tar.zst").to_string_lossy().to_string(),
+            cache_dir.join("mesa-1:24.1.0-1-x86_64.pkg.tar.zst").to_string_lossy().to_string(),
+        ]);
+
+        let recorded = &history.get_all_entries()[0];
+        assert!(matches!(recorded.operation_type, OperationType::Rollback));
+        assert!(recorded.success);
+        assert_eq!(recorded.updates[0].new_version, "91.0-1");
+        assert!(recorded.message.contains("absents du cache: yay"));
+    }
+
+    #[tokio::test]
+    async fn test_rollback_without_cached_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = ScriptedRunner::new();
+        let manager = RollbackManager::with_runner(runner.clone(), temp_dir.path().to_path_buf())
+            .with_local_db(local_db(&temp_dir.path().join("db"), &[("linux", "6.6.2-1")]));
+        let mut history = UpdateHistory::new(temp_dir.path().to_path_buf());
+
+        let source = entry(vec![PackageUpdate::new("linux".to_string(), "6.6.1-1".to_string(), "6.6.2-1".to_string())]);
+        assert!(manager.rollback(&source, &mut history).await.is_err());
+        assert!(runner.invocations().is_empty());
+        assert!(!history.get_all_entries()[0].success);
+
+        // Les anciennes entrées sans détail de versions ne peuvent pas être annulées
+        assert!(manager.plan(&entry(Vec::new())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_skipped_when_nothing_changed() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("linux-6.6.1-1-x86_64.pkg.tar.zst"), "").unwrap();
+        let runner = ScriptedRunner::new();
+        let manager = RollbackManager::with_runner(runner.clone(), temp_dir.path().to_path_buf())
+            .with_local_db(local_db(&temp_dir.path().join("db"), &[("linux", "6.6.1-1")]));
+        let mut history = UpdateHistory::new(temp_dir.path().to_path_buf());
+
+        // La transaction a échoué avant de modifier quoi que ce soit
+        let source = entry(vec![PackageUpdate::new("linux".to_string(), "6.6.1-1".to_string(), "6.6.2-1".to_string())]);
+        let plan = manager.rollback(&source, &mut history).await.unwrap();
+
+        assert!(plan.is_empty());
+        assert!(runner.invocations().is_empty());
+        assert!(history.get_all_entries().is_empty());
+    }
+}
@@ -0,0 +1,399 @@
+//! Abstraction d'exécution des commandes externes
+//!
+//! Les gestionnaires (pacman, service systemd...) passent par un `CommandRunner`
+//! au lieu d'appeler `tokio::process::Command` directement. `SystemRunner`
+//! exécute réellement les commandes, `ScriptedRunner` rejoue des réponses
+//! préenregistrées et mémorise les invocations pour les tests.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::debug;
+
+/// Description d'une commande à exécuter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+impl CommandSpec {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args.extend(args.into_iter().map(|a| a.as_ref().to_string()));
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Ligne de commande complète (programme + arguments)
+    pub fn argv(&self) -> Vec<String> {
+        std::iter::once(self.program.clone())
+            .chain(self.args.iter().cloned())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for CommandSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.argv().join(" "))
+    }
+}
+
+/// Résultat d'une commande terminée
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandOutput {
+    /// Code de sortie (None si le processus a été tué par un signal)
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    #[allow(dead_code)]
+    pub fn new(code: i32, stdout: &str, stderr: &str) -> Self {
+        Self {
+            code: Some(code),
+            stdout: stdout.to_string(),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Flux de sortie d'une commande
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Ligne émise par une commande en cours d'exécution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub text: String,
+}
+
+/// Exécuteur de commandes externes
+pub trait CommandRunner: std::fmt::Debug + Clone + Send + Sync + 'static {
+    /// Exécute la commande jusqu'à sa fin et capture ses sorties
+    fn run(&self, command: &CommandSpec) -> impl Future<Output = Result<CommandOutput>> + Send;
+
+    /// Exécute la commande en publiant chaque ligne de sortie dès qu'elle est produite
+    ///
+    /// L'implémentation par défaut publie les lignes une fois la commande terminée.
+    fn run_streaming(
+        &self,
+        command: &CommandSpec,
+        lines: UnboundedSender<OutputLine>,
+    ) -> impl Future<Output = Result<CommandOutput>> + Send {
+        async move {
+            let output = self.run(command).await?;
+            for (stream, content) in [(OutputStream::Stdout, &output.stdout), (OutputStream::Stderr, &output.stderr)] {
+                for text in content.lines() {
+                    let _ = lines.send(OutputLine { stream, text: text.to_string() });
+                }
+            }
+            Ok(output)
+        }
+    }
+}
+
+/// Exécuteur réel basé sur `tokio::process::Command`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    async fn run(&self, command: &CommandSpec) -> Result<CommandOutput> {
+        debug!("▶️ Exécution: {}", command);
+
+        let output = Command::new(&command.program)
+            .args(&command.args)
+            .envs(command.env.iter().map(|(k, v)| (k, v)))
+            .kill_on_drop(true)
+            .output()
+            .await
+            .with_context(|| format!("Impossible d'exécuter {}", command.program))?;
+
+        Ok(CommandOutput {
+            code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    async fn run_streaming(&self, command: &CommandSpec, lines: UnboundedSender<OutputLine>) -> Result<CommandOutput> {
+        debug!("▶️ Exécution (flux): {}", command);
+
+        let mut child = Command::new(&command.program)
+            .args(&command.args)
+            .envs(command.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Impossible d'exécuter {}", command.program))?;
+
+        let stdout = child.stdout.take().context("Sortie standard indisponible")?;
+        let stderr = child.stderr.take().context("Sortie d'erreur indisponible")?;
+
+        let (stdout, stderr, status) = tokio::join!(
+            forward_lines(stdout, OutputStream::Stdout, &lines),
+            forward_lines(stderr, OutputStream::Stderr, &lines),
+            child.wait(),
+        );
+        let status = status.context("Erreur lors de l'attente du processus")?;
+
+        Ok(CommandOutput {
+            code: status.code(),
+            stdout: stdout?,
+            stderr: stderr?,
+        })
+    }
+}
+
+/// Lit un flux ligne par ligne, publie chaque ligne et renvoie le contenu complet
+async fn forward_lines<T: AsyncRead + Unpin>(
+    reader: T,
+    stream: OutputStream,
+    lines: &UnboundedSender<OutputLine>,
+) -> Result<String> {
+    let mut reader = BufReader::new(reader).lines();
+    let mut content = String::new();
+
+    while let Some(text) = reader.next_line().await.context("Erreur de lecture de la sortie")? {
+        content.push_str(&text);
+        content.push('\n');
+        let _ = lines.send(OutputLine { stream, text });
+    }
+
+    Ok(content)
+}
+
+/// Réponse préenregistrée d'un `ScriptedRunner`
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct ScriptedResponse {
+    pub output: CommandOutput,
+    /// Délai simulé avant la fin de la commande
+    pub delay: Option<Duration>,
+}
+
+#[allow(dead_code)]
+impl ScriptedResponse {
+    pub fn ok(stdout: &str) -> Self {
+        Self {
+            output: CommandOutput::new(0, stdout, ""),
+            delay: None,
+        }
+    }
+
+    pub fn exit(code: i32, stdout: &str, stderr: &str) -> Self {
+        Self {
+            output: CommandOutput::new(code, stdout, stderr),
+            delay: None,
+        }
+    }
+
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct ScriptRule {
+    prefix: Vec<String>,
+    responses: VecDeque<ScriptedResponse>,
+}
+
+#[derive(Debug, Default)]
+#[allow(dead_code)]
+struct ScriptState {
+    rules: Vec<ScriptRule>,
+    invocations: Vec<CommandSpec>,
+}
+
+/// Exécuteur factice pour les tests
+///
+/// Chaque règle associe un préfixe de ligne de commande à une file de réponses.
+/// Les réponses sont consommées dans l'ordre; la dernière est rejouée indéfiniment.
+/// Une commande sans règle correspondante échoue comme un programme introuvable.
+#[derive(Debug, Clone, Default)]
+#[allow(dead_code)]
+pub struct ScriptedRunner {
+    state: Arc<Mutex<ScriptState>>,
+}
+
+#[allow(dead_code)]
+impl ScriptedRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ajoute une réponse pour les commandes commençant par `prefix`
+    pub fn on(self, prefix: &[&str], response: ScriptedResponse) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            let prefix: Vec<String> = prefix.iter().map(ToString::to_string).collect();
+            if let Some(rule) = state.rules.iter_mut().find(|r| r.prefix == prefix) {
+                rule.responses.push_back(response);
+            } else {
+                state.rules.push(ScriptRule {
+                    prefix,
+                    responses: VecDeque::from([response]),
+                });
+            }
+        }
+        self
+    }
+
+    /// Commandes exécutées jusqu'ici, dans l'ordre
+    pub fn invocations(&self) -> Vec<CommandSpec> {
+        self.state.lock().unwrap().invocations.clone()
+    }
+
+    /// Nombre d'exécutions dont la ligne de commande commence par `prefix`
+    pub fn count(&self, prefix: &[&str]) -> usize {
+        self.invocations()
+            .iter()
+            .filter(|c| starts_with(&c.argv(), prefix))
+            .count()
+    }
+
+    fn next_response(&self, command: &CommandSpec) -> Option<ScriptedResponse> {
+        let mut state = self.state.lock().unwrap();
+        state.invocations.push(command.clone());
+
+        let argv = command.argv();
+        // La règle la plus spécifique (préfixe le plus long) l'emporte
+        let rule = state
+            .rules
+            .iter_mut()
+            .filter(|r| starts_with(&argv, &r.prefix))
+            .max_by_key(|r| r.prefix.len())?;
+
+        if rule.responses.len() > 1 {
+            rule.responses.pop_front()
+        } else {
+            rule.responses.front().cloned()
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn starts_with<S: AsRef<str>>(argv: &[String], prefix: &[S]) -> bool {
+    argv.len() >= prefix.len() && argv.iter().zip(prefix).all(|(a, p)| a == p.as_ref())
+}
+
+impl CommandRunner for ScriptedRunner {
+    async fn run(&self, command: &CommandSpec) -> Result<CommandOutput> {
+        let response = self
+            .next_response(command)
+            .ok_or_else(|| anyhow::anyhow!("Commande non scriptée: {}", command))?;
+
+        if let Some(delay) = response.delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        Ok(response.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scripted_runner_replays_responses() {
+        let runner = ScriptedRunner::new()
+            .on(&["pacman", "-Q"], ScriptedResponse::ok("firefox 92.0-1\n"))
+            .on(&["sudo", "pacman"], ScriptedResponse::exit(1, "", "erreur"))
+            .on(&["sudo", "pacman"], ScriptedResponse::ok(""));
+
+        let output = runner.run(&CommandSpec::new("pacman").arg("-Q")).await.unwrap();
+        assert_eq!(output.stdout, "firefox 92.0-1\n");
+
+        let spec = CommandSpec::new("sudo").args(["pacman", "-Syu"]);
+        assert!(!runner.run(&spec).await.unwrap().success());
+        assert!(runner.run(&spec).await.unwrap().success());
+        // La dernière réponse est rejouée
+        assert!(runner.run(&spec).await.unwrap().success());
+
+        assert!(runner.run(&CommandSpec::new("which")).await.is_err());
+        assert_eq!(runner.count(&["sudo", "pacman"]), 3);
+        assert_eq!(runner.invocations().len(), 5);
+        assert_eq!(runner.invocations()[1].to_string(), "sudo pacman -Syu");
+    }
+
+    #[tokio::test]
+    async fn test_system_runner_captures_output() {
+        let output = SystemRunner
+            .run(&CommandSpec::new("sh").args(["-c", "echo sortie; echo erreur >&2; exit 3"]))
+            .await
+            .unwrap();
+
+        assert_eq!(output.code, Some(3));
+        assert_eq!(output.stdout, "sortie\n");
+        assert_eq!(output.stderr, "erreur\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_streaming_publishes_lines() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let output = SystemRunner
+            .run_streaming(&CommandSpec::new("sh").args(["-c", "echo un; echo deux; echo trois >&2"]), tx)
+            .await
+            .unwrap();
+
+        assert!(output.success());
+        assert_eq!(output.stdout, "un\ndeux\n");
+
+        let mut received = Vec::new();
+        while let Some(line) = rx.recv().await {
+            received.push(line);
+        }
+        assert_eq!(received.len(), 3);
+        assert!(received.contains(&OutputLine { stream: OutputStream::Stderr, text: "trois".to_string() }));
+
+        // Le faux exécuteur publie aussi ses réponses ligne par ligne
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let runner = ScriptedRunner::new().on(&["pacman"], ScriptedResponse::ok("a\nb\n"));
+        runner.run_streaming(&CommandSpec::new("pacman"), tx).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().text, "a");
+        assert_eq!(rx.recv().await.unwrap().text, "b");
+    }
+}
@@ -206,7 +206,6 @@ impl SchedulerManager {
     }
 
     /// Vérifie si on est dans une fenêtre de maintenance
-    #[allow(dead_code)]
     pub fn is_in_maintenance_window(&self, start_time: &str, end_time: &str) -> Result<bool> {
         let now = Local::now();
         
@@ -2,9 +2,10 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use tokio::fs;
-use tokio::process::Command;
 use tracing::{debug, info};
 
+use crate::runner::{CommandRunner, CommandSpec, SystemRunner};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ServiceStatus {
     Running,
@@ -38,14 +39,22 @@ pub struct SystemdUnit {
 }
 
 #[derive(Debug)]
-pub struct ServiceManager {
+pub struct ServiceManager<R: CommandRunner = SystemRunner> {
     service_name: String,
     systemd_unit: SystemdUnit,
     config_dir: PathBuf,
+    runner: R,
 }
 
 impl ServiceManager {
     pub fn new(service_name: String, config_dir: PathBuf) -> Self {
+        Self::with_runner(service_name, config_dir, SystemRunner)
+    }
+}
+
+impl<R: CommandRunner> ServiceManager<R> {
+    /// Crée un gestionnaire utilisant un exécuteur de commandes spécifique
+    pub fn with_runner(service_name: String, config_dir: PathBuf, runner: R) -> Self {
         let systemd_unit = SystemdUnit {
             unit_file: format!("{}.service", service_name),
             description: "CachyPac - Automated Pacman Update Manager".to_string(),
@@ -64,6 +73,7 @@ impl ServiceManager {
             service_name,
             systemd_unit,
             config_dir,
+            runner,
         }
     }
 
@@ -99,14 +109,12 @@ impl ServiceManager {
     pub async fn get_service_status(&self) -> Result<ServiceInfo> {
         debug!("Récupération du statut du service: {}", self.service_name);
 
-        let output = Command::new("systemctl")
-            .args(&["status", &self.service_name, "--no-pager"])
-            .output()
+        let output = self.runner
+            .run(&CommandSpec::new("systemctl").args(["status", &self.service_name, "--no-pager"]))
             .await
             .context("Impossible d'exécuter systemctl status")?;
 
-        let status_output = String::from_utf8_lossy(&output.stdout);
-        let status = self.parse_service_status(&status_output)?;
+        let status = self.parse_service_status(&output.stdout)?;
 
         let enabled = self.is_service_enabled().await.unwrap_or(false);
 
@@ -168,39 +176,34 @@ impl ServiceManager {
 
     /// Vérifie si le service est activé
     pub async fn is_service_enabled(&self) -> Result<bool> {
-        let output = Command::new("systemctl")
-            .args(&["is-enabled", &self.service_name])
-            .output()
+        let output = self.runner
+            .run(&CommandSpec::new("systemctl").args(["is-enabled", &self.service_name]))
             .await
             .context("Impossible d'exécuter systemctl is-enabled")?;
 
-        Ok(output.status.success())
+        Ok(output.success())
     }
 
     /// Vérifie si le processus s'exécute en tant que root
     async fn is_running_as_root(&self) -> Result<bool> {
-        let output = Command::new("id")
-            .args(&["-u"])
-            .output()
+        let output = self.runner
+            .run(&CommandSpec::new("id").arg("-u"))
             .await
             .context("Impossible d'exécuter id -u")?;
 
-        let uid_str = String::from_utf8_lossy(&output.stdout);
-        let uid = uid_str.trim().parse::<u32>().unwrap_or(1000);
+        let uid = output.stdout.trim().parse::<u32>().unwrap_or(1000);
         Ok(uid == 0)
     }
 
     /// Recharge la configuration systemd
     async fn systemctl_daemon_reload(&self) -> Result<()> {
-        let output = Command::new("systemctl")
-            .args(&["daemon-reload"])
-            .output()
+        let output = self.runner
+            .run(&CommandSpec::new("systemctl").arg("daemon-reload"))
             .await
             .context("Impossible d'exécuter systemctl daemon-reload")?;
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Échec du rechargement systemd: {}", error));
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec du rechargement systemd: {}", output.stderr));
         }
 
         Ok(())
@@ -210,30 +213,27 @@ impl ServiceManager {
     async fn create_system_user(&self) -> Result<()> {
         info!("Création de l'utilisateur système: {}", self.systemd_unit.user);
 
-        let output = Command::new("id")
-            .args(&[&self.systemd_unit.user])
-            .output()
+        let output = self.runner
+            .run(&CommandSpec::new("id").arg(&self.systemd_unit.user))
             .await;
 
-        if output.is_ok() && output.unwrap().status.success() {
+        if output.is_ok_and(|o| o.success()) {
             debug!("L'utilisateur {} existe déjà", self.systemd_unit.user);
             return Ok(());
         }
 
-        let output = Command::new("useradd")
-            .args(&[
+        let output = self.runner
+            .run(&CommandSpec::new("useradd").args([
                 "--system",
                 "--no-create-home",
                 "--shell", "/bin/false",
                 &self.systemd_unit.user,
-            ])
-            .output()
+            ]))
             .await
             .context("Impossible d'exécuter useradd")?;
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Échec de la création de l'utilisateur: {}", error));
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de la création de l'utilisateur: {}", output.stderr));
         }
 
         info!("Utilisateur système créé: {}", self.systemd_unit.user);
@@ -280,6 +280,7 @@ impl ServiceManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
     use tempfile::tempdir;
 
     #[test]
@@ -309,4 +310,18 @@ mod tests {
         let status = service_manager.parse_service_status(stopped_output).unwrap();
         assert!(matches!(status, ServiceStatus::Stopped));
     }
+
+    #[tokio::test]
+    async fn test_service_status_with_scripted_runner() {
+        let temp_dir = tempdir().unwrap();
+        let runner = ScriptedRunner::new()
+            .on(&["systemctl", "status"], ScriptedResponse::exit(3, "   Active: failed (Result: exit-code)", ""))
+            .on(&["systemctl", "is-enabled"], ScriptedResponse::ok("enabled\n"));
+        let service_manager = ServiceManager::with_runner("test-service".to_string(), temp_dir.path().to_path_buf(), runner.clone());
+
+        let info = service_manager.get_service_status().await.unwrap();
+        assert!(matches!(info.status, ServiceStatus::Failed));
+        assert!(info.enabled);
+        assert_eq!(runner.invocations()[0].to_string(), "systemctl status test-service --no-pager");
+    }
 }
\ No newline at end of file
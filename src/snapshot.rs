@@ -0,0 +1,632 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::LazyLock;
+use tracing::{debug, info, warn};
+
+use crate::config::SnapshotConfig;
+use crate::runner::{CommandOutput, CommandRunner, CommandSpec, SystemRunner};
+
+/// Préfixe des descriptions, sert à reconnaître les instantanés de CachyPac
+const DESCRIPTION_PREFIX: &str = "CachyPac";
+
+/// Préfixe des sous-volumes créés par le backend btrfs
+const BTRFS_NAME_PREFIX: &str = "cachypac-";
+
+static TIMESHIFT_TAGGED_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"Tagged snapshot '([^']+)'").unwrap());
+
+/// Outil utilisé pour les instantanés du système de fichiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotBackend {
+    /// Détection automatique: snapper, puis timeshift, puis btrfs
+    #[default]
+    Auto,
+    Snapper,
+    /// Sous-volumes btrfs en lecture seule (`btrfs subvolume snapshot -r`)
+    Btrfs,
+    Timeshift,
+}
+
+impl std::fmt::Display for SnapshotBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotBackend::Auto => write!(f, "auto"),
+            SnapshotBackend::Snapper => write!(f, "snapper"),
+            SnapshotBackend::Btrfs => write!(f, "btrfs"),
+            SnapshotBackend::Timeshift => write!(f, "timeshift"),
+        }
+    }
+}
+
+/// Moment de l'instantané par rapport à la mise à jour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotKind {
+    Pre,
+    Post,
+}
+
+impl std::fmt::Display for SnapshotKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotKind::Pre => write!(f, "pre"),
+            SnapshotKind::Post => write!(f, "post"),
+        }
+    }
+}
+
+impl SnapshotKind {
+    fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "pre" => Some(SnapshotKind::Pre),
+            "post" => Some(SnapshotKind::Post),
+            _ => None,
+        }
+    }
+}
+
+/// Instantané créé par CachyPac
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Numéro snapper, nom du sous-volume btrfs ou nom timeshift
+    pub id: String,
+    pub backend: SnapshotBackend,
+    pub kind: SnapshotKind,
+    pub description: String,
+    pub created: Option<DateTime<Local>>,
+}
+
+impl std::fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} #{} ({})", self.backend, self.id, self.kind)
+    }
+}
+
+/// Crée, liste et purge les instantanés encadrant les mises à jour
+#[derive(Debug, Clone)]
+pub struct SnapshotManager<R: CommandRunner = SystemRunner> {
+    config: SnapshotConfig,
+    runner: R,
+}
+
+impl SnapshotManager {
+    pub fn new(config: SnapshotConfig) -> Self {
+        Self::with_runner(config, SystemRunner)
+    }
+}
+
+impl<R: CommandRunner> SnapshotManager<R> {
+    pub fn with_runner(config: SnapshotConfig, runner: R) -> Self {
+        Self { config, runner }
+    }
+
+    /// Backend disponible sur le système, None si aucun n'est utilisable
+    pub async fn detect(&self) -> Option<SnapshotBackend> {
+        if self.config.backend != SnapshotBackend::Auto {
+            return Some(self.config.backend);
+        }
+
+        if self.command_exists("snapper").await {
+            return Some(SnapshotBackend::Snapper);
+        }
+        if self.command_exists("timeshift").await {
+            return Some(SnapshotBackend::Timeshift);
+        }
+
+        let fstype = self.runner
+            .run(&CommandSpec::new("findmnt").args(["-n", "-o", "FSTYPE", &self.config.btrfs_subvolume]))
+            .await
+            .ok()
+            .filter(CommandOutput::success)
+            .map(|output| output.stdout.trim().to_string());
+        if fstype.as_deref() == Some("btrfs") && self.command_exists("btrfs").await {
+            return Some(SnapshotBackend::Btrfs);
+        }
+
+        None
+    }
+
+    /// Instantané avant mise à jour, None si aucun backend n'est disponible
+    pub async fn create_pre(&self, description: &str) -> Result<Option<Snapshot>> {
+        let Some(backend) = self.detect().await else {
+            warn!("⚠️ Aucun outil d'instantané détecté (snapper, timeshift, btrfs), mise à jour sans instantané");
+            return Ok(None);
+        };
+
+        self.create(backend, SnapshotKind::Pre, description, None).await.map(Some)
+    }
+
+    /// Instantané après une mise à jour réussie, associé à l'instantané `pre`
+    pub async fn create_post(&self, pre: &Snapshot, description: &str) -> Result<Snapshot> {
+        self.create(pre.backend, SnapshotKind::Post, description, Some(pre)).await
+    }
+
+    /// Exécute `operation` entre un instantané pre et un instantané post
+    ///
+    /// L'opération n'est pas lancée si l'instantané pre échoue. L'instantané
+    /// post et la purge ne sont que des avertissements en cas d'erreur.
+    pub async fn with_snapshots<T, F>(&self, description: &str, operation: F) -> (Vec<Snapshot>, Result<T>)
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let pre = match self.create_pre(description).await {
+            Ok(pre) => pre,
+            Err(e) => {
+                return (Vec::new(), Err(e.context("Instantané avant mise à jour impossible, mise à jour annulée")));
+            }
+        };
+
+        let result = operation.await;
+        let Some(pre) = pre else {
+            return (Vec::new(), result);
+        };
+
+        let mut snapshots = vec![pre.clone()];
+        if result.is_ok() {
+            match self.create_post(&pre, description).await {
+                Ok(post) => snapshots.push(post),
+                Err(e) => warn!("⚠️ Instantané après mise à jour impossible: {}", e),
+            }
+            if let Err(e) = self.prune(pre.backend).await {
+                warn!("⚠️ Erreur lors de la purge des anciens instantanés: {}", e);
+            }
+        }
+
+        (snapshots, result)
+    }
+
+    /// Crée un instantané avec le backend donné
+    pub async fn create(
+        &self,
+        backend: SnapshotBackend,
+        kind: SnapshotKind,
+        description: &str,
+        pre: Option<&Snapshot>,
+    ) -> Result<Snapshot> {
+        let description = format!("{} {}: {}", DESCRIPTION_PREFIX, kind, description);
+        let created = Local::now();
+
+        let id = match backend {
+            SnapshotBackend::Snapper => {
+                let mut args = vec![
+                    "snapper".to_string(),
+                    "-c".to_string(),
+                    self.config.snapper_config.clone(),
+                    "create".to_string(),
+                    "--type".to_string(),
+                    kind.to_string(),
+                ];
+                if let Some(pre) = pre {
+                    args.push("--pre-number".to_string());
+                    args.push(pre.id.clone());
+                }
+                args.extend([
+                    "--print-number",
+                    "--cleanup-algorithm",
+                    "number",
+                    "--userdata",
+                    "cachypac=yes",
+                    "--description",
+                ].map(String::from));
+                args.push(description.clone());
+
+                let stdout = self.run_privileged(&args).await?;
+                let number = stdout.trim();
+                if number.parse::<u64>().is_err() {
+                    return Err(anyhow::anyhow!("Numéro d'instantané snapper invalide: {}", number));
+                }
+                number.to_string()
+            }
+            SnapshotBackend::Btrfs => {
+                let name = format!("{}{}-{}", BTRFS_NAME_PREFIX, created.format("%Y%m%d-%H%M%S"), kind);
+                let target = format!("{}/{}", self.config.btrfs_snapshot_dir.trim_end_matches('/'), name);
+
+                self.run_privileged(&["mkdir", "-p", &self.config.btrfs_snapshot_dir]).await?;
+                self.run_privileged(&[
+                    "btrfs", "subvolume", "snapshot", "-r", &self.config.btrfs_subvolume, &target,
+                ]).await?;
+                name
+            }
+            SnapshotBackend::Timeshift => {
+                let stdout = self.run_privileged(&[
+                    "timeshift", "--create", "--scripted", "--tags", "O", "--comments", &description,
+                ]).await?;
+                TIMESHIFT_TAGGED_RE
+                    .captures(&stdout)
+                    .map(|captures| captures[1].to_string())
+                    .context("Nom de l'instantané timeshift introuvable dans la sortie")?
+            }
+            SnapshotBackend::Auto => {
+                return Err(anyhow::anyhow!("Backend d'instantané non résolu"));
+            }
+        };
+
+        let snapshot = Snapshot {
+            id,
+            backend,
+            kind,
+            description,
+            created: Some(created),
+        };
+        info!("📸 Instantané créé: {}", snapshot);
+        Ok(snapshot)
+    }
+
+    /// Instantanés créés par CachyPac, du plus ancien au plus récent
+    pub async fn list(&self, backend: SnapshotBackend) -> Result<Vec<Snapshot>> {
+        let snapshots = match backend {
+            SnapshotBackend::Snapper => {
+                let stdout = self.run_privileged(&[
+                    "snapper", "-c", &self.config.snapper_config, "--csvout", "list",
+                    "--columns", "number,type,date,description,userdata",
+                ]).await?;
+                parse_snapper_list(&stdout)
+            }
+            SnapshotBackend::Btrfs => {
+                let stdout = self.run_privileged(&[
+                    "btrfs", "subvolume", "list", "-o", &self.config.btrfs_snapshot_dir,
+                ]).await?;
+                parse_btrfs_list(&stdout)
+            }
+            SnapshotBackend::Timeshift => {
+                let stdout = self.run_privileged(&["timeshift", "--list", "--scripted"]).await?;
+                parse_timeshift_list(&stdout)
+            }
+            SnapshotBackend::Auto => Vec::new(),
+        };
+
+        debug!("📸 {} instantanés CachyPac ({})", snapshots.len(), backend);
+        Ok(snapshots)
+    }
+
+    /// Supprime les instantanés des mises à jour les plus anciennes au-delà de la rétention
+    ///
+    /// La rétention compte des mises à jour: une paire pre/post (ou un pre seul
+    /// après un échec) est conservée ou supprimée en entier. Une rétention de 0
+    /// conserve tous les instantanés.
+    pub async fn prune(&self, backend: SnapshotBackend) -> Result<Vec<Snapshot>> {
+        let retention = self.config.retention;
+        let pairs = pair_snapshots(self.list(backend).await?);
+        if retention == 0 || pairs.len() <= retention {
+            return Ok(Vec::new());
+        }
+
+        let mut expired = Vec::new();
+        for pair in &pairs[..pairs.len() - retention] {
+            // Le post d'abord: un pre seul reste l'état cohérent d'une mise à jour en échec
+            for snapshot in pair.iter().rev() {
+                self.delete(snapshot).await?;
+                expired.push(snapshot.clone());
+            }
+        }
+
+        info!(
+            "🧹 {} anciens instantanés supprimés ({} mises à jour, rétention: {})",
+            expired.len(),
+            pairs.len() - retention,
+            retention
+        );
+        Ok(expired)
+    }
+
+    pub async fn delete(&self, snapshot: &Snapshot) -> Result<()> {
+        debug!("🗑️ Suppression de l'instantané {}", snapshot);
+
+        match snapshot.backend {
+            SnapshotBackend::Snapper => {
+                self.run_privileged(&["snapper", "-c", &self.config.snapper_config, "delete", &snapshot.id]).await?;
+            }
+            SnapshotBackend::Btrfs => {
+                let path = format!("{}/{}", self.config.btrfs_snapshot_dir.trim_end_matches('/'), snapshot.id);
+                self.run_privileged(&["btrfs", "subvolume", "delete", &path]).await?;
+            }
+            SnapshotBackend::Timeshift => {
+                self.run_privileged(&["timeshift", "--delete", "--scripted", "--snapshot", &snapshot.id]).await?;
+            }
+            SnapshotBackend::Auto => {}
+        }
+
+        Ok(())
+    }
+
+    async fn command_exists(&self, program: &str) -> bool {
+        self.runner
+            .run(&CommandSpec::new("which").arg(program))
+            .await
+            .is_ok_and(|output| output.success())
+    }
+
+    async fn run_privileged<S: AsRef<str> + Sync>(&self, args: &[S]) -> Result<String> {
+        let cmd = CommandSpec::new("sudo").args(args);
+        let output = self.runner
+            .run(&cmd)
+            .await
+            .context(format!("Impossible d'exécuter {}", cmd))?;
+
+        if !output.success() {
+            return Err(anyhow::anyhow!("Échec de {}: {}", cmd, output.stderr.trim()));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+/// Regroupe des instantanés triés par mise à jour: chaque post rejoint le pre qui le précède
+pub fn pair_snapshots(snapshots: Vec<Snapshot>) -> Vec<Vec<Snapshot>> {
+    let mut pairs: Vec<Vec<Snapshot>> = Vec::new();
+
+    for snapshot in snapshots {
+        match pairs.last_mut() {
+            Some(pair) if snapshot.kind == SnapshotKind::Post && matches!(pair.as_slice(), [pre] if pre.kind == SnapshotKind::Pre) => {
+                pair.push(snapshot);
+            }
+            _ => pairs.push(vec![snapshot]),
+        }
+    }
+
+    pairs
+}
+
+/// Parse `snapper --csvout list --columns number,type,date,description,userdata`
+pub fn parse_snapper_list(output: &str) -> Vec<Snapshot> {
+    let mut snapshots: Vec<(u64, Snapshot)> = output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = split_csv_line(line);
+            let [number, kind, date, description, userdata] = fields.as_slice() else {
+                return None;
+            };
+            if !userdata.contains("cachypac=yes") {
+                return None;
+            }
+
+            let number: u64 = number.parse().ok()?;
+            let created = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .and_then(|date| Local.from_local_datetime(&date).single());
+
+            Some((number, Snapshot {
+                id: number.to_string(),
+                backend: SnapshotBackend::Snapper,
+                kind: SnapshotKind::from_label(kind)?,
+                description: description.clone(),
+                created,
+            }))
+        })
+        .collect();
+
+    snapshots.sort_by_key(|(number, _)| *number);
+    snapshots.into_iter().map(|(_, snapshot)| snapshot).collect()
+}
+
+/// Parse `btrfs subvolume list -o` ("ID 261 gen 42 top level 5 path .snapshots/cachypac/...")
+pub fn parse_btrfs_list(output: &str) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = output
+        .lines()
+        .filter_map(|line| {
+            let path = line.split_once(" path ")?.1.trim();
+            let name = path.rsplit('/').next()?;
+            let stamp_and_kind = name.strip_prefix(BTRFS_NAME_PREFIX)?;
+            let (stamp, kind) = stamp_and_kind.rsplit_once('-')?;
+            let created = NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S")
+                .ok()
+                .and_then(|date| Local.from_local_datetime(&date).single());
+
+            Some(Snapshot {
+                id: name.to_string(),
+                backend: SnapshotBackend::Btrfs,
+                kind: SnapshotKind::from_label(kind)?,
+                description: String::new(),
+                created,
+            })
+        })
+        .collect();
+
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    snapshots
+}
+
+/// Parse `timeshift --list` ("0    >  2024-09-14_10-00-01  O     CachyPac pre: ...")
+pub fn parse_timeshift_list(output: &str) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.next()?.parse::<u32>().ok()?;
+            let name = fields.next().filter(|field| *field != ">").or_else(|| fields.next())?;
+            let _tags = fields.next()?;
+            let description = fields.collect::<Vec<_>>().join(" ");
+
+            let kind = description
+                .strip_prefix(DESCRIPTION_PREFIX)?
+                .trim_start()
+                .split(':')
+                .next()
+                .and_then(SnapshotKind::from_label)?;
+            let created = NaiveDateTime::parse_from_str(name, "%Y-%m-%d_%H-%M-%S")
+                .ok()
+                .and_then(|date| Local.from_local_datetime(&date).single());
+
+            Some(Snapshot {
+                id: name.to_string(),
+                backend: SnapshotBackend::Timeshift,
+                kind,
+                description,
+                created,
+            })
+        })
+        .collect();
+
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    snapshots
+}
+
+/// Découpe une ligne CSV simple (guillemets doublés pour les champs avec virgules)
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ScriptedResponse, ScriptedRunner};
+
+    fn config(backend: SnapshotBackend, retention: usize) -> SnapshotConfig {
+        SnapshotConfig {
+            backend,
+            retention,
+            ..SnapshotConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_snapper_list() {
+        let output = "number,type,date,description,userdata\n\
+            0,single,,current,\n\
+            12,pre,2024-09-14 10:00:00,\"CachyPac pre: 3 paquets, dont linux\",cachypac=yes\n\
+            13,post,2024-09-14 10:02:00,CachyPac post: 3 paquets,cachypac=yes\n\
+            14,single,2024-09-15 08:00:00,timeline,\n";
+
+        let snapshots = parse_snapper_list(output);
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].id, "12");
+        assert_eq!(snapshots[0].kind, SnapshotKind::Pre);
+        assert_eq!(snapshots[0].description, "CachyPac pre: 3 paquets, dont linux");
+        assert!(snapshots[0].created.is_some());
+        assert_eq!(snapshots[1].kind, SnapshotKind::Post);
+    }
+
+    #[test]
+    fn test_parse_btrfs_and_timeshift_lists() {
+        let btrfs = "ID 262 gen 50 top level 5 path .snapshots/cachypac/cachypac-20240914-100200-post\n\
+            ID 261 gen 42 top level 5 path .snapshots/cachypac/cachypac-20240914-100000-pre\n\
+            ID 270 gen 60 top level 5 path .snapshots/cachypac/autre\n";
+        let snapshots = parse_btrfs_list(btrfs);
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].id, "cachypac-20240914-100000-pre");
+        assert_eq!(snapshots[0].kind, SnapshotKind::Pre);
+        assert_eq!(snapshots[1].kind, SnapshotKind::Post);
+
+        let timeshift = "Device : /dev/sda2\n\
+            Num     Name                 Tags  Description\n\
+            ------------------------------------------------------------------------------\n\
+            0    >  2024-09-01_02-00-01  D                                                \n\
+            1    >  2024-09-14_10-00-01  O     CachyPac pre: 3 paquets\n\
+            2    >  2024-09-14_10-02-11  O     CachyPac post: 3 paquets\n";
+        let snapshots = parse_timeshift_list(timeshift);
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].id, "2024-09-14_10-00-01");
+        assert_eq!(snapshots[0].description, "CachyPac pre: 3 paquets");
+        assert_eq!(snapshots[1].kind, SnapshotKind::Post);
+    }
+
+    #[tokio::test]
+    async fn test_detect_backend() {
+        let runner = ScriptedRunner::new()
+            .on(&["which", "snapper"], ScriptedResponse::exit(1, "", ""))
+            .on(&["which", "timeshift"], ScriptedResponse::exit(1, "", ""))
+            .on(&["which", "btrfs"], ScriptedResponse::ok("/usr/bin/btrfs\n"))
+            .on(&["findmnt"], ScriptedResponse::ok("btrfs\n"));
+        let manager = SnapshotManager::with_runner(config(SnapshotBackend::Auto, 10), runner);
+        assert_eq!(manager.detect().await, Some(SnapshotBackend::Btrfs));
+
+        let runner = ScriptedRunner::new()
+            .on(&["which", "snapper"], ScriptedResponse::ok("/usr/bin/snapper\n"));
+        let manager = SnapshotManager::with_runner(config(SnapshotBackend::Auto, 10), runner);
+        assert_eq!(manager.detect().await, Some(SnapshotBackend::Snapper));
+
+        // ext4 sans outil d'instantané
+        let runner = ScriptedRunner::new().on(&["findmnt"], ScriptedResponse::ok("ext4\n"));
+        let manager = SnapshotManager::with_runner(config(SnapshotBackend::Auto, 10), runner);
+        assert_eq!(manager.detect().await, None);
+        assert_eq!(manager.create_pre("test").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_snapper_pre_post_and_prune() {
+        let list = "number,type,date,description,userdata\n\
+            10,pre,2024-09-01 10:00:00,CachyPac pre: a,cachypac=yes\n\
+            11,post,2024-09-01 10:01:00,CachyPac post: a,cachypac=yes\n\
+            20,pre,2024-09-07 10:00:00,CachyPac pre: échec,cachypac=yes\n\
+            42,pre,2024-09-14 10:00:00,CachyPac pre: b,cachypac=yes\n\
+            43,post,2024-09-14 10:01:00,CachyPac post: b,cachypac=yes\n";
+        let runner = ScriptedRunner::new()
+            .on(&["sudo", "snapper", "-c", "root", "create", "--type", "pre"], ScriptedResponse::ok("42\n"))
+            .on(&["sudo", "snapper", "-c", "root", "create", "--type", "post"], ScriptedResponse::ok("43\n"))
+            .on(&["sudo", "snapper", "-c", "root", "--csvout", "list"], ScriptedResponse::ok(list))
+            .on(&["sudo", "snapper", "-c", "root", "delete"], ScriptedResponse::ok(""));
+        let manager = SnapshotManager::with_runner(config(SnapshotBackend::Snapper, 2), runner.clone());
+
+        let (snapshots, result) = manager.with_snapshots("2 paquets", async { Ok(()) }).await;
+        result.unwrap();
+
+        let ids: Vec<&str> = snapshots.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["42", "43"]);
+
+        let post = runner.invocations()
+            .into_iter()
+            .find(|c| c.args.contains(&"post".to_string()))
+            .unwrap();
+        assert!(post.to_string().contains("--pre-number 42"));
+        assert!(post.to_string().ends_with("--description CachyPac post: 2 paquets"));
+
+        // Seules les deux mises à jour les plus récentes sont conservées, par paire entière
+        let deleted: Vec<String> = runner.invocations()
+            .iter()
+            .filter(|c| c.args.contains(&"delete".to_string()))
+            .map(|c| c.args.last().unwrap().clone())
+            .collect();
+        assert_eq!(deleted, vec!["11", "10"]);
+    }
+
+    #[tokio::test]
+    async fn test_btrfs_snapshot_and_failed_pre() {
+        let runner = ScriptedRunner::new()
+            .on(&["sudo", "mkdir", "-p", "/.snapshots/cachypac"], ScriptedResponse::ok(""))
+            .on(&["sudo", "btrfs", "subvolume", "snapshot", "-r", "/"], ScriptedResponse::ok(""));
+        let manager = SnapshotManager::with_runner(config(SnapshotBackend::Btrfs, 0), runner.clone());
+
+        let snapshot = manager.create_pre("1 paquet").await.unwrap().unwrap();
+        assert!(snapshot.id.starts_with("cachypac-"));
+        assert!(snapshot.id.ends_with("-pre"));
+        assert_eq!(
+            runner.invocations().last().unwrap().args.last().unwrap(),
+            &format!("/.snapshots/cachypac/{}", snapshot.id)
+        );
+
+        // Un instantané pre en échec bloque l'opération
+        let runner = ScriptedRunner::new()
+            .on(&["sudo", "mkdir"], ScriptedResponse::ok(""))
+            .on(&["sudo", "btrfs"], ScriptedResponse::exit(1, "", "ERROR: not a subvolume"));
+        let manager = SnapshotManager::with_runner(config(SnapshotBackend::Btrfs, 0), runner);
+
+        let mut executed = false;
+        let (snapshots, result) = manager.with_snapshots("1 paquet", async { executed = true; Ok(()) }).await;
+        assert!(snapshots.is_empty());
+        assert!(result.is_err());
+        assert!(!executed);
+    }
+}
@@ -0,0 +1,345 @@
+//! Vérification native des mises à jour
+//!
+//! Les bases de synchronisation (`<dépôt>.db`, archives tar compressées) sont
+//! téléchargées depuis les miroirs de pacman.conf dans un répertoire privé, puis
+//! comparées à la base locale avec vercmp. Contrairement à `checkupdates`, ni
+//! pacman-contrib, ni fakeroot, ni les droits root ne sont nécessaires, et la
+//! base de synchronisation du système n'est pas modifiée.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::localdb::{parse_sections, parse_timestamp, LocalDatabase, LocalPackage};
+use crate::pacman::PackageUpdate;
+use crate::pacman_conf::PacmanConf;
+use crate::preview::PackageInfo;
+use crate::version::vercmp;
+
+/// Source de la vérification des mises à jour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateBackend {
+    /// checkupdates s'il est installé, sinon vérification native
+    #[default]
+    Auto,
+    /// checkupdates (pacman-contrib) uniquement
+    Checkupdates,
+    /// Bases de synchronisation téléchargées et lues par CachyPac
+    Native,
+}
+
+/// Paquet d'une base de synchronisation
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SyncPackage {
+    pub name: String,
+    pub version: String,
+    pub repository: String,
+    /// Archive à télécharger (`%FILENAME%`)
+    pub filename: String,
+    /// Taille de l'archive (`%CSIZE%`)
+    pub download_size: u64,
+    /// Taille installée (`%ISIZE%`)
+    pub installed_size: u64,
+    pub groups: Vec<String>,
+    pub build_date: Option<DateTime<Local>>,
+}
+
+impl SyncPackage {
+    /// Champs au format de `pacman -Si`, pour les règles de retenue
+    pub fn info(&self) -> HashMap<String, String> {
+        let mut info = HashMap::from([
+            ("Repository".to_string(), self.repository.clone()),
+            ("Name".to_string(), self.name.clone()),
+            ("Version".to_string(), self.version.clone()),
+            ("Groups".to_string(), if self.groups.is_empty() { "None".to_string() } else { self.groups.join(" ") }),
+        ]);
+        if let Some(date) = self.build_date {
+            info.insert("Build Date".to_string(), date.format("%a %b %d %H:%M:%S %Y").to_string());
+        }
+        info
+    }
+}
+
+/// Analyse le `desc` d'un paquet d'une base de synchronisation
+fn parse_sync_desc(content: &str, repository: &str) -> Option<SyncPackage> {
+    let sections = parse_sections(content);
+    let single = |key: &str| sections.get(key).and_then(|values| values.first()).copied();
+    let size = |key: &str| single(key).and_then(|value| value.parse().ok()).unwrap_or(0);
+
+    Some(SyncPackage {
+        name: single("NAME")?.to_string(),
+        version: single("VERSION")?.to_string(),
+        repository: repository.to_string(),
+        filename: single("FILENAME").unwrap_or_default().to_string(),
+        download_size: size("CSIZE"),
+        installed_size: size("ISIZE"),
+        groups: sections
+            .get("GROUPS")
+            .map(|values| values.iter().map(ToString::to_string).collect())
+            .unwrap_or_default(),
+        build_date: single("BUILDDATE").and_then(parse_timestamp),
+    })
+}
+
+/// Décompresse une base selon sa signature (gzip, zstd ou tar non compressé)
+fn decompress(data: &[u8]) -> Result<Box<dyn Read + '_>> {
+    match data {
+        [0x1f, 0x8b, ..] => Ok(Box::new(flate2::read::GzDecoder::new(data))),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => Ok(Box::new(
+            ruzstd::decoding::StreamingDecoder::new(data).context("Archive zstd invalide")?,
+        )),
+        [0xfd, b'7', b'z', b'X', b'Z', ..] => Err(anyhow::anyhow!("Bases compressées en xz non prises en charge")),
+        _ => Ok(Box::new(data)),
+    }
+}
+
+/// Lit les paquets d'une base de synchronisation (`<dépôt>.db`)
+pub fn parse_sync_db(data: &[u8], repository: &str) -> Result<Vec<SyncPackage>> {
+    let mut archive = tar::Archive::new(decompress(data)?);
+    let mut packages = Vec::new();
+    for entry in archive.entries().context("Base de synchronisation illisible")? {
+        let mut entry = entry.context("Entrée de la base de synchronisation illisible")?;
+        let is_desc = entry.path().is_ok_and(|path| path.file_name().is_some_and(|name| name == "desc"));
+        if !is_desc {
+            continue;
+        }
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content).context("Fichier desc illisible")?;
+        match parse_sync_desc(&content, repository) {
+            Some(package) => packages.push(package),
+            None => warn!("⚠️ Paquet sans nom ni version ignoré dans {}", repository),
+        }
+    }
+    Ok(packages)
+}
+
+/// Compare la base locale aux bases de synchronisation, dans l'ordre des dépôts
+///
+/// Comme pacman, le premier dépôt qui contient un paquet l'emporte. Renvoie les
+/// mises à jour triées par nom et les champs `pacman -Si` correspondants.
+pub fn compute_updates(local: &[LocalPackage], sync: &[SyncPackage]) -> (Vec<PackageUpdate>, PackageInfo) {
+    let mut candidates: HashMap<&str, &SyncPackage> = HashMap::new();
+    for package in sync {
+        candidates.entry(package.name.as_str()).or_insert(package);
+    }
+
+    let mut updates = Vec::new();
+    let mut info = PackageInfo::new();
+    for installed in local {
+        let Some(candidate) = candidates.get(installed.name.as_str()) else {
+            continue;
+        };
+        if vercmp(&candidate.version, &installed.version) != std::cmp::Ordering::Greater {
+            continue;
+        }
+
+        let mut update = PackageUpdate::new(installed.name.clone(), installed.version.clone(), candidate.version.clone());
+        update.repository.clone_from(&candidate.repository);
+        update.download_size = Some(candidate.download_size);
+        update.installed_size_delta = i64::try_from(candidate.installed_size)
+            .ok()
+            .zip(i64::try_from(installed.size).ok())
+            .map(|(new, current)| new - current);
+        info.insert(installed.name.clone(), candidate.info());
+        updates.push(update);
+    }
+    updates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    (updates, info)
+}
+
+/// Téléchargement des bases de synchronisation dans un répertoire privé
+#[derive(Debug, Clone)]
+pub struct SyncDbManager {
+    pacman_conf: PathBuf,
+    /// Répertoire privé des bases téléchargées (jamais celui de pacman)
+    sync_dir: PathBuf,
+    local_db: LocalDatabase,
+    timeout: Duration,
+}
+
+impl SyncDbManager {
+    pub fn new(pacman_conf: PathBuf, sync_dir: PathBuf, local_db: LocalDatabase, timeout_secs: u64) -> Self {
+        Self {
+            pacman_conf,
+            sync_dir,
+            local_db,
+            timeout: Duration::from_secs(timeout_secs),
+        }
+    }
+
+    /// Télécharge une base depuis un miroir (http(s) ou file://)
+    async fn fetch(&self, client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+        if let Some(path) = url.strip_prefix("file://") {
+            return tokio::fs::read(path).await.context(format!("Impossible de lire {}", path));
+        }
+
+        let bytes = client
+            .get(url)
+            .send()
+            .await
+            .context(format!("Impossible de joindre {}", url))?
+            .error_for_status()
+            .context(format!("Réponse HTTP invalide pour {}", url))?
+            .bytes()
+            .await
+            .context(format!("Téléchargement interrompu: {}", url))?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Télécharge les bases de tous les dépôts (premier miroir qui répond) et
+    /// renvoie leurs paquets, dans l'ordre des dépôts
+    ///
+    /// Si aucun miroir ne répond, la copie du téléchargement précédent est utilisée.
+    pub async fn refresh(&self) -> Result<Vec<SyncPackage>> {
+        let conf = PacmanConf::load(&self.pacman_conf).await?;
+        if conf.repositories.is_empty() {
+            return Err(anyhow::anyhow!("Aucun dépôt dans {}", self.pacman_conf.display()));
+        }
+        tokio::fs::create_dir_all(&self.sync_dir)
+            .await
+            .context(format!("Impossible de créer {}", self.sync_dir.display()))?;
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .user_agent(concat!("CachyPac/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("Impossible de créer le client HTTP")?;
+
+        let mut packages = Vec::new();
+        for repository in &conf.repositories {
+            let path = self.sync_dir.join(format!("{}.db", repository.name));
+            let mut data = None;
+            for url in repository.db_urls(conf.architecture()) {
+                match self.fetch(&client, &url).await {
+                    Ok(bytes) => {
+                        debug!("📥 {} ({} octets)", url, bytes.len());
+                        data = Some(bytes);
+                        break;
+                    }
+                    Err(e) => warn!("⚠️ Miroir indisponible pour {}: {:#}", repository.name, e),
+                }
+            }
+
+            let data = match data {
+                Some(bytes) => {
+                    write_atomically(&path, &bytes).await?;
+                    bytes
+                }
+                None => {
+                    warn!("⚠️ Aucun miroir n'a répondu pour {}: base précédente utilisée", repository.name);
+                    tokio::fs::read(&path)
+                        .await
+                        .context(format!("Base de synchronisation de {} indisponible", repository.name))?
+                }
+            };
+
+            let name = repository.name.clone();
+            let mut repository_packages = tokio::task::spawn_blocking(move || parse_sync_db(&data, &name))
+                .await
+                .context("Échec de la lecture de la base de synchronisation")?
+                .context(format!("Base de synchronisation de {} invalide", repository.name))?;
+            debug!("📦 {}: {} paquets", repository.name, repository_packages.len());
+            packages.append(&mut repository_packages);
+        }
+
+        Ok(packages)
+    }
+
+    /// Mises à jour disponibles, sans checkupdates ni pacman
+    pub async fn check_updates(&self) -> Result<(Vec<PackageUpdate>, PackageInfo)> {
+        let sync = self.refresh().await?;
+        let local = self.local_db.packages().await?;
+        let (updates, info) = compute_updates(&local, &sync);
+        info!("📦 {} mises à jour trouvées dans les bases de synchronisation", updates.len());
+        Ok((updates, info))
+    }
+}
+
+/// Remplace un fichier sans jamais laisser de version tronquée
+async fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let mut part = path.to_path_buf().into_os_string();
+    part.push(".part");
+    tokio::fs::write(&part, data)
+        .await
+        .context(format!("Impossible d'écrire {}", path.display()))?;
+    tokio::fs::rename(&part, path)
+        .await
+        .context(format!("Impossible d'écrire {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localdb::InstallReason;
+
+    /// Construit une base de synchronisation `tar.gz` à partir de fichiers desc
+    fn build_sync_db(packages: &[(&str, &str)]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, version) in packages {
+            let desc = format!(
+                "%FILENAME%\n{name}-{version}-x86_64.pkg.tar.zst\n\n%NAME%\n{name}\n\n%VERSION%\n{version}\n\n\
+                 %CSIZE%\n1000\n\n%ISIZE%\n5000\n\n%GROUPS%\nbase\n\n%BUILDDATE%\n1700000000\n\n"
+            );
+            let mut header = tar::Header::new_gnu();
+            header.set_size(desc.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, format!("{name}-{version}/desc"), desc.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn local(name: &str, version: &str) -> LocalPackage {
+        LocalPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            size: 4000,
+            reason: InstallReason::Explicit,
+            ..LocalPackage::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_sync_db() {
+        let data = build_sync_db(&[("firefox", "92.0-1"), ("linux", "6.1.2-1")]);
+        let packages = parse_sync_db(&data, "extra").unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "firefox");
+        assert_eq!(packages[0].filename, "firefox-92.0-1-x86_64.pkg.tar.zst");
+        assert_eq!((packages[0].download_size, packages[0].installed_size), (1000, 5000));
+        assert_eq!(packages[0].repository, "extra");
+        assert_eq!(packages[0].info()["Groups"], "base");
+
+        assert!(parse_sync_db(b"not a database", "extra").is_err());
+    }
+
+    #[test]
+    fn test_compute_updates() {
+        let mut sync = parse_sync_db(&build_sync_db(&[("firefox", "92.0-1"), ("linux", "6.1.2-1")]), "core").unwrap();
+        // Le premier dépôt l'emporte, même avec une version plus récente plus loin
+        sync.extend(parse_sync_db(&build_sync_db(&[("linux", "6.2.0-1"), ("gtk2", "2.24.33-3")]), "testing").unwrap());
+        let installed = vec![
+            local("firefox", "91.0-1"),
+            local("gtk2", "2.24.33-3"),
+            local("linux", "6.1.2-1"),
+            local("yay", "12.0-1"),
+        ];
+
+        let (updates, info) = compute_updates(&installed, &sync);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].to_string(), "firefox 91.0-1 -> 92.0-1 [core] (1000 B)");
+        assert_eq!(updates[0].installed_size_delta, Some(1000));
+        assert_eq!(info["firefox"]["Repository"], "core");
+        assert!(info["firefox"].contains_key("Build Date"));
+    }
+}
@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Version d'un paquet Arch (`epoch:pkgver-pkgrel`), ordonnée comme libalpm
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+#[allow(dead_code)]
+pub struct Version(String);
+
+#[allow(dead_code)]
+impl Version {
+    pub fn new(version: impl Into<String>) -> Self {
+        Self(version.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Époque de la version ("0" si absente)
+    pub fn epoch(&self) -> &str {
+        parse_evr(&self.0).0
+    }
+
+    pub fn pkgver(&self) -> &str {
+        parse_evr(&self.0).1
+    }
+
+    pub fn pkgrel(&self) -> Option<&str> {
+        parse_evr(&self.0).2
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::str::FromStr for Version {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s))
+    }
+}
+
+impl From<&str> for Version {
+    fn from(version: &str) -> Self {
+        Self::new(version)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        vercmp(&self.0, &other.0)
+    }
+}
+
+/// Compare deux versions complètes avec la sémantique de `alpm_pkg_vercmp`
+///
+/// L'époque est comparée en premier, puis pkgver, puis pkgrel uniquement
+/// si les deux versions en possèdent un.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let (epoch_a, ver_a, rel_a) = parse_evr(a);
+    let (epoch_b, ver_b, rel_b) = parse_evr(b);
+
+    rpmvercmp(epoch_a, epoch_b)
+        .then_with(|| rpmvercmp(ver_a, ver_b))
+        .then_with(|| match (rel_a, rel_b) {
+            (Some(rel_a), Some(rel_b)) => rpmvercmp(rel_a, rel_b),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Découpe `epoch:pkgver-pkgrel` comme `parseEVR` de libalpm
+fn parse_evr(evr: &str) -> (&str, &str, Option<&str>) {
+    let digits = evr.bytes().take_while(u8::is_ascii_digit).count();
+
+    let (epoch, rest) = if evr.as_bytes().get(digits) == Some(&b':') {
+        (&evr[..digits], &evr[digits + 1..])
+    } else {
+        ("0", evr)
+    };
+    let epoch = if epoch.is_empty() { "0" } else { epoch };
+
+    rest.rsplit_once('-')
+        .map_or((epoch, rest, None), |(version, release)| (epoch, version, Some(release)))
+}
+
+/// Compare deux segments de version (algorithme `rpmvercmp` de libalpm)
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let (mut one, mut two) = (0, 0);
+
+    while one < a.len() && two < b.len() {
+        let (start_one, start_two) = (one, two);
+        while one < a.len() && !a[one].is_ascii_alphanumeric() {
+            one += 1;
+        }
+        while two < b.len() && !b[two].is_ascii_alphanumeric() {
+            two += 1;
+        }
+
+        if one >= a.len() || two >= b.len() {
+            break;
+        }
+
+        // Des séparateurs de longueurs différentes décident seuls
+        if one - start_one != two - start_two {
+            return (one - start_one).cmp(&(two - start_two));
+        }
+
+        let is_num = a[one].is_ascii_digit();
+        let segment_end = |bytes: &[u8], mut pos: usize| {
+            while pos < bytes.len()
+                && (if is_num { bytes[pos].is_ascii_digit() } else { bytes[pos].is_ascii_alphabetic() })
+            {
+                pos += 1;
+            }
+            pos
+        };
+        let end_one = segment_end(a, one);
+        let end_two = segment_end(b, two);
+
+        // Un segment numérique est toujours plus récent qu'un segment alphabétique
+        if two == end_two {
+            return if is_num { Ordering::Greater } else { Ordering::Less };
+        }
+
+        let mut segment_one = &a[one..end_one];
+        let mut segment_two = &b[two..end_two];
+        if is_num {
+            while segment_one.first() == Some(&b'0') {
+                segment_one = &segment_one[1..];
+            }
+            while segment_two.first() == Some(&b'0') {
+                segment_two = &segment_two[1..];
+            }
+            let by_length = segment_one.len().cmp(&segment_two.len());
+            if by_length != Ordering::Equal {
+                return by_length;
+            }
+        }
+
+        let by_content = segment_one.cmp(segment_two);
+        if by_content != Ordering::Equal {
+            return by_content;
+        }
+
+        one = end_one;
+        two = end_two;
+    }
+
+    match (one >= a.len(), two >= b.len()) {
+        (true, true) => Ordering::Equal,
+        // Le reste alphabétique ("1.0a" contre "1.0") indique une pré-version
+        (true, false) if !b[two].is_ascii_alphabetic() => Ordering::Less,
+        (false, _) if a[one].is_ascii_alphabetic() => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Table reprise de test/util/vercmptest.sh de pacman
+    const VERCMP_CASES: &[(&str, &str, i8)] = &[
+        // versions de même longueur, sans pkgrel
+        ("1.5.0", "1.5.0", 0),
+        ("1.5.1", "1.5.0", 1),
+        // longueurs différentes
+        ("1.5.1", "1.5", 1),
+        // avec pkgrel, cas simples
+        ("1.5.0-1", "1.5.0-1", 0),
+        ("1.5.0-1", "1.5.0-2", -1),
+        ("1.5.0-1", "1.5.1-1", -1),
+        ("1.5.0-2", "1.5.1-1", -1),
+        // avec pkgrel, longueurs différentes
+        ("1.5-1", "1.5.1-1", -1),
+        ("1.5-2", "1.5.1-1", -1),
+        ("1.5-2", "1.5.1-2", -1),
+        // pkgrel présent d'un seul côté
+        ("1.5", "1.5-1", 0),
+        ("1.5-1", "1.5", 0),
+        ("1.1-1", "1.1", 0),
+        ("1.0-1", "1.1", -1),
+        ("1.1-1", "1.0", 1),
+        // versions alphanumériques
+        ("1.5b-1", "1.5-1", -1),
+        ("1.5b", "1.5", -1),
+        ("1.5b-1", "1.5", -1),
+        ("1.5b", "1.5.1", -1),
+        // exemples de la page de manuel
+        ("1.0a", "1.0alpha", -1),
+        ("1.0alpha", "1.0b", -1),
+        ("1.0b", "1.0beta", -1),
+        ("1.0beta", "1.0rc", -1),
+        ("1.0rc", "1.0", -1),
+        // segments alphabétiques séparés par des points
+        ("1.5.a", "1.5", 1),
+        ("1.5.b", "1.5.a", 1),
+        ("1.5.1", "1.5.b", 1),
+        // points et tirets
+        ("1.5.b-1", "1.5.b", 0),
+        ("1.5-1", "1.5.b", -1),
+        // même contenu, séparateurs différents
+        ("2.0", "2_0", 0),
+        ("2.0_a", "2_0.a", 0),
+        ("2.0a", "2.0.a", -1),
+        ("2___a", "2_a", 1),
+        // comparaisons avec époque
+        ("0:1.0", "0:1.0", 0),
+        ("0:1.0", "0:1.1", -1),
+        ("1:1.0", "0:1.0", 1),
+        ("1:1.0", "0:1.1", 1),
+        ("1:1.0", "2:1.1", -1),
+        // époque et pkgrel optionnel
+        ("1:1.0", "0:1.0-1", 1),
+        ("1:1.0-1", "0:1.1-1", 1),
+        // époque d'un seul côté
+        ("0:1.0", "1.0", 0),
+        ("0:1.1", "1.0", 1),
+        ("0:1.1", "1.1", 0),
+        ("1.0", "0:1.1", -1),
+        ("1:1.0", "1.0", 1),
+        ("1:1.0", "1.1", 1),
+        ("1:1.1", "1.1", 1),
+        ("1.1", "1:1.1", -1),
+    ];
+
+    fn expected(value: i8) -> Ordering {
+        value.cmp(&0)
+    }
+
+    #[test]
+    fn test_vercmp_table() {
+        for &(a, b, result) in VERCMP_CASES {
+            assert_eq!(vercmp(a, b), expected(result), "vercmp {} {}", a, b);
+            // La comparaison inverse doit donner le résultat opposé
+            assert_eq!(vercmp(b, a), expected(result).reverse(), "vercmp {} {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_vercmp_leading_zeros_and_numbers() {
+        assert_eq!(vercmp("1.001", "1.1"), Ordering::Equal);
+        assert_eq!(vercmp("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(vercmp("20240101", "20231231"), Ordering::Greater);
+        assert_eq!(vercmp("6.6.1.arch1-1", "6.6.1.arch2-1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_version_type() {
+        let mut versions: Vec<Version> = ["1:1.0-1", "1.5-1", "1.5.1-1", "1.5rc1-1", "1.5"]
+            .iter()
+            .map(|v| Version::from(*v))
+            .collect();
+        versions.sort();
+
+        let sorted: Vec<&str> = versions.iter().map(Version::as_str).collect();
+        assert_eq!(sorted, vec!["1.5rc1-1", "1.5-1", "1.5", "1.5.1-1", "1:1.0-1"]);
+        assert_eq!(Version::from("1.5"), Version::from("1.5-3"));
+
+        let version = Version::from("2:6.6.1.arch1-1");
+        assert_eq!(version.epoch(), "2");
+        assert_eq!(version.pkgver(), "6.6.1.arch1");
+        assert_eq!(version.pkgrel(), Some("1"));
+        assert_eq!(Version::from("1.0").epoch(), "0");
+        assert_eq!(Version::from("1.0").pkgrel(), None);
+    }
+}
@@ -1,8 +1,18 @@
 use std::path::PathBuf;
 use tokio::time::{sleep, Duration};
 use cachypac::{
-    config::Config,
-    pacman::PacmanManager,
+    advisory::{AdvisoryManager, AutoUpdatePolicy, Severity},
+    config::{Config, MirrorsConfig},
+    helper::{AccessPolicy, HelperServer},
+    hold::{HoldReason, HoldRule},
+    lock::{find_lock_error, LockError},
+    mirrors::{MirrorManager, MirrorState},
+    pacman::{PackageUpdate, PacmanManager},
+    pacman_conf::Mirrorlist,
+    plan::UpgradeMode,
+    runner::{ScriptedResponse, ScriptedRunner},
+    snapshot::{SnapshotBackend, SnapshotKind, SnapshotManager},
+    syncdb::UpdateBackend,
     scheduler::SchedulerManager,
     telegram::TelegramNotifier,
     history::{UpdateHistory, HistoryEntry, OperationType},
@@ -78,6 +88,11 @@ async fn test_update_history_operations() {
         timestamp: chrono::Local::now(),
         operation_type: OperationType::CheckUpdates,
         packages: vec!["test-package".to_string()],
+        updates: Vec::new(),
+        pacnew_files: Vec::new(),
+        snapshots: Vec::new(),
+        freed_bytes: None,
+        restart: None,
         success: true,
         message: "Test successful".to_string(),
         duration: Duration::from_secs(5),
@@ -178,6 +193,11 @@ async fn test_full_workflow_simulation() {
         timestamp: chrono::Local::now(),
         operation_type: OperationType::CheckUpdates,
         packages: vec!["simulated-package".to_string()],
+        updates: Vec::new(),
+        pacnew_files: Vec::new(),
+        snapshots: Vec::new(),
+        freed_bytes: None,
+        restart: None,
         success: true,
         message: "Workflow test successful".to_string(),
         duration: Duration::from_millis(100),
@@ -319,4 +339,726 @@ fn test_log_level_display() {
     assert_eq!(LogLevel::Info.to_string(), "INFO");
     assert_eq!(LogLevel::Debug.to_string(), "DEBUG");
     assert_eq!(LogLevel::Trace.to_string(), "TRACE");
-}
\ No newline at end of file
+}
+
+fn scripted_pacman_config() -> cachypac::config::PacmanConfig {
+    let mut config = Config::default().pacman;
+    config.timeout = 1;
+    config.retry_count = 3;
+    config.retry_delay = 0;
+    config.clean_cache_after = false;
+    config
+}
+
+#[tokio::test]
+async fn test_check_updates_with_scripted_runner() {
+    // Vérification complète sans pacman: checkupdates puis pacman -Si/-Qi
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::ok("/usr/bin/checkupdates\n"))
+        .on(&["checkupdates"], ScriptedResponse::ok("firefox 91.0-1 -> 92.0-1\nlinux 6.1.1-1 -> 6.1.2-1\n"))
+        .on(&["pacman", "-Si"], ScriptedResponse::ok(
+            "Repository      : extra\nName            : firefox\nDownload Size   : 60.00 MiB\nInstalled Size  : 230.00 MiB\n\n\
+             Repository      : core\nName            : linux\nDownload Size   : 130.00 MiB\nInstalled Size  : 140.00 MiB\n"
+        ))
+        .on(&["pacman", "-Qi"], ScriptedResponse::ok(
+            "Name            : firefox\nInstalled Size  : 228.00 MiB\n\nName            : linux\nInstalled Size  : 141.00 MiB\n"
+        ));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner.clone());
+
+    let updates = manager.check_updates().await.unwrap();
+
+    assert_eq!(updates.len(), 2);
+    assert_eq!(updates[0].repository, "extra");
+    assert_eq!(updates[0].download_size, Some(60 * 1024 * 1024));
+    assert_eq!(updates[0].installed_size_delta, Some(2 * 1024 * 1024));
+    assert_eq!(updates[1].repository, "core");
+    assert_eq!(updates[1].installed_size_delta, Some(-1024 * 1024));
+    assert_eq!(runner.invocations()[2].to_string(), "pacman -Si firefox linux");
+}
+
+#[tokio::test]
+async fn test_check_updates_honours_pacman_ignores() {
+    // IgnorePkg et IgnoreGroup de pacman.conf écartent les mises à jour
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let pacman_conf = temp_dir.path().join("pacman.conf");
+    std::fs::write(&pacman_conf, "[options]\nIgnorePkg = linux*\nIgnoreGroup = gnome\n").unwrap();
+
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::ok("/usr/bin/checkupdates\n"))
+        .on(&["checkupdates"], ScriptedResponse::ok(
+            "firefox 91.0-1 -> 92.0-1\nlinux 6.1.1-1 -> 6.1.2-1\ngedit 46.1-1 -> 46.2-1\nvlc 3.0.20-1 -> 3.0.21-1\n"
+        ))
+        .on(&["pacman", "-Si"], ScriptedResponse::ok(
+            "Repository      : extra\nName            : gedit\nGroups          : gnome\n"
+        ))
+        .on(&["pacman", "-Qi"], ScriptedResponse::ok(""));
+    let mut config = scripted_pacman_config();
+    config.pacman_conf = pacman_conf;
+    config.exclude_packages = vec!["vlc".to_string()];
+    let manager = PacmanManager::with_runner(config, runner);
+
+    let updates = manager.check_updates().await.unwrap();
+
+    // vlc reste visible: c'est le plan qui l'écarte de la transaction
+    let names: Vec<&str> = updates.iter().map(|update| update.name.as_str()).collect();
+    assert_eq!(names, vec!["firefox", "vlc"]);
+    let plan = manager.plan_upgrade(&updates, &[]).await.unwrap();
+    assert_eq!(plan.target_names(), vec!["firefox"]);
+    assert_eq!(plan.ignored, vec!["vlc"]);
+}
+
+#[tokio::test]
+async fn test_on_security_fix_policy_from_local_tracker() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let tracker = temp_dir.path().join("all.json");
+    std::fs::write(&tracker, r#"[
+        {"name": "AVG-3000", "packages": ["firefox"], "status": "Fixed", "severity": "Critical", "type": "arbitrary code execution",
+         "affected": "91.0-1", "fixed": "92.0-1", "issues": ["CVE-2024-0001"]},
+        {"name": "AVG-3001", "packages": ["linux"], "status": "Vulnerable", "severity": "High", "type": "privilege escalation",
+         "affected": "6.1.1-1", "fixed": null, "issues": ["CVE-2024-0002"]}
+    ]"#).unwrap();
+
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::ok("/usr/bin/checkupdates\n"))
+        .on(&["checkupdates"], ScriptedResponse::ok("firefox 91.0-1 -> 92.0-1\nlinux 6.1.1-1 -> 6.1.2-1\n"))
+        .on(&["pacman", "-Q"], ScriptedResponse::ok("firefox 91.0-1\nlinux 6.1.1-1\nbash 5.2-1\n"));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner);
+    let mut updates = manager.check_updates().await.unwrap();
+
+    let database = AdvisoryManager::new(tracker.to_string_lossy().to_string()).fetch().await.unwrap();
+    database.tag_updates(&mut updates);
+    assert_eq!(updates[0].security.as_ref().unwrap().severity, Severity::Critical);
+    assert!(updates[1].security.is_none());
+    assert!(AutoUpdatePolicy::OnSecurityFix.should_update(&updates));
+    assert!(!AutoUpdatePolicy::OnSecurityFix.should_update(&updates[1..]));
+
+    // linux reste vulnérable malgré la mise à jour: c'est la seule alerte à envoyer
+    let installed = manager.installed_packages().await.unwrap();
+    let unfixed: Vec<String> = database
+        .vulnerabilities(&installed, &updates)
+        .iter()
+        .filter(|v| v.is_unfixed())
+        .map(|v| v.package.clone())
+        .collect();
+    assert_eq!(unfixed, vec!["linux"]);
+}
+
+#[tokio::test]
+async fn test_check_updates_applies_hold_rules() {
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::ok("/usr/bin/checkupdates\n"))
+        .on(&["checkupdates"], ScriptedResponse::ok("firefox 91.0-1 -> 92.0-1\nnautilus 46.1-1 -> 46.2-1\nnvidia-utils 550.1-1 -> 560.1-1\n"))
+        .on(&["pacman", "-Si"], ScriptedResponse::ok(
+            "Repository      : extra\nName            : firefox\nGroups          : None\n\n\
+             Repository      : extra\nName            : nautilus\nGroups          : gnome\n\n\
+             Repository      : extra\nName            : nvidia-utils\nGroups          : None\n"
+        ))
+        .on(&["pacman", "-Qi"], ScriptedResponse::ok(""));
+    let mut config = scripted_pacman_config();
+    config.hold_rules = vec![
+        HoldRule { group: Some("gnome".to_string()), ..HoldRule::default() },
+        HoldRule { package: Some("nvidia-*".to_string()), major_only: true, ..HoldRule::default() },
+    ];
+    let manager = PacmanManager::with_runner(config, runner);
+
+    let updates = manager.check_updates().await.unwrap();
+
+    assert!(updates[0].hold.is_none());
+    assert_eq!(updates[1].hold.as_ref().unwrap().reason, HoldReason::Pinned);
+    assert!(matches!(updates[2].hold.as_ref().unwrap().reason, HoldReason::MajorVersionBump { .. }));
+
+    let selected: Vec<String> = updates.iter().map(|u| u.name.clone()).collect();
+    let plan = manager.plan_upgrade(&updates, &selected).await.unwrap();
+    assert_eq!(plan.ignored, vec!["nautilus", "nvidia-utils"]);
+    assert_eq!(plan.targets.len(), 1);
+}
+
+#[tokio::test]
+async fn test_install_waits_for_database_lock() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let mut config = scripted_pacman_config();
+    config.db_path = temp_dir.path().to_path_buf();
+    config.lock_timeout = 0;
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    // pacman signale le verrou: pas de tentative normale consommée, erreur distincte
+    let runner = ScriptedRunner::new().on(&["sudo", "pacman"], ScriptedResponse::exit(
+        1, "", "error: failed to init transaction (unable to lock database)\n"
+    ));
+    let manager = PacmanManager::with_runner(config.clone(), runner.clone());
+    let error = manager.install_updates(&updates).await.unwrap_err();
+    assert!(matches!(find_lock_error(&error), Some(LockError::Refused(_))));
+    assert_eq!(runner.count(&["sudo", "pacman"]), 4);
+
+    // Verrou détenu par un pacman actif: aucune transaction lancée
+    std::fs::write(temp_dir.path().join("db.lck"), "").unwrap();
+    let runner = ScriptedRunner::new()
+        .on(&["pgrep"], ScriptedResponse::ok("4242 pacman\n"))
+        .on(&["sudo", "pacman"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(config.clone(), runner.clone());
+    let error = manager.install_updates(&updates).await.unwrap_err();
+    assert!(matches!(find_lock_error(&error), Some(LockError::Held { .. })));
+    assert_eq!(runner.count(&["sudo", "pacman"]), 0);
+
+    // Verrou orphelin supprimé si la configuration l'autorise
+    config.remove_stale_lock = true;
+    let runner = ScriptedRunner::new()
+        .on(&["pgrep"], ScriptedResponse::exit(1, "", ""))
+        .on(&["sudo", "rm", "-f"], ScriptedResponse::ok(""))
+        .on(&["sudo", "pacman"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(config, runner.clone());
+    manager.install_updates(&updates).await.unwrap();
+    assert_eq!(runner.count(&["sudo", "rm", "-f"]), 1);
+    assert_eq!(runner.count(&["sudo", "pacman"]), 1);
+}
+
+#[tokio::test]
+async fn test_install_through_privileged_helper() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let socket = temp_dir.path().join("helper.sock");
+    let helper_runner = ScriptedRunner::new()
+        .on(&["id", "-Gn"], ScriptedResponse::ok("cachypac\n"))
+        .on(&["pacman", "-Syu"], ScriptedResponse::ok("(1/1) upgrading firefox\n"))
+        .on(&["pacman", "-Qq"], ScriptedResponse::ok("firefox\n"));
+    let cache_dir = temp_dir.path().join("pkg");
+    std::fs::create_dir(&cache_dir).unwrap();
+    for name in ["firefox-91.0-1-x86_64.pkg.tar.zst", "firefox-92.0-1-x86_64.pkg.tar.zst"] {
+        std::fs::write(cache_dir.join(name), "archive").unwrap();
+    }
+    let server = HelperServer::with_runner(helper_runner.clone(), socket.clone(), AccessPolicy::default())
+        .with_cache_dir(cache_dir.clone());
+    let listener = server.bind().await.unwrap();
+    tokio::spawn(async move { server.serve(listener).await });
+
+    // Le manager n'exécute plus rien lui-même: ni sudo ni pacman
+    let mut config = scripted_pacman_config();
+    config.helper_socket = Some(socket);
+    config.cache_dir = cache_dir.clone();
+    config.cache_retention.keep_installed = 1;
+    let runner = ScriptedRunner::new().on(&["pacman", "-Qq"], ScriptedResponse::ok("firefox\n"));
+    let manager = PacmanManager::with_runner(config, runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+    manager.install_updates(&updates).await.unwrap();
+
+    // Le nettoyage du cache est fait par le helper, qui supprime lui-même les archives
+    let mut history = UpdateHistory::new(temp_dir.path().join("data"));
+    assert_eq!(manager.clean_cache(&mut history).await.unwrap(), 7);
+    assert!(!cache_dir.join("firefox-91.0-1-x86_64.pkg.tar.zst").exists());
+    assert!(cache_dir.join("firefox-92.0-1-x86_64.pkg.tar.zst").exists());
+
+    assert_eq!(runner.count(&["sudo"]), 0);
+    assert_eq!(helper_runner.count(&["pacman", "-Syu", "--noconfirm"]), 1);
+    assert_eq!(helper_runner.count(&["pacman", "-Sc"]), 0);
+}
+
+#[tokio::test]
+async fn test_check_updates_without_checkupdates() {
+    // checkupdates imposé mais absent: la vérification échoue proprement
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::exit(1, "", "which: no checkupdates"));
+    let mut config = scripted_pacman_config();
+    config.update_backend = UpdateBackend::Checkupdates;
+    let manager = PacmanManager::with_runner(config, runner.clone());
+
+    let result = manager.check_updates().await;
+
+    assert!(result.is_err());
+    assert_eq!(runner.count(&["checkupdates"]), 0);
+}
+
+#[tokio::test]
+async fn test_check_updates_native_fallback() {
+    // Sans pacman-contrib, les bases de synchronisation sont téléchargées
+    // depuis le miroir et comparées à la base locale
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let mirror = temp_dir.path().join("mirror");
+    std::fs::create_dir_all(&mirror).unwrap();
+
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, version) in [("firefox", "92.0-1"), ("linux", "6.1.1-1"), ("nvidia", "560.35-1")] {
+        let desc = format!("%NAME%\n{name}\n\n%VERSION%\n{version}\n\n%CSIZE%\n1000\n\n%ISIZE%\n5000\n\n");
+        let mut header = tar::Header::new_gnu();
+        header.set_size(desc.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, format!("{name}-{version}/desc"), desc.as_bytes()).unwrap();
+    }
+    let db = builder.into_inner().unwrap().finish().unwrap();
+    std::fs::write(mirror.join("extra.db"), db).unwrap();
+
+    let pacman_conf = temp_dir.path().join("pacman.conf");
+    std::fs::write(
+        &pacman_conf,
+        format!("[options]\nArchitecture = auto\n\n[extra]\nServer = file://{}\n", mirror.display()),
+    )
+    .unwrap();
+
+    let db_path = temp_dir.path().join("db");
+    for (name, version) in [("firefox", "91.0-1"), ("linux", "6.1.1-1"), ("nvidia", "550.40-1")] {
+        let entry = db_path.join("local").join(format!("{name}-{version}"));
+        std::fs::create_dir_all(&entry).unwrap();
+        std::fs::write(entry.join("desc"), format!("%NAME%\n{name}\n\n%VERSION%\n{version}\n\n")).unwrap();
+    }
+
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::exit(1, "", "which: no checkupdates"));
+    let mut config = scripted_pacman_config();
+    config.pacman_conf = pacman_conf;
+    config.sync_db_dir = temp_dir.path().join("sync");
+    config.db_path = db_path;
+    config.exclude_packages = vec!["nvidia".to_string()];
+    let manager = PacmanManager::with_runner(config, runner.clone());
+
+    let updates = manager.check_updates().await.unwrap();
+
+    assert_eq!(updates.len(), 2);
+    assert_eq!(updates[0].name, "firefox");
+    assert_eq!(updates[0].current_version, "91.0-1");
+    assert_eq!(updates[0].new_version, "92.0-1");
+    assert_eq!(updates[0].repository, "extra");
+    assert_eq!(runner.count(&["checkupdates"]), 0);
+    assert!(temp_dir.path().join("sync").join("extra.db").exists());
+
+    // nvidia est exclu de la transaction quel que soit le vérificateur
+    let plan = manager.plan_upgrade(&updates, &[]).await.unwrap();
+    assert_eq!(plan.target_names(), vec!["firefox"]);
+    assert_eq!(plan.held_back[0].name, "nvidia");
+    assert_eq!(plan.ignored, vec!["nvidia"]);
+}
+
+/// Démarre un faux serveur RPC AUR local qui renvoie toujours `body`
+async fn spawn_aur_stand_in(body: &'static str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    });
+
+    format!("http://{}/rpc/", address)
+}
+
+#[tokio::test]
+async fn test_check_updates_includes_aur() {
+    let endpoint = spawn_aur_stand_in(
+        r#"{"version":5,"type":"multiinfo","resultcount":2,"results":[
+            {"Name":"yay","Version":"12.3.5-1"},
+            {"Name":"paru","Version":"2.0.3-1"}
+        ]}"#
+    ).await;
+
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::ok("/usr/bin/checkupdates\n"))
+        .on(&["checkupdates"], ScriptedResponse::exit(2, "", ""))
+        .on(&["pacman", "-Qm"], ScriptedResponse::ok("yay 12.3.4-1\nparu 2.0.3-1\nlocal-only 1.0-1\n"));
+    let mut config = scripted_pacman_config();
+    config.include_aur = true;
+    config.aur_rpc_url = endpoint;
+    let manager = PacmanManager::with_runner(config, runner.clone());
+
+    let updates = manager.check_updates().await.unwrap();
+
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0].name, "yay");
+    assert_eq!(updates[0].new_version, "12.3.5-1");
+    assert_eq!(updates[0].repository, "aur");
+}
+
+#[tokio::test]
+async fn test_install_updates_retries_then_succeeds() {
+    // Deux échecs puis un succès: trois tentatives au total
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::exit(1, "", "error: failed to commit transaction"))
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::exit(1, "", "error: failed to commit transaction"))
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    manager.install_updates(&updates).await.unwrap();
+
+    assert_eq!(runner.count(&["sudo", "pacman", "-Syu"]), 3);
+}
+
+#[tokio::test]
+async fn test_install_updates_gives_up_after_retry_count() {
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::exit(1, "", "error: failed to commit transaction"));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    let result = manager.install_updates(&updates).await;
+
+    assert!(result.unwrap_err().to_string().contains("failed to commit transaction"));
+    assert_eq!(runner.count(&["sudo", "pacman", "-Syu"]), 3);
+}
+
+#[tokio::test]
+async fn test_install_updates_timeout() {
+    // Une transaction plus longue que le délai autorisé est interrompue
+    let mut config = scripted_pacman_config();
+    config.retry_count = 1;
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::ok("").delayed(Duration::from_secs(5)));
+    let manager = PacmanManager::with_runner(config, runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    let result = manager.install_updates(&updates).await;
+
+    assert!(result.unwrap_err().to_string().contains("Timeout"));
+}
+
+#[tokio::test]
+async fn test_clean_cache_records_history() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let cache_dir = temp_dir.path().join("pkg");
+    std::fs::create_dir(&cache_dir).unwrap();
+    for name in [
+        "firefox-90.0-1-x86_64.pkg.tar.zst",
+        "firefox-91.0-1-x86_64.pkg.tar.zst",
+        "firefox-92.0-1-x86_64.pkg.tar.zst",
+        "gtk2-2.24.33-3-x86_64.pkg.tar.zst",
+    ] {
+        std::fs::write(cache_dir.join(name), "archive").unwrap();
+    }
+
+    let mut config = scripted_pacman_config();
+    config.clean_cache_after = true;
+    config.cache_dir = cache_dir.clone();
+    config.cache_retention.keep_installed = 2;
+    config.cache_retention.keep_uninstalled = 0;
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::ok(""))
+        .on(&["pacman", "-Qq"], ScriptedResponse::ok("firefox\nlinux\n"))
+        .on(&["sudo", "rm"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(config, runner.clone());
+
+    // L'installation ne nettoie pas le cache: l'appelant le fait et l'enregistre
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+    manager.install_updates(&updates).await.unwrap();
+    assert!(manager.clean_cache_after());
+    assert_eq!(runner.count(&["sudo", "rm"]), 0);
+
+    let cleanup = manager.cache_manager().plan().await.unwrap();
+    assert_eq!(cleanup.reclaimable(), 14);
+
+    let mut history = UpdateHistory::new(temp_dir.path().join("data"));
+    assert_eq!(manager.clean_cache(&mut history).await.unwrap(), 14);
+    let rm = runner.invocations().into_iter().find(|cmd| cmd.to_string().starts_with("sudo rm")).unwrap();
+    assert_eq!(
+        rm.to_string(),
+        format!(
+            "sudo rm -f -- {} {}",
+            cache_dir.join("firefox-90.0-1-x86_64.pkg.tar.zst").display(),
+            cache_dir.join("gtk2-2.24.33-3-x86_64.pkg.tar.zst").display()
+        )
+    );
+
+    let entry = &history.get_all_entries()[0];
+    assert!(matches!(entry.operation_type, OperationType::CleanCache));
+    assert_eq!(entry.packages, vec!["firefox", "gtk2"]);
+    assert_eq!(entry.freed_bytes, Some(14));
+    assert!(entry.success);
+}
+
+#[tokio::test]
+async fn test_prefetch_downloads_missing_archives() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let cache_dir = temp_dir.path().join("pkg");
+    std::fs::create_dir(&cache_dir).unwrap();
+    std::fs::write(cache_dir.join("linux-6.1.2-1-x86_64.pkg.tar.zst"), "archive").unwrap();
+
+    let mut config = scripted_pacman_config();
+    config.cache_dir = cache_dir.clone();
+    config.exclude_packages = vec!["nvidia".to_string()];
+    config.hold_rules = vec![HoldRule { package: Some("mesa".to_string()), ..HoldRule::default() }];
+    let runner = ScriptedRunner::new()
+        .on(&["which", "checkupdates"], ScriptedResponse::ok("/usr/bin/checkupdates\n"))
+        .on(&["checkupdates"], ScriptedResponse::ok("firefox 91.0-1 -> 92.0-1\nlinux 6.1.1-1 -> 6.1.2-1\nmesa 24.1-1 -> 24.2-1\n"))
+        .on(&["pacman", "-Si"], ScriptedResponse::ok(""))
+        .on(&["pacman", "-Qi"], ScriptedResponse::ok(""))
+        .on(&["sudo", "pacman", "-Sy"], ScriptedResponse::ok(""))
+        .on(&["pacman", "-Sup"], ScriptedResponse::ok(
+            "firefox 92.0-1 2048 https://mirror.example/extra/os/x86_64/firefox-92.0-1-x86_64.pkg.tar.zst\n\
+             linux 6.1.2-1 4096 https://mirror.example/core/os/x86_64/linux-6.1.2-1-x86_64.pkg.tar.zst\n"
+        ))
+        .on(&["sudo", "curl"], ScriptedResponse::ok(""))
+        .on(&["sudo", "mv"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(config, runner.clone());
+    let mut history = UpdateHistory::new(temp_dir.path().join("data"));
+
+    let plan = manager.prefetch(Some(256), &mut history).await.unwrap();
+    assert_eq!(plan.to_string(), "1 paquets à télécharger (2.00 KiB), 1 déjà en cache");
+    // Exclusions et paquets retenus ne sont pas téléchargés
+    assert_eq!(runner.count(&["pacman", "-Sup", "--print-format", "%n %v %s %l", "--ignore", "nvidia,mesa"]), 1);
+    assert_eq!(runner.count(&["sudo", "curl"]), 1);
+    assert!(runner.invocations().iter().any(|cmd| cmd.to_string().contains("--limit-rate 256k")));
+    // Aucune transaction pacman n'installe quoi que ce soit
+    assert_eq!(runner.count(&["sudo", "pacman", "-Syu"]), 0);
+
+    let entry = &history.get_all_entries()[0];
+    assert!(matches!(entry.operation_type, OperationType::Prefetch));
+    assert_eq!(entry.packages, vec!["firefox"]);
+    assert!(entry.success);
+
+    // Tout est déjà en cache: rien à télécharger ni à enregistrer
+    std::fs::write(cache_dir.join("firefox-92.0-1-x86_64.pkg.tar.zst"), "archive").unwrap();
+    let plan = manager.prefetch(None, &mut history).await.unwrap();
+    assert_eq!(plan.to_string(), "Prêt à installer, rien à télécharger (2 paquets en cache)");
+    assert_eq!(runner.count(&["sudo", "curl"]), 1);
+    assert_eq!(history.get_all_entries().len(), 1);
+}
+
+#[tokio::test]
+async fn test_install_updates_reports_new_pacnew_files() {
+    let config_root = tempfile::TempDir::new().unwrap();
+    std::fs::write(config_root.path().join("old.conf.pacnew"), "").unwrap();
+
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::ok(
+            "(1/1) upgrading pacman-mirrorlist\n\
+             warning: /etc/pacman.d/mirrorlist installed as /etc/pacman.d/mirrorlist.pacnew\n"
+        ));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner)
+        .with_config_root(config_root.path().to_path_buf());
+    let updates = vec![PackageUpdate::new("pacman-mirrorlist".to_string(), "20240101-1".to_string(), "20240201-1".to_string())];
+
+    let pacnew_files = manager.install_updates(&updates).await.unwrap();
+
+    // Le fichier déjà présent avant la transaction n'est pas signalé
+    assert_eq!(pacnew_files.len(), 1);
+    assert_eq!(pacnew_files[0].path, PathBuf::from("/etc/pacman.d/mirrorlist.pacnew"));
+    assert_eq!(pacnew_files[0].original, PathBuf::from("/etc/pacman.d/mirrorlist"));
+}
+
+#[tokio::test]
+async fn test_install_updates_upgrades_keyring_first() {
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Sy"], ScriptedResponse::ok(""))
+        .on(&["sudo", "pacman", "-S", "--needed"], ScriptedResponse::ok(""))
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner.clone());
+    let updates = vec![
+        PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string()),
+        PackageUpdate::new("archlinux-keyring".to_string(), "20240101-1".to_string(), "20240201-1".to_string()),
+    ];
+
+    manager.install_updates(&updates).await.unwrap();
+
+    let invocations: Vec<String> = runner.invocations().iter().map(ToString::to_string).collect();
+    assert_eq!(invocations, vec![
+        "sudo pacman -Sy",
+        "sudo pacman -S --needed --noconfirm archlinux-keyring",
+        "sudo pacman -Syu --noconfirm",
+    ]);
+}
+
+#[tokio::test]
+async fn test_install_updates_refreshes_keyring_on_signature_error() {
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::exit(
+            1,
+            "",
+            "error: firefox: signature from \"Jane <jane@archlinux.org>\" is unknown trust\n\
+             error: failed to commit transaction (invalid or corrupted package (PGP signature))"
+        ))
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::ok(""))
+        .on(&["pacman", "-Qq"], ScriptedResponse::exit(1, "archlinux-keyring\ncachyos-keyring\n", ""))
+        .on(&["sudo", "pacman", "-S", "--noconfirm"], ScriptedResponse::ok(""))
+        .on(&["sudo", "pacman-key", "--populate"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    manager.install_updates(&updates).await.unwrap();
+
+    let invocations: Vec<String> = runner.invocations().iter().map(ToString::to_string).collect();
+    assert_eq!(invocations, vec![
+        "sudo pacman -Syu --noconfirm",
+        "pacman -Qq archlinux-keyring cachyos-keyring chaotic-keyring",
+        "sudo pacman -S --noconfirm archlinux-keyring cachyos-keyring",
+        "sudo pacman-key --populate",
+        "sudo pacman -Syu --noconfirm",
+    ]);
+}
+
+#[tokio::test]
+async fn test_install_updates_passes_exclusions_as_ignore() {
+    let mut config = scripted_pacman_config();
+    config.exclude_packages = vec!["linux".to_string(), "nvidia-dkms".to_string()];
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(config, runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    manager.install_updates(&updates).await.unwrap();
+
+    assert_eq!(runner.invocations()[0].to_string(), "sudo pacman -Syu --noconfirm --ignore linux,nvidia-dkms");
+}
+
+#[tokio::test]
+async fn test_selective_plan_refuses_partial_upgrade() {
+    let mut config = scripted_pacman_config();
+    config.upgrade_mode = UpgradeMode::Selective;
+    let runner = ScriptedRunner::new()
+        .on(&["pacman", "-Si"], ScriptedResponse::ok("Name            : firefox\nDepends On      : gtk3  nss>=3.90\n"))
+        .on(&["pacman", "-Qi"], ScriptedResponse::ok("Name            : firefox\nRequired By     : None\n"))
+        .on(&["sudo", "pacman", "-S"], ScriptedResponse::ok(""));
+    let manager = PacmanManager::with_runner(config, runner.clone());
+    let available = vec![
+        PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string()),
+        PackageUpdate::new("nss".to_string(), "3.89-1".to_string(), "3.90-1".to_string()),
+    ];
+
+    let plan = manager.plan_upgrade(&available, &["firefox".to_string()]).await.unwrap();
+    assert!(plan.is_partial());
+    assert_eq!(plan.pacman_args(), vec!["-S", "--needed", "--noconfirm", "firefox"]);
+
+    let result = manager.execute_plan(&plan, None).await;
+    assert!(result.unwrap_err().to_string().contains("partielle"));
+    assert_eq!(runner.count(&["sudo", "pacman"]), 0);
+
+    // En sélectionnant aussi la dépendance, le plan est cohérent
+    let plan = manager.plan_upgrade(&available, &["firefox".to_string(), "nss".to_string()]).await.unwrap();
+    assert!(!plan.is_partial());
+    manager.execute_plan(&plan, None).await.unwrap();
+    assert_eq!(runner.invocations().last().unwrap().to_string(), "sudo pacman -S --needed --noconfirm firefox nss");
+}
+
+#[tokio::test]
+async fn test_failed_update_keeps_only_pre_snapshot() {
+    let mut snapshot_config = Config::default().snapshots;
+    snapshot_config.backend = SnapshotBackend::Btrfs;
+    let runner = ScriptedRunner::new()
+        .on(&["sudo", "mkdir"], ScriptedResponse::ok(""))
+        .on(&["sudo", "btrfs", "subvolume", "snapshot"], ScriptedResponse::ok(""))
+        .on(&["sudo", "pacman", "-Syu"], ScriptedResponse::exit(1, "", "error: failed to commit transaction"));
+    let snapshots = SnapshotManager::with_runner(snapshot_config, runner.clone());
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    let plan = manager.plan_upgrade(&updates, &["firefox".to_string()]).await.unwrap();
+
+    let (entry, result) = manager
+        .execute_plan_with_snapshots(&plan, OperationType::ManualUpdate, Some(&snapshots), None)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(entry.snapshots.len(), 1);
+    assert_eq!(entry.snapshots[0].kind, SnapshotKind::Pre);
+    assert_eq!(runner.count(&["sudo", "btrfs", "subvolume", "snapshot"]), 1);
+    // Aucune purge après un échec
+    assert_eq!(runner.count(&["sudo", "btrfs", "subvolume", "list"]), 0);
+
+    // L'identifiant de l'instantané et les versions sont conservés dans l'historique
+    assert!(!entry.success);
+    assert_eq!(entry.updates, updates);
+    let json = serde_json::to_string(&entry).unwrap();
+    let restored: HistoryEntry = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.snapshots, entry.snapshots);
+}
+
+#[tokio::test]
+async fn test_preview_transaction_from_print_format() {
+    let runner = ScriptedRunner::new()
+        .on(&["pacman", "-Sup"], ScriptedResponse::ok("firefox 92.0-1 extra 60000000\npipewire-jack 1:1.2.3-1 extra 100000\n"))
+        .on(&["pacman", "-Q"], ScriptedResponse::ok("firefox 91.0-1\njack2 1.9.22-1\nglibc 2.40-1\n"))
+        .on(&["pacman", "-Si"], ScriptedResponse::ok(
+            "Name            : firefox\nInstalled Size  : 240.00 MiB\nReplaces        : None\nConflicts With  : None\n\n\
+             Name            : pipewire-jack\nInstalled Size  : 1.00 MiB\nReplaces        : jack2\nConflicts With  : jack  jack2\n"
+        ))
+        .on(&["pacman", "-Qi"], ScriptedResponse::ok(
+            "Name            : jack2\nInstalled Size  : 2.00 MiB\n\nName            : firefox\nInstalled Size  : 230.00 MiB\n"
+        ));
+    let manager = PacmanManager::with_runner(scripted_pacman_config(), runner.clone());
+    let updates = vec![PackageUpdate::new("firefox".to_string(), "91.0-1".to_string(), "92.0-1".to_string())];
+
+    let plan = manager.plan_upgrade(&updates, &[]).await.unwrap();
+    let preview = manager.preview_transaction(&plan).await.unwrap();
+
+    assert_eq!(preview.upgrades.len(), 1);
+    assert_eq!(preview.replacements[0].old, "jack2");
+    assert_eq!(preview.replacements[0].new.name, "pipewire-jack");
+    assert!(preview.removals.is_empty());
+    assert_eq!(preview.download_size, 60_100_000);
+    assert_eq!(preview.installed_size_delta, 9 * 1024 * 1024);
+
+    // Rien n'est installé pendant l'aperçu
+    assert_eq!(runner.count(&["sudo"]), 0);
+    assert_eq!(
+        runner.invocations()[0].to_string(),
+        "pacman -Sup --print-format %n %v %r %s"
+    );
+}
+
+/// Démarre un faux miroir local qui renvoie `lastsync` pour toute requête
+async fn spawn_mirror_stand_in(lastsync: String) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                lastsync.len(),
+                lastsync
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    });
+
+    format!("http://{}", address)
+}
+
+#[tokio::test]
+async fn test_mirror_health_and_ranking() {
+    let now = chrono::Utc::now().timestamp();
+    let fresh = spawn_mirror_stand_in(now.to_string()).await;
+    let stale = spawn_mirror_stand_in((now - 3 * 24 * 3600).to_string()).await;
+    // Port libéré aussitôt: miroir injoignable
+    let unreachable = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let mirrorlist = temp_dir.path().join("mirrorlist");
+    let original = format!(
+        "## Stale\nServer = {stale}/$repo/os/$arch\n## Down\nServer = {unreachable}/$repo/os/$arch\n\
+         ## Fresh\nServer = {fresh}/$repo/os/$arch\n#Server = https://disabled.example/$repo/os/$arch\n"
+    );
+    std::fs::write(&mirrorlist, &original).unwrap();
+
+    let config = MirrorsConfig { mirrorlist: mirrorlist.clone(), timeout: 5, ..MirrorsConfig::default() };
+    let manager = MirrorManager::new(config);
+    let health = manager.check().await.unwrap();
+
+    assert_eq!(health.mirrors.len(), 3);
+    assert_eq!(health.mirrors[0].state, MirrorState::Stale { lag_hours: 72 });
+    assert!(matches!(health.mirrors[1].state, MirrorState::Unreachable(_)));
+    assert_eq!(health.mirrors[2].state, MirrorState::UpToDate);
+    assert!(health.needs_attention());
+
+    let backup = manager.rank(&health).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), original);
+    let ranked = Mirrorlist::parse(&std::fs::read_to_string(&mirrorlist).unwrap());
+    assert_eq!(ranked.servers().collect::<Vec<_>>(), vec![format!("{fresh}/$repo/os/$arch")]);
+    assert_eq!(ranked.entries.len(), 4);
+
+    // Liste reclassée: le premier miroir est à jour
+    assert!(!manager.check().await.unwrap().needs_attention());
+}